@@ -0,0 +1,47 @@
+/*
+ * C interface of vcf_batcher, built into the shared library with the `ffi` feature:
+ *
+ *     cargo build --release --lib --no-default-features --features ffi
+ *
+ * See the documentation of the `ffi` module for details.
+ */
+#ifndef VCF_BATCHER_H
+#define VCF_BATCHER_H
+
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+/* The error codes are the exit codes of the CLI. */
+#define VCF_BATCHER_OK 0
+#define VCF_BATCHER_ERROR_USAGE 2
+#define VCF_BATCHER_ERROR_INPUT 3
+#define VCF_BATCHER_ERROR_OUTPUT 4
+#define VCF_BATCHER_ERROR_INTERNAL 70
+#define VCF_BATCHER_ERROR_INTERRUPTED 130
+
+/* Called with the records read and batches completed so far, possibly from another thread. */
+typedef void (*vcf_batcher_progress_callback)(uint64_t records, uint64_t batches, void *user_data);
+
+/*
+ * Splits the VCF file `input` into batches of `batch_size` records in the directory `output_dir`.
+ * `on_progress` and `batches_written` may be NULL. Returns VCF_BATCHER_OK or an error code.
+ */
+int vcf_batcher_split(const char *input, const char *output_dir, size_t batch_size,
+                      vcf_batcher_progress_callback on_progress, void *user_data,
+                      size_t *batches_written);
+
+/*
+ * The message of the last error on the calling thread, or NULL if the last call succeeded.
+ * Valid until the next call of vcf_batcher_split on the same thread.
+ */
+const char *vcf_batcher_last_error(void);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif /* VCF_BATCHER_H */
@@ -0,0 +1,265 @@
+//! Appending the records of a new input to the batches of earlier runs, see [`BatchOptions::append`].
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::bed::BED_FILE_NAME;
+use crate::manifest::{file_sha256, Manifest};
+use crate::merge::batch_files;
+use crate::{
+    is_header_line, read_lines, AppendLine, AppendMode, BatchInfo, BatchOptions, Locus,
+    OutputFormat, SplitMode, VcfBatcherError,
+};
+
+/// The batches that are already in the output directory.
+#[derive(Debug, Default)]
+pub(crate) struct ExistingBatches {
+    /// The batches that are kept, in order.
+    pub(crate) batches: Vec<BatchInfo>,
+    /// The header of the first batch, which the appended batches must have as well.
+    pub(crate) headers: Option<String>,
+    /// The last batch, if it is refilled with the first records of the input.
+    pub(crate) tail: Option<Tail>,
+    /// The lines of the BED file for the kept batches.
+    pub(crate) bed: String,
+    /// The directories of the groups of the kept batches, see [`BatchOptions::group_by_info`].
+    pub(crate) groups: BTreeMap<String, PathBuf>,
+}
+
+/// A batch with fewer records than the batch size, which is written again together with the first
+/// records of the input, see [`AppendMode::Rebalance`].
+#[derive(Debug)]
+pub(crate) struct Tail {
+    pub(crate) path: PathBuf,
+    pub(crate) records: String,
+    pub(crate) record_count: usize,
+}
+
+/// Finds the batches in the output directory from its manifest or, without one, like
+/// [`crate::merge::merge_batches`] does, reading them to count their records.
+pub(crate) fn existing_batches(
+    output_path: &Path,
+    mode: AppendMode,
+    options: &BatchOptions,
+) -> Result<ExistingBatches, VcfBatcherError> {
+    if !output_path.is_dir() {
+        return Ok(ExistingBatches::default());
+    }
+    let read_error = |path: &Path| {
+        let path = path.to_path_buf();
+        move |source| VcfBatcherError::Read { path, source }
+    };
+
+    let mut groups = BTreeMap::new();
+    let mut batches = match Manifest::read(output_path).map_err(read_error(output_path))? {
+        Some(manifest) => {
+            groups.extend(
+                manifest
+                    .groups
+                    .into_iter()
+                    .map(|(group, dir)| (group, output_path.join(dir))),
+            );
+            let mut entries = manifest.batches;
+            entries.sort_by_key(|entry| entry.number);
+            entries
+                .into_iter()
+                .map(|entry| BatchInfo {
+                    number: entry.number,
+                    path: output_path.join(entry.file),
+                    records: entry.records,
+                    sha256: entry.sha256,
+                    size: entry.size,
+                    first: entry.first,
+                    last: entry.last,
+                    verified: entry.verified,
+                })
+                .collect()
+        }
+        None => {
+            let mut batches = Vec::new();
+            for (index, path) in batch_files(output_path, Path::new(""))?
+                .into_iter()
+                .enumerate()
+            {
+                let (_, records) = read_batch(&path).map_err(read_error(&path))?;
+                batches.push(BatchInfo {
+                    number: index + 1,
+                    records: records.lines().count(),
+                    sha256: file_sha256(&path).map_err(read_error(&path))?,
+                    size: fs::metadata(&path).map_err(read_error(&path))?.len(),
+                    first: records.lines().next().and_then(Locus::from_record),
+                    last: records.lines().next_back().and_then(Locus::from_record),
+                    verified: false,
+                    path,
+                });
+            }
+            batches
+        }
+    };
+
+    // Only VCF batches can be read back
+    let vcf = options.format == OutputFormat::Vcf;
+    let headers = match batches.first() {
+        Some(first) if vcf => Some(read_batch(&first.path).map_err(read_error(&first.path))?.0),
+        _ => None,
+    };
+
+    let refill = mode == AppendMode::Rebalance
+        && vcf
+        && options.split_by == SplitMode::Records
+        && options.group_by_info.is_none()
+        && batches.last().is_some_and(|last| {
+            last.records > 0 && last.records < options.batch_size_of(last.number)
+        });
+    let tail = match refill.then(|| batches.pop()).flatten() {
+        Some(last) => {
+            let (_, records) = read_batch(&last.path).map_err(read_error(&last.path))?;
+            Some(Tail {
+                record_count: records.lines().count(),
+                records,
+                path: last.path,
+            })
+        }
+        None => None,
+    };
+
+    let mut bed = String::new();
+    if options.batches_bed {
+        let bed_path = output_path.join(BED_FILE_NAME);
+        let contents = match fs::read_to_string(&bed_path) {
+            Ok(contents) => contents,
+            Err(error) if error.kind() == io::ErrorKind::NotFound => String::new(),
+            Err(error) => return Err(read_error(&bed_path)(error)),
+        };
+        let tail_name = tail
+            .as_ref()
+            .and_then(|tail| tail.path.file_name())
+            .map(|name| name.to_string_lossy().into_owned());
+        for line in contents.lines() {
+            if line.split('\t').nth(3) != tail_name.as_deref() {
+                bed.append_line(line);
+            }
+        }
+    }
+
+    Ok(ExistingBatches {
+        batches,
+        headers,
+        tail,
+        bed,
+        groups,
+    })
+}
+
+/// Reads the header lines and the records of a batch, each followed by a line ending.
+fn read_batch(path: &Path) -> io::Result<(String, String)> {
+    let (mut headers, mut records) = (String::new(), String::new());
+    for line in read_lines(path)? {
+        let line = line?;
+        match is_header_line(&line) {
+            true => headers.append_line(&line),
+            false if line.is_empty() => continue,
+            false => records.append_line(&line),
+        };
+    }
+    Ok((headers, records))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::path::Path;
+
+    use crate::merge::merge_batches;
+    use crate::testdata::SyntheticVcf;
+    use crate::{
+        extract_variants_to_batches_with_options, AppendMode, BatchOptions, OverwritePolicy,
+        VcfBatcherError,
+    };
+
+    /// Writes a delivery of a synthetic cohort with the same header as the other deliveries.
+    fn delivery(path: &Path, records: usize, seed: u64) {
+        SyntheticVcf {
+            records,
+            seed,
+            ..Default::default()
+        }
+        .write_file(path, None)
+        .unwrap();
+        // The header names the seed
+        let contents = fs::read_to_string(path).unwrap();
+        fs::write(
+            path,
+            contents.replace(&format!("(seed {seed})"), "(seed 0)"),
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn test_append() {
+        let root = Path::new("./test_data/append");
+        let _ = fs::remove_dir_all(root);
+        let (first, second) = (root.join("january.vcf"), root.join("february.vcf"));
+        delivery(&first, 95, 1);
+        delivery(&second, 50, 2);
+
+        for (mode, batches, last_records) in [
+            (AppendMode::Continue, 6, 20),
+            (AppendMode::Rebalance, 5, 25),
+        ] {
+            let output = root.join(format!("{mode:?}"));
+            let options = BatchOptions {
+                batch_size: 30,
+                quiet: true,
+                batches_bed: true,
+                overwrite: OverwritePolicy::Refuse,
+                ..Default::default()
+            };
+            extract_variants_to_batches_with_options(first.to_str().unwrap(), &output, &options)
+                .unwrap();
+            let options = BatchOptions {
+                append: Some(mode),
+                ..options
+            };
+            let report = extract_variants_to_batches_with_options(
+                second.to_str().unwrap(),
+                &output,
+                &options,
+            )
+            .unwrap();
+
+            assert_eq!(report.batches.len(), batches, "{mode:?}");
+            assert_eq!(report.total_records(), 145);
+            assert_eq!(report.batches.last().unwrap().records, last_records);
+            let bed = fs::read_to_string(output.join("batches.bed")).unwrap();
+            assert!(bed.contains("batch_01.vcf"));
+            assert!(bed.contains(&format!("batch_{batches:02}.vcf")));
+            let merged = merge_batches(&output, &root.join(format!("{mode:?}.vcf"))).unwrap();
+            assert_eq!(merged.records, 145);
+        }
+
+        let other = root.join("other.vcf");
+        SyntheticVcf {
+            samples: 2,
+            records: 10,
+            ..Default::default()
+        }
+        .write_file(&other, None)
+        .unwrap();
+        let options = BatchOptions {
+            batch_size: 30,
+            quiet: true,
+            append: Some(AppendMode::Continue),
+            ..Default::default()
+        };
+        let error = extract_variants_to_batches_with_options(
+            other.to_str().unwrap(),
+            &root.join("Continue"),
+            &options,
+        )
+        .unwrap_err();
+        assert!(matches!(error, VcfBatcherError::HeaderMismatch { .. }));
+    }
+}
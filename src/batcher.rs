@@ -0,0 +1,211 @@
+//! A builder for splitting runs, so that new options do not change the signature of existing calls.
+
+use std::path::PathBuf;
+use std::sync::atomic::AtomicBool;
+use std::sync::Arc;
+
+use bgzip::Compression;
+
+use crate::filter::RecordFilter;
+use crate::{
+    extract_variants_to_batches_with_options, extract_variants_to_sink, BatchCallback, BatchInfo,
+    BatchOptions, BatchSink, CompressionFormat, IndexFormat, OutputFormat, Progress,
+    ProgressCallback, RunReport, SplitMode, VcfBatcherError,
+};
+
+/// Splits a VCF file into batches, configured step by step instead of through positional arguments.
+///
+/// Settings that have no method of their own can be made on a [`BatchOptions`] that is passed to
+/// [`Batcher::options`]. Unset settings keep the defaults of [`BatchOptions`].
+///
+/// # Examples
+///
+/// ```
+/// use vcf_batcher::{Batcher, Compression};
+///
+/// let output = std::path::Path::new("test_data/doctest_batcher");
+/// # let _ = std::fs::remove_dir_all(output);
+/// let report = Batcher::new("test_data/batch_01.vcf.gz")
+///     .batch_size(25)
+///     .compression(Compression::fast())
+///     .output_dir(output)
+///     .quiet(true)
+///     .run()
+///     .unwrap();
+/// assert_eq!(report.batches.len(), 4);
+/// assert_eq!(report.batches[0].path, output.join("batch_01.vcf.gz"));
+/// ```
+#[derive(Debug, Clone)]
+pub struct Batcher {
+    input: String,
+    output_dir: Option<PathBuf>,
+    options: BatchOptions,
+}
+
+impl Batcher {
+    /// Starts the configuration of a run that reads the VCF file at `input`.
+    pub fn new(input: impl Into<String>) -> Self {
+        Batcher {
+            input: input.into(),
+            output_dir: None,
+            options: BatchOptions::default(),
+        }
+    }
+
+    /// The directory the batches are written to by [`Batcher::run`].
+    pub fn output_dir(mut self, output_dir: impl Into<PathBuf>) -> Self {
+        self.output_dir = Some(output_dir.into());
+        self
+    }
+
+    /// Replaces all options, including those set by earlier calls, e.g. to make settings that have no
+    /// method of their own. Later calls change the given options.
+    pub fn options(mut self, options: BatchOptions) -> Self {
+        self.options = options;
+        self
+    }
+
+    /// See [`BatchOptions::batch_size`].
+    pub fn batch_size(mut self, batch_size: usize) -> Self {
+        self.options.batch_size = batch_size;
+        self
+    }
+
+    /// Bgzips the batches at this level, see [`BatchOptions::compression_level`]. Batches compressed with zstd
+    /// take its number as the zstd level, see [`BatchOptions::zstd_level`].
+    pub fn compression(mut self, compression: Compression) -> Self {
+        self.options.compression_level = Some(compression);
+        self.options.zstd_level = None;
+        self
+    }
+
+    /// See [`BatchOptions::compression_format`].
+    pub fn compression_format(mut self, compression_format: CompressionFormat) -> Self {
+        self.options.compression_format = compression_format;
+        self
+    }
+
+    /// See [`BatchOptions::split_by`].
+    pub fn split_by(mut self, split_by: SplitMode) -> Self {
+        self.options.split_by = split_by;
+        self
+    }
+
+    /// See [`BatchOptions::format`].
+    pub fn format(mut self, format: OutputFormat) -> Self {
+        self.options.format = format;
+        self
+    }
+
+    /// See [`BatchOptions::filter`].
+    pub fn filter(mut self, filter: RecordFilter) -> Self {
+        self.options.filter = filter;
+        self
+    }
+
+    /// See [`BatchOptions::index`].
+    pub fn index(mut self, index: IndexFormat) -> Self {
+        self.options.index = Some(index);
+        self
+    }
+
+    /// See [`BatchOptions::cancel`].
+    pub fn cancel(mut self, cancel: Arc<AtomicBool>) -> Self {
+        self.options.cancel = Some(cancel);
+        self
+    }
+
+    /// See [`BatchOptions::on_batch`].
+    pub fn on_batch(mut self, callback: impl Fn(&BatchInfo) + Send + Sync + 'static) -> Self {
+        self.options.on_batch = Some(BatchCallback::new(callback));
+        self
+    }
+
+    /// See [`BatchOptions::on_progress`].
+    pub fn on_progress(mut self, callback: impl Fn(&Progress) + Send + Sync + 'static) -> Self {
+        self.options.on_progress = Some(ProgressCallback::new(callback));
+        self
+    }
+
+    /// See [`BatchOptions::quiet`].
+    pub fn quiet(mut self, quiet: bool) -> Self {
+        self.options.quiet = quiet;
+        self
+    }
+
+    /// Writes the batches to the output directory, see [`extract_variants_to_batches_with_options`].
+    ///
+    /// Returns [`VcfBatcherError::InvalidOptions`] if no output directory was set.
+    pub fn run(&self) -> Result<RunReport, VcfBatcherError> {
+        let output_dir =
+            self.output_dir
+                .as_deref()
+                .ok_or_else(|| VcfBatcherError::InvalidOptions {
+                    reason: "an output directory is required to write batches".to_string(),
+                })?;
+        extract_variants_to_batches_with_options(&self.input, output_dir, &self.options)
+    }
+
+    /// Passes the batches to `sink` instead of writing them, see [`extract_variants_to_sink`]. The output
+    /// directory is not used.
+    pub fn run_to_sink(&self, sink: &mut dyn BatchSink) -> Result<RunReport, VcfBatcherError> {
+        extract_variants_to_sink(&self.input, sink, &self.options)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::path::Path;
+
+    use super::Batcher;
+    use crate::{extract_variants_to_batches, BatchOptions, SplitMode, VcfBatcherError};
+
+    #[test]
+    fn test_batcher_matches_extract_variants_to_batches() {
+        let expected = Path::new("test_data/batcher_expected");
+        let output = Path::new("test_data/batcher_output");
+        let _ = fs::remove_dir_all(expected);
+        let _ = fs::remove_dir_all(output);
+        let expected_report =
+            extract_variants_to_batches("test_data/batch_01.vcf.gz", 30, expected, None).unwrap();
+        let report = Batcher::new("test_data/batch_01.vcf.gz")
+            .batch_size(30)
+            .output_dir(output)
+            .run()
+            .unwrap();
+        assert_eq!(report.batches.len(), expected_report.batches.len());
+        for (batch, expected_batch) in report.batches.iter().zip(&expected_report.batches) {
+            assert_eq!(batch.records, expected_batch.records);
+            assert_eq!(
+                fs::read(&batch.path).unwrap(),
+                fs::read(&expected_batch.path).unwrap()
+            );
+        }
+    }
+
+    #[test]
+    fn test_batcher_options() {
+        // Methods called after options change the given options
+        let batcher = Batcher::new("test_data/batch_01.vcf.gz")
+            .batch_size(10)
+            .options(BatchOptions {
+                split_by: SplitMode::Chromosome,
+                ..Default::default()
+            })
+            .quiet(true);
+        let mut names = Vec::new();
+        let mut sink = |batch: &crate::BatchInfo, _: &[u8]| {
+            names.push(batch.path.clone());
+            Ok(())
+        };
+        let report = batcher.run_to_sink(&mut sink).unwrap();
+        assert_eq!(report.batches.len(), 1);
+        assert_eq!(names, vec![Path::new("22.vcf")]);
+
+        assert!(matches!(
+            batcher.run(),
+            Err(VcfBatcherError::InvalidOptions { .. })
+        ));
+    }
+}
@@ -0,0 +1,71 @@
+//! Writing batches as BCF files, see [`crate::OutputFormat::Bcf`]. The batches are converted from VCF
+//! with htslib, so writing them needs the `htslib` feature, as reading BCF inputs does.
+
+use std::path::{Path, PathBuf};
+
+/// Returns the path of a batch with the `.bcf` extension instead of `.vcf` or `.vcf.gz`. BCF files are
+/// always named `.bcf`, whether they are compressed or not.
+///
+/// # Examples
+///
+/// ```
+/// use std::path::Path;
+/// use vcf_batcher::bcf::bcf_path;
+///
+/// assert_eq!(bcf_path(Path::new("out/batch_01.vcf.gz")), Path::new("out/batch_01.bcf"));
+/// assert_eq!(bcf_path(Path::new("chr1.vcf")), Path::new("chr1.bcf"));
+/// assert_eq!(bcf_path(Path::new("sample_01.bcf")), Path::new("sample_01.bcf"));
+/// ```
+pub fn bcf_path(path: &Path) -> PathBuf {
+    let name = path
+        .file_name()
+        .map(|name| name.to_string_lossy())
+        .unwrap_or_default();
+    let stem = name.strip_suffix(".gz").unwrap_or(&name);
+    let stem = stem
+        .strip_suffix(".vcf")
+        .or_else(|| stem.strip_suffix(".bcf"))
+        .unwrap_or(stem);
+    path.with_file_name(format!("{stem}.bcf"))
+}
+
+/// Whether a path names a BCF file, which is only read with htslib.
+pub fn is_bcf(path: &Path) -> bool {
+    path.extension().is_some_and(|extension| extension == "bcf")
+}
+
+/// Converts the VCF text of a batch into a BCF file, compressed with BGZF if `compressed` is set.
+/// htslib only reads and writes files, so both are written to `staging_dir` first.
+#[cfg(feature = "htslib")]
+pub(crate) fn bcf_batch(
+    contents: &[u8],
+    compressed: bool,
+    staging_dir: &Path,
+) -> std::io::Result<Vec<u8>> {
+    use std::fs;
+    use std::io;
+
+    use rust_htslib::bcf::{self, Read};
+
+    let htslib_error = |error| io::Error::new(io::ErrorKind::InvalidData, error);
+    fs::create_dir_all(staging_dir)?;
+    let vcf_path = crate::staging_path(staging_dir, Path::new(".batch.vcf"));
+    let bcf_path = crate::staging_path(staging_dir, Path::new(".batch.bcf"));
+    let result = fs::write(&vcf_path, contents).and_then(|()| {
+        let mut reader = bcf::Reader::from_path(&vcf_path).map_err(htslib_error)?;
+        let header = bcf::Header::from_template(reader.header());
+        let mut writer = bcf::Writer::from_path(&bcf_path, &header, !compressed, bcf::Format::Bcf)
+            .map_err(htslib_error)?;
+        for record in reader.records() {
+            let mut record = record.map_err(htslib_error)?;
+            writer.translate(&mut record);
+            writer.write(&record).map_err(htslib_error)?;
+        }
+        // The file is only complete once the writer is closed
+        drop(writer);
+        fs::read(&bcf_path)
+    });
+    let _ = fs::remove_file(&vcf_path);
+    let _ = fs::remove_file(&bcf_path);
+    result
+}
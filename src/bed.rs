@@ -0,0 +1,46 @@
+//! BED files of the genomic spans that the batches cover, e.g. to pass them as `-L` intervals to
+//! GATK-style tools that should only look at the regions of one batch.
+
+/// Name of the BED file in the output directory, see [`crate::BatchOptions::batches_bed`].
+pub const BED_FILE_NAME: &str = "batches.bed";
+
+/// Lists the spans of the variant records of a batch as BED lines, with `name` in the name column.
+///
+/// A batch has a line per run of consecutive records on the same chromosome, from the smallest
+/// POS to the end of the longest REF allele, with the 0-based, half-open coordinates of BED.
+/// Records without a valid POS are left out.
+///
+/// # Examples
+///
+/// ```
+/// use vcf_batcher::bed::batch_spans;
+///
+/// let records = "chr1\t5\t.\tA\tC\t.\t.\t.\nchr1\t9\t.\tGTT\tG\t.\t.\t.\nchr2\t3\t.\tC\tT\t.\t.\t.\n";
+/// assert_eq!(
+///     batch_spans(records, "batch_01.vcf.gz"),
+///     "chr1\t4\t11\tbatch_01.vcf.gz\nchr2\t2\t3\tbatch_01.vcf.gz\n"
+/// );
+/// ```
+pub fn batch_spans(records: &str, name: &str) -> String {
+    let mut spans: Vec<(&str, u64, u64)> = Vec::new();
+    for record in records.lines() {
+        let mut columns = record.split('\t');
+        let (Some(chrom), Some(Ok(pos))) = (columns.next(), columns.next().map(str::parse::<u64>))
+        else {
+            continue;
+        };
+        let length = columns.nth(1).map_or(1, |reference| reference.len().max(1)) as u64;
+        let (start, end) = (pos.saturating_sub(1), pos.saturating_sub(1) + length);
+        match spans.last_mut() {
+            Some((last_chrom, last_start, last_end)) if *last_chrom == chrom => {
+                *last_start = (*last_start).min(start);
+                *last_end = (*last_end).max(end);
+            }
+            _ => spans.push((chrom, start, end)),
+        }
+    }
+    spans
+        .into_iter()
+        .map(|(chrom, start, end)| format!("{chrom}\t{start}\t{end}\t{name}\n"))
+        .collect()
+}
@@ -0,0 +1,266 @@
+//! A SQLite catalog of runs and their batches, enabled by the `catalog` feature, to find out which
+//! run wrote which batches once there are many output directories.
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use rusqlite::{params, Connection, Transaction};
+use time::format_description::well_known::Rfc3339;
+use time::OffsetDateTime;
+
+use crate::{RunReport, VcfBatcherError};
+
+/// Name of the catalog in the output directory if no other path is given.
+pub const CATALOG_FILE_NAME: &str = "catalog.sqlite";
+
+/// The tables of the catalog. Paths are absolute, times are in RFC 3339 format (UTC) and every run
+/// has one input and any number of batches.
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS runs (
+    id INTEGER PRIMARY KEY,
+    started_at TEXT NOT NULL,
+    finished_at TEXT NOT NULL,
+    version TEXT NOT NULL,
+    output_dir TEXT NOT NULL,
+    fingerprint TEXT NOT NULL,
+    parameters TEXT NOT NULL,
+    batches INTEGER NOT NULL,
+    records INTEGER NOT NULL,
+    filtered_records INTEGER NOT NULL,
+    bad_records INTEGER NOT NULL,
+    elapsed_seconds REAL NOT NULL
+);
+CREATE TABLE IF NOT EXISTS inputs (
+    id INTEGER PRIMARY KEY,
+    run_id INTEGER NOT NULL REFERENCES runs (id),
+    path TEXT NOT NULL,
+    size_bytes INTEGER,
+    modified_at TEXT
+);
+CREATE TABLE IF NOT EXISTS batches (
+    id INTEGER PRIMARY KEY,
+    run_id INTEGER NOT NULL REFERENCES runs (id),
+    number INTEGER NOT NULL,
+    path TEXT NOT NULL,
+    records INTEGER NOT NULL,
+    sha256 TEXT NOT NULL,
+    first_chrom TEXT,
+    first_pos INTEGER,
+    last_chrom TEXT,
+    last_pos INTEGER
+);
+CREATE INDEX IF NOT EXISTS batches_by_run ON batches (run_id);
+CREATE INDEX IF NOT EXISTS batches_by_region ON batches (first_chrom, first_pos);
+";
+
+/// A SQLite database with a row in `runs` and `inputs` per run and a row in `batches` per batch.
+/// Runs are appended, so one catalog can be shared by all runs, e.g. to look up where the batches
+/// covering a region are:
+///
+/// ```sql
+/// SELECT batches.path FROM batches JOIN runs ON runs.id = batches.run_id
+/// WHERE first_chrom = 'chr1' AND first_pos <= 20000 AND last_pos >= 10000;
+/// ```
+///
+/// # Examples
+///
+/// ```
+/// use std::path::Path;
+/// use vcf_batcher::catalog::Catalog;
+/// use vcf_batcher::{extract_variants_to_batches_with_options, BatchOptions};
+///
+/// let output = Path::new("test_data/doc_catalog");
+/// let report = extract_variants_to_batches_with_options(
+///     "test_data/batch_01.vcf.gz",
+///     output,
+///     &BatchOptions { batch_size: 50, ..Default::default() },
+/// )
+/// .unwrap();
+///
+/// let mut catalog = Catalog::open(output.join("catalog.sqlite")).unwrap();
+/// let run = catalog
+///     .record_run(Path::new("test_data/batch_01.vcf.gz"), &report, &serde_json::json!({ "batch_size": 50 }))
+///     .unwrap();
+/// assert!(run >= 1);
+/// ```
+pub struct Catalog {
+    path: PathBuf,
+    connection: Connection,
+}
+
+impl Catalog {
+    /// Opens the catalog, creating the file and its tables if necessary.
+    pub fn open(path: impl Into<PathBuf>) -> Result<Catalog, VcfBatcherError> {
+        let path = path.into();
+        let connection = Connection::open(&path)
+            .and_then(|connection| {
+                connection.execute_batch(SCHEMA)?;
+                Ok(connection)
+            })
+            .map_err(|error| catalog_error(&path, error))?;
+        Ok(Catalog { path, connection })
+    }
+
+    /// The path of the database file.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Adds a completed run with its input and batches and returns the id of the run.
+    /// `parameters` are stored as JSON, e.g. the options the run was started with.
+    pub fn record_run(
+        &mut self,
+        input: &Path,
+        report: &RunReport,
+        parameters: &serde_json::Value,
+    ) -> Result<i64, VcfBatcherError> {
+        let transaction = self
+            .connection
+            .transaction()
+            .map_err(|error| catalog_error(&self.path, error))?;
+        let run = insert_run(&transaction, input, report, parameters)
+            .and_then(|run| transaction.commit().map(|_| run))
+            .map_err(|error| catalog_error(&self.path, error))?;
+        Ok(run)
+    }
+}
+
+/// Inserts the rows of a run. They only become visible once the transaction is committed.
+fn insert_run(
+    transaction: &Transaction,
+    input: &Path,
+    report: &RunReport,
+    parameters: &serde_json::Value,
+) -> rusqlite::Result<i64> {
+    let finished = OffsetDateTime::now_utc();
+    let started = finished - report.elapsed;
+    transaction.execute(
+        "INSERT INTO runs (started_at, finished_at, version, output_dir, fingerprint, parameters, \
+         batches, records, filtered_records, bad_records, elapsed_seconds) \
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11)",
+        params![
+            timestamp(started),
+            timestamp(finished),
+            env!("CARGO_PKG_VERSION"),
+            absolute(&report.output_path),
+            report.fingerprint,
+            parameters.to_string(),
+            report.batches.len(),
+            report.total_records(),
+            report.filtered_records,
+            report.bad_records,
+            report.elapsed.as_secs_f64(),
+        ],
+    )?;
+    let run = transaction.last_insert_rowid();
+
+    // The input may have been read from stdin
+    let metadata = fs::metadata(input).ok();
+    let modified = metadata
+        .as_ref()
+        .and_then(|metadata| metadata.modified().ok())
+        .map(OffsetDateTime::from);
+    transaction.execute(
+        "INSERT INTO inputs (run_id, path, size_bytes, modified_at) VALUES (?1, ?2, ?3, ?4)",
+        params![
+            run,
+            absolute(input),
+            metadata.as_ref().map(|metadata| metadata.len()),
+            modified.map(timestamp),
+        ],
+    )?;
+
+    let mut insert = transaction.prepare(
+        "INSERT INTO batches (run_id, number, path, records, sha256, first_chrom, first_pos, \
+         last_chrom, last_pos) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+    )?;
+    for batch in &report.batches {
+        insert.execute(params![
+            run,
+            batch.number,
+            absolute(&batch.path),
+            batch.records,
+            batch.sha256,
+            batch.first.as_ref().map(|locus| &locus.chrom),
+            batch.first.as_ref().map(|locus| locus.pos),
+            batch.last.as_ref().map(|locus| &locus.chrom),
+            batch.last.as_ref().map(|locus| locus.pos),
+        ])?;
+    }
+    Ok(run)
+}
+
+fn catalog_error(path: &Path, error: rusqlite::Error) -> VcfBatcherError {
+    VcfBatcherError::Write {
+        path: path.to_path_buf(),
+        source: io::Error::other(error).into(),
+    }
+}
+
+fn timestamp(time: OffsetDateTime) -> String {
+    time.format(&Rfc3339).unwrap_or_default()
+}
+
+/// The absolute path, so that the entries can be found from any working directory.
+fn absolute(path: &Path) -> String {
+    path.canonicalize()
+        .unwrap_or_else(|_| path.to_path_buf())
+        .to_string_lossy()
+        .into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use serde_json::json;
+
+    use super::Catalog;
+    use crate::{extract_variants_to_batches_with_options, BatchOptions};
+
+    #[test]
+    fn test_record_runs() {
+        let root = Path::new("./test_data/catalog");
+        let _ = std::fs::remove_dir_all(root);
+        let input = Path::new("./test_data/batch_01.vcf.gz");
+        let options = BatchOptions {
+            batch_size: 30,
+            quiet: true,
+            ..Default::default()
+        };
+        let report =
+            extract_variants_to_batches_with_options("./test_data/batch_01.vcf.gz", root, &options)
+                .unwrap();
+
+        let catalog_path = root.join("catalog.sqlite");
+        let mut catalog = Catalog::open(&catalog_path).unwrap();
+        let first = catalog.record_run(input, &report, &json!({})).unwrap();
+        drop(catalog);
+        let mut catalog = Catalog::open(&catalog_path).unwrap();
+        let second = catalog.record_run(input, &report, &json!({})).unwrap();
+        assert_ne!(first, second);
+
+        let connection = &catalog.connection;
+        let count = |sql: &str| -> i64 { connection.query_row(sql, [], |row| row.get(0)).unwrap() };
+        assert_eq!(count("SELECT count(*) FROM runs"), 2);
+        assert_eq!(count("SELECT count(*) FROM inputs"), 2);
+        assert_eq!(count("SELECT count(*) FROM batches"), 8);
+        assert_eq!(
+            count("SELECT sum(records) FROM batches WHERE run_id = 1"),
+            100
+        );
+        let (path, first_pos): (String, i64) = connection
+            .query_row(
+                "SELECT path, first_pos FROM batches WHERE run_id = 1 AND number = 1",
+                [],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .unwrap();
+        assert!(Path::new(&path).is_absolute());
+        assert_eq!(
+            first_pos as u64,
+            report.batches[0].first.as_ref().unwrap().pos
+        );
+    }
+}
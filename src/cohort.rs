@@ -0,0 +1,299 @@
+//! Splitting a cohort of VCF files, e.g. hundreds of per-sample files, concurrently, each into a subdirectory
+//! of the output with its own manifest.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use rayon::prelude::*;
+use serde::Serialize;
+
+use crate::manifest::{Manifest, MANIFEST_FILE_NAME};
+use crate::naming::input_stem;
+use crate::watch::is_vcf_file;
+use crate::{
+    extract_variants_to_batches_with_options, BatchOptions, Instant, RunReport, VcfBatcherError,
+};
+
+/// The name of the summary of all inputs that [`split_cohort`] writes to the output directory.
+pub const COHORT_SUMMARY_FILE_NAME: &str = "cohort.json";
+
+/// The outcome of splitting one input of a cohort.
+#[derive(Debug)]
+pub struct CohortRun {
+    pub input: PathBuf,
+    /// The subdirectory of the output directory that the batches of the input were written to.
+    pub output_path: PathBuf,
+    pub result: Result<RunReport, VcfBatcherError>,
+}
+
+/// The outcome of splitting every input of a cohort, in the order of the inputs.
+#[derive(Debug, Default)]
+pub struct CohortReport {
+    pub runs: Vec<CohortRun>,
+    /// Wall-clock time of splitting all inputs.
+    pub elapsed: Duration,
+}
+
+impl CohortReport {
+    /// The runs of the inputs that could not be split.
+    pub fn failures(&self) -> impl Iterator<Item = &CohortRun> {
+        self.runs.iter().filter(|run| run.result.is_err())
+    }
+
+    /// The number of batches written across all inputs.
+    pub fn total_batches(&self) -> usize {
+        self.reports().map(|report| report.batches.len()).sum()
+    }
+
+    /// The number of variant records written across all inputs.
+    pub fn total_records(&self) -> usize {
+        self.reports().map(RunReport::total_records).sum()
+    }
+
+    /// A serializable summary of the runs, as written to [`COHORT_SUMMARY_FILE_NAME`].
+    pub fn summary(&self) -> CohortSummary {
+        CohortSummary {
+            inputs: self.runs.len(),
+            failed: self.failures().count(),
+            batches: self.total_batches(),
+            records: self.total_records(),
+            elapsed_seconds: self.elapsed.as_secs_f64(),
+            runs: self
+                .runs
+                .iter()
+                .map(|run| CohortRunSummary {
+                    input: run.input.clone(),
+                    output_path: run.output_path.clone(),
+                    batches: run.result.as_ref().map_or(0, |report| report.batches.len()),
+                    records: run.result.as_ref().map_or(0, RunReport::total_records),
+                    error: run.result.as_ref().err().map(ToString::to_string),
+                })
+                .collect(),
+        }
+    }
+
+    fn reports(&self) -> impl Iterator<Item = &RunReport> {
+        self.runs.iter().filter_map(|run| run.result.as_ref().ok())
+    }
+}
+
+/// The combined summary of a cohort, see [`CohortReport::summary`].
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct CohortSummary {
+    pub inputs: usize,
+    pub failed: usize,
+    pub batches: usize,
+    pub records: usize,
+    pub elapsed_seconds: f64,
+    pub runs: Vec<CohortRunSummary>,
+}
+
+/// The summary of one input of a cohort, with the error if it could not be split.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct CohortRunSummary {
+    pub input: PathBuf,
+    pub output_path: PathBuf,
+    pub batches: usize,
+    pub records: usize,
+    pub error: Option<String>,
+}
+
+/// Lists the `.vcf`, `.vcf.gz` and `.vcf.bgz` files of a directory, sorted by name. Hidden files and
+/// subdirectories are skipped.
+pub fn find_inputs(directory: &Path) -> io::Result<Vec<PathBuf>> {
+    let mut inputs = Vec::new();
+    for entry in fs::read_dir(directory)? {
+        let path = entry?.path();
+        if path.is_file() && is_vcf_file(&path) {
+            inputs.push(path);
+        }
+    }
+    inputs.sort();
+    Ok(inputs)
+}
+
+/// Splits every input into batches in `output/<stem>/`, where `<stem>` is the name of the input without its
+/// extensions, at most `jobs` inputs at a time. Every input gets its own manifest, and a summary of all inputs
+/// is written to [`COHORT_SUMMARY_FILE_NAME`] in the output directory. `on_run` is called whenever an input
+/// is complete, in the order that they complete.
+///
+/// A failed input does not stop the others; the failures are listed in the returned report. Fails before any
+/// input is split if two inputs have the same stem, since their batches would end up in the same directory.
+///
+/// # Examples
+///
+/// ```
+/// use std::path::{Path, PathBuf};
+///
+/// use vcf_batcher::cohort::split_cohort;
+/// use vcf_batcher::BatchOptions;
+///
+/// let inputs = [PathBuf::from("test_data/batch_01.vcf.gz")];
+/// let options = BatchOptions { batch_size: 50, quiet: true, ..Default::default() };
+/// let output = Path::new("test_data/doctest_cohort");
+/// # let _ = std::fs::remove_dir_all(output);
+/// let report = split_cohort(&inputs, output, &options, 2, |_| {}).unwrap();
+/// assert_eq!(report.failures().count(), 0);
+/// assert_eq!(report.runs[0].output_path, output.join("batch_01"));
+/// assert!(output.join("cohort.json").is_file());
+/// ```
+pub fn split_cohort(
+    inputs: &[PathBuf],
+    output: &Path,
+    options: &BatchOptions,
+    jobs: usize,
+    on_run: impl Fn(&CohortRun) + Sync,
+) -> Result<CohortReport, VcfBatcherError> {
+    let started = Instant::now();
+    let mut stems: BTreeMap<String, &Path> = BTreeMap::new();
+    for input in inputs {
+        let stem = input_stem(input);
+        if let Some(other) = stems.insert(stem.clone(), input) {
+            return Err(VcfBatcherError::Read {
+                path: input.clone(),
+                source: io::Error::new(
+                    io::ErrorKind::AlreadyExists,
+                    format!(
+                        "{} is also named '{stem}', so their batches would be written to the same directory",
+                        other.display()
+                    ),
+                ),
+            });
+        }
+    }
+    let pool = rayon::ThreadPoolBuilder::new()
+        .num_threads(jobs.max(1))
+        .build()
+        .map_err(|error| VcfBatcherError::Write {
+            path: output.to_path_buf(),
+            source: io::Error::other(error).into(),
+        })?;
+
+    let runs = pool.install(|| {
+        inputs
+            .par_iter()
+            .map(|input| {
+                let output_path = output.join(input_stem(input));
+                let result = extract_variants_to_batches_with_options(
+                    &input.to_string_lossy(),
+                    &output_path,
+                    options,
+                )
+                .and_then(write_manifest);
+                let run = CohortRun {
+                    input: input.clone(),
+                    output_path,
+                    result,
+                };
+                on_run(&run);
+                run
+            })
+            .collect()
+    });
+
+    let report = CohortReport {
+        runs,
+        elapsed: started.elapsed(),
+    };
+    write_summary(output, &report.summary()).map_err(|source| VcfBatcherError::Write {
+        path: output.join(COHORT_SUMMARY_FILE_NAME),
+        source: source.into(),
+    })?;
+    Ok(report)
+}
+
+/// Writes the manifest of an input, which split runs only write for some options, so that every
+/// subdirectory of the cohort lists its batches.
+fn write_manifest(report: RunReport) -> Result<RunReport, VcfBatcherError> {
+    Manifest::from_report(&report, true)
+        .write(&report.output_path)
+        .map_err(|source| VcfBatcherError::Write {
+            path: report.output_path.join(MANIFEST_FILE_NAME),
+            source: source.into(),
+        })?;
+    Ok(report)
+}
+
+fn write_summary(output: &Path, summary: &CohortSummary) -> io::Result<()> {
+    fs::create_dir_all(output)?;
+    let temporary_path = output.join(format!(".{}.tmp", COHORT_SUMMARY_FILE_NAME));
+    fs::write(&temporary_path, serde_json::to_string_pretty(summary)?)?;
+    fs::rename(&temporary_path, output.join(COHORT_SUMMARY_FILE_NAME))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::{Path, PathBuf};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use super::{find_inputs, split_cohort};
+    use crate::{BatchOptions, VcfBatcherError};
+
+    #[test]
+    fn test_split_cohort() {
+        let input_dir = Path::new("./test_data/cohort_inputs");
+        let _ = std::fs::remove_dir_all(input_dir);
+        std::fs::create_dir_all(input_dir).unwrap();
+        let record = |pos: usize| format!("chr1\t{pos}\t.\tA\tG\t.\tPASS\t.\n");
+        let headers = "##fileformat=VCFv4.2\n#CHROM\tPOS\tID\tREF\tALT\tQUAL\tFILTER\tINFO\n";
+        for (name, records) in [("sampleA.vcf", 3), ("sampleB.vcf", 5), ("sampleC.vcf", 1)] {
+            let contents: String = (1..=records).map(record).collect();
+            std::fs::write(input_dir.join(name), headers.to_string() + &contents).unwrap();
+        }
+        std::fs::write(input_dir.join("broken.vcf"), "not a vcf\n").unwrap();
+        std::fs::write(input_dir.join("notes.txt"), "").unwrap();
+
+        let inputs = find_inputs(input_dir).unwrap();
+        let names: Vec<_> = inputs
+            .iter()
+            .map(|input| input.file_name().unwrap())
+            .collect();
+        assert_eq!(
+            names,
+            ["broken.vcf", "sampleA.vcf", "sampleB.vcf", "sampleC.vcf"]
+        );
+
+        let output_path = Path::new("./test_data/cohort");
+        let _ = std::fs::remove_dir_all(output_path);
+        let options = BatchOptions {
+            batch_size: 2,
+            quiet: true,
+            ..Default::default()
+        };
+        let completed = AtomicUsize::new(0);
+        let report = split_cohort(&inputs, output_path, &options, 2, |_| {
+            completed.fetch_add(1, Ordering::Relaxed);
+        })
+        .unwrap();
+        assert_eq!(completed.into_inner(), 4);
+        assert_eq!(report.total_batches(), 2 + 3 + 1);
+        assert_eq!(report.total_records(), 9);
+        let failures: Vec<_> = report.failures().map(|run| &run.input).collect();
+        assert_eq!(failures, [&input_dir.join("broken.vcf")]);
+        assert!(output_path.join("sampleB/manifest.json").is_file());
+
+        let summary: serde_json::Value = serde_json::from_str(
+            &std::fs::read_to_string(output_path.join("cohort.json")).unwrap(),
+        )
+        .unwrap();
+        assert_eq!(summary["inputs"], 4);
+        assert_eq!(summary["failed"], 1);
+        assert_eq!(summary["runs"][2]["batches"], 3);
+
+        let error = split_cohort(
+            &[
+                PathBuf::from("a/sample.vcf"),
+                PathBuf::from("b/sample.vcf.gz"),
+            ],
+            output_path,
+            &options,
+            1,
+            |_| {},
+        )
+        .unwrap_err();
+        assert!(matches!(error, VcfBatcherError::Read { .. }));
+    }
+}
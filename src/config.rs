@@ -0,0 +1,647 @@
+//! Run configuration files, so that the options of a run can be checked in next to the data.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Deserializer, Serialize};
+
+use crate::filter::{read_sample_list, RecordFilter, Region};
+use crate::mask::SampleMask;
+use crate::naming::NameTemplate;
+use crate::recompress::Level;
+use crate::regions::read_bed;
+#[cfg(feature = "parquet")]
+use crate::ParquetExport;
+use crate::{
+    parse_batch_sizes, parse_byte_size, AppendMode, BadRecordPolicy, BatchOptions,
+    CompressionFormat, ContigCheck, DuplicateRecordPolicy, DuplicateSamplePolicy, EmptyInputPolicy,
+    IndexFormat, JsonField, LateHeaderPolicy, OutputFormat, OverwritePolicy, SplitMode,
+    VcfBatcherError,
+};
+
+/// The options of a run as they are written in a TOML file. Keys are named like the flags of the
+/// CLI, and every key is optional; options that are not set keep their defaults.
+///
+/// A file can also define named profiles in `[profiles.<name>]` tables with the same keys, which
+/// bundle options for a downstream tool, see [`Config::with_profile`].
+///
+/// # Examples
+///
+/// ```
+/// use vcf_batcher::config::Config;
+///
+/// let config = Config::parse(
+///     r#"
+///     input = "cohort.vcf.gz"
+///     output = "batches/"
+///     batch-size = 1000
+///     compression-level = "Fast"
+///     regions = ["chr1:10000-20000", "chr2"]
+///     pass-only = true
+///     max-line-length = "64M"
+///     "#,
+/// )
+/// .unwrap();
+///
+/// let options = config.to_options().unwrap();
+/// assert_eq!(options.batch_size, 1000);
+/// assert_eq!(options.max_line_length, Some(64 * 1024 * 1024));
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct Config {
+    /// The profile whose options are applied, see [`Config::with_profile`].
+    pub profile: Option<String>,
+    /// Profiles defined in the file, by name. They take precedence over built-in profiles of the same name.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub profiles: BTreeMap<String, Config>,
+    /// The VCF file to split.
+    pub input: Option<PathBuf>,
+    /// The directory the batches are written to.
+    pub output: Option<PathBuf>,
+    /// A directory that batches are staged in before they are moved to the output, see [`BatchOptions::tmp_dir`].
+    pub tmp_dir: Option<PathBuf>,
+    /// A file that a structured log of the run is appended to, see [`crate::run_log::RunLog`].
+    pub log_file: Option<PathBuf>,
+    pub batch_size: Option<usize>,
+    /// A schedule of batch sizes, as a list such as `[100, 1000, 25000]` or a string such as `"100,1000,25000..."`,
+    /// whose last size applies to all remaining batches, see [`BatchOptions::set_batch_sizes`]. Replaces `batch_size`.
+    #[serde(default, deserialize_with = "deserialize_batch_sizes")]
+    pub batch_sizes: Option<Vec<usize>>,
+    /// Divide the records into this many batches of nearly the same size, see [`BatchOptions::num_batches`].
+    /// Replaces `batch_size` and `batch_sizes`.
+    pub num_batches: Option<usize>,
+    /// A number of bytes, or a size such as `"500M"`, see [`BatchOptions::target_compressed_size`]. Without a
+    /// `batch_size`, the batches then have no limit on their records.
+    #[serde(default, deserialize_with = "deserialize_byte_size")]
+    pub target_compressed_size: Option<u64>,
+    /// A number of bytes, or a size such as `"500M"`, see [`BatchOptions::target_size`]. Without a `batch_size`,
+    /// the batches then have no limit on their records.
+    #[serde(default, deserialize_with = "deserialize_byte_size")]
+    pub target_size: Option<u64>,
+    /// `"none"`, `"fast"`, `"default"`, `"best"` or `"0"` to `"12"`, as for the CLI.
+    pub compression_level: Option<String>,
+    pub compression_format: Option<CompressionFormat>,
+    pub split_by: Option<SplitMode>,
+    /// How many genes each batch has with `split-by = "gene"`.
+    pub genes_per_batch: Option<usize>,
+    /// The INFO key whose values the records are grouped by, see [`BatchOptions::group_by_info`].
+    pub group_by_info: Option<String>,
+    /// A BED file with an interval for every batch, see [`SplitMode::Region`]. Replaces `split-by`.
+    pub regions_bed: Option<PathBuf>,
+    /// How many sample columns each batch has, see [`SplitMode::Samples`]. Replaces `split-by`.
+    pub samples_per_batch: Option<usize>,
+    /// Also accepted as `output-template`, like the flag of the CLI.
+    #[serde(alias = "output-template")]
+    pub name_template: Option<String>,
+    /// Name the batches like `cohortA_batch_001.vcf.gz` after the input, unless a name template is given.
+    pub prefix_from_input: Option<bool>,
+    /// Name the batches after their span like `chr1_000000001-005000000.vcf.gz`, unless a name template is given.
+    pub region_names: Option<bool>,
+    pub on_empty_input: Option<EmptyInputPolicy>,
+    pub on_late_header: Option<LateHeaderPolicy>,
+    pub check_contigs: Option<ContigCheck>,
+    pub on_duplicate_samples: Option<DuplicateSamplePolicy>,
+    pub on_bad_record: Option<BadRecordPolicy>,
+    pub dedup: Option<DuplicateRecordPolicy>,
+    pub resume: Option<bool>,
+    pub append: Option<AppendMode>,
+    pub overwrite: Option<OverwritePolicy>,
+    /// A number of bytes, or a size such as `"64M"`.
+    #[serde(default, deserialize_with = "deserialize_byte_size")]
+    pub max_line_length: Option<u64>,
+    pub regions: Option<Vec<String>>,
+    pub samples: Option<Vec<String>>,
+    /// A file with the sample names in the order that the sample columns are written in, one per line.
+    pub sample_order: Option<PathBuf>,
+    /// A file with the names of the samples whose genotypes are set to missing, one per line.
+    pub mask_samples: Option<PathBuf>,
+    /// Set all FORMAT fields of the masked samples to missing, not only GT.
+    pub mask_all_fields: Option<bool>,
+    pub pass_only: Option<bool>,
+    pub min_qual: Option<f64>,
+    pub format: Option<OutputFormat>,
+    pub json_fields: Option<Vec<JsonField>>,
+    pub site_lists: Option<bool>,
+    pub batches_bed: Option<bool>,
+    pub index: Option<IndexFormat>,
+    pub verify_output: Option<bool>,
+    pub manifest: Option<bool>,
+    #[cfg(feature = "parquet")]
+    pub parquet: Option<ParquetExport>,
+    /// INFO keys that get a column of their own in the Parquet files.
+    #[cfg(feature = "parquet")]
+    pub parquet_info: Option<Vec<String>>,
+}
+
+impl Config {
+    /// Reads a configuration from a TOML file.
+    pub fn read(path: &Path) -> Result<Config, VcfBatcherError> {
+        let contents = fs::read_to_string(path).map_err(|source| VcfBatcherError::Read {
+            path: path.to_path_buf(),
+            source,
+        })?;
+        Config::parse(&contents).map_err(|error| match error {
+            VcfBatcherError::InvalidConfig { reason, .. } => VcfBatcherError::InvalidConfig {
+                path: path.to_path_buf(),
+                reason,
+            },
+            error => error,
+        })
+    }
+
+    /// Parses a configuration from the contents of a TOML file.
+    pub fn parse(contents: &str) -> Result<Config, VcfBatcherError> {
+        toml::from_str(contents).map_err(|error| VcfBatcherError::InvalidConfig {
+            path: PathBuf::new(),
+            reason: error.message().to_string(),
+        })
+    }
+
+    /// Returns this configuration with every option that is set in `overrides` replaced,
+    /// e.g. to let command line flags take precedence over the values of a file.
+    pub fn merge(self, overrides: Config) -> Config {
+        let mut profiles = self.profiles;
+        profiles.extend(overrides.profiles);
+        // A batch size, a schedule of batch sizes and a number of batches replace each other
+        let (batch_size, batch_sizes, num_batches) = match (
+            overrides.batch_size,
+            overrides.batch_sizes,
+            overrides.num_batches,
+        ) {
+            (None, None, None) => (self.batch_size, self.batch_sizes, self.num_batches),
+            overridden => overridden,
+        };
+        Config {
+            profile: overrides.profile.or(self.profile),
+            profiles,
+            input: overrides.input.or(self.input),
+            output: overrides.output.or(self.output),
+            tmp_dir: overrides.tmp_dir.or(self.tmp_dir),
+            log_file: overrides.log_file.or(self.log_file),
+            batch_size,
+            batch_sizes,
+            num_batches,
+            target_compressed_size: overrides
+                .target_compressed_size
+                .or(self.target_compressed_size),
+            target_size: overrides.target_size.or(self.target_size),
+            compression_level: overrides.compression_level.or(self.compression_level),
+            compression_format: overrides.compression_format.or(self.compression_format),
+            split_by: overrides.split_by.or(self.split_by),
+            genes_per_batch: overrides.genes_per_batch.or(self.genes_per_batch),
+            group_by_info: overrides.group_by_info.or(self.group_by_info),
+            regions_bed: overrides.regions_bed.or(self.regions_bed),
+            samples_per_batch: overrides.samples_per_batch.or(self.samples_per_batch),
+            name_template: overrides.name_template.or(self.name_template),
+            prefix_from_input: overrides.prefix_from_input.or(self.prefix_from_input),
+            region_names: overrides.region_names.or(self.region_names),
+            on_empty_input: overrides.on_empty_input.or(self.on_empty_input),
+            on_late_header: overrides.on_late_header.or(self.on_late_header),
+            check_contigs: overrides.check_contigs.or(self.check_contigs),
+            on_duplicate_samples: overrides.on_duplicate_samples.or(self.on_duplicate_samples),
+            on_bad_record: overrides.on_bad_record.or(self.on_bad_record),
+            dedup: overrides.dedup.or(self.dedup),
+            resume: overrides.resume.or(self.resume),
+            append: overrides.append.or(self.append),
+            overwrite: overrides.overwrite.or(self.overwrite),
+            max_line_length: overrides.max_line_length.or(self.max_line_length),
+            regions: overrides.regions.or(self.regions),
+            samples: overrides.samples.or(self.samples),
+            sample_order: overrides.sample_order.or(self.sample_order),
+            mask_samples: overrides.mask_samples.or(self.mask_samples),
+            mask_all_fields: overrides.mask_all_fields.or(self.mask_all_fields),
+            pass_only: overrides.pass_only.or(self.pass_only),
+            min_qual: overrides.min_qual.or(self.min_qual),
+            format: overrides.format.or(self.format),
+            json_fields: overrides.json_fields.or(self.json_fields),
+            site_lists: overrides.site_lists.or(self.site_lists),
+            batches_bed: overrides.batches_bed.or(self.batches_bed),
+            index: overrides.index.or(self.index),
+            verify_output: overrides.verify_output.or(self.verify_output),
+            manifest: overrides.manifest.or(self.manifest),
+            #[cfg(feature = "parquet")]
+            parquet: overrides.parquet.or(self.parquet),
+            #[cfg(feature = "parquet")]
+            parquet_info: overrides.parquet_info.or(self.parquet_info),
+        }
+    }
+
+    /// Applies the options of a profile on top of this configuration. `name` takes precedence over the
+    /// `profile` key of the configuration; if neither is set, the configuration is returned unchanged.
+    /// The profile is looked up in [`Config::profiles`] first, then in the [`BUILTIN_PROFILES`].
+    ///
+    /// Options given on the command line should be merged afterwards, so that they take precedence over the profile.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use vcf_batcher::config::Config;
+    /// use vcf_batcher::SplitMode;
+    ///
+    /// let config = Config::parse(
+    ///     r#"
+    ///     batch-size = 1000
+    ///
+    ///     [profiles.small]
+    ///     batch-size = 100
+    ///     "#,
+    /// )
+    /// .unwrap();
+    /// assert_eq!(config.clone().with_profile(Some("small")).unwrap().batch_size, Some(100));
+    ///
+    /// let config = config.with_profile(Some("imputation")).unwrap();
+    /// assert_eq!(config.split_by, Some(SplitMode::Chromosome));
+    /// ```
+    pub fn with_profile(mut self, name: Option<&str>) -> Result<Config, VcfBatcherError> {
+        let Some(name) = name.map(str::to_string).or(self.profile.take()) else {
+            return Ok(self);
+        };
+        let profile = match self.profiles.get(&name) {
+            Some(profile) => profile.clone(),
+            None => builtin_profile(&name).ok_or_else(|| VcfBatcherError::UnknownProfile {
+                name: name.clone(),
+                available: self
+                    .profiles
+                    .keys()
+                    .map(String::as_str)
+                    .chain(BUILTIN_PROFILES.iter().map(|(name, _)| *name))
+                    .collect::<BTreeSet<_>>()
+                    .into_iter()
+                    .map(str::to_string)
+                    .collect(),
+            })?,
+        };
+        Ok(Config {
+            profile: Some(name),
+            profiles: BTreeMap::new(),
+            ..self.merge(Config {
+                profiles: BTreeMap::new(),
+                ..profile
+            })
+        })
+    }
+
+    /// Converts the configuration into [`BatchOptions`], with defaults for the options that are not set.
+    /// The input and output paths and the log file are not part of the options.
+    pub fn to_options(&self) -> Result<BatchOptions, VcfBatcherError> {
+        let defaults = BatchOptions::default();
+        let level = match &self.compression_level {
+            Some(level) => Level::parse(level)?,
+            None => None,
+        };
+        let name_template = match &self.name_template {
+            Some(template) => Some(NameTemplate::parse(template)?),
+            None => None,
+        };
+        let regions = match &self.regions {
+            Some(regions) => regions
+                .iter()
+                .map(|region| region.parse::<Region>())
+                .collect::<Result<_, _>>()?,
+            None => Vec::new(),
+        };
+
+        let filter = RecordFilter {
+            regions,
+            samples: self.samples.clone(),
+            sample_order: match &self.sample_order {
+                Some(path) => Some(read_sample_list(path)?),
+                None => None,
+            },
+            pass_only: self.pass_only.unwrap_or_default(),
+            min_qual: self.min_qual,
+        };
+        filter.validate()?;
+        let mask = match &self.mask_samples {
+            Some(path) => Some(SampleMask {
+                samples: read_sample_list(path)?,
+                all_fields: self.mask_all_fields.unwrap_or_default(),
+            }),
+            None => None,
+        };
+
+        let mut options = BatchOptions {
+            batch_size: match (
+                self.batch_size,
+                self.target_compressed_size.or(self.target_size),
+            ) {
+                (Some(batch_size), _) => batch_size,
+                (None, Some(_)) => usize::MAX,
+                (None, None) => defaults.batch_size,
+            },
+            num_batches: self.num_batches,
+            target_compressed_size: self.target_compressed_size,
+            target_size: self.target_size,
+            compression_level: level.map(Level::bgzf),
+            zstd_level: level,
+            compression_format: self
+                .compression_format
+                .unwrap_or(defaults.compression_format),
+            empty_input: self.on_empty_input.unwrap_or(defaults.empty_input),
+            late_header: self.on_late_header.unwrap_or(defaults.late_header),
+            check_contigs: self.check_contigs.unwrap_or(defaults.check_contigs),
+            duplicate_samples: self
+                .on_duplicate_samples
+                .unwrap_or(defaults.duplicate_samples),
+            bad_records: self.on_bad_record.unwrap_or(defaults.bad_records),
+            dedup: self.dedup.or(defaults.dedup),
+            overwrite: self.overwrite.unwrap_or(defaults.overwrite),
+            resume: self.resume.unwrap_or(defaults.resume),
+            append: self.append.or(defaults.append),
+            max_line_length: self.max_line_length.map(|length| length as usize),
+            name_template,
+            split_by: match (&self.regions_bed, self.samples_per_batch) {
+                (Some(_), _) => SplitMode::Region,
+                (None, Some(_)) => SplitMode::Samples,
+                (None, None) => self.split_by.unwrap_or(defaults.split_by),
+            },
+            split_regions: match &self.regions_bed {
+                Some(path) => read_bed(path)?,
+                None => Vec::new(),
+            },
+            genes_per_batch: self.genes_per_batch.unwrap_or(defaults.genes_per_batch),
+            samples_per_batch: self.samples_per_batch.unwrap_or(defaults.samples_per_batch),
+            group_by_info: self.group_by_info.clone(),
+            tmp_dir: self.tmp_dir.clone(),
+            filter,
+            mask,
+            format: match self.format {
+                Some(format) => format,
+                // A template like `{stem}_{number}.bcf` asks for BCF files
+                None if self
+                    .name_template
+                    .as_deref()
+                    .is_some_and(|template| template.ends_with(".bcf")) =>
+                {
+                    OutputFormat::Bcf
+                }
+                None => defaults.format,
+            },
+            json_fields: self.json_fields.clone().unwrap_or_default(),
+            site_lists: self.site_lists.unwrap_or(defaults.site_lists),
+            batches_bed: self.batches_bed.unwrap_or(defaults.batches_bed),
+            index: self.index.or(defaults.index),
+            verify_output: self.verify_output.unwrap_or(defaults.verify_output),
+            manifest: self.manifest.unwrap_or(defaults.manifest),
+            #[cfg(feature = "parquet")]
+            parquet: self.parquet.unwrap_or(defaults.parquet),
+            #[cfg(feature = "parquet")]
+            parquet_info_keys: self.parquet_info.clone().unwrap_or_default(),
+            ..defaults
+        };
+        options.set_batch_sizes(self.batch_sizes.as_deref().unwrap_or_default());
+        options.name_template = match options.name_template.take() {
+            None if self.prefix_from_input == Some(true) => {
+                Some(NameTemplate::input_prefixed(options.vcf_extension()))
+            }
+            None if self.region_names == Some(true) => {
+                Some(NameTemplate::region(options.vcf_extension()))
+            }
+            template => template,
+        };
+        Ok(options)
+    }
+}
+
+/// The profiles that are built in, with a description.
+pub const BUILTIN_PROFILES: &[(&str, &str)] = &[
+    (
+        "imputation",
+        "one bgzipped file per chromosome, named like chr1.vcf.gz, as imputation servers expect",
+    ),
+    (
+        "scatter",
+        "batches of 5000 records with fast compression, named after the input, for scatter-gather workflows",
+    ),
+    (
+        "archive",
+        "large batches of 100000 records with the best compression, for long-term storage",
+    ),
+];
+
+/// Returns the options of a built-in profile, see [`BUILTIN_PROFILES`].
+pub fn builtin_profile(name: &str) -> Option<Config> {
+    let profile = match name {
+        "imputation" => Config {
+            split_by: Some(SplitMode::Chromosome),
+            compression_level: Some("default".to_string()),
+            ..Default::default()
+        },
+        "scatter" => Config {
+            batch_size: Some(5000),
+            compression_level: Some("fast".to_string()),
+            prefix_from_input: Some(true),
+            ..Default::default()
+        },
+        "archive" => Config {
+            batch_size: Some(100_000),
+            compression_level: Some("best".to_string()),
+            ..Default::default()
+        },
+        _ => return None,
+    };
+    Some(profile)
+}
+
+/// Accepts a number of bytes or a size string such as `"64M"`, see [`parse_byte_size`].
+fn deserialize_byte_size<'de, D>(deserializer: D) -> Result<Option<u64>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum ByteSize {
+        Bytes(u64),
+        Text(String),
+    }
+
+    match ByteSize::deserialize(deserializer)? {
+        ByteSize::Bytes(bytes) => Ok(Some(bytes)),
+        ByteSize::Text(text) => parse_byte_size(&text)
+            .map(Some)
+            .map_err(serde::de::Error::custom),
+    }
+}
+
+/// Reads a schedule of batch sizes from a list of sizes or a string, see [`parse_batch_sizes`].
+fn deserialize_batch_sizes<'de, D>(deserializer: D) -> Result<Option<Vec<usize>>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum BatchSizes {
+        List(Vec<usize>),
+        Text(String),
+    }
+
+    let sizes = match BatchSizes::deserialize(deserializer)? {
+        BatchSizes::List(sizes) => sizes,
+        BatchSizes::Text(text) => parse_batch_sizes(&text).map_err(serde::de::Error::custom)?,
+    };
+    match sizes.is_empty() || sizes.contains(&0) {
+        true => Err(serde::de::Error::custom(
+            "the batch sizes must be a non-empty list of sizes of at least 1",
+        )),
+        false => Ok(Some(sizes)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::Config;
+    use crate::{OutputFormat, SplitMode, VcfBatcherError};
+
+    #[test]
+    fn test_parse_config() {
+        let config = Config::parse(
+            r#"
+            output = "batches"
+            batch-size = 500
+            split-by = "chromosome"
+            max-line-length = 1024
+            samples = ["HG00096"]
+            "#,
+        )
+        .unwrap();
+        assert_eq!(config.output, Some(PathBuf::from("batches")));
+        assert_eq!(config.max_line_length, Some(1024));
+
+        let options = config.to_options().unwrap();
+        assert_eq!(options.batch_size, 500);
+        assert_eq!(options.split_by, SplitMode::Chromosome);
+        assert_eq!(options.filter.samples, Some(vec!["HG00096".to_string()]));
+
+        let config = Config::parse("output-template = \"{stem}_part{num:04}.vcf.gz\"").unwrap();
+        assert_eq!(
+            config.name_template.as_deref(),
+            Some("{stem}_part{num:04}.vcf.gz")
+        );
+
+        let options = Config::parse("prefix-from-input = true\ncompression-level = \"fast\"")
+            .unwrap()
+            .to_options()
+            .unwrap();
+        assert_eq!(
+            options.name_template.unwrap().as_str(),
+            "{stem}_batch_{number:03}.vcf.gz"
+        );
+        let options = Config::parse("region-names = true")
+            .unwrap()
+            .to_options()
+            .unwrap();
+        assert_eq!(options.name_template.unwrap().as_str(), "{region}.vcf");
+        let options = Config::parse("output-template = \"{stem}_{number}.bcf\"")
+            .unwrap()
+            .to_options()
+            .unwrap();
+        assert_eq!(options.format, OutputFormat::Bcf);
+
+        // Without a batch size, only the size of the batches limits them
+        let options = Config::parse("target-compressed-size = \"500M\"")
+            .unwrap()
+            .to_options()
+            .unwrap();
+        assert_eq!(options.target_compressed_size, Some(500 << 20));
+        assert_eq!(options.batch_size, usize::MAX);
+
+        for schedule in [
+            "batch-sizes = [10, 100, 1000]",
+            "batch-sizes = \"10,100,1000...\"",
+        ] {
+            let options = Config::parse(schedule).unwrap().to_options().unwrap();
+            assert_eq!(options.batch_sizes, [10, 100]);
+            assert_eq!(options.batch_size, 1000);
+        }
+        assert!(Config::parse("batch-sizes = [10, 0]").is_err());
+
+        assert!(matches!(
+            Config::parse("batch-sise = 500"),
+            Err(VcfBatcherError::InvalidConfig { .. })
+        ));
+        assert!(matches!(
+            Config::parse("regions = [\"chr1:20-10\"]")
+                .unwrap()
+                .to_options(),
+            Err(VcfBatcherError::InvalidRegion { .. })
+        ));
+    }
+
+    #[test]
+    fn test_profiles() {
+        let file = Config::parse(
+            r#"
+            profile = "small"
+            batch-size = 1000
+            compression-level = "fast"
+
+            [profiles.small]
+            batch-size = 100
+
+            [profiles.archive]
+            batch-size = 10
+            "#,
+        )
+        .unwrap();
+
+        let config = file.clone().with_profile(None).unwrap();
+        assert_eq!(config.batch_size, Some(100));
+        // Options that the profile does not set are kept
+        assert_eq!(config.compression_level.as_deref(), Some("fast"));
+        assert!(config.profiles.is_empty());
+
+        // Profiles of the file replace built-in profiles of the same name
+        let config = file.clone().with_profile(Some("archive")).unwrap();
+        assert_eq!(config.batch_size, Some(10));
+        assert_eq!(config.profile.as_deref(), Some("archive"));
+
+        let config = file.clone().with_profile(Some("imputation")).unwrap();
+        assert_eq!(config.split_by, Some(SplitMode::Chromosome));
+        assert_eq!(config.compression_level.as_deref(), Some("default"));
+
+        // Flags are merged after the profile
+        let flags = Config {
+            batch_size: Some(5),
+            ..Default::default()
+        };
+        let config = file.clone().with_profile(None).unwrap().merge(flags);
+        assert_eq!(config.batch_size, Some(5));
+
+        match file.with_profile(Some("imputaton")) {
+            Err(VcfBatcherError::UnknownProfile { available, .. }) => {
+                assert!(available.contains(&"small".to_string()));
+                assert!(available.contains(&"imputation".to_string()));
+            }
+            result => panic!("expected an unknown profile, got {:?}", result),
+        }
+        for (name, _) in super::BUILTIN_PROFILES {
+            let profile = super::builtin_profile(name).unwrap();
+            profile.to_options().unwrap();
+        }
+    }
+
+    #[test]
+    fn test_merge_config() {
+        let file = Config::parse("batch-size = 500\nresume = true").unwrap();
+        let flags = Config {
+            batch_size: Some(100),
+            ..Default::default()
+        };
+
+        let config = file.merge(flags);
+        assert_eq!(config.batch_size, Some(100));
+        assert_eq!(config.resume, Some(true));
+
+        // A schedule of batch sizes replaces the batch size of the file
+        let config = config.merge(Config {
+            batch_sizes: Some(vec![10, 1000]),
+            ..Default::default()
+        });
+        assert_eq!(config.batch_size, None);
+        assert_eq!(config.batch_sizes, Some(vec![10, 1000]));
+    }
+}
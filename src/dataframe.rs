@@ -0,0 +1,312 @@
+//! Variant records and run reports as polars DataFrames, enabled by the `polars` feature, for Rust
+//! analytics that would otherwise write VCF text only to parse it again.
+//!
+//! The fixed VCF columns get types: `POS` is an unsigned integer, `QUAL` a float and missing (`.`)
+//! `ID`, `QUAL` and `FILTER` values are null. `INFO`, `FORMAT` and the sample columns keep their text.
+
+use std::io;
+use std::path::Path;
+
+use polars::prelude::{Column, DataFrame, PolarsResult};
+
+use crate::{read_lines, RunReport, VcfBatcherError};
+
+/// The values of one column of the DataFrame.
+enum Values {
+    Text(Vec<String>),
+    /// Text where `.` is null.
+    OptionalText(Vec<Option<String>>),
+    Position(Vec<u64>),
+    Quality(Vec<Option<f64>>),
+}
+
+impl Values {
+    fn for_column(name: &str) -> Values {
+        match name {
+            "POS" => Values::Position(Vec::new()),
+            "QUAL" => Values::Quality(Vec::new()),
+            "ID" | "FILTER" => Values::OptionalText(Vec::new()),
+            _ => Values::Text(Vec::new()),
+        }
+    }
+
+    /// Adds a value, returning why it is not valid for the column.
+    fn push(&mut self, value: &str) -> Result<(), String> {
+        match self {
+            Values::Text(values) => values.push(value.to_string()),
+            Values::OptionalText(values) => values.push((value != ".").then(|| value.to_string())),
+            Values::Position(values) => values.push(
+                value
+                    .parse()
+                    .map_err(|_| format!("POS '{value}' is not a whole number"))?,
+            ),
+            Values::Quality(values) => values.push(match value {
+                "." => None,
+                value => Some(
+                    value
+                        .parse()
+                        .map_err(|_| format!("QUAL '{value}' is not a number"))?,
+                ),
+            }),
+        }
+        Ok(())
+    }
+
+    fn into_column(self, name: &str) -> Column {
+        match self {
+            Values::Text(values) => Column::new(name.into(), values),
+            Values::OptionalText(values) => Column::new(name.into(), values),
+            Values::Position(values) => Column::new(name.into(), values),
+            Values::Quality(values) => Column::new(name.into(), values),
+        }
+    }
+}
+
+/// Reads the variant records of a plain or bgzipped VCF file into a DataFrame with the given
+/// columns, in this order. Columns are named like in the `#CHROM` header line, e.g. `CHROM`,
+/// `POS`, `INFO` or a sample name; an empty list selects all columns of the file.
+///
+/// # Examples
+///
+/// ```
+/// use vcf_batcher::dataframe::to_dataframe;
+///
+/// let records = to_dataframe("test_data/batch_01.vcf.gz", &["CHROM", "POS", "QUAL"]).unwrap();
+/// assert_eq!(records.shape(), (100, 3));
+/// assert_eq!(records.get_column_names_str(), ["CHROM", "POS", "QUAL"]);
+/// ```
+pub fn to_dataframe<P: AsRef<Path>>(
+    path: P,
+    columns: &[&str],
+) -> Result<DataFrame, VcfBatcherError> {
+    let path = path.as_ref();
+    let read_error = |source: io::Error| VcfBatcherError::Read {
+        path: path.to_path_buf(),
+        source,
+    };
+
+    let mut header_columns: Vec<String> = Vec::new();
+    let mut selected: Vec<(usize, Values)> = Vec::new();
+    for (index, line) in read_lines(path).map_err(read_error)?.enumerate() {
+        let line = line.map_err(read_error)?;
+        if line.starts_with('#') {
+            if line.starts_with("#CHROM") {
+                header_columns = line.split('\t').map(str::to_string).collect();
+                selected = select_columns(&header_columns, columns)?;
+            }
+            continue;
+        }
+        if line.is_empty() {
+            continue;
+        }
+
+        let line_number = index + 1;
+        let fields: Vec<&str> = line.split('\t').collect();
+        for (column, values) in &mut selected {
+            let value = fields
+                .get(*column)
+                .ok_or_else(|| VcfBatcherError::MalformedRecord {
+                    line_number,
+                    reason: format!(
+                        "it has {} columns instead of {}",
+                        fields.len(),
+                        header_columns.len()
+                    ),
+                })?;
+            values
+                .push(value)
+                .map_err(|reason| VcfBatcherError::MalformedRecord {
+                    line_number,
+                    reason,
+                })?;
+        }
+    }
+    if header_columns.is_empty() {
+        // Without a #CHROM line, the names of the columns are unknown
+        selected = select_columns(&[], columns)?;
+    }
+
+    let columns = selected
+        .into_iter()
+        .map(|(column, values)| values.into_column(column_name(&header_columns[column])))
+        .collect();
+    DataFrame::new(columns).map_err(|error| read_error(io::Error::other(error)))
+}
+
+/// Finds the index of every requested column in the `#CHROM` header line.
+fn select_columns(
+    header_columns: &[String],
+    columns: &[&str],
+) -> Result<Vec<(usize, Values)>, VcfBatcherError> {
+    if columns.is_empty() {
+        return Ok(header_columns
+            .iter()
+            .enumerate()
+            .map(|(index, column)| (index, Values::for_column(column_name(column))))
+            .collect());
+    }
+
+    let mut unknown = Vec::new();
+    let mut selected = Vec::new();
+    for &column in columns {
+        match header_columns
+            .iter()
+            .position(|header| column_name(header) == column)
+        {
+            Some(index) => selected.push((index, Values::for_column(column))),
+            None => unknown.push(column.to_string()),
+        }
+    }
+    match unknown.is_empty() {
+        true => Ok(selected),
+        false => Err(VcfBatcherError::UnknownColumns { columns: unknown }),
+    }
+}
+
+/// The name of a column of the `#CHROM` header line, e.g. `CHROM` for `#CHROM`.
+fn column_name(header_column: &str) -> &str {
+    header_column.trim_start_matches('#')
+}
+
+/// Summarizes the batches of a run in a DataFrame with a row per batch and the columns `number`,
+/// `path`, `records`, `sha256`, `first_chrom`, `first_pos`, `last_chrom`, `last_pos` and `verified`.
+///
+/// # Examples
+///
+/// ```
+/// use std::path::Path;
+/// use vcf_batcher::dataframe::batches_dataframe;
+/// use vcf_batcher::{extract_variants_to_batches_with_options, BatchOptions};
+///
+/// let options = BatchOptions { batch_size: 30, quiet: true, ..Default::default() };
+/// let output = Path::new("test_data/doc_dataframe");
+/// let report = extract_variants_to_batches_with_options("test_data/batch_01.vcf.gz", output, &options).unwrap();
+///
+/// let batches = batches_dataframe(&report).unwrap();
+/// assert_eq!(batches.height(), 4);
+/// let records = batches.column("records").unwrap().u64().unwrap();
+/// assert_eq!(records.into_iter().flatten().sum::<u64>(), 100);
+/// ```
+pub fn batches_dataframe(report: &RunReport) -> PolarsResult<DataFrame> {
+    let batches = &report.batches;
+    let locus = |first: bool| {
+        batches.iter().map(move |batch| match first {
+            true => batch.first.as_ref(),
+            false => batch.last.as_ref(),
+        })
+    };
+    DataFrame::new(vec![
+        Column::new(
+            "number".into(),
+            batches
+                .iter()
+                .map(|batch| batch.number as u64)
+                .collect::<Vec<_>>(),
+        ),
+        Column::new(
+            "path".into(),
+            batches
+                .iter()
+                .map(|batch| batch.path.to_string_lossy().into_owned())
+                .collect::<Vec<_>>(),
+        ),
+        Column::new(
+            "records".into(),
+            batches
+                .iter()
+                .map(|batch| batch.records as u64)
+                .collect::<Vec<_>>(),
+        ),
+        Column::new(
+            "sha256".into(),
+            batches
+                .iter()
+                .map(|batch| batch.sha256.clone())
+                .collect::<Vec<_>>(),
+        ),
+        Column::new(
+            "first_chrom".into(),
+            locus(true)
+                .map(|locus| locus.map(|locus| locus.chrom.clone()))
+                .collect::<Vec<_>>(),
+        ),
+        Column::new(
+            "first_pos".into(),
+            locus(true)
+                .map(|locus| locus.map(|locus| locus.pos))
+                .collect::<Vec<_>>(),
+        ),
+        Column::new(
+            "last_chrom".into(),
+            locus(false)
+                .map(|locus| locus.map(|locus| locus.chrom.clone()))
+                .collect::<Vec<_>>(),
+        ),
+        Column::new(
+            "last_pos".into(),
+            locus(false)
+                .map(|locus| locus.map(|locus| locus.pos))
+                .collect::<Vec<_>>(),
+        ),
+        Column::new(
+            "verified".into(),
+            batches
+                .iter()
+                .map(|batch| batch.verified)
+                .collect::<Vec<_>>(),
+        ),
+    ])
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::to_dataframe;
+    use crate::testdata::SyntheticVcf;
+    use crate::VcfBatcherError;
+
+    #[test]
+    fn test_to_dataframe() {
+        let path = Path::new("./test_data/dataframe/input.vcf");
+        let vcf = SyntheticVcf {
+            samples: 2,
+            records: 50,
+            ..Default::default()
+        };
+        vcf.write_file(path, None).unwrap();
+
+        let records = to_dataframe(path, &[]).unwrap();
+        assert_eq!(records.shape(), (50, 11));
+        assert_eq!(records.get_column_names_str()[..2], ["CHROM", "POS"]);
+        assert_eq!(records.column("SAMPLE_0002").unwrap().len(), 50);
+        let qual = records.column("QUAL").unwrap().f64().unwrap();
+        assert!(qual.into_iter().flatten().all(|qual| qual >= 10.0));
+
+        let error = to_dataframe(path, &["POS", "DP", "SAMPLE_0003"]).unwrap_err();
+        assert!(matches!(
+            error,
+            VcfBatcherError::UnknownColumns { columns } if columns == ["DP", "SAMPLE_0003"]
+        ));
+    }
+
+    #[test]
+    fn test_malformed_record() {
+        let path = Path::new("./test_data/dataframe/malformed.vcf");
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(
+            path,
+            "##fileformat=VCFv4.2\n#CHROM\tPOS\tID\tREF\tALT\tQUAL\tFILTER\tINFO\n\
+             chr1\t10\t.\tA\tC\t.\tPASS\t.\nchr1\tten\t.\tA\tC\t.\tPASS\t.\n",
+        )
+        .unwrap();
+
+        let error = to_dataframe(path, &["CHROM", "POS", "ID"]).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "line 4 is not a valid variant record: POS 'ten' is not a whole number"
+        );
+        let ids = to_dataframe(path, &["ID"]).unwrap();
+        assert_eq!(ids.column("ID").unwrap().null_count(), 2);
+    }
+}
@@ -0,0 +1,243 @@
+use std::io;
+use std::path::PathBuf;
+
+use bgzip::BGZFError;
+use thiserror::Error;
+
+use crate::RunReport;
+
+/// Errors that can occur while splitting a VCF file into batches.
+#[derive(Debug, Error)]
+pub enum VcfBatcherError {
+    /// The input file could not be opened or one of its lines could not be read.
+    #[error("could not read {}: {source}", path.display())]
+    Read {
+        path: PathBuf,
+        #[source]
+        source: io::Error,
+    },
+
+    /// A batch could not be written completely, e.g. because the disk is full.
+    /// The partially written file has already been removed; `completed` lists
+    /// the batches that were written successfully before the failure.
+    #[error("could not write batch {batch_number} to {}: {source}", path.display())]
+    BatchWrite {
+        batch_number: usize,
+        path: PathBuf,
+        completed: Box<RunReport>,
+        #[source]
+        source: BGZFError,
+    },
+
+    /// A batch file already exists and [`crate::OverwritePolicy::Refuse`] is set. `completed`
+    /// lists the batches that were written before.
+    #[error("{} already exists; remove it or choose how to handle existing files", path.display())]
+    OutputExists {
+        path: PathBuf,
+        completed: Box<RunReport>,
+    },
+
+    /// A file other than a batch (e.g. a merged VCF file) could not be written.
+    #[error("could not write {}: {source}", path.display())]
+    Write {
+        path: PathBuf,
+        #[source]
+        source: BGZFError,
+    },
+
+    /// A directory contains no batches to merge.
+    #[error("no batches found in {}", path.display())]
+    NoBatches { path: PathBuf },
+
+    /// A batch has a different header than the first batch, so they cannot be merged.
+    #[error("the header of {} differs from the header of the first batch", path.display())]
+    HeaderMismatch { path: PathBuf },
+
+    /// Batches would be rewritten into the directory they are read from.
+    #[error("{} is both the input and the output directory", path.display())]
+    SameDirectory { path: PathBuf },
+
+    /// The input contains headers but no variant records.
+    #[error("{} contains no variant records", path.display())]
+    EmptyInput { path: PathBuf },
+
+    /// A line is longer than the configured maximum line length.
+    #[error("line {line_number} is longer than the maximum line length of {limit} bytes")]
+    LineTooLong { line_number: usize, limit: usize },
+
+    /// A header line appeared after the first variant record.
+    #[error("line {line_number} is a header line after the first variant record: {line}")]
+    LateHeaderLine { line_number: usize, line: String },
+
+    /// A record is located on a contig that is not declared in the header.
+    #[error("line {line_number} is on contig {contig}, which is not declared in the header's ##contig lines")]
+    UnknownContig { line_number: usize, contig: String },
+
+    /// The `#CHROM` header line lists the same sample more than once.
+    #[error("the header contains duplicate sample names: {}", samples.join(", "))]
+    DuplicateSamples { samples: Vec<String> },
+
+    /// A file name template is malformed or would write outside the output directory.
+    #[error("invalid file name template '{template}': {reason}")]
+    InvalidTemplate { template: String, reason: String },
+
+    /// A new header or header patch cannot be used, see [`crate::reheader::HeaderEdit`].
+    #[error("invalid header for {}: {reason}", path.display())]
+    InvalidHeader { path: PathBuf, reason: String },
+
+    /// A compression level is neither `none`, one of the names nor a supported number.
+    #[error("unknown compression level '{level}', expected none, fast, default, best or a number from 0 to 12")]
+    InvalidCompressionLevel { level: String },
+
+    /// The selected samples or the minimum QUAL of a [`crate::filter::RecordFilter`] are invalid.
+    #[error("invalid filter: {reason}")]
+    InvalidFilter { reason: String },
+
+    /// A configuration profile is neither defined in the configuration file nor built in.
+    #[error("unknown profile '{name}', the available profiles are {}", available.join(", "))]
+    UnknownProfile {
+        name: String,
+        available: Vec<String>,
+    },
+
+    /// A variant record has the wrong number of columns or an invalid POS, see [`crate::BadRecordPolicy`].
+    #[error("line {line_number} is not a valid variant record: {reason}")]
+    MalformedRecord { line_number: usize, reason: String },
+
+    /// A variant record has the same CHROM, POS, REF and ALT as an earlier one, see [`crate::DuplicateRecordPolicy`].
+    #[error("line {line_number} repeats the record {record} (same CHROM, POS, REF and ALT)")]
+    DuplicateRecord { line_number: usize, record: String },
+
+    /// A directory could not be watched for new files.
+    #[error("could not watch {}: {source}", path.display())]
+    Watch {
+        path: PathBuf,
+        #[source]
+        source: notify::Error,
+    },
+
+    /// A region is malformed, e.g. because its end lies before its start.
+    #[error("invalid region '{region}': {reason}")]
+    InvalidRegion { region: String, reason: String },
+
+    /// Samples that should be selected do not appear in the `#CHROM` header line.
+    #[error("the header does not contain the samples {}", samples.join(", "))]
+    UnknownSamples { samples: Vec<String> },
+
+    /// Samples of the `#CHROM` header line are missing from the sample order, see [`crate::filter::RecordFilter::sample_order`].
+    #[error("the sample order does not list the samples {}", samples.join(", "))]
+    UnorderedSamples { samples: Vec<String> },
+
+    /// Columns that should be selected do not appear in the `#CHROM` header line.
+    #[error("the header does not contain the columns {}", columns.join(", "))]
+    UnknownColumns { columns: Vec<String> },
+
+    /// When splitting by chromosome, the records of a chromosome continue after records of another one.
+    #[error("line {line_number} continues chromosome {chrom} after records of another chromosome; sort the input first")]
+    NonContiguousChromosome { line_number: usize, chrom: String },
+
+    /// A configuration file is malformed or contains unknown options.
+    #[error("invalid configuration in {}: {reason}", path.display())]
+    InvalidConfig { path: PathBuf, reason: String },
+
+    /// Another run is currently writing to the same output directory.
+    #[error(
+        "the output directory is locked by another run (process {holder}); \
+         if no other run is active, remove {}",
+        path.display()
+    )]
+    OutputLocked { path: PathBuf, holder: String },
+
+    /// The lock file in the output directory could not be created.
+    #[error("could not lock the output directory with {}: {source}", path.display())]
+    Lock {
+        path: PathBuf,
+        #[source]
+        source: io::Error,
+    },
+
+    /// A batch that was read back after it was written does not match what was written,
+    /// see [`crate::BatchOptions::verify_output`].
+    #[error("batch {} failed verification: {reason}", path.display())]
+    VerificationFailed {
+        path: PathBuf,
+        reason: String,
+        completed: Box<RunReport>,
+    },
+
+    /// The command that the batches were piped into failed for some of them, see [`crate::sink::CommandSink`].
+    /// `completed` lists all batches, including the failed ones.
+    #[error("'{command}' failed for {} of {} batches", failures.len(), completed.batches.len())]
+    CommandFailed {
+        command: String,
+        failures: Vec<crate::sink::CommandFailure>,
+        completed: Box<RunReport>,
+    },
+
+    /// Some inputs of a cohort could not be split, see [`crate::cohort::split_cohort`]. The other inputs
+    /// were split completely; `first` is the error of the first input that failed.
+    #[error("{failed} of {inputs} inputs could not be split, the first because {first}")]
+    CohortFailed {
+        failed: usize,
+        inputs: usize,
+        first: Box<VcfBatcherError>,
+    },
+
+    /// Options that cannot be combined, e.g. an index for uncompressed batches.
+    #[error("invalid options: {reason}")]
+    InvalidOptions { reason: String },
+
+    /// An input or option needs a cargo feature that this build does not include.
+    #[error(
+        "{what} needs the {feature} feature, which this build of vcf_batcher does not include"
+    )]
+    FeatureDisabled { what: String, feature: &'static str },
+
+    /// Batching was cancelled before the whole input was processed.
+    /// `completed` lists the batches that were written completely.
+    #[error("interrupted after writing {} batches", completed.batches.len())]
+    Interrupted { completed: Box<RunReport> },
+}
+
+impl VcfBatcherError {
+    /// Returns the batches that were written completely before the error occurred,
+    /// if the error happened after batching had started.
+    pub fn completed_batches(&self) -> Option<&RunReport> {
+        match self {
+            VcfBatcherError::BatchWrite { completed, .. }
+            | VcfBatcherError::OutputExists { completed, .. }
+            | VcfBatcherError::VerificationFailed { completed, .. }
+            | VcfBatcherError::CommandFailed { completed, .. }
+            | VcfBatcherError::Interrupted { completed } => Some(completed),
+            VcfBatcherError::Read { .. }
+            | VcfBatcherError::Write { .. }
+            | VcfBatcherError::NoBatches { .. }
+            | VcfBatcherError::HeaderMismatch { .. }
+            | VcfBatcherError::SameDirectory { .. }
+            | VcfBatcherError::EmptyInput { .. }
+            | VcfBatcherError::LineTooLong { .. }
+            | VcfBatcherError::LateHeaderLine { .. }
+            | VcfBatcherError::UnknownContig { .. }
+            | VcfBatcherError::DuplicateSamples { .. }
+            | VcfBatcherError::InvalidTemplate { .. }
+            | VcfBatcherError::InvalidHeader { .. }
+            | VcfBatcherError::InvalidCompressionLevel { .. }
+            | VcfBatcherError::InvalidRegion { .. }
+            | VcfBatcherError::InvalidFilter { .. }
+            | VcfBatcherError::Watch { .. }
+            | VcfBatcherError::MalformedRecord { .. }
+            | VcfBatcherError::DuplicateRecord { .. }
+            | VcfBatcherError::UnknownSamples { .. }
+            | VcfBatcherError::UnorderedSamples { .. }
+            | VcfBatcherError::UnknownColumns { .. }
+            | VcfBatcherError::NonContiguousChromosome { .. }
+            | VcfBatcherError::InvalidConfig { .. }
+            | VcfBatcherError::UnknownProfile { .. }
+            | VcfBatcherError::OutputLocked { .. }
+            | VcfBatcherError::Lock { .. }
+            | VcfBatcherError::InvalidOptions { .. }
+            | VcfBatcherError::FeatureDisabled { .. }
+            | VcfBatcherError::CohortFailed { .. } => None,
+        }
+    }
+}
@@ -0,0 +1,261 @@
+//! C interface, enabled by the `ffi` feature, so that C, C++ or Java (through JNI) pipelines can
+//! batch files in-process instead of running the CLI. The declarations for C are in
+//! `include/vcf_batcher.h`.
+//!
+//! The error codes are the exit codes of the CLI, and the message of the last error of a thread
+//! can be read with [`vcf_batcher_last_error`].
+
+use std::cell::RefCell;
+use std::ffi::{c_char, c_int, c_void, CStr, CString};
+use std::panic::{self, AssertUnwindSafe};
+use std::path::Path;
+use std::ptr;
+
+use crate::{
+    extract_variants_to_batches_with_options, BatchOptions, Progress, ProgressCallback,
+    VcfBatcherError,
+};
+
+/// The batches were written.
+pub const VCF_BATCHER_OK: c_int = 0;
+/// An argument or option is invalid.
+pub const VCF_BATCHER_ERROR_USAGE: c_int = 2;
+/// The input cannot be read or does not have the expected structure.
+pub const VCF_BATCHER_ERROR_INPUT: c_int = 3;
+/// The output cannot be written.
+pub const VCF_BATCHER_ERROR_OUTPUT: c_int = 4;
+/// A bug in the batcher, i.e. a panic, which is not unwound into the caller.
+pub const VCF_BATCHER_ERROR_INTERNAL: c_int = 70;
+/// The run was cancelled.
+pub const VCF_BATCHER_ERROR_INTERRUPTED: c_int = 130;
+
+/// Called with the number of records read and batches completed so far, and the `user_data`
+/// pointer that was passed to [`vcf_batcher_split`].
+pub type VcfBatcherProgressCallback =
+    extern "C" fn(records: u64, batches: u64, user_data: *mut c_void);
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+/// The callback and its `user_data`, which the caller has to be able to use from any thread.
+struct ForeignProgress {
+    callback: VcfBatcherProgressCallback,
+    user_data: *mut c_void,
+}
+
+// SAFETY: the documentation of `vcf_batcher_split` requires the callback and its data to be thread-safe
+unsafe impl Send for ForeignProgress {}
+unsafe impl Sync for ForeignProgress {}
+
+impl ForeignProgress {
+    fn call(&self, progress: &Progress) {
+        (self.callback)(
+            progress.records as u64,
+            progress.batches as u64,
+            self.user_data,
+        )
+    }
+}
+
+/// Splits the VCF file `input` into batches of `batch_size` records in the directory `output_dir`,
+/// like [`extract_variants_to_batches_with_options`] with the default options. The paths are
+/// UTF-8, NUL-terminated strings.
+///
+/// `on_progress` may be `NULL`; otherwise it is called every few thousand records with
+/// `user_data`. If `batches_written` is not `NULL`, the number of batches is stored there on
+/// success.
+///
+/// Returns [`VCF_BATCHER_OK`] or one of the `VCF_BATCHER_ERROR_*` codes, in which case
+/// [`vcf_batcher_last_error`] describes the error.
+///
+/// # Safety
+///
+/// `input` and `output_dir` have to be valid, NUL-terminated strings and `batches_written` has to
+/// be `NULL` or valid for writes. `on_progress` may be called from another thread than the
+/// calling one, so it and `user_data` have to be safe to use from there.
+#[no_mangle]
+pub unsafe extern "C" fn vcf_batcher_split(
+    input: *const c_char,
+    output_dir: *const c_char,
+    batch_size: usize,
+    on_progress: Option<VcfBatcherProgressCallback>,
+    user_data: *mut c_void,
+    batches_written: *mut usize,
+) -> c_int {
+    let result = panic::catch_unwind(AssertUnwindSafe(|| {
+        let input = path_argument(input, "input")?;
+        let output_dir = path_argument(output_dir, "output_dir")?;
+        let options = BatchOptions {
+            batch_size,
+            quiet: true,
+            on_progress: on_progress.map(|callback| {
+                let progress = ForeignProgress {
+                    callback,
+                    user_data,
+                };
+                ProgressCallback::new(move |update| progress.call(update))
+            }),
+            ..Default::default()
+        };
+        extract_variants_to_batches_with_options(input, Path::new(output_dir), &options)
+            .map_err(|error| (error_code(&error), error.to_string()))
+    }));
+
+    let (code, message) = match result {
+        Ok(Ok(report)) => {
+            if !batches_written.is_null() {
+                *batches_written = report.batches.len();
+            }
+            (VCF_BATCHER_OK, None)
+        }
+        Ok(Err((code, message))) => (code, Some(message)),
+        Err(_) => (
+            VCF_BATCHER_ERROR_INTERNAL,
+            Some("the batcher panicked".to_string()),
+        ),
+    };
+    LAST_ERROR.with(|last| {
+        *last.borrow_mut() = message.map(|message| {
+            CString::new(message.replace('\0', " ")).expect("NUL bytes were replaced")
+        })
+    });
+    code
+}
+
+/// The message of the last error of [`vcf_batcher_split`] on the calling thread, or `NULL` if the
+/// last call succeeded. The string belongs to the library and stays valid until the next call of
+/// [`vcf_batcher_split`] on the same thread.
+#[no_mangle]
+pub extern "C" fn vcf_batcher_last_error() -> *const c_char {
+    LAST_ERROR.with(|last| {
+        last.borrow()
+            .as_ref()
+            .map_or(ptr::null(), |message| message.as_ptr())
+    })
+}
+
+/// Reads a path argument, which has to be a non-null UTF-8 string.
+///
+/// # Safety
+///
+/// `value` has to be `NULL` or a valid, NUL-terminated string.
+unsafe fn path_argument<'a>(value: *const c_char, name: &str) -> Result<&'a str, (c_int, String)> {
+    if value.is_null() {
+        return Err((VCF_BATCHER_ERROR_USAGE, format!("{name} is NULL")));
+    }
+    CStr::from_ptr(value).to_str().map_err(|_| {
+        (
+            VCF_BATCHER_ERROR_USAGE,
+            format!("{name} is not valid UTF-8"),
+        )
+    })
+}
+
+/// The same grouping of errors as the exit codes of the CLI.
+fn error_code(error: &VcfBatcherError) -> c_int {
+    match error {
+        VcfBatcherError::InvalidTemplate { .. }
+        | VcfBatcherError::InvalidHeader { .. }
+        | VcfBatcherError::InvalidCompressionLevel { .. }
+        | VcfBatcherError::InvalidRegion { .. }
+        | VcfBatcherError::InvalidFilter { .. }
+        | VcfBatcherError::UnknownColumns { .. }
+        | VcfBatcherError::SameDirectory { .. }
+        | VcfBatcherError::InvalidConfig { .. }
+        | VcfBatcherError::UnknownProfile { .. }
+        | VcfBatcherError::InvalidOptions { .. }
+        | VcfBatcherError::FeatureDisabled { .. } => VCF_BATCHER_ERROR_USAGE,
+        VcfBatcherError::Read { .. }
+        | VcfBatcherError::NoBatches { .. }
+        | VcfBatcherError::HeaderMismatch { .. }
+        | VcfBatcherError::EmptyInput { .. }
+        | VcfBatcherError::LineTooLong { .. }
+        | VcfBatcherError::LateHeaderLine { .. }
+        | VcfBatcherError::UnknownContig { .. }
+        | VcfBatcherError::DuplicateSamples { .. }
+        | VcfBatcherError::UnknownSamples { .. }
+        | VcfBatcherError::UnorderedSamples { .. }
+        | VcfBatcherError::NonContiguousChromosome { .. }
+        | VcfBatcherError::Watch { .. }
+        | VcfBatcherError::MalformedRecord { .. }
+        | VcfBatcherError::DuplicateRecord { .. } => VCF_BATCHER_ERROR_INPUT,
+        VcfBatcherError::BatchWrite { .. }
+        | VcfBatcherError::Write { .. }
+        | VcfBatcherError::OutputExists { .. }
+        | VcfBatcherError::VerificationFailed { .. }
+        | VcfBatcherError::CommandFailed { .. }
+        | VcfBatcherError::OutputLocked { .. }
+        | VcfBatcherError::Lock { .. } => VCF_BATCHER_ERROR_OUTPUT,
+        VcfBatcherError::Interrupted { .. } => VCF_BATCHER_ERROR_INTERRUPTED,
+        VcfBatcherError::CohortFailed { first, .. } => error_code(first),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ffi::{c_void, CStr, CString};
+    use std::ptr;
+    use std::sync::atomic::{AtomicU64, Ordering};
+
+    use super::{
+        vcf_batcher_last_error, vcf_batcher_split, VCF_BATCHER_ERROR_INPUT,
+        VCF_BATCHER_ERROR_USAGE, VCF_BATCHER_OK,
+    };
+
+    extern "C" fn store_records(records: u64, _batches: u64, user_data: *mut c_void) {
+        let seen = unsafe { &*(user_data as *const AtomicU64) };
+        seen.store(records, Ordering::Relaxed);
+    }
+
+    #[test]
+    fn test_vcf_batcher_split() {
+        let _ = std::fs::remove_dir_all("./test_data/ffi");
+        let input = CString::new("./test_data/batch_01.vcf.gz").unwrap();
+        let output = CString::new("./test_data/ffi").unwrap();
+        let records = AtomicU64::new(0);
+        let mut batches = 0;
+
+        let code = unsafe {
+            vcf_batcher_split(
+                input.as_ptr(),
+                output.as_ptr(),
+                30,
+                Some(store_records),
+                &records as *const AtomicU64 as *mut c_void,
+                &mut batches,
+            )
+        };
+        assert_eq!(code, VCF_BATCHER_OK);
+        assert_eq!(batches, 4);
+        assert!(records.load(Ordering::Relaxed) <= 100);
+        assert!(vcf_batcher_last_error().is_null());
+
+        let missing = CString::new("./test_data/missing.vcf").unwrap();
+        let code = unsafe {
+            vcf_batcher_split(
+                missing.as_ptr(),
+                output.as_ptr(),
+                30,
+                None,
+                ptr::null_mut(),
+                ptr::null_mut(),
+            )
+        };
+        assert_eq!(code, VCF_BATCHER_ERROR_INPUT);
+        let message = unsafe { CStr::from_ptr(vcf_batcher_last_error()) };
+        assert!(message.to_str().unwrap().contains("missing.vcf"));
+
+        let code = unsafe {
+            vcf_batcher_split(
+                ptr::null(),
+                output.as_ptr(),
+                30,
+                None,
+                ptr::null_mut(),
+                ptr::null_mut(),
+            )
+        };
+        assert_eq!(code, VCF_BATCHER_ERROR_USAGE);
+    }
+}
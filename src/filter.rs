@@ -0,0 +1,415 @@
+//! Selection of the records and samples that are written into the batches.
+
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use std::str::FromStr;
+
+use crate::{header, VcfBatcherError};
+
+/// A genomic region such as `chr1`, `chr1:10000-20000` or `chr1:10000-`, with 1-based, inclusive coordinates.
+///
+/// # Examples
+///
+/// ```
+/// use vcf_batcher::filter::Region;
+///
+/// let region: Region = "chr1:1,000-2,000".parse().unwrap();
+/// assert!(region.contains("chr1", 1500));
+/// assert!(!region.contains("chr1", 2001));
+/// assert!("chr1:2000-1000".parse::<Region>().is_err());
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Region {
+    pub chrom: String,
+    pub start: u64,
+    /// `None` if the region extends to the end of the contig.
+    pub end: Option<u64>,
+}
+
+impl Region {
+    /// Whether a record at the given position lies in the region.
+    pub fn contains(&self, chrom: &str, pos: u64) -> bool {
+        self.chrom == chrom && pos >= self.start && self.end.is_none_or(|end| pos <= end)
+    }
+}
+
+impl FromStr for Region {
+    type Err = VcfBatcherError;
+
+    fn from_str(region: &str) -> Result<Self, Self::Err> {
+        let invalid = |reason: &str| VcfBatcherError::InvalidRegion {
+            region: region.to_string(),
+            reason: reason.to_string(),
+        };
+        let position = |value: &str| {
+            value
+                .replace(',', "")
+                .parse::<u64>()
+                .map_err(|_| invalid("positions must be positive whole numbers"))
+        };
+
+        // Contig names may contain ':' themselves (e.g. HLA-DRB1*15:01:01), so the range is split off at the last one
+        let (chrom, range) = match region.rsplit_once(':') {
+            Some((chrom, range))
+                if range
+                    .chars()
+                    .all(|c| c.is_ascii_digit() || c == ',' || c == '-') =>
+            {
+                (chrom, Some(range))
+            }
+            _ => (region, None),
+        };
+        if chrom.is_empty() {
+            return Err(invalid("the contig name is missing"));
+        }
+
+        let (start, end) = match range {
+            None => (1, None),
+            Some(range) => match range.split_once('-') {
+                Some((start, "")) => (position(start)?, None),
+                Some((start, end)) => (position(start)?, Some(position(end)?)),
+                None => {
+                    let pos = position(range)?;
+                    (pos, Some(pos))
+                }
+            },
+        };
+        if start == 0 {
+            return Err(invalid("positions start at 1"));
+        }
+        if end.is_some_and(|end| end < start) {
+            return Err(invalid("the end lies before the start"));
+        }
+
+        Ok(Region {
+            chrom: chrom.to_string(),
+            start,
+            end,
+        })
+    }
+}
+
+impl fmt::Display for Region {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.end {
+            Some(end) => write!(f, "{}:{}-{}", self.chrom, self.start, end),
+            None if self.start == 1 => f.write_str(&self.chrom),
+            None => write!(f, "{}:{}-", self.chrom, self.start),
+        }
+    }
+}
+
+/// Which records and sample columns end up in the batches. The default keeps everything.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct RecordFilter {
+    /// Only keep records whose POS lies in one of these regions. Empty keeps records everywhere.
+    pub regions: Vec<Region>,
+    /// Only keep these sample columns, in this order. `None` keeps all samples.
+    pub samples: Option<Vec<String>>,
+    /// Write the sample columns in this order, e.g. so that all batches of several inputs have the same
+    /// order. Unlike [`RecordFilter::samples`], it must list every sample of the input. `None` keeps the
+    /// order of the input.
+    pub sample_order: Option<Vec<String>>,
+    /// Only keep records whose FILTER column is `PASS`.
+    pub pass_only: bool,
+    /// Only keep records with at least this QUAL. Records with a missing QUAL (`.`) are dropped.
+    pub min_qual: Option<f64>,
+}
+
+impl RecordFilter {
+    /// Whether the filter keeps every record, so that records do not need to be inspected.
+    pub fn keeps_all_records(&self) -> bool {
+        self.regions.is_empty() && !self.pass_only && self.min_qual.is_none()
+    }
+
+    /// Checks the filter before any input is read: selected samples must be named and unique, so
+    /// that the batches do not get empty or repeated columns, and the minimum QUAL must be a
+    /// non-negative number. Selecting no samples at all is allowed and writes sites-only batches.
+    pub fn validate(&self) -> Result<(), VcfBatcherError> {
+        let invalid = |reason: String| Err(VcfBatcherError::InvalidFilter { reason });
+        if self.samples.is_some() && self.sample_order.is_some() {
+            return invalid(
+                "samples and a sample order cannot be given together, selected samples are written in the order they are listed"
+                    .to_string(),
+            );
+        }
+        if let Some(samples) = self.samples.as_ref().or(self.sample_order.as_ref()) {
+            if samples.iter().any(|sample| sample.trim().is_empty()) {
+                return invalid("sample names must not be empty".to_string());
+            }
+            let names: Vec<&str> = samples.iter().map(String::as_str).collect();
+            let duplicates = header::duplicate_samples(&names);
+            if !duplicates.is_empty() {
+                return invalid(format!(
+                    "samples are selected more than once: {}",
+                    duplicates.join(", ")
+                ));
+            }
+        }
+        if let Some(min_qual) = self.min_qual {
+            if !(min_qual.is_finite() && min_qual >= 0.0) {
+                return invalid(format!(
+                    "the minimum QUAL must be a non-negative number, not {}",
+                    min_qual
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// Whether a variant record passes the region, FILTER and QUAL conditions.
+    pub fn keeps(&self, record: &str) -> bool {
+        if self.keeps_all_records() {
+            return true;
+        }
+        let mut columns = record.split('\t');
+        let chrom = columns.next().unwrap_or_default();
+        let pos = columns.next().and_then(|pos| pos.parse::<u64>().ok());
+        // ID, REF and ALT are not needed
+        let mut columns = columns.skip(3);
+        let qual = columns.next().unwrap_or(".");
+        let filter = columns.next().unwrap_or(".");
+
+        if !self.regions.is_empty() {
+            let Some(pos) = pos else { return false };
+            if !self
+                .regions
+                .iter()
+                .any(|region| region.contains(chrom, pos))
+            {
+                return false;
+            }
+        }
+        if self.pass_only && filter != "PASS" {
+            return false;
+        }
+        if let Some(min_qual) = self.min_qual {
+            match qual.parse::<f64>() {
+                Ok(qual) if qual >= min_qual => {}
+                _ => return false,
+            }
+        }
+        true
+    }
+
+    /// The selected samples or the sample order, whose columns are rearranged.
+    pub fn selected_samples(&self) -> Option<&Vec<String>> {
+        self.samples.as_ref().or(self.sample_order.as_ref())
+    }
+
+    /// Returns the indices of the columns to keep for the `#CHROM` header line: the fixed columns
+    /// up to FORMAT, followed by the selected samples or all samples in the sample order. `None` if all
+    /// columns are kept in their order.
+    pub fn column_indices(&self, chrom_line: &str) -> Result<Option<Vec<usize>>, VcfBatcherError> {
+        let Some(samples) = self.selected_samples() else {
+            return Ok(None);
+        };
+        let available = header::sample_names(chrom_line);
+        if self.sample_order.is_some() {
+            let unlisted: Vec<String> = available
+                .iter()
+                .filter(|available| !samples.iter().any(|sample| sample == *available))
+                .map(|sample| sample.to_string())
+                .collect();
+            if !unlisted.is_empty() {
+                return Err(VcfBatcherError::UnorderedSamples { samples: unlisted });
+            }
+        }
+        let mut indices: Vec<usize> = (0..9).collect();
+        let mut unknown = Vec::new();
+        for sample in samples {
+            match available.iter().position(|available| available == sample) {
+                Some(index) => indices.push(index + 9),
+                None => unknown.push(sample.clone()),
+            }
+        }
+        if !unknown.is_empty() {
+            return Err(VcfBatcherError::UnknownSamples { samples: unknown });
+        }
+        if self.sample_order.is_some() && indices.iter().enumerate().all(|(i, index)| i == *index) {
+            // The input already has the required order
+            return Ok(None);
+        }
+        Ok(Some(indices))
+    }
+}
+
+/// Reads sample names from a file with one name per line, e.g. for [`RecordFilter::sample_order`].
+/// Blank lines and lines starting with `#` are ignored.
+pub fn read_sample_list(path: &Path) -> Result<Vec<String>, VcfBatcherError> {
+    let contents = fs::read_to_string(path).map_err(|source| VcfBatcherError::Read {
+        path: path.to_path_buf(),
+        source,
+    })?;
+    Ok(contents
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(str::to_string)
+        .collect())
+}
+
+/// Keeps only the columns at the given indices (in their order) of a tab-separated line.
+pub fn select_columns(line: &str, indices: &[usize]) -> String {
+    let columns: Vec<&str> = line.split('\t').collect();
+    let mut selected = String::with_capacity(line.len());
+    for (position, index) in indices.iter().enumerate() {
+        if let Some(column) = columns.get(*index) {
+            if position > 0 {
+                selected.push('\t');
+            }
+            selected.push_str(column);
+        }
+    }
+    selected
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{select_columns, RecordFilter, Region};
+    use crate::VcfBatcherError;
+
+    #[test]
+    fn test_parse_region() {
+        assert_eq!(
+            "chr1".parse::<Region>().unwrap(),
+            Region {
+                chrom: "chr1".to_string(),
+                start: 1,
+                end: None
+            }
+        );
+        assert_eq!(
+            "HLA-DRB1*15:01:01:100-200".parse::<Region>().unwrap(),
+            Region {
+                chrom: "HLA-DRB1*15:01:01".to_string(),
+                start: 100,
+                end: Some(200)
+            }
+        );
+        assert_eq!("chr2:500".parse::<Region>().unwrap().end, Some(500));
+        assert_eq!(
+            "chr2:500-".parse::<Region>().unwrap().to_string(),
+            "chr2:500-"
+        );
+        for invalid in ["", ":1-2", "chr1:0-5", "chr1:9-5", "chr1:1-2-3"] {
+            assert!(
+                matches!(
+                    invalid.parse::<Region>(),
+                    Err(VcfBatcherError::InvalidRegion { .. })
+                ),
+                "{} should be invalid",
+                invalid
+            );
+        }
+    }
+
+    #[test]
+    fn test_record_filter() {
+        let filter = RecordFilter {
+            regions: vec!["chr1:100-200".parse().unwrap(), "chr2".parse().unwrap()],
+            pass_only: true,
+            min_qual: Some(30.0),
+            ..Default::default()
+        };
+
+        assert!(filter.keeps("chr1\t150\t.\tA\tC\t50\tPASS\t."));
+        assert!(filter.keeps("chr2\t9999999\t.\tA\tC\t30\tPASS\t."));
+        assert!(!filter.keeps("chr1\t250\t.\tA\tC\t50\tPASS\t."));
+        assert!(!filter.keeps("chr1\t150\t.\tA\tC\t50\tLowQual\t."));
+        assert!(!filter.keeps("chr1\t150\t.\tA\tC\t29.5\tPASS\t."));
+        assert!(!filter.keeps("chr1\t150\t.\tA\tC\t.\tPASS\t."));
+        assert!(RecordFilter::default().keeps("anything"));
+    }
+
+    #[test]
+    fn test_validate_filter() {
+        let filter = |samples: &[&str], min_qual: Option<f64>| RecordFilter {
+            samples: Some(samples.iter().map(|sample| sample.to_string()).collect()),
+            min_qual,
+            ..Default::default()
+        };
+        assert!(RecordFilter::default().validate().is_ok());
+        assert!(filter(&["NA00001", "NA00002"], Some(30.0))
+            .validate()
+            .is_ok());
+        // Sites-only batches
+        assert!(filter(&[], None).validate().is_ok());
+
+        for invalid in [
+            filter(&["NA00001", "NA00001"], None),
+            filter(&["NA00001", ""], None),
+            filter(&["NA00001"], Some(-1.0)),
+            filter(&["NA00001"], Some(f64::NAN)),
+        ] {
+            assert!(matches!(
+                invalid.validate(),
+                Err(VcfBatcherError::InvalidFilter { .. })
+            ));
+        }
+    }
+
+    #[test]
+    fn test_select_samples() {
+        let chrom_line = "#CHROM\tPOS\tID\tREF\tALT\tQUAL\tFILTER\tINFO\tFORMAT\tA\tB\tC";
+        let filter = RecordFilter {
+            samples: Some(vec!["C".to_string(), "A".to_string()]),
+            ..Default::default()
+        };
+
+        let indices = filter.column_indices(chrom_line).unwrap().unwrap();
+        assert_eq!(
+            select_columns(chrom_line, &indices),
+            "#CHROM\tPOS\tID\tREF\tALT\tQUAL\tFILTER\tINFO\tFORMAT\tC\tA"
+        );
+        assert_eq!(
+            select_columns("1\t5\t.\tA\tC\t.\tPASS\t.\tGT\t0/0\t0/1\t1/1", &indices),
+            "1\t5\t.\tA\tC\t.\tPASS\t.\tGT\t1/1\t0/0"
+        );
+
+        let filter = RecordFilter {
+            samples: Some(vec!["D".to_string()]),
+            ..Default::default()
+        };
+        assert!(matches!(
+            filter.column_indices(chrom_line),
+            Err(VcfBatcherError::UnknownSamples { samples }) if samples == vec!["D"]
+        ));
+    }
+
+    #[test]
+    fn test_sample_order() {
+        let chrom_line = "#CHROM\tPOS\tID\tREF\tALT\tQUAL\tFILTER\tINFO\tFORMAT\tA\tB\tC";
+        let filter = |order: &[&str]| RecordFilter {
+            sample_order: Some(order.iter().map(|sample| sample.to_string()).collect()),
+            ..Default::default()
+        };
+
+        let indices = filter(&["B", "C", "A"])
+            .column_indices(chrom_line)
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            select_columns("1\t5\t.\tA\tC\t.\tPASS\t.\tGT\t0/0\t0/1\t1/1", &indices),
+            "1\t5\t.\tA\tC\t.\tPASS\t.\tGT\t0/1\t1/1\t0/0"
+        );
+        assert_eq!(
+            filter(&["A", "B", "C"]).column_indices(chrom_line).unwrap(),
+            None
+        );
+        assert!(matches!(
+            filter(&["C", "A"]).column_indices(chrom_line),
+            Err(VcfBatcherError::UnorderedSamples { samples }) if samples == vec!["B"]
+        ));
+        assert!(matches!(
+            filter(&["C", "A", "B", "D"]).column_indices(chrom_line),
+            Err(VcfBatcherError::UnknownSamples { samples }) if samples == vec!["D"]
+        ));
+        let both = RecordFilter {
+            samples: Some(vec!["A".to_string()]),
+            ..filter(&["A", "B", "C"])
+        };
+        assert!(both.validate().is_err());
+    }
+}
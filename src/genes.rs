@@ -0,0 +1,193 @@
+//! Grouping records by the gene that their VEP (`CSQ`) or SnpEff (`ANN`) annotation names, e.g. for
+//! gene-burden tests that need all variants of a gene in one batch, see [`crate::SplitMode::Gene`].
+
+use std::collections::HashMap;
+
+use crate::header::StructuredLine;
+use crate::AppendLine;
+
+/// The name that the batches of records without a gene symbol get instead of a gene.
+pub const INTERGENIC: &str = "intergenic";
+
+/// Where the gene symbol is in the `CSQ` and `ANN` INFO fields of the records.
+///
+/// Both annotations list the consequences of a variant, separated by commas, with `|`-separated fields
+/// in the order that the `Description` of their `##INFO` header line gives. Without such a header line,
+/// the default orders of VEP (`Allele|Consequence|IMPACT|SYMBOL|...`) and SnpEff
+/// (`Allele|Annotation|Annotation_Impact|Gene_Name|...`) are assumed, which have the symbol in the fourth field.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct GeneAnnotations {
+    csq: usize,
+    ann: usize,
+}
+
+impl Default for GeneAnnotations {
+    fn default() -> Self {
+        GeneAnnotations { csq: 3, ann: 3 }
+    }
+}
+
+impl GeneAnnotations {
+    /// Takes the field order from a `##INFO=<ID=CSQ,...>` or `##INFO=<ID=ANN,...>` header line.
+    /// Other lines, and descriptions without a `SYMBOL` or `Gene_Name` field, are ignored.
+    pub fn read_header(&mut self, line: &str) {
+        if !line.starts_with("##INFO=") {
+            return;
+        }
+        let Some(info) = StructuredLine::parse(line) else {
+            return;
+        };
+        let (index, symbol) = match info.id() {
+            Some("CSQ") => (&mut self.csq, "SYMBOL"),
+            Some("ANN") => (&mut self.ann, "Gene_Name"),
+            _ => return,
+        };
+        // e.g. "Consequence annotations from Ensembl VEP. Format: Allele|Consequence|IMPACT|SYMBOL"
+        // or "Functional annotations: 'Allele | Annotation | Annotation_Impact | Gene_Name'"
+        let description = info.get("Description").unwrap_or_default();
+        let format = description
+            .rsplit_once(": ")
+            .map_or(description, |(_, format)| format);
+        let position = format
+            .trim_matches(|character: char| character == '\'' || character.is_whitespace())
+            .split('|')
+            .position(|field| field.trim() == symbol);
+        if let Some(position) = position {
+            *index = position;
+        }
+    }
+
+    /// Returns the gene symbol of the first consequence of a record that names one, from `CSQ` or,
+    /// if the record has no `CSQ` field, from `ANN`. `None` if neither names a gene.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use vcf_batcher::genes::GeneAnnotations;
+    ///
+    /// let mut annotations = GeneAnnotations::default();
+    /// annotations.read_header(
+    ///     r#"##INFO=<ID=CSQ,Number=.,Type=String,Description="Consequence annotations from Ensembl VEP. Format: Allele|SYMBOL|Consequence">"#,
+    /// );
+    /// let record = "chr17\t7675088\t.\tC\tT\t.\tPASS\tDP=12;CSQ=T|||intergenic_variant,T|TP53|missense_variant";
+    /// assert_eq!(annotations.gene(record), Some("TP53"));
+    /// assert_eq!(annotations.gene("chr17\t7675088\t.\tC\tT\t.\tPASS\tDP=12"), None);
+    /// ```
+    pub fn gene<'r>(&self, record: &'r str) -> Option<&'r str> {
+        let info = record.split('\t').nth(7)?;
+        let mut fields = info.split(';');
+        let (annotation, index) = match fields.clone().find_map(|field| field.strip_prefix("CSQ="))
+        {
+            Some(csq) => (csq, self.csq),
+            None => (
+                fields.find_map(|field| field.strip_prefix("ANN="))?,
+                self.ann,
+            ),
+        };
+        annotation
+            .split(',')
+            .filter_map(|consequence| consequence.split('|').nth(index))
+            .find(|symbol| !symbol.is_empty())
+    }
+}
+
+/// The records of one gene, or of all records without a gene symbol.
+#[derive(Debug)]
+pub(crate) struct GeneGroup {
+    pub(crate) gene: Option<String>,
+    /// The records, each followed by a line ending.
+    pub(crate) records: String,
+    pub(crate) record_count: usize,
+}
+
+impl GeneGroup {
+    /// The gene symbol, or [`INTERGENIC`].
+    pub(crate) fn name(&self) -> &str {
+        self.gene.as_deref().unwrap_or(INTERGENIC)
+    }
+}
+
+/// Collects the records of a chromosome by gene, in the order that the genes first appear.
+#[derive(Debug, Default)]
+pub(crate) struct GeneGroups {
+    groups: Vec<GeneGroup>,
+    indices: HashMap<Option<String>, usize>,
+}
+
+impl GeneGroups {
+    pub(crate) fn add(&mut self, gene: Option<&str>, record: &str) {
+        let gene = gene.map(str::to_string);
+        let index = match self.indices.get(&gene) {
+            Some(&index) => index,
+            None => {
+                self.indices.insert(gene.clone(), self.groups.len());
+                self.groups.push(GeneGroup {
+                    gene,
+                    records: String::new(),
+                    record_count: 0,
+                });
+                self.groups.len() - 1
+            }
+        };
+        let group = &mut self.groups[index];
+        group.records.append_line(record);
+        group.record_count += 1;
+    }
+
+    /// Removes and returns the groups, in the order that their genes first appeared.
+    pub(crate) fn take(&mut self) -> Vec<GeneGroup> {
+        self.indices.clear();
+        std::mem::take(&mut self.groups)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{GeneAnnotations, GeneGroups};
+
+    #[test]
+    fn test_gene_annotations() {
+        let mut annotations = GeneAnnotations::default();
+        let record = |info: &str| format!("1\t100\t.\tA\tG\t.\tPASS\t{info}");
+        assert_eq!(
+            annotations.gene(&record("ANN=G|missense_variant|MODERATE|BRCA1|ENSG1")),
+            Some("BRCA1")
+        );
+        // CSQ takes precedence over ANN
+        assert_eq!(
+            annotations.gene(&record("ANN=G|x|y|BRCA1;CSQ=G|x|y|NBR2")),
+            Some("NBR2")
+        );
+        assert_eq!(annotations.gene(&record("CSQ=G|x|y|,G|x|y|")), None);
+        assert_eq!(annotations.gene(&record(".")), None);
+
+        annotations.read_header(
+            "##INFO=<ID=ANN,Number=.,Type=String,Description=\"Functional annotations: 'Allele | Gene_Name | Annotation'\">",
+        );
+        annotations
+            .read_header("##INFO=<ID=CSQ,Number=.,Type=String,Description=\"Consequences\">");
+        assert_eq!(
+            annotations.gene(&record("ANN=G|BRCA1|missense_variant")),
+            Some("BRCA1")
+        );
+        assert_eq!(annotations.gene(&record("CSQ=G|x|y|NBR2")), Some("NBR2"));
+    }
+
+    #[test]
+    fn test_gene_groups() {
+        let mut groups = GeneGroups::default();
+        for (gene, record) in [
+            (Some("A"), "1"),
+            (None, "2"),
+            (Some("B"), "3"),
+            (Some("A"), "4"),
+        ] {
+            groups.add(gene, record);
+        }
+        let groups = groups.take();
+        let names: Vec<&str> = groups.iter().map(|group| group.name()).collect();
+        assert_eq!(names, ["A", "intergenic", "B"]);
+        assert_eq!(groups[0].records, "1\n4\n");
+        assert_eq!(groups[0].record_count, 2);
+    }
+}
@@ -0,0 +1,381 @@
+use std::collections::HashSet;
+use std::path::Path;
+
+use serde::Serialize;
+
+use crate::{is_header_line, read_lines, VcfBatcherError};
+
+/// A structured meta-information line such as `##contig=<ID=chr1,length=248956422>`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StructuredLine {
+    /// The key of the line, e.g. `contig`, `INFO` or `FORMAT`.
+    pub key: String,
+    /// The fields between the angle brackets, in the order they appear. Quotes around values are removed.
+    pub fields: Vec<(String, String)>,
+}
+
+impl StructuredLine {
+    /// Parses a `##key=<...>` line. Returns `None` for any other kind of line.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use vcf_batcher::header::StructuredLine;
+    ///
+    /// let line = StructuredLine::parse(r#"##INFO=<ID=AF,Number=A,Type=Float,Description="Allele Frequency, estimated">"#).unwrap();
+    /// assert_eq!(line.key, "INFO");
+    /// assert_eq!(line.get("Description"), Some("Allele Frequency, estimated"));
+    /// ```
+    pub fn parse(line: &str) -> Option<StructuredLine> {
+        let (key, value) = line.strip_prefix("##")?.split_once('=')?;
+        let body = value.strip_prefix('<')?.strip_suffix('>')?;
+
+        let mut fields = Vec::new();
+        let mut name = String::new();
+        let mut current = String::new();
+        let mut in_value = false;
+        let mut in_quotes = false;
+        let mut escaped = false;
+
+        for character in body.chars() {
+            match character {
+                _ if escaped => {
+                    current.push(character);
+                    escaped = false;
+                }
+                '\\' if in_quotes => escaped = true,
+                '"' => in_quotes = !in_quotes,
+                '=' if !in_value && !in_quotes => {
+                    name = std::mem::take(&mut current);
+                    in_value = true;
+                }
+                ',' if !in_quotes => {
+                    fields.push((std::mem::take(&mut name), std::mem::take(&mut current)));
+                    in_value = false;
+                }
+                _ => current.push(character),
+            }
+        }
+        if in_value || !current.is_empty() {
+            fields.push((name, current));
+        }
+
+        Some(StructuredLine {
+            key: key.to_string(),
+            fields,
+        })
+    }
+
+    /// Returns the value of the field with the given name.
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.fields
+            .iter()
+            .find(|(field, _)| field == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// The `ID` field, which identifies most structured lines.
+    pub fn id(&self) -> Option<&str> {
+        self.get("ID")
+    }
+}
+
+/// Returns the contig name declared by a `##contig=<ID=...>` line.
+pub fn contig_id(line: &str) -> Option<String> {
+    if !line.starts_with("##contig=") {
+        return None;
+    }
+    StructuredLine::parse(line)?.id().map(str::to_string)
+}
+
+/// Returns the sample names of a `#CHROM` header line, i.e. all columns after `FORMAT`.
+pub fn sample_names(chrom_line: &str) -> Vec<&str> {
+    chrom_line.split('\t').skip(9).collect()
+}
+
+/// Returns every sample name that appears more than once, in order of first repetition.
+pub fn duplicate_samples(samples: &[&str]) -> Vec<String> {
+    let mut seen = HashSet::new();
+    let mut duplicates = Vec::new();
+    for sample in samples {
+        if !seen.insert(*sample) && !duplicates.iter().any(|duplicate| duplicate == sample) {
+            duplicates.push(sample.to_string());
+        }
+    }
+    duplicates
+}
+
+/// A contig declared by a `##contig` line.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ContigDefinition {
+    pub name: String,
+    /// The `length` field, if present.
+    pub length: Option<u64>,
+}
+
+/// An INFO or FORMAT field declared in the header.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct FieldDefinition {
+    pub id: String,
+    /// The `Number` field, e.g. `1`, `A` or `.`.
+    pub number: Option<String>,
+    /// The `Type` field, e.g. `Integer` or `String`.
+    #[serde(rename = "type")]
+    pub kind: Option<String>,
+    pub description: Option<String>,
+}
+
+impl FieldDefinition {
+    fn from_line(line: &StructuredLine) -> Option<FieldDefinition> {
+        Some(FieldDefinition {
+            id: line.id()?.to_string(),
+            number: line.get("Number").map(str::to_string),
+            kind: line.get("Type").map(str::to_string),
+            description: line.get("Description").map(str::to_string),
+        })
+    }
+}
+
+/// The metadata of a VCF header.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct VcfHeader {
+    /// The version of the `##fileformat` line, e.g. `VCFv4.2`.
+    pub fileformat: Option<String>,
+    pub contigs: Vec<ContigDefinition>,
+    pub info: Vec<FieldDefinition>,
+    pub format: Vec<FieldDefinition>,
+    /// The sample names of the `#CHROM` line.
+    pub samples: Vec<String>,
+}
+
+/// Reads the header of a (possibly bgzipped) VCF file, stopping at the first variant record.
+///
+/// # Examples
+///
+/// ```
+/// use std::path::Path;
+/// use vcf_batcher::header::read_header;
+///
+/// let header = read_header(Path::new("test_data/batch_01.vcf.gz")).unwrap();
+/// assert_eq!(header.fileformat.as_deref(), Some("VCFv4.1"));
+/// assert!(header.info.iter().any(|info| info.id == "AF"));
+/// ```
+pub fn read_header(file_path: &Path) -> Result<VcfHeader, VcfBatcherError> {
+    let read_error = |source| VcfBatcherError::Read {
+        path: file_path.to_path_buf(),
+        source,
+    };
+    let mut header = VcfHeader::default();
+    for line in read_lines(file_path).map_err(read_error)? {
+        let line = line.map_err(read_error)?;
+        if !is_header_line(&line) {
+            break;
+        }
+        if let Some(version) = line.strip_prefix("##fileformat=") {
+            header.fileformat = Some(version.to_string());
+        } else if line.starts_with("#CHROM") {
+            header.samples = sample_names(&line)
+                .into_iter()
+                .map(str::to_string)
+                .collect();
+        } else if let Some(structured) = StructuredLine::parse(&line) {
+            match structured.key.as_str() {
+                "contig" => {
+                    if let Some(name) = structured.id() {
+                        header.contigs.push(ContigDefinition {
+                            name: name.to_string(),
+                            length: structured
+                                .get("length")
+                                .and_then(|length| length.parse().ok()),
+                        });
+                    }
+                }
+                "INFO" => header.info.extend(FieldDefinition::from_line(&structured)),
+                "FORMAT" => header
+                    .format
+                    .extend(FieldDefinition::from_line(&structured)),
+                _ => {}
+            }
+        }
+    }
+    Ok(header)
+}
+
+/// Reads the sample names from the `#CHROM` line of a (possibly bgzipped) VCF file.
+/// Only the header is read, so this is fast even for large files. Files without a `#CHROM`
+/// line or with only the fixed columns have no samples.
+///
+/// # Examples
+///
+/// ```
+/// use std::path::Path;
+/// use vcf_batcher::header::read_sample_names;
+///
+/// let samples = read_sample_names(Path::new("test_data/batch_01.vcf.gz")).unwrap();
+/// assert_eq!(samples.len(), 1092);
+/// ```
+pub fn read_sample_names(file_path: &Path) -> Result<Vec<String>, VcfBatcherError> {
+    let read_error = |source| VcfBatcherError::Read {
+        path: file_path.to_path_buf(),
+        source,
+    };
+    for line in read_lines(file_path).map_err(read_error)? {
+        let line = line.map_err(read_error)?;
+        if !is_header_line(&line) {
+            break;
+        }
+        if line.starts_with("#CHROM") {
+            return Ok(sample_names(&line)
+                .into_iter()
+                .map(str::to_string)
+                .collect());
+        }
+    }
+    Ok(Vec::new())
+}
+
+/// Describes why a variant record is malformed, if it is: it must have as many columns as the
+/// `#CHROM` line (`columns`), or at least the 8 fixed columns if there is none, and its POS must be a whole number.
+pub fn malformed_record_reason(line: &str, columns: Option<usize>) -> Option<String> {
+    let found = line.bytes().filter(|&byte| byte == b'\t').count() + 1;
+    match columns {
+        Some(expected) if found != expected => {
+            return Some(format!(
+                "expected {} columns like the #CHROM line, found {}",
+                expected, found
+            ))
+        }
+        None if found < 8 => {
+            return Some(format!("expected at least 8 columns, found {}", found));
+        }
+        _ => {}
+    }
+    let pos = line.split('\t').nth(1).unwrap_or_default();
+    if pos.parse::<u64>().is_err() {
+        return Some(format!("POS '{}' is not a whole number", pos));
+    }
+    None
+}
+
+/// Returns the value of an INFO key of a variant record, an empty string for a flag and `None` if the
+/// record does not have the key.
+///
+/// # Examples
+///
+/// ```
+/// use vcf_batcher::header::info_value;
+///
+/// let record = "chr1\t5\t.\tA\tC\t.\tPASS\tDB;CLNSIG=Benign;AF=0.1";
+/// assert_eq!(info_value(record, "CLNSIG"), Some("Benign"));
+/// assert_eq!(info_value(record, "DB"), Some(""));
+/// assert_eq!(info_value(record, "AC"), None);
+/// ```
+pub fn info_value<'r>(line: &'r str, key: &str) -> Option<&'r str> {
+    line.split('\t')
+        .nth(7)?
+        .split(';')
+        .find_map(|field| match field.split_once('=') {
+            Some((name, value)) => (name == key).then_some(value),
+            None => (field == key).then_some(""),
+        })
+}
+
+/// Returns the CHROM column of a variant record.
+pub fn record_chrom(line: &str) -> &str {
+    line.split('\t').next().unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::{
+        contig_id, duplicate_samples, read_header, read_sample_names, sample_names,
+        ContigDefinition, StructuredLine,
+    };
+
+    #[test]
+    fn test_parse_structured_line() {
+        let line = StructuredLine::parse("##contig=<ID=chr1,length=248956422,assembly=\"GRCh38\">")
+            .unwrap();
+        assert_eq!(line.key, "contig");
+        assert_eq!(line.id(), Some("chr1"));
+        assert_eq!(line.get("length"), Some("248956422"));
+        assert_eq!(line.get("assembly"), Some("GRCh38"));
+
+        assert_eq!(StructuredLine::parse("##fileformat=VCFv4.2"), None);
+        assert_eq!(StructuredLine::parse("#CHROM\tPOS"), None);
+    }
+
+    #[test]
+    fn test_duplicate_samples() {
+        let line = "#CHROM\tPOS\tID\tREF\tALT\tQUAL\tFILTER\tINFO\tFORMAT\tA\tB\tA\tC\tA\tB";
+        let samples = sample_names(line);
+        assert_eq!(samples, vec!["A", "B", "A", "C", "A", "B"]);
+        assert_eq!(duplicate_samples(&samples), vec!["A", "B"]);
+        assert!(duplicate_samples(&["A", "B"]).is_empty());
+    }
+
+    #[test]
+    fn test_contig_id() {
+        assert_eq!(
+            contig_id("##contig=<ID=HLA-DRB1*15:01:01,length=11080>"),
+            Some("HLA-DRB1*15:01:01".to_string())
+        );
+        assert_eq!(contig_id("##INFO=<ID=AF,Number=A>"), None);
+    }
+
+    #[test]
+    fn test_read_sample_names() {
+        let path = Path::new("./test_data/inputs/sample_names.vcf");
+        std::fs::create_dir_all("./test_data/inputs").unwrap();
+        std::fs::write(
+            path,
+            "##fileformat=VCFv4.2\n\
+             #CHROM\tPOS\tID\tREF\tALT\tQUAL\tFILTER\tINFO\tFORMAT\tNA1\tNA2\n\
+             1\t5\t.\tA\tC\t.\tPASS\t.\tGT\t0/1\t0/0\n",
+        )
+        .unwrap();
+        assert_eq!(read_sample_names(path).unwrap(), vec!["NA1", "NA2"]);
+    }
+
+    #[test]
+    fn test_read_header() {
+        let path = Path::new("./test_data/inputs/read_header.vcf");
+        std::fs::create_dir_all("./test_data/inputs").unwrap();
+        std::fs::write(
+            path,
+            "##fileformat=VCFv4.3\n\
+             ##contig=<ID=chr1,length=248956422>\n\
+             ##contig=<ID=chrUn>\n\
+             ##INFO=<ID=DP,Number=1,Type=Integer,Description=\"Total depth\">\n\
+             ##FORMAT=<ID=GT,Number=1,Type=String,Description=\"Genotype\">\n\
+             ##reference=GRCh38\n\
+             #CHROM\tPOS\tID\tREF\tALT\tQUAL\tFILTER\tINFO\tFORMAT\tNA1\n\
+             chr1\t5\t.\tA\tC\t.\tPASS\tDP=3\tGT\t0/1\n\
+             ##INFO=<ID=LATE,Number=0,Type=Flag,Description=\"Not part of the header\">\n",
+        )
+        .unwrap();
+
+        let header = read_header(path).unwrap();
+
+        assert_eq!(header.fileformat.as_deref(), Some("VCFv4.3"));
+        assert_eq!(
+            header.contigs,
+            vec![
+                ContigDefinition {
+                    name: "chr1".to_string(),
+                    length: Some(248956422)
+                },
+                ContigDefinition {
+                    name: "chrUn".to_string(),
+                    length: None
+                }
+            ]
+        );
+        assert_eq!(header.info.len(), 1);
+        assert_eq!(header.info[0].kind.as_deref(), Some("Integer"));
+        assert_eq!(header.format[0].description.as_deref(), Some("Genotype"));
+        assert_eq!(header.samples, vec!["NA1"]);
+    }
+}
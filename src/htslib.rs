@@ -0,0 +1,199 @@
+//! Reading inputs with [htslib](https://github.com/samtools/htslib), enabled by the `htslib` feature.
+//!
+//! htslib reads BCF files as well as VCF files, and parses them exactly like `bcftools` and the
+//! other samtools-family tools do. The records are converted back to VCF lines and batched like
+//! those of any other input, so the batches are still written as VCF files.
+
+use std::borrow::Cow;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::ptr;
+
+use rust_htslib::bcf::{self, Read};
+use rust_htslib::htslib;
+
+use crate::lock::OutputLock;
+use crate::writer::BatchWriter;
+use crate::{
+    manifest, naming, BatchOptions, BatchOutput, Destination, Instant, Progress, RunReport, Stage,
+    VcfBatcherError, PROGRESS_INTERVAL,
+};
+
+/// Same as [`crate::extract_variants_to_batches_with_options`], but the input is read with htslib,
+/// so it can also be a BCF file. Only the number of records is reported as progress.
+pub fn extract_variants_with_htslib(
+    file_path: &str,
+    output_path: &Path,
+    options: &BatchOptions,
+) -> Result<RunReport, VcfBatcherError> {
+    let read_error = |source| VcfBatcherError::Read {
+        path: PathBuf::from(file_path),
+        source,
+    };
+    let htslib_error = |error: rust_htslib::errors::Error| {
+        read_error(io::Error::new(io::ErrorKind::InvalidData, error))
+    };
+
+    let _lock = OutputLock::acquire(output_path)?;
+    let mut reader = bcf::Reader::from_path(file_path).map_err(htslib_error)?;
+    let fingerprint = manifest::run_fingerprint(file_path, options).map_err(read_error)?;
+    let output = BatchOutput::new(
+        naming::input_stem(Path::new(file_path)),
+        Destination::Directory(Cow::Borrowed(output_path)),
+        Cow::Borrowed(options),
+        Some(fingerprint),
+    )?;
+    let mut writer = BatchWriter::new(file_path.to_string(), output);
+    let run_started = Instant::now();
+
+    let mut text = KString::default();
+    // SAFETY: the header belongs to the reader, which outlives the call
+    let status = unsafe { htslib::bcf_hdr_format(reader.header().inner, 0, &mut text.0) };
+    if status != 0 {
+        return Err(read_error(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "the header cannot be formatted as VCF",
+        )));
+    }
+    for line in text.to_str().map_err(read_error)?.lines() {
+        writer.write_line(line)?;
+    }
+
+    let mut record = reader.empty_record();
+    let mut records = 0;
+    loop {
+        let started = writer.stage_start();
+        match reader.read(&mut record) {
+            None => break,
+            Some(result) => result.map_err(htslib_error)?,
+        }
+        text.clear();
+        // SAFETY: the record was read with the header of the reader
+        let status =
+            unsafe { htslib::vcf_format(reader.header().inner, record.inner, &mut text.0) };
+        if status != 0 {
+            return Err(read_error(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("record {} cannot be formatted as VCF", records + 1),
+            )));
+        }
+        writer.stage_end(Stage::Reading, started);
+        writer.write_line(text.to_str().map_err(read_error)?)?;
+
+        records += 1;
+        if records % PROGRESS_INTERVAL == 0 {
+            if let Some(on_progress) = &options.on_progress {
+                on_progress.call(&Progress {
+                    bytes_read: 0,
+                    total_bytes: None,
+                    records: writer.records_read(),
+                    batches: writer.report().batches.len(),
+                    elapsed: run_started.elapsed(),
+                });
+            }
+        }
+    }
+    writer.finish()
+}
+
+/// A string that htslib allocates and grows, freed when it is dropped.
+struct KString(htslib::kstring_t);
+
+impl Default for KString {
+    fn default() -> Self {
+        KString(htslib::kstring_t {
+            l: 0,
+            m: 0,
+            s: ptr::null_mut(),
+        })
+    }
+}
+
+impl KString {
+    fn clear(&mut self) {
+        self.0.l = 0;
+    }
+
+    fn to_str(&self) -> io::Result<&str> {
+        if self.0.s.is_null() {
+            return Ok("");
+        }
+        // SAFETY: htslib keeps `l` bytes of `s` initialized
+        let bytes = unsafe { std::slice::from_raw_parts(self.0.s as *const u8, self.0.l as usize) };
+        std::str::from_utf8(bytes)
+            .map_err(|error| io::Error::new(io::ErrorKind::InvalidData, error))
+    }
+}
+
+impl Drop for KString {
+    fn drop(&mut self) {
+        // SAFETY: the buffer was allocated by htslib
+        unsafe { htslib::hts_free(self.0.s as *mut std::ffi::c_void) };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use bgzip::Compression;
+
+    use super::extract_variants_with_htslib;
+    use crate::{extract_variants_to_batches_with_options, read_lines, BatchOptions, OutputFormat};
+
+    #[test]
+    fn test_extract_variants_with_htslib() {
+        let output_path = Path::new("./test_data/htslib");
+        let options = BatchOptions {
+            batch_size: 30,
+            ..Default::default()
+        };
+
+        let report =
+            extract_variants_with_htslib("./test_data/batch_01.vcf.gz", output_path, &options)
+                .unwrap();
+
+        assert_eq!(report.batches.len(), 4);
+        assert_eq!(report.total_records(), 100);
+        let batch: Vec<String> = read_lines(&report.batches[0].path)
+            .unwrap()
+            .map(Result::unwrap)
+            .collect();
+        assert!(batch[0].starts_with("##fileformat=VCF"));
+        assert!(batch.iter().any(|line| line.starts_with("#CHROM")));
+    }
+
+    #[test]
+    fn test_bcf_batches() {
+        let output_path = Path::new("./test_data/htslib_bcf");
+        let _ = std::fs::remove_dir_all(output_path);
+        let options = BatchOptions {
+            batch_size: 30,
+            compression_level: Some(Compression::fast()),
+            format: OutputFormat::Bcf,
+            quiet: true,
+            ..Default::default()
+        };
+
+        let report = extract_variants_to_batches_with_options(
+            "./test_data/batch_01.vcf.gz",
+            output_path,
+            &options,
+        )
+        .unwrap();
+        assert_eq!(report.batches[0].path, output_path.join("batch_01.bcf"));
+
+        // BCF inputs are read with htslib
+        let read_back = Path::new("./test_data/htslib_bcf_read");
+        let report = extract_variants_to_batches_with_options(
+            &report.batches[0].path.to_string_lossy(),
+            read_back,
+            &BatchOptions {
+                quiet: true,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        assert_eq!(report.total_records(), 30);
+    }
+}
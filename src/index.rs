@@ -0,0 +1,586 @@
+//! Reading where the contigs of a bgzipped VCF file start and end from its tabix (`.tbi`) or CSI (`.csi`)
+//! index, so that they can be read independently, see [`crate::parallel`], and writing tabix indexes of
+//! batches, see [`crate::BatchOptions::index`].
+
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::path::{Path, PathBuf};
+
+use bgzip::deflate::Decompress;
+use bgzip::read::{decompress_block, load_block};
+use bgzip::write::BGZFWriter;
+use bgzip::{BGZFError, BGZFReader, Compression};
+
+/// The depth of the binning scheme of tabix indexes, which CSI indexes store instead.
+const TABIX_DEPTH: u32 = 5;
+/// The size of the windows of the linear index of tabix indexes, as a power of two.
+const TABIX_MIN_SHIFT: u32 = 14;
+
+/// Where the records of a contig are in a bgzipped file, as BGZF virtual offsets: the offset of the
+/// compressed block shifted left by 16 bits, plus the position within the uncompressed block.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ContigSpan {
+    /// The name of the contig, as in the CHROM column.
+    pub name: String,
+    /// The virtual offset of the first record of the contig.
+    pub start: u64,
+    /// The virtual offset after the last record of the contig.
+    pub end: u64,
+}
+
+impl ContigSpan {
+    /// Roughly how many bytes of the compressed file the contig takes up.
+    pub fn compressed_bytes(&self) -> u64 {
+        (self.end >> 16).saturating_sub(self.start >> 16)
+    }
+}
+
+/// Returns the index next to a bgzipped file, `<file>.tbi` or else `<file>.csi`, if there is one.
+pub fn find_index(file_path: &Path) -> Option<PathBuf> {
+    ["tbi", "csi"].into_iter().find_map(|extension| {
+        let mut index_path = file_path.as_os_str().to_owned();
+        index_path.push(".");
+        index_path.push(extension);
+        let index_path = PathBuf::from(index_path);
+        index_path.is_file().then_some(index_path)
+    })
+}
+
+/// Returns the path of the tabix index of a bgzipped file, `<file>.tbi`.
+///
+/// # Examples
+///
+/// ```
+/// use std::path::Path;
+/// use vcf_batcher::index::tabix_path;
+///
+/// assert_eq!(tabix_path(Path::new("out/batch_01.vcf.gz")), Path::new("out/batch_01.vcf.gz.tbi"));
+/// ```
+pub fn tabix_path(file_path: &Path) -> PathBuf {
+    let mut index_path = file_path.as_os_str().to_owned();
+    index_path.push(".tbi");
+    PathBuf::from(index_path)
+}
+
+/// Builds the tabix index of a bgzipped VCF file, as `tabix -p vcf` does, and returns it bgzipped as it is
+/// stored in the `.tbi` file. The records of a contig must follow each other and be sorted by position, and
+/// no record may end after 2^29, the largest position that tabix indexes can hold.
+///
+/// # Examples
+///
+/// ```
+/// use std::path::Path;
+/// use vcf_batcher::index::{read_contig_spans, tabix_index, tabix_path};
+///
+/// let index_path = tabix_path(Path::new("test_data/inputs/doc_tabix.vcf.gz"));
+/// std::fs::create_dir_all("test_data/inputs").unwrap();
+/// std::fs::write(&index_path, tabix_index(Path::new("test_data/batch_01.vcf.gz")).unwrap()).unwrap();
+/// let spans = read_contig_spans(&index_path).unwrap();
+/// assert_eq!(spans.len(), 1);
+/// assert_eq!(spans[0].name, "22");
+/// ```
+pub fn tabix_index(file_path: &Path) -> io::Result<Vec<u8>> {
+    let invalid = |error: BGZFError| io::Error::new(io::ErrorKind::InvalidData, error);
+    let mut reader = BufReader::new(File::open(file_path)?);
+    let mut index = TabixBuilder::default();
+    let mut decompress = Decompress::new();
+    let (mut block, mut contents, mut line) = (Vec::new(), Vec::new(), Vec::new());
+    // The blocks are read one by one because `BGZFReader::bgzf_pos` misses the header of the first block
+    let mut block_offset = 0;
+    let mut line_start = None;
+    while !reader.fill_buf()?.is_empty() {
+        let block_header = load_block(&mut reader, &mut block).map_err(invalid)?;
+        let next_block = block_offset + block_header.header_size() + block.len() as u64;
+        contents.clear();
+        decompress_block(&mut contents, &block, &mut decompress).map_err(invalid)?;
+        let mut position = 0;
+        while position < contents.len() {
+            line_start.get_or_insert(block_offset << 16 | position as u64);
+            let Some(length) = contents[position..].iter().position(|byte| *byte == b'\n') else {
+                line.extend_from_slice(&contents[position..]);
+                break;
+            };
+            line.extend_from_slice(&contents[position..=position + length]);
+            position += length + 1;
+            // A line that ends with its block ends where the next block starts, as htslib counts it
+            let end = match position == contents.len() {
+                true => next_block << 16,
+                false => block_offset << 16 | position as u64,
+            };
+            if let Some(start) = line_start.take() {
+                index.push_line(&line, start, end)?;
+            }
+            line.clear();
+        }
+        block_offset = next_block;
+    }
+    if let Some(start) = line_start {
+        index.push_line(&line, start, block_offset << 16)?;
+    }
+    index.finish()
+}
+
+/// Collects the bins, the linear index and the statistics of every contig of a tabix index.
+#[derive(Default)]
+struct TabixBuilder {
+    names: Vec<String>,
+    contigs: Vec<TabixContig>,
+}
+
+struct TabixContig {
+    /// The chunks of every bin, as virtual offsets of their start and end.
+    bins: BTreeMap<u32, Vec<(u64, u64)>>,
+    /// The virtual offset of the first record that overlaps each window of 2^14 positions.
+    linear: Vec<Option<u64>>,
+    first_offset: u64,
+    end_offset: u64,
+    records: u64,
+    last_start: u64,
+}
+
+impl TabixBuilder {
+    /// Adds a line that starts at the virtual offset `start` and ends before `end`, unless it is a header line.
+    fn push_line(&mut self, line: &[u8], start: u64, end: u64) -> io::Result<()> {
+        if line.starts_with(b"#") || line.iter().all(u8::is_ascii_whitespace) {
+            return Ok(());
+        }
+        self.push(&String::from_utf8_lossy(line), start, end)
+    }
+
+    /// Adds the record `line`, which starts at the virtual offset `start` and ends before `end`.
+    fn push(&mut self, line: &str, start: u64, end: u64) -> io::Result<()> {
+        let line = line.trim_end();
+        let mut columns = line.split('\t');
+        let chrom = columns.next().unwrap_or_default();
+        let pos: u64 = columns
+            .next()
+            .and_then(|pos| pos.parse().ok())
+            .ok_or_else(|| invalid_record(format!("record on {chrom} has no valid POS")))?;
+        let reference = columns.nth(1).unwrap_or_default();
+        // Tabix uses 0-based, half-open intervals that also cover the END of symbolic alleles
+        let begin = pos.saturating_sub(1);
+        let info_end = crate::header::info_value(line, "END").and_then(|end| end.parse().ok());
+        let stop = (begin + reference.len().max(1) as u64).max(info_end.unwrap_or(0));
+        if stop > 1 << 29 {
+            return Err(invalid_record(format!(
+                "{chrom}:{pos} lies beyond the largest position that tabix indexes can hold"
+            )));
+        }
+
+        if self.names.last().map(String::as_str) != Some(chrom) {
+            if self.names.iter().any(|name| name == chrom) {
+                return Err(invalid_record(format!(
+                    "the records of {chrom} do not follow each other"
+                )));
+            }
+            self.names.push(chrom.to_string());
+            self.contigs.push(TabixContig {
+                bins: BTreeMap::new(),
+                linear: Vec::new(),
+                first_offset: start,
+                end_offset: end,
+                records: 0,
+                last_start: 0,
+            });
+        }
+        let contig = self
+            .contigs
+            .last_mut()
+            .expect("a contig was added for the record");
+        if begin < contig.last_start {
+            return Err(invalid_record(format!(
+                "the records of {chrom} are not sorted by position at {pos}"
+            )));
+        }
+        contig.last_start = begin;
+
+        let bin = bgzip::tabix::reg2bin(begin as u32, stop as u32);
+        let chunks = contig.bins.entry(bin).or_default();
+        match chunks.last_mut() {
+            // Records that follow each other in the file extend the same chunk
+            Some(chunk) if chunk.1 == start => chunk.1 = end,
+            _ => chunks.push((start, end)),
+        }
+        let last_window = ((stop - 1) >> TABIX_MIN_SHIFT) as usize;
+        if contig.linear.len() <= last_window {
+            contig.linear.resize(last_window + 1, None);
+        }
+        for window in &mut contig.linear[(begin >> TABIX_MIN_SHIFT) as usize..=last_window] {
+            window.get_or_insert(start);
+        }
+        contig.end_offset = end;
+        contig.records += 1;
+        Ok(())
+    }
+
+    /// Returns the bgzipped index.
+    fn finish(self) -> io::Result<Vec<u8>> {
+        let mut index = b"TBI\x01".to_vec();
+        let names: Vec<u8> = self
+            .names
+            .iter()
+            .flat_map(|name| name.bytes().chain([0]))
+            .collect();
+        // n_ref, format (VCF), col_seq, col_beg, col_end, meta ('#'), skip, l_nm
+        for field in [self.names.len(), 2, 1, 2, 0, 35, 0, names.len()] {
+            index.extend(count(field)?.to_le_bytes());
+        }
+        index.extend(names);
+        for contig in self.contigs {
+            index.extend(count(contig.bins.len() + 1)?.to_le_bytes());
+            for (bin, chunks) in &contig.bins {
+                index.extend(bin.to_le_bytes());
+                index.extend(count(chunks.len())?.to_le_bytes());
+                for (start, end) in chunks {
+                    index.extend(start.to_le_bytes());
+                    index.extend(end.to_le_bytes());
+                }
+            }
+            // The pseudo-bin holds where the contig starts and ends and how many records it has
+            index.extend(pseudo_bin(TABIX_DEPTH).to_le_bytes());
+            index.extend(2i32.to_le_bytes());
+            for field in [contig.first_offset, contig.end_offset, contig.records, 0] {
+                index.extend(field.to_le_bytes());
+            }
+            // Windows without records point to the next record, as the windows after them do
+            let mut next = contig.end_offset;
+            let mut linear = vec![0; contig.linear.len()];
+            for (offset, window) in linear.iter_mut().zip(&contig.linear).rev() {
+                next = window.unwrap_or(next);
+                *offset = next;
+            }
+            index.extend(count(linear.len())?.to_le_bytes());
+            for offset in linear {
+                index.extend(offset.to_le_bytes());
+            }
+        }
+        // The number of records without a position
+        index.extend(0u64.to_le_bytes());
+
+        let mut compressed = Vec::new();
+        let mut writer = BGZFWriter::new(&mut compressed, Compression::default());
+        writer.write_all(&index)?;
+        writer.close()?;
+        Ok(compressed)
+    }
+}
+
+fn count(count: usize) -> io::Result<i32> {
+    i32::try_from(count).map_err(|_| io::Error::other("the index is too large for tabix"))
+}
+
+fn invalid_record(reason: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, reason)
+}
+
+/// Reads the contigs that have records from a tabix or CSI index, in the order that they appear in
+/// the indexed file. CSI indexes of BCF files name no contigs and are rejected.
+pub fn read_contig_spans(index_path: &Path) -> io::Result<Vec<ContigSpan>> {
+    let file = std::fs::File::open(index_path)?;
+    let mut contents = Vec::new();
+    BGZFReader::new(file)
+        .map_err(BGZFError::into_io_error)?
+        .read_to_end(&mut contents)?;
+    parse_index(&contents)
+}
+
+fn parse_index(contents: &[u8]) -> io::Result<Vec<ContigSpan>> {
+    let mut index = IndexReader(contents);
+    let (names, reference_count, pseudo_bin, csi) = match index.take(4)? {
+        b"TBI\x01" => {
+            let reference_count = index.count()?;
+            let names = index.tabix_names()?;
+            (names, reference_count, pseudo_bin(TABIX_DEPTH), false)
+        }
+        b"CSI\x01" => {
+            let _min_shift = index.i32()?;
+            let depth = index.count()?;
+            let aux_length = index.count()?;
+            let mut aux = IndexReader(index.take(aux_length)?);
+            let names = match aux_length {
+                0 => return Err(invalid_index("the CSI index names no contigs")),
+                _ => aux.tabix_names()?,
+            };
+            let depth = u32::try_from(depth)
+                .ok()
+                .filter(|depth| *depth <= 9)
+                .ok_or_else(|| invalid_index("the CSI index is too deep"))?;
+            (names, index.count()?, pseudo_bin(depth), true)
+        }
+        _ => return Err(invalid_index("not a tabix or CSI index")),
+    };
+    if names.len() != reference_count {
+        return Err(invalid_index("the number of contig names does not match"));
+    }
+
+    let mut spans = Vec::new();
+    for name in names {
+        let mut span: Option<(u64, u64)> = None;
+        for _ in 0..index.count()? {
+            let bin = index.u32()?;
+            if csi {
+                let _first_offset = index.u64()?;
+            }
+            for _ in 0..index.count()? {
+                let (begin, end) = (index.u64()?, index.u64()?);
+                // The pseudo-bin holds statistics about the contig instead of chunks
+                if bin == pseudo_bin {
+                    continue;
+                }
+                span = Some(span.map_or((begin, end), |(start, stop)| {
+                    (start.min(begin), stop.max(end))
+                }));
+            }
+        }
+        if !csi {
+            let interval_count = index.count()?;
+            index.take(interval_count * 8)?;
+        }
+        if let Some((start, end)) = span {
+            spans.push(ContigSpan { name, start, end });
+        }
+    }
+    spans.sort_by_key(|span| span.start);
+    Ok(spans)
+}
+
+/// The bin after the `((8^(depth+1)) - 1) / 7` regular bins of an index.
+fn pseudo_bin(depth: u32) -> u32 {
+    ((1 << (3 * depth + 3)) - 1) / 7 + 1
+}
+
+fn invalid_index(reason: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, reason)
+}
+
+/// Reads the little-endian fields of an index.
+struct IndexReader<'a>(&'a [u8]);
+
+impl<'a> IndexReader<'a> {
+    fn take(&mut self, length: usize) -> io::Result<&'a [u8]> {
+        if self.0.len() < length {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "the index ends early",
+            ));
+        }
+        let (taken, rest) = self.0.split_at(length);
+        self.0 = rest;
+        Ok(taken)
+    }
+
+    fn array<const N: usize>(&mut self) -> io::Result<[u8; N]> {
+        let mut array = [0; N];
+        array.copy_from_slice(self.take(N)?);
+        Ok(array)
+    }
+
+    fn i32(&mut self) -> io::Result<i32> {
+        Ok(i32::from_le_bytes(self.array()?))
+    }
+
+    fn u32(&mut self) -> io::Result<u32> {
+        Ok(u32::from_le_bytes(self.array()?))
+    }
+
+    fn u64(&mut self) -> io::Result<u64> {
+        Ok(u64::from_le_bytes(self.array()?))
+    }
+
+    /// A number of items, which must not be negative.
+    fn count(&mut self) -> io::Result<usize> {
+        usize::try_from(self.i32()?).map_err(|_| invalid_index("negative count"))
+    }
+
+    /// Skips the format and the columns of a tabix header and reads its NUL-terminated contig names.
+    fn tabix_names(&mut self) -> io::Result<Vec<String>> {
+        // format, col_seq, col_beg, col_end, meta and skip
+        self.take(6 * 4)?;
+        let names_length = self.count()?;
+        Ok(self
+            .take(names_length)?
+            .split(|byte| *byte == 0)
+            .filter(|name| !name.is_empty())
+            .map(|name| String::from_utf8_lossy(name).into_owned())
+            .collect())
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use std::io::{BufRead, Write};
+    use std::path::Path;
+
+    use bgzip::deflate::Compress;
+    use bgzip::tabix::{reg2bins, Tabix};
+    use bgzip::write::{write_block, BGZFWriter};
+    use bgzip::{BGZFReader, Compression};
+
+    use super::{find_index, read_contig_spans, tabix_index, tabix_path, ContigSpan};
+
+    /// Writes a bgzipped VCF file with the given records per contig and a tabix index for it, with
+    /// one chunk per contig. Every contig is compressed into a block of its own.
+    pub(crate) fn write_indexed_vcf(path: &Path, headers: &str, contigs: &[(&str, Vec<String>)]) {
+        let mut compress = Compress::new(Compression::fast());
+        let mut contents = Vec::new();
+        write_block(&mut contents, headers.as_bytes(), &mut compress).unwrap();
+        let mut spans = Vec::new();
+        for (name, records) in contigs {
+            let start = (contents.len() as u64) << 16;
+            let records: String = records.iter().map(|record| record.clone() + "\n").collect();
+            write_block(&mut contents, records.as_bytes(), &mut compress).unwrap();
+            spans.push(ContigSpan {
+                name: name.to_string(),
+                start,
+                end: (contents.len() as u64) << 16,
+            });
+        }
+        // An empty writer only adds the end-of-file marker
+        BGZFWriter::new(&mut contents, Compression::fast())
+            .close()
+            .unwrap();
+        std::fs::write(path, contents).unwrap();
+
+        let mut index = b"TBI\x01".to_vec();
+        let names: Vec<u8> = spans
+            .iter()
+            .flat_map(|span| span.name.bytes().chain([0]))
+            .collect();
+        // n_ref, format (VCF), col_seq, col_beg, col_end, meta ('#'), skip, l_nm
+        for field in [spans.len() as i32, 2, 1, 2, 0, 35, 0, names.len() as i32] {
+            index.extend(field.to_le_bytes());
+        }
+        index.extend(names);
+        for span in &spans {
+            // One bin with one chunk, the pseudo-bin with its two statistics "chunks", no linear index
+            index.extend(2i32.to_le_bytes());
+            index.extend(4681u32.to_le_bytes());
+            index.extend(1i32.to_le_bytes());
+            index.extend(span.start.to_le_bytes());
+            index.extend(span.end.to_le_bytes());
+            index.extend(37450u32.to_le_bytes());
+            index.extend(2i32.to_le_bytes());
+            index.extend([0u8; 32]);
+            index.extend(0i32.to_le_bytes());
+        }
+        let mut index_path = path.as_os_str().to_owned();
+        index_path.push(".tbi");
+        let mut writer = BGZFWriter::new(
+            std::fs::File::create(index_path).unwrap(),
+            Compression::fast(),
+        );
+        writer.write_all(&index).unwrap();
+        writer.close().unwrap();
+    }
+
+    #[test]
+    fn test_read_contig_spans() {
+        let input_dir = Path::new("./test_data/inputs");
+        std::fs::create_dir_all(input_dir).unwrap();
+        let path = input_dir.join("indexed.vcf.gz");
+        let record = |chrom: &str, pos: usize| format!("{chrom}\t{pos}\t.\tA\tG\t.\tPASS\t.");
+        write_indexed_vcf(
+            &path,
+            "##fileformat=VCFv4.2\n#CHROM\tPOS\tID\tREF\tALT\tQUAL\tFILTER\tINFO\n",
+            &[
+                ("chr1", vec![record("chr1", 1), record("chr1", 2)]),
+                ("chr2", vec![record("chr2", 1)]),
+            ],
+        );
+
+        let index_path = find_index(&path).unwrap();
+        assert_eq!(index_path, input_dir.join("indexed.vcf.gz.tbi"));
+        let spans = read_contig_spans(&index_path).unwrap();
+        let names: Vec<&str> = spans.iter().map(|span| span.name.as_str()).collect();
+        assert_eq!(names, ["chr1", "chr2"]);
+        assert_eq!(spans[0].end, spans[1].start);
+        assert!(spans[0].compressed_bytes() > 0);
+        assert!(find_index(Path::new("test_data/batch_01.vcf.gz")).is_none());
+    }
+
+    fn write_bgzipped(path: &Path, contents: &str) {
+        let mut writer = BGZFWriter::new(std::fs::File::create(path).unwrap(), Compression::fast());
+        writer.write_all(contents.as_bytes()).unwrap();
+        writer.close().unwrap();
+    }
+
+    #[test]
+    fn test_tabix_index() {
+        let input_dir = Path::new("./test_data/inputs");
+        std::fs::create_dir_all(input_dir).unwrap();
+        let path = input_dir.join("tabix.vcf.gz");
+        let mut contents =
+            "##fileformat=VCFv4.2\n#CHROM\tPOS\tID\tREF\tALT\tQUAL\tFILTER\tINFO\n".to_string();
+        for chrom in ["chr1", "chr2"] {
+            for i in 0..3000 {
+                // A deletion that reaches into the queried region from far before it
+                let info = if i == 1000 { "END=160000" } else { "." };
+                contents += &format!("{chrom}\t{}\t.\tA\t<DEL>\t.\tPASS\t{info}\n", i * 100 + 1);
+            }
+        }
+        write_bgzipped(&path, &contents);
+
+        let index = tabix_index(&path).unwrap();
+        std::fs::write(tabix_path(&path), &index).unwrap();
+        let spans = read_contig_spans(&tabix_path(&path)).unwrap();
+        let names: Vec<&str> = spans.iter().map(|span| span.name.as_str()).collect();
+        assert_eq!(names, ["chr1", "chr2"]);
+
+        // Query chr1:150001-150500 as tabix would, through the bins and the linear index
+        let tabix = Tabix::from_reader(index.as_slice()).unwrap();
+        let chr1 = &tabix.sequences[0];
+        let min_offset = chr1.intervals[150_000 >> 14];
+        let mut reader = BGZFReader::new(std::fs::File::open(&path).unwrap()).unwrap();
+        let mut found = Vec::new();
+        for bin in reg2bins(150_000, 150_500) {
+            for chunk in chr1.bins.get(&bin).map_or(&[][..], |bin| &bin.chunks) {
+                if chunk.end <= min_offset {
+                    continue;
+                }
+                // Reads on from the start of the chunk until the records start after the region
+                reader.bgzf_seek(chunk.begin).unwrap();
+                for line in (&mut reader).lines() {
+                    let line = line.unwrap();
+                    let pos: u64 = line.split('\t').nth(1).unwrap().parse().unwrap();
+                    if !line.starts_with("chr1\t") || pos > 150_500 {
+                        break;
+                    }
+                    let end = crate::header::info_value(&line, "END")
+                        .map_or(pos, |end| end.parse().unwrap());
+                    if end > 150_000 {
+                        found.push(pos);
+                    }
+                }
+            }
+        }
+        found.sort();
+        found.dedup();
+        assert_eq!(
+            found,
+            [100_001, 150_001, 150_101, 150_201, 150_301, 150_401]
+        );
+    }
+
+    #[test]
+    fn test_tabix_index_of_unsorted_file() {
+        let input_dir = Path::new("./test_data/inputs");
+        std::fs::create_dir_all(input_dir).unwrap();
+        let path = input_dir.join("tabix_unsorted.vcf.gz");
+        let record = |chrom: &str, pos: usize| format!("{chrom}\t{pos}\t.\tA\tG\t.\tPASS\t.\n");
+
+        write_bgzipped(&path, &(record("chr1", 20) + &record("chr1", 10)));
+        let error = tabix_index(&path).unwrap_err();
+        assert!(error.to_string().contains("not sorted"), "{error}");
+
+        write_bgzipped(
+            &path,
+            &(record("chr1", 10) + &record("chr2", 10) + &record("chr1", 20)),
+        );
+        let error = tabix_index(&path).unwrap_err();
+        assert!(
+            error.to_string().contains("do not follow each other"),
+            "{error}"
+        );
+    }
+}
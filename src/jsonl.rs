@@ -0,0 +1,214 @@
+//! Converting variant records to JSON Lines, one JSON object per record, for services that do not read VCF.
+
+use std::path::{Path, PathBuf};
+
+use serde_json::Value;
+
+use crate::JsonField;
+
+impl JsonField {
+    /// All fields, in the order in which they are written.
+    pub const ALL: [JsonField; 9] = [
+        JsonField::Chrom,
+        JsonField::Pos,
+        JsonField::Id,
+        JsonField::Ref,
+        JsonField::Alt,
+        JsonField::Qual,
+        JsonField::Filter,
+        JsonField::Info,
+        JsonField::Samples,
+    ];
+
+    /// The key of the field in the JSON objects, the name of the VCF column or `samples`.
+    pub fn key(self) -> &'static str {
+        match self {
+            JsonField::Chrom => "CHROM",
+            JsonField::Pos => "POS",
+            JsonField::Id => "ID",
+            JsonField::Ref => "REF",
+            JsonField::Alt => "ALT",
+            JsonField::Qual => "QUAL",
+            JsonField::Filter => "FILTER",
+            JsonField::Info => "INFO",
+            JsonField::Samples => "samples",
+        }
+    }
+}
+
+/// Replaces the `.vcf` extension of a batch path with `.jsonl`, keeping a `.gz` extension.
+///
+/// # Examples
+///
+/// ```
+/// use std::path::Path;
+/// use vcf_batcher::jsonl::jsonl_path;
+///
+/// assert_eq!(jsonl_path(Path::new("out/batch_01.vcf.gz")), Path::new("out/batch_01.jsonl.gz"));
+/// assert_eq!(jsonl_path(Path::new("chr1.vcf")), Path::new("chr1.jsonl"));
+/// ```
+pub fn jsonl_path(path: &Path) -> PathBuf {
+    let name = path
+        .file_name()
+        .map(|name| name.to_string_lossy())
+        .unwrap_or_default();
+    let (stem, gz) = match name.strip_suffix(".gz") {
+        Some(stem) => (stem, ".gz"),
+        None => (name.as_ref(), ""),
+    };
+    let stem = stem.strip_suffix(".vcf").unwrap_or(stem);
+    path.with_file_name(format!("{stem}.jsonl{gz}"))
+}
+
+/// Converts the variant records of a batch to JSON Lines. The sample names are taken from the
+/// `#CHROM` line of the headers, which are not written otherwise. An empty list of fields writes
+/// all of them.
+///
+/// The fields have these types, with `null` for missing (`.`) values:
+/// - CHROM, ID and REF: strings
+/// - POS and QUAL: numbers
+/// - ALT and FILTER: arrays of strings
+/// - INFO: an object with the value of every key as a string, or `true` for flags
+/// - samples: an object with an object per sample, which maps the FORMAT keys to the values of the sample
+///
+/// # Examples
+///
+/// ```
+/// use vcf_batcher::jsonl::jsonl_batch;
+/// use vcf_batcher::JsonField;
+///
+/// let headers = "##fileformat=VCFv4.2\n#CHROM\tPOS\tID\tREF\tALT\tQUAL\tFILTER\tINFO\tFORMAT\tNA1\n";
+/// let records = "chr1\t5\trs1\tA\tC,G\t30\tPASS\tAF=0.5;DB\tGT:DP\t0/1:12\n";
+/// assert_eq!(
+///     jsonl_batch(headers, records, &[]),
+///     "{\"CHROM\":\"chr1\",\"POS\":5,\"ID\":\"rs1\",\"REF\":\"A\",\"ALT\":[\"C\",\"G\"],\"QUAL\":30.0,\
+///      \"FILTER\":[\"PASS\"],\"INFO\":{\"AF\":\"0.5\",\"DB\":true},\"samples\":{\"NA1\":{\"GT\":\"0/1\",\"DP\":\"12\"}}}\n"
+/// );
+/// assert_eq!(
+///     jsonl_batch(headers, records, &[JsonField::Chrom, JsonField::Pos]),
+///     "{\"CHROM\":\"chr1\",\"POS\":5}\n"
+/// );
+/// ```
+pub fn jsonl_batch(headers: &str, records: &str, fields: &[JsonField]) -> String {
+    let fields = match fields.is_empty() {
+        true => &JsonField::ALL[..],
+        false => fields,
+    };
+    let samples: Vec<&str> = headers
+        .lines()
+        .find(|line| line.starts_with("#CHROM"))
+        .map(|line| line.split('\t').skip(9).collect())
+        .unwrap_or_default();
+
+    let mut output = String::with_capacity(records.len() * 2);
+    for record in records.lines() {
+        let columns: Vec<&str> = record.split('\t').collect();
+        let column = |index: usize| columns.get(index).copied().unwrap_or(".");
+        let entries = fields.iter().map(|&field| {
+            let value = match field {
+                JsonField::Chrom => string(column(0)),
+                JsonField::Pos => match column(1).parse::<u64>() {
+                    Ok(pos) => Value::from(pos).to_string(),
+                    Err(_) => string(column(1)),
+                },
+                JsonField::Id => match column(2) {
+                    "." => "null".to_string(),
+                    value => string(value),
+                },
+                JsonField::Ref => string(column(3)),
+                JsonField::Alt => list(column(4), ','),
+                JsonField::Qual => column(5)
+                    .parse::<f64>()
+                    .ok()
+                    .map_or(Value::Null, Value::from)
+                    .to_string(),
+                JsonField::Filter => list(column(6), ';'),
+                JsonField::Info => info(column(7)),
+                JsonField::Samples => {
+                    let keys: Vec<&str> = column(8).split(':').collect();
+                    object(samples.iter().enumerate().map(|(index, name)| {
+                        let values = column(9 + index).split(':');
+                        (*name, object(keys.iter().copied().zip(values.map(string))))
+                    }))
+                }
+            };
+            (field.key(), value)
+        });
+        output.push_str(&object(entries));
+        output.push('\n');
+    }
+    output
+}
+
+/// Encodes a string as JSON.
+fn string(value: &str) -> String {
+    Value::from(value).to_string()
+}
+
+/// Encodes a list such as ALT or FILTER as an array, or `null` if it is missing.
+fn list(value: &str, separator: char) -> String {
+    match value {
+        "." => "null".to_string(),
+        value => Value::from(value.split(separator).collect::<Vec<_>>()).to_string(),
+    }
+}
+
+/// Encodes an INFO column as an object, with `true` for flags.
+fn info(value: &str) -> String {
+    if value == "." {
+        return "{}".to_string();
+    }
+    object(value.split(';').map(|entry| match entry.split_once('=') {
+        Some((key, value)) => (key, string(value)),
+        None => (entry, "true".to_string()),
+    }))
+}
+
+/// Writes an object from keys and values that are already encoded, keeping their order.
+fn object<'a>(entries: impl IntoIterator<Item = (&'a str, String)>) -> String {
+    let mut object = String::from("{");
+    for (index, (key, value)) in entries.into_iter().enumerate() {
+        if index > 0 {
+            object.push(',');
+        }
+        object.push_str(&string(key));
+        object.push(':');
+        object.push_str(&value);
+    }
+    object.push('}');
+    object
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::{json, Value};
+
+    use super::jsonl_batch;
+    use crate::JsonField;
+
+    #[test]
+    fn test_jsonl_batch_missing_values() {
+        let headers = "#CHROM\tPOS\tID\tREF\tALT\tQUAL\tFILTER\tINFO\tFORMAT\tNA1\tNA2\n";
+        let records = "chr2\t9\t.\tG\t.\t.\t.\t.\tGT:DP\t./.\t1/1:3\n";
+
+        let lines = jsonl_batch(headers, records, &[]);
+        let record: Value = serde_json::from_str(lines.trim_end()).unwrap();
+        assert_eq!(
+            record,
+            json!({
+                "CHROM": "chr2",
+                "POS": 9,
+                "ID": null,
+                "REF": "G",
+                "ALT": null,
+                "QUAL": null,
+                "FILTER": null,
+                "INFO": {},
+                "samples": {"NA1": {"GT": "./."}, "NA2": {"GT": "1/1", "DP": "3"}},
+            })
+        );
+
+        let lines = jsonl_batch(headers, records, &[JsonField::Samples, JsonField::Chrom]);
+        assert!(lines.starts_with("{\"samples\":"));
+    }
+}
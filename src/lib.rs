@@ -1,15 +1,115 @@
 //! # VCF Batcher
-//! A library for converting large VCF files into batches of smaller VCF files containing a fixed number of samples.
+//! A library for converting large VCF files into batches of smaller VCF files containing a fixed number of records.
 //! Can also be used as a command line tool.
+//!
+//! Errors are returned as [`VcfBatcherError`] instead of panicking, so that the library can be embedded, e.g. in
+//! the Python bindings. Outside of tests, `unwrap` and `panic!` are rejected by clippy; `expect` is reserved for
+//! invariants that the code upholds, with a message that states the invariant.
+#![cfg_attr(not(test), deny(clippy::unwrap_used, clippy::panic))]
 
+use std::borrow::Cow;
+use std::collections::HashMap;
 use std::fs;
 use std::fs::File;
-use std::io::{self, BufRead, BufReader, Write};
-use std::path::Path;
+use std::io::{self, BufRead, BufReader, Read, Seek, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
 
-use self::ReaderLines::{UnzippedLines, ZippedLines};
-use bgzip::{write::BGZFMultiThreadWriter, BGZFError, BGZFReader, Compression};
-use pyo3::prelude::*;
+#[cfg(not(target_arch = "wasm32"))]
+use self::ReaderLines::ZstdLines;
+use self::ReaderLines::{GzippedLines, UnzippedLines, ZippedLines};
+use bgzip::{write::BGZFMultiThreadWriter, BGZFError, BGZFReader};
+use flate2::bufread::MultiGzDecoder;
+
+mod append;
+mod batcher;
+pub mod bcf;
+pub mod bed;
+#[cfg(feature = "catalog")]
+pub mod catalog;
+pub mod cohort;
+pub mod config;
+#[cfg(feature = "polars")]
+pub mod dataframe;
+mod error;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+pub mod filter;
+pub mod genes;
+pub mod header;
+#[cfg(feature = "htslib")]
+pub mod htslib;
+mod http;
+pub mod index;
+pub mod jsonl;
+pub mod lines;
+pub mod lock;
+pub mod manifest;
+pub mod mask;
+pub mod merge;
+pub mod metrics;
+pub mod naming;
+mod options;
+pub mod parallel;
+#[cfg(feature = "python")]
+mod python;
+pub mod rebalance;
+pub mod recompress;
+pub mod regions;
+pub mod reheader;
+mod report;
+pub mod run_log;
+mod samples;
+pub mod serve;
+pub mod sink;
+pub mod sites;
+pub mod stats;
+mod stream;
+#[cfg(feature = "otel")]
+pub mod telemetry;
+pub mod testdata;
+pub mod validate;
+pub mod verify;
+pub mod watch;
+pub mod webhook;
+pub mod writer;
+
+pub use batcher::Batcher;
+/// The compression level of bgzipped batches, re-exported so that callers do not need to depend on `bgzip`.
+pub use bgzip::Compression;
+pub use error::VcfBatcherError;
+use lines::{BoundedLines, LineTooLong};
+use lock::OutputLock;
+use manifest::{Journal, Manifest, ManifestEntry};
+use naming::NameValues;
+#[cfg(feature = "parquet")]
+pub use options::ParquetExport;
+pub use options::{
+    parse_batch_sizes, parse_byte_size, AppendMode, BadRecordPolicy, BatchCallback, BatchOptions,
+    CompressionFormat, ContigCheck, DuplicateRecordPolicy, DuplicateSamplePolicy, EmptyInputPolicy,
+    IndexFormat, JsonField, LateHeaderPolicy, OutputFormat, OverwritePolicy, ProgressCallback,
+    SplitMode,
+};
+use report::Stage;
+pub use report::{BatchInfo, Locus, Progress, RunReport, StageTimings};
+pub use sink::BatchSink;
+use stream::BatchFile;
+use writer::BatchWriter;
+
+/// `std::time::Instant` panics in the browser, so the clock of the page is used there instead.
+#[cfg(not(target_arch = "wasm32"))]
+pub(crate) use std::time::Instant;
+#[cfg(target_arch = "wasm32")]
+pub(crate) use web_time::Instant;
+
+/// The first two bytes of gzip and BGZF files.
+const GZIP_MAGIC: &[u8] = &[0x1f, 0x8b];
+
+/// How many records the batches of the dry run have that counts the records for [`BatchOptions::num_batches`].
+const COUNTING_BATCH_SIZE: usize = 10_000;
+
+/// [`BatchOptions::on_progress`] is called after every this many lines.
+const PROGRESS_INTERVAL: usize = 4096;
 
 trait AppendLine {
     fn append_line(&mut self, line: &str) -> &String;
@@ -23,65 +123,168 @@ impl AppendLine for String {
     }
 }
 
-/// Wrapper for the lines of a file.
-/// If the file is bgzipped, the lines are read with a BGZFReader.
-pub enum ReaderLines {
-    UnzippedLines(io::Lines<BufReader<File>>),
-    ZippedLines(io::Lines<BGZFReader<File>>),
+/// Wrapper for the lines of a file, or of any other reader such as stdin or a pipe.
+/// If the input is bgzipped, the lines are read with a BGZFReader. Files compressed with plain
+/// gzip, which have no BGZF blocks, are decompressed as a stream of gzip members instead, and zstd compressed
+/// files such as batches written with [`CompressionFormat::Zstd`] as a stream of zstd frames.
+pub enum ReaderLines<R: Read = File> {
+    UnzippedLines(BoundedLines<BufReader<R>>),
+    ZippedLines(BoundedLines<BGZFReader<BufReader<R>>>),
+    GzippedLines(Box<BoundedLines<BufReader<MultiGzDecoder<BufReader<R>>>>>),
+    #[cfg(not(target_arch = "wasm32"))]
+    ZstdLines(Box<BoundedLines<BufReader<zstd::Decoder<'static, BufReader<R>>>>>),
 }
 
-impl Iterator for ReaderLines {
+impl<R: Read> Iterator for ReaderLines<R> {
     type Item = Result<String, io::Error>;
 
     fn next(&mut self) -> Option<Self::Item> {
         match self {
             UnzippedLines(lines) => lines.next(),
             ZippedLines(lines) => lines.next(),
+            GzippedLines(lines) => lines.next(),
+            #[cfg(not(target_arch = "wasm32"))]
+            ZstdLines(lines) => lines.next(),
         }
     }
 }
 
-/// Saves a batch of variants to a file.
+/// Saves a batch of variants to a file and returns the path of the written file.
+///
+/// If writing fails part way through (e.g. because the disk is full), the partially
+/// written file is removed again, so that only complete batches remain on disk.
 ///
 /// # Examples
 ///
 /// ```
 /// use std::path::Path;
 /// use vcf_batcher::save_batch;
-/// save_batch("Hello, world!".to_string(), &1, Path::new("test_data/temporary"), None);
+/// save_batch("Hello, world!".to_string(), 1, Path::new("test_data/temporary"), None).unwrap();
 /// ```
 pub fn save_batch(
     contents: String,
-    batch_number: &i32,
+    batch_number: usize,
     output_path: &Path,
     compression_level: Option<Compression>,
-) -> Result<(), BGZFError> {
-    fs::create_dir_all(output_path).expect("An error occurred creating the directory");
+) -> Result<PathBuf, BGZFError> {
+    fs::create_dir_all(output_path)?;
+    let vcf_path = output_path.join(batch_file_name(batch_number, compression_level));
+    let bytes = encode_batch(contents.as_bytes(), compression_level, None)?;
+    write_batch_file(&vcf_path, &bytes)?;
+    Ok(vcf_path)
+}
 
-    let mut file_name = format!("batch_{:02}.vcf", batch_number);
+/// Writes the (already compressed) bytes of a batch to the given path, creating missing parent directories.
+fn write_batch_file(vcf_path: &Path, bytes: &[u8]) -> io::Result<()> {
+    if let Some(parent) = vcf_path.parent() {
+        fs::create_dir_all(parent)?;
+    }
 
-    if let Some(..) = compression_level {
-        file_name.push_str(".gz");
-        let vcf_path = output_path.join(file_name);
+    // Open a file in write-only mode, returns `io::Result<File>`
+    let file = File::create(vcf_path)?;
+
+    write_batch_contents(file, bytes).inspect_err(|_| {
+        // A truncated batch would look complete to downstream tools, so it must not stay around
+        let _ = fs::remove_file(vcf_path);
+    })
+}
+
+/// Same as [`write_batch_file`], but the batch is written to `tmp_dir` first, if one is given,
+/// and then moved to `vcf_path`.
+fn write_staged_batch_file(
+    vcf_path: &Path,
+    tmp_dir: Option<&Path>,
+    bytes: &[u8],
+) -> io::Result<()> {
+    let Some(tmp_dir) = tmp_dir else {
+        return write_batch_file(vcf_path, bytes);
+    };
+    let staged_path = staging_path(tmp_dir, vcf_path);
+    write_batch_file(&staged_path, bytes)?;
+    if let Some(parent) = vcf_path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    move_file(&staged_path, vcf_path).inspect_err(|_| {
+        let _ = fs::remove_file(&staged_path);
+    })
+}
 
-        // Open a file in write-only mode, returns `io::Result<File>`
-        let mut file = File::create(vcf_path)?;
+/// Returns a path in `tmp_dir` under which a file is written before it is moved to `path`.
+/// The name is unique within the process, so that runs that share the directory do not collide.
+pub(crate) fn staging_path(tmp_dir: &Path, path: &Path) -> PathBuf {
+    static STAGED_FILES: AtomicUsize = AtomicUsize::new(0);
+    let file_name = path.file_name().unwrap_or_default().to_string_lossy();
+    tmp_dir.join(format!(
+        "{}.{}-{}.tmp",
+        file_name,
+        std::process::id(),
+        STAGED_FILES.fetch_add(1, Ordering::Relaxed)
+    ))
+}
 
-        let mut write_buffer = Vec::new();
-        let mut writer = BGZFMultiThreadWriter::new(&mut write_buffer, compression_level.unwrap());
-        writer.write_all(contents.as_bytes())?;
-        writer.close()?;
+/// Moves a file. If it cannot be renamed, e.g. because the target is on another file system, it is
+/// copied to a hidden file next to the target first, which is then renamed, so that the target only
+/// ever appears complete.
+pub(crate) fn move_file(from: &Path, to: &Path) -> io::Result<()> {
+    if fs::rename(from, to).is_ok() {
+        return Ok(());
+    }
+    let file_name = to.file_name().unwrap_or_default().to_string_lossy();
+    let copy_path = to.with_file_name(format!(".{}.tmp", file_name));
+    let result = fs::copy(from, &copy_path)
+        .and_then(|_| File::open(&copy_path)?.sync_all())
+        .and_then(|_| fs::rename(&copy_path, to));
+    match result {
+        Ok(()) => fs::remove_file(from),
+        Err(error) => {
+            let _ = fs::remove_file(&copy_path);
+            Err(error)
+        }
+    }
+}
 
-        // Write the content string to `file`, returns `io::Result<()>`
-        file.write_all(&write_buffer)?;
-    } else {
-        let vcf_path = output_path.join(file_name);
-        // Open a file in write-only mode, returns `io::Result<File>`
-        let mut file = File::create(vcf_path)?;
-        file.write_all(contents.as_bytes())?;
+/// Returns the file name of a batch, e.g. `batch_01.vcf` or `batch_01.vcf.gz` if it is compressed.
+pub fn batch_file_name(batch_number: usize, compression_level: Option<Compression>) -> String {
+    let mut file_name = format!("batch_{:02}.vcf", batch_number);
+    if compression_level.is_some() {
+        file_name.push_str(".gz");
     }
+    file_name
+}
 
-    Ok(())
+/// Writes the bytes of a batch and makes sure they reached the disk.
+fn write_batch_contents(mut file: File, bytes: &[u8]) -> io::Result<()> {
+    // Write the content string to `file`, returns `io::Result<()>`
+    file.write_all(bytes)?;
+
+    // Errors such as a full disk are sometimes only reported once the data is flushed
+    file.sync_all()
+}
+
+/// Returns the bytes of a batch as they are stored, i.e. compressed with zstd as `zstd` says, or else bgzipped if
+/// a compression level is given.
+fn encode_batch<'a>(
+    contents: &'a [u8],
+    compression_level: Option<Compression>,
+    zstd: Option<&recompress::RecompressOptions>,
+) -> Result<Cow<'a, [u8]>, BGZFError> {
+    match (compression_level, zstd) {
+        (Some(_), Some(options)) => {
+            let mut write_buffer = Vec::new();
+            let mut encoder = recompress::Encoder::new(&mut write_buffer, options)?;
+            encoder.write_all(contents)?;
+            encoder.finish()?;
+            Ok(Cow::Owned(write_buffer))
+        }
+        (Some(level), None) => {
+            let mut write_buffer = Vec::new();
+            let mut writer = BGZFMultiThreadWriter::new(&mut write_buffer, level);
+            writer.write_all(contents)?;
+            writer.close()?;
+            Ok(Cow::Owned(write_buffer))
+        }
+        (None, _) => Ok(Cow::Borrowed(contents)),
+    }
 }
 
 /// The output is wrapped in a Result to allow matching on errors
@@ -90,14 +293,79 @@ pub fn read_lines<P>(file_path: P) -> Result<ReaderLines, io::Error>
 where
     P: AsRef<Path>,
 {
-    let file = File::open(&file_path).expect("File does not exist.");
-    // If the file ends in .gz, we assume it is bgzipped
-    if file_path.as_ref().to_str().unwrap().ends_with(".gz") {
-        let reader = BGZFReader::new(file).expect("An error occurred reading the compressed file.");
-        return Ok(ZippedLines(reader.lines()));
+    read_lines_with_limit(file_path, None)
+}
+
+/// Same as [`read_lines`], but lines longer than `max_line_length` bytes produce an error
+/// (see [`lines::LineTooLong`]) instead of being read into memory completely.
+pub fn read_lines_with_limit<P>(
+    file_path: P,
+    max_line_length: Option<usize>,
+) -> Result<ReaderLines, io::Error>
+where
+    P: AsRef<Path>,
+{
+    let file = File::open(&file_path)?;
+    read_lines_from(file, max_line_length)
+}
+
+/// Same as [`read_lines_with_limit`], but the lines are read from `reader`, e.g. stdin, a pipe or a
+/// file that was opened already. Bgzipped, gzipped and zstd compressed input is recognized by its first bytes,
+/// which are looked at without seeking, so readers that cannot seek work as well.
+///
+/// # Examples
+///
+/// ```
+/// use vcf_batcher::read_lines_from;
+///
+/// let vcf = "##fileformat=VCFv4.2\n#CHROM\tPOS\tID\tREF\tALT\tQUAL\tFILTER\tINFO\n1\t5\t.\tA\tC\t.\tPASS\t.\n";
+/// let lines: Vec<String> = read_lines_from(vcf.as_bytes(), None).unwrap().map(Result::unwrap).collect();
+/// assert_eq!(lines.len(), 3);
+///
+/// let bgzipped = std::fs::File::open("test_data/batch_01.vcf.gz").unwrap();
+/// assert_eq!(read_lines_from(bgzipped, None).unwrap().count(), 130);
+/// ```
+pub fn read_lines_from<R: Read>(
+    reader: R,
+    max_line_length: Option<usize>,
+) -> Result<ReaderLines<R>, io::Error> {
+    let mut reader = BufReader::new(reader);
+    let start = reader.fill_buf()?;
+    if is_bgzf(start) {
+        let reader = BGZFReader::new(reader).map_err(BGZFError::into_io_error)?;
+        return Ok(ZippedLines(BoundedLines::new(reader, max_line_length)));
+    }
+    if start.starts_with(GZIP_MAGIC) {
+        let reader = BufReader::new(MultiGzDecoder::new(reader));
+        return Ok(GzippedLines(Box::new(BoundedLines::new(
+            reader,
+            max_line_length,
+        ))));
+    }
+    #[cfg(not(target_arch = "wasm32"))]
+    if start.starts_with(recompress::ZSTD_MAGIC) {
+        let reader = BufReader::new(zstd::Decoder::with_buffer(reader)?);
+        return Ok(ZstdLines(Box::new(BoundedLines::new(
+            reader,
+            max_line_length,
+        ))));
+    }
+    #[cfg(target_arch = "wasm32")]
+    if start.starts_with(recompress::ZSTD_MAGIC) {
+        return Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "zstd compressed files cannot be read in WebAssembly",
+        ));
     }
+    Ok(UnzippedLines(BoundedLines::new(reader, max_line_length)))
+}
 
-    Ok(UnzippedLines(BufReader::new(file).lines()))
+/// Whether a file starts with a gzip member with the `BC` extra field of BGZF blocks.
+pub(crate) fn is_bgzf(start: &[u8]) -> bool {
+    start.starts_with(GZIP_MAGIC)
+        && start.len() >= 14
+        && start[3] & 4 != 0
+        && &start[12..14] == b"BC"
 }
 
 /// In VCF-Files header lines containing metadata start with a `#`.
@@ -137,128 +405,1121 @@ pub fn is_header_line(line: &str) -> bool {
     line.starts_with('#')
 }
 
-/// Converts a large VCF file into batches of smaller VCF files containing a fixed number of samples
+/// Converts a large VCF file into batches of smaller VCF files containing a fixed number of records
+///
+/// Returns a report of all written batches. If a batch cannot be written, the partial file is
+/// removed and the returned error lists the batches that were completed before the failure.
 pub fn extract_variants_to_batches(
     file_path: &str,
     batch_size: usize,
     output_path: &Path,
     compression_level: Option<Compression>,
-) {
-    let mut current_batch = String::new();
-    let mut headers = String::new();
-
-    // File hosts must exist in current path before this produces output
-    let mut current_batch_counter = 0;
-    let mut batch_count = 0;
-
-    if let Ok(lines) = read_lines(file_path) {
-        // Consumes the iterator, returns an (Optional) String
-        for line in lines.flatten() {
-            if is_header_line(&line) {
-                headers.append_line(&line);
-                continue;
+) -> Result<RunReport, VcfBatcherError> {
+    let options = BatchOptions {
+        batch_size,
+        compression_level,
+        ..Default::default()
+    };
+    extract_variants_to_batches_with_options(file_path, output_path, &options)
+}
+
+/// Same as [`extract_variants_to_batches`], but configured through [`BatchOptions`].
+///
+/// If the cancel flag of the options is set, batching stops before the next line is read
+/// and [`VcfBatcherError::Interrupted`] is returned with all batches completed so far.
+///
+/// The output directory is locked for the duration of the run, so that two runs cannot
+/// write batches with the same names into the same directory.
+///
+/// # Examples
+///
+/// With [`SplitMode::Chromosome`], every chromosome is written to a file of its own instead of
+/// batches of [`BatchOptions::batch_size`] records, as imputation and phasing tools expect:
+///
+/// ```
+/// use std::path::Path;
+/// use vcf_batcher::{extract_variants_to_batches_with_options, BatchOptions, SplitMode};
+///
+/// let options = BatchOptions { split_by: SplitMode::Chromosome, quiet: true, ..Default::default() };
+/// let output = Path::new("test_data/doctest_chromosomes");
+/// # let _ = std::fs::remove_dir_all(output);
+/// let report = extract_variants_to_batches_with_options("test_data/batch_01.vcf.gz", output, &options).unwrap();
+/// assert_eq!(report.batches.len(), 1);
+/// assert_eq!(report.batches[0].path, output.join("22.vcf"));
+/// ```
+pub fn extract_variants_to_batches_with_options(
+    file_path: &str,
+    output_path: &Path,
+    options: &BatchOptions,
+) -> Result<RunReport, VcfBatcherError> {
+    let options = &*equal_batch_sizes(file_path, options)?;
+    #[cfg(feature = "htslib")]
+    if bcf::is_bcf(Path::new(file_path)) {
+        return htslib::extract_variants_with_htslib(file_path, output_path, options);
+    }
+    let _lock = OutputLock::acquire(output_path)?;
+    extract_variants(
+        file_path,
+        Destination::Directory(Cow::Borrowed(output_path)),
+        options,
+    )
+}
+
+/// Sets the batch sizes so that the records are divided into [`BatchOptions::num_batches`] batches, whose sizes
+/// differ by at most one record. The records are counted with a dry run first. Returns the options unchanged if
+/// no number of batches is set.
+fn equal_batch_sizes<'o>(
+    file_path: &str,
+    options: &'o BatchOptions,
+) -> Result<Cow<'o, BatchOptions>, VcfBatcherError> {
+    let Some(num_batches) = options.num_batches else {
+        return Ok(Cow::Borrowed(options));
+    };
+    if options.split_by != SplitMode::Records
+        || options.group_by_info.is_some()
+        || options.target_compressed_size.is_some()
+        || options.target_size.is_some()
+        || options.append.is_some()
+    {
+        return Err(VcfBatcherError::InvalidOptions {
+            reason: "a number of batches can only be set when splitting into batches of records, without a \
+                     target size, grouping or appending"
+                .to_string(),
+        });
+    }
+    if bcf::is_bcf(Path::new(file_path)) {
+        return Err(VcfBatcherError::InvalidOptions {
+            reason:
+                "the records of BCF files cannot be counted to divide them into a number of batches"
+                    .to_string(),
+        });
+    }
+    if !options.quiet {
+        println!("Counting the records of {file_path} to divide them into {num_batches} batches");
+    }
+    let counting = BatchOptions {
+        num_batches: None,
+        // Dry runs collect the records of a batch, so they are counted in small batches
+        batch_size: COUNTING_BATCH_SIZE,
+        batch_sizes: Vec::new(),
+        resume: false,
+        // Templates without a number would name the small batches alike
+        name_template: None,
+        quiet: true,
+        on_batch: None,
+        on_progress: None,
+        ..options.clone()
+    };
+    let report = extract_variants(
+        file_path,
+        Destination::DryRun(Cow::Borrowed(Path::new(""))),
+        &counting,
+    )?;
+    let records = report.total_records();
+    let num_batches = num_batches.max(1);
+    let batch_size = (records / num_batches).max(1);
+    // The first batches take one more record each, for the records that do not divide evenly
+    let larger_batches = match records >= num_batches {
+        true => records % num_batches,
+        false => 0,
+    };
+    Ok(Cow::Owned(BatchOptions {
+        num_batches: None,
+        batch_size,
+        batch_sizes: vec![batch_size + 1; larger_batches],
+        ..options.clone()
+    }))
+}
+
+/// Splits a VCF file into one batch per interval of the BED file at `bed_path`, each with the records that lie
+/// in the interval, e.g. to shard a whole-genome VCF file by capture regions, see [`SplitMode::Region`]. The
+/// other options are taken from `options`.
+///
+/// # Examples
+///
+/// ```
+/// use std::path::Path;
+/// use vcf_batcher::{split_by_regions, BatchOptions};
+///
+/// # std::fs::create_dir_all("test_data/inputs").unwrap();
+/// let bed = Path::new("test_data/inputs/doctest_regions.bed");
+/// std::fs::write(bed, "22\t18570345\t18570842\tfirst\n22\t18570850\t18571784\tsecond\n").unwrap();
+/// let output = Path::new("test_data/doctest_regions");
+/// # let _ = std::fs::remove_dir_all(output);
+/// let options = BatchOptions { quiet: true, ..Default::default() };
+/// let report = split_by_regions("test_data/batch_01.vcf.gz", bed, output, &options).unwrap();
+/// assert_eq!(report.batches[1].path, output.join("second.vcf"));
+/// assert_eq!(report.batches[1].records, 20);
+/// ```
+pub fn split_by_regions(
+    file_path: &str,
+    bed_path: &Path,
+    output_path: &Path,
+    options: &BatchOptions,
+) -> Result<RunReport, VcfBatcherError> {
+    let options = BatchOptions {
+        split_by: SplitMode::Region,
+        split_regions: regions::read_bed(bed_path)?,
+        ..options.clone()
+    };
+    extract_variants_to_batches_with_options(file_path, output_path, &options)
+}
+
+/// Same as [`extract_variants_to_batches_with_options`], but every batch is passed to `sink`
+/// instead of being written to a file, e.g. to upload it or to process it in memory.
+///
+/// The paths in the returned report are the file names the batches would have had.
+/// [`BatchOptions::resume`] has no effect, because nothing is stored between runs.
+pub fn extract_variants_to_sink(
+    file_path: &str,
+    sink: &mut dyn BatchSink,
+    options: &BatchOptions,
+) -> Result<RunReport, VcfBatcherError> {
+    let options = equal_batch_sizes(file_path, options)?;
+    extract_variants(file_path, Destination::Sink(sink), &options)
+}
+
+/// Same as [`extract_variants_to_batches_with_options`], but the VCF file is read from `reader`,
+/// e.g. from stdin in a pipeline. Bgzipped input is recognized by its first bytes.
+///
+/// [`BatchOptions::resume`] and [`BatchOptions::on_progress`] have no effect, because a stream
+/// cannot be compared with a previous run and its size is unknown. Name templates use `batch` for `{stem}`.
+///
+/// # Examples
+///
+/// ```
+/// use std::path::Path;
+/// use vcf_batcher::{extract_variants_from_reader, BatchOptions};
+///
+/// let vcf = "##fileformat=VCFv4.2\n#CHROM\tPOS\tID\tREF\tALT\tQUAL\tFILTER\tINFO\n1\t5\t.\tA\tC\t.\tPASS\t.\n";
+/// let options = BatchOptions { batch_size: 10, ..Default::default() };
+/// let report = extract_variants_from_reader(vcf.as_bytes(), Path::new("test_data/doc_reader"), &options).unwrap();
+/// assert_eq!(report.total_records(), 1);
+/// ```
+pub fn extract_variants_from_reader<R: Read>(
+    reader: R,
+    output_path: &Path,
+    options: &BatchOptions,
+) -> Result<RunReport, VcfBatcherError> {
+    let _lock = OutputLock::acquire(output_path)?;
+    extract_from_reader(
+        reader,
+        Destination::Directory(Cow::Borrowed(output_path)),
+        options,
+    )
+}
+
+/// Same as [`extract_variants_from_reader`], but every batch is passed to `sink` like with
+/// [`extract_variants_to_sink`]. Nothing is read from or written to the file system, so this also
+/// works where there is none, e.g. in the browser when the crate is compiled to WebAssembly.
+///
+/// # Examples
+///
+/// ```
+/// use vcf_batcher::{extract_variants_from_reader_to_sink, BatchInfo, BatchOptions};
+///
+/// let vcf = "##fileformat=VCFv4.2\n#CHROM\tPOS\tID\tREF\tALT\tQUAL\tFILTER\tINFO\n\
+///            1\t5\t.\tA\tC\t.\tPASS\t.\n1\t9\t.\tG\tT\t.\tPASS\t.\n";
+/// let mut batches = Vec::new();
+/// let mut sink = |batch: &BatchInfo, contents: &[u8]| {
+///     batches.push((batch.path.clone(), contents.to_vec()));
+///     Ok(())
+/// };
+/// let options = BatchOptions { batch_size: 1, ..Default::default() };
+/// let report = extract_variants_from_reader_to_sink(vcf.as_bytes(), &mut sink, &options).unwrap();
+/// assert_eq!(report.batches.len(), 2);
+/// assert_eq!(batches.len(), 2);
+/// ```
+pub fn extract_variants_from_reader_to_sink<R: Read>(
+    reader: R,
+    sink: &mut dyn BatchSink,
+    options: &BatchOptions,
+) -> Result<RunReport, VcfBatcherError> {
+    extract_from_reader(reader, Destination::Sink(sink), options)
+}
+
+fn extract_from_reader<'a, R: Read>(
+    reader: R,
+    destination: Destination<'a>,
+    options: &'a BatchOptions,
+) -> Result<RunReport, VcfBatcherError> {
+    let read_error = |source| VcfBatcherError::Read {
+        path: PathBuf::from("the input"),
+        source,
+    };
+    if options.num_batches.is_some() {
+        return Err(VcfBatcherError::InvalidOptions {
+            reason:
+                "the records of a stream cannot be counted to divide them into a number of batches"
+                    .to_string(),
+        });
+    }
+
+    let lines = read_lines_from(reader, options.max_line_length).map_err(read_error)?;
+
+    let output = BatchOutput::new(
+        "batch".to_string(),
+        destination,
+        Cow::Borrowed(options),
+        None,
+    )?;
+    let mut writer = BatchWriter::new("the input".to_string(), output);
+    let mut lines = lines;
+    loop {
+        let started = writer.stage_start();
+        let Some(line) = lines.next() else { break };
+        writer.stage_end(Stage::Reading, started);
+        let line = line.map_err(|error| match LineTooLong::from_io_error(&error) {
+            Some(LineTooLong { line_number, limit }) => {
+                VcfBatcherError::LineTooLong { line_number, limit }
             }
+            None => read_error(error),
+        })?;
+        writer.write_line(&line)?;
+    }
+    writer.finish()
+}
 
-            current_batch_counter += 1;
-            current_batch.append_line(&line);
-
-            if current_batch_counter >= batch_size {
-                batch_count += 1;
-                if let Err(error) = save_batch(
-                    headers.to_owned() + &current_batch,
-                    &batch_count,
-                    output_path,
-                    compression_level,
-                ) {
-                    panic!(
-                        "An error occurred while trying to save batch {}: {}",
-                        batch_count, error
-                    )
-                }
+/// Goes through the input like [`extract_variants_to_batches_with_options`], but does not write
+/// anything, to check the options and see which batches a run would produce. The input is read
+/// completely, so the batches and their record counts are exact. The batches in the returned
+/// report have no checksums, and [`BatchOptions::resume`] and [`BatchOptions::on_batch`] have no effect.
+///
+/// # Examples
+///
+/// ```
+/// use std::path::Path;
+/// use vcf_batcher::{plan_batches, BatchOptions};
+///
+/// let options = BatchOptions { batch_size: 30, ..Default::default() };
+/// let output_path = Path::new("test_data/doc_plan");
+/// let report = plan_batches("test_data/batch_01.vcf.gz", output_path, &options).unwrap();
+/// assert_eq!(report.batches.len(), 4);
+/// assert!(!output_path.exists());
+/// ```
+pub fn plan_batches(
+    file_path: &str,
+    output_path: &Path,
+    options: &BatchOptions,
+) -> Result<RunReport, VcfBatcherError> {
+    let options = equal_batch_sizes(file_path, options)?;
+    extract_variants(
+        file_path,
+        Destination::DryRun(Cow::Borrowed(output_path)),
+        &options,
+    )
+}
 
-                if compression_level.is_some() {
-                    println!("Saving batch_{:02}.vcf.gz", batch_count);
-                } else {
-                    println!("Saving batch_{:02}.vcf", batch_count);
+/// Where the batches of a run go.
+enum Destination<'a> {
+    Directory(Cow<'a, Path>),
+    Sink(&'a mut dyn BatchSink),
+    /// Only the names and record counts of the batches are determined, see [`plan_batches`].
+    DryRun(Cow<'a, Path>),
+}
+
+fn extract_variants<'a>(
+    file_path: &str,
+    destination: Destination<'a>,
+    options: &'a BatchOptions,
+) -> Result<RunReport, VcfBatcherError> {
+    let read_error = |source| VcfBatcherError::Read {
+        path: PathBuf::from(file_path),
+        source,
+    };
+    if bcf::is_bcf(Path::new(file_path)) {
+        #[cfg(not(feature = "htslib"))]
+        return Err(VcfBatcherError::FeatureDisabled {
+            what: format!("reading the BCF file {file_path}"),
+            feature: "htslib",
+        });
+        #[cfg(feature = "htslib")]
+        return Err(read_error(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "BCF files can only be split into a directory, see extract_variants_with_htslib",
+        )));
+    }
+
+    let file = File::open(file_path).map_err(read_error)?;
+    // Pipes, e.g. from process substitution, have no size
+    let total_bytes = file
+        .metadata()
+        .ok()
+        .filter(|metadata| metadata.is_file())
+        .map(|metadata| metadata.len());
+    // Shares the position of the file that is being read, to report how much of it was consumed
+    let position = file.try_clone().map_err(read_error)?;
+    let lines = read_lines_from(file, options.max_line_length).map_err(read_error)?;
+    let fingerprint = manifest::run_fingerprint(file_path, options).map_err(read_error)?;
+    let output = BatchOutput::new(
+        naming::input_stem(Path::new(file_path)),
+        destination,
+        Cow::Borrowed(options),
+        Some(fingerprint),
+    )?;
+
+    let started = Instant::now();
+    let report_progress = |writer: &BatchWriter| {
+        if let Some(on_progress) = &options.on_progress {
+            on_progress.call(&Progress {
+                bytes_read: (&position).stream_position().unwrap_or_default(),
+                total_bytes,
+                records: writer.records_read(),
+                batches: writer.report().batches.len(),
+                elapsed: started.elapsed(),
+            });
+        }
+    };
+
+    let mut writer = BatchWriter::new(file_path.to_string(), output);
+    // The input is read and decompressed while the records are routed into batches, whose blocks are
+    // compressed on rayon's thread pool
+    lines::read_ahead(lines, |lines| {
+        let mut lines = lines.enumerate();
+        loop {
+            let started = writer.stage_start();
+            let Some((line_index, line)) = lines.next() else {
+                return Ok(());
+            };
+            writer.stage_end(Stage::Reading, started);
+            let line = line.map_err(|error| match LineTooLong::from_io_error(&error) {
+                Some(LineTooLong { line_number, limit }) => {
+                    VcfBatcherError::LineTooLong { line_number, limit }
                 }
+                None => read_error(error),
+            })?;
+            writer.write_line(&line)?;
+            if line_index % PROGRESS_INTERVAL == 0 {
+                report_progress(&writer);
+            }
+        }
+    })?;
+    let report = writer.finish()?;
+    if let Some(on_progress) = &options.on_progress {
+        on_progress.call(&Progress {
+            bytes_read: total_bytes.unwrap_or_default(),
+            total_bytes,
+            records: report.total_records() + report.filtered_records,
+            batches: report.batches.len(),
+            elapsed: started.elapsed(),
+        });
+    }
+    Ok(report)
+}
+
+/// What a batch is named after besides its number and records.
+#[derive(Debug, Clone, Copy, Default)]
+struct BatchLabel<'a> {
+    /// The first gene of the batch, see [`SplitMode::Gene`].
+    gene: Option<&'a str>,
+    /// The value of [`BatchOptions::group_by_info`] that the records of the batch have and the number of the
+    /// batch within its group.
+    group: Option<(&'a str, usize)>,
+    /// The name of the interval of the batch, see [`SplitMode::Region`].
+    region: Option<&'a str>,
+}
+
+/// The records of a batch that is saved.
+enum BatchContents<'b> {
+    /// Records that were collected in memory, each followed by a line ending, and the batch file if the
+    /// records were already compressed.
+    Records {
+        records: &'b str,
+        compressed: Option<Vec<u8>>,
+    },
+    /// A file that the records were written to as they arrived, see [`BatchOutput::streams_batches`].
+    File(Box<BatchFile>),
+}
+
+/// Writes the batches of a run into the output directory and keeps track of them in the report.
+struct BatchOutput<'a> {
+    output_path: Cow<'a, Path>,
+    options: Cow<'a, BatchOptions>,
+    /// Name of the input file without extensions, for the `{stem}` placeholder of name templates.
+    stem: String,
+    started: Instant,
+    report: RunReport,
+    /// Batches written by a previous run with the same fingerprint, if the run is resumed.
+    previous: HashMap<usize, ManifestEntry>,
+    /// Receives the batches instead of files being written, see [`extract_variants_to_sink`].
+    sink: Option<&'a mut dyn BatchSink>,
+    journal: Option<Journal>,
+    /// The lines of the BED file, if [`BatchOptions::batches_bed`] is set.
+    bed: Option<String>,
+    /// Nothing is written, see [`plan_batches`].
+    dry_run: bool,
+    /// The header of the batches that the run appends to, until the first batch was checked against it.
+    append_headers: Option<String>,
+    /// The last batch that the run appends to, whose records are written again, see [`AppendMode::Rebalance`].
+    /// Taken by the [`BatchWriter`].
+    tail: Option<append::Tail>,
+    /// The file of the tail, which is replaced instead of counting as existing.
+    replaced: Option<PathBuf>,
+}
 
-                current_batch = String::new();
-                current_batch_counter = 0;
+impl<'a> BatchOutput<'a> {
+    /// Runs without a fingerprint cannot be resumed.
+    fn new(
+        stem: String,
+        destination: Destination<'a>,
+        options: Cow<'a, BatchOptions>,
+        fingerprint: Option<String>,
+    ) -> Result<Self, VcfBatcherError> {
+        if options.format == OutputFormat::Bcf && !cfg!(feature = "htslib") {
+            return Err(VcfBatcherError::FeatureDisabled {
+                what: "writing BCF files".to_string(),
+                feature: "htslib",
+            });
+        }
+        if options.index.is_some()
+            && (options.compression_level.is_none() || options.format != OutputFormat::Vcf)
+        {
+            return Err(VcfBatcherError::InvalidOptions {
+                reason: "an index can only be written for compressed VCF batches, set a compression level"
+                    .to_string(),
+            });
+        }
+        if options.compression_format == CompressionFormat::Zstd
+            && options.compression_level.is_some()
+        {
+            let conflict = match () {
+                _ if options.format != OutputFormat::Vcf => {
+                    Some("batches in other formats than VCF")
+                }
+                _ if options.index.is_some() => Some("an index"),
+                _ if options.target_compressed_size.is_some() => Some("a target compressed size"),
+                _ => None,
+            };
+            if let Some(conflict) = conflict {
+                return Err(VcfBatcherError::InvalidOptions {
+                    reason: format!("zstd compression cannot be used with {conflict}"),
+                });
             }
         }
+        let (output_path, sink, dry_run) = match destination {
+            Destination::Directory(output_path) => (output_path, None, false),
+            Destination::Sink(sink) => (Cow::Borrowed(Path::new("")), Some(sink), false),
+            Destination::DryRun(output_path) => (output_path, None, true),
+        };
+        let mut existing = match options.append {
+            Some(mode) if sink.is_none() => append::existing_batches(&output_path, mode, &options)?,
+            _ => append::ExistingBatches::default(),
+        };
+        let bed = (options.batches_bed && sink.is_none() && !dry_run)
+            .then(|| std::mem::take(&mut existing.bed));
 
-        if !current_batch.is_empty() {
-            batch_count += 1;
-            println!(
-                "Saving final batch with less than {} samples to batch_{:02}.vcf.gz",
-                batch_size, batch_count
+        let mut previous = HashMap::new();
+        let mut journal = None;
+        if let Some(fingerprint) = fingerprint
+            .as_ref()
+            .filter(|_| options.resume && sink.is_none() && !dry_run)
+        {
+            previous = manifest::previous_batches(&output_path, fingerprint);
+            journal = Some(
+                Journal::create(&output_path, fingerprint).map_err(|source| {
+                    VcfBatcherError::BatchWrite {
+                        batch_number: 1,
+                        path: output_path.join(manifest::JOURNAL_FILE_NAME),
+                        completed: Box::default(),
+                        source: source.into(),
+                    }
+                })?,
             );
+        }
 
-            if let Err(error) = save_batch(
-                headers.to_owned() + &current_batch,
-                &batch_count,
-                output_path,
-                compression_level,
-            ) {
-                panic!(
-                    "An error occurred while trying to save batch {}: {}",
-                    batch_count, error
-                )
+        Ok(BatchOutput {
+            report: RunReport {
+                output_path: output_path.to_path_buf(),
+                fingerprint: fingerprint.unwrap_or_default(),
+                timings: options.timings.then(StageTimings::default),
+                existing_batches: existing.batches.len(),
+                batches: existing.batches,
+                groups: existing.groups,
+                ..Default::default()
+            },
+            output_path,
+            options,
+            stem,
+            started: Instant::now(),
+            previous,
+            journal,
+            sink,
+            bed,
+            dry_run,
+            append_headers: existing.headers,
+            replaced: existing.tail.as_ref().map(|tail| tail.path.clone()),
+            tail: existing.tail,
+        })
+    }
+
+    /// Describes where the batches went, for messages.
+    fn destination_name(&self) -> String {
+        match self.sink {
+            Some(_) => "the sink".to_string(),
+            None => self.output_path.display().to_string(),
+        }
+    }
+
+    /// The directory of the batches of a value of [`BatchOptions::group_by_info`], relative to the output
+    /// directory, e.g. `CLNSIG=Pathogenic`.
+    fn group_dir(&self, group: &str) -> PathBuf {
+        let key = self.options.group_by_info.as_deref().unwrap_or_default();
+        PathBuf::from(naming::sanitize_component(&format!("{key}={group}")))
+    }
+
+    /// How many of the batches that the run appends to belong to a group.
+    fn existing_group_batches(&self, group: &str) -> usize {
+        let dir = self.output_path.join(self.group_dir(group));
+        self.report.batches[..self.report.existing_batches]
+            .iter()
+            .filter(|batch| batch.path.starts_with(&dir))
+            .count()
+    }
+
+    /// Whether batches are written to their files while their records are collected, instead of being
+    /// collected in memory and written at once. Outputs that need all records of a batch at once are collected:
+    /// sinks, dry runs, JSON Lines and Parquet files, site lists and the BED file of the batches.
+    fn streams_batches(&self) -> bool {
+        #[cfg(feature = "parquet")]
+        if self.options.parquet != ParquetExport::Off {
+            return false;
+        }
+        self.sink.is_none()
+            && !self.dry_run
+            && self.options.format == OutputFormat::Vcf
+            && !self.options.site_lists
+            && self.bed.is_none()
+    }
+
+    /// Creates the file of the next batch and writes the header to it, see [`BatchOutput::streams_batches`].
+    fn open_batch(&mut self, headers: &str) -> Result<BatchFile, VcfBatcherError> {
+        // The batches of samples are all written at once, so each writes its blocks as they fill up instead of
+        // compressing many of them at a time
+        let measured = self.options.target_compressed_size.is_some()
+            || self.options.split_by == SplitMode::Samples;
+        BatchFile::create(
+            self.staging_dir(),
+            headers,
+            self.options.compression_level,
+            self.options.zstd_options(),
+            measured,
+        )
+        .map_err(|source| self.batch_file_error(source))
+    }
+
+    /// The directory that batches are written to before they are complete.
+    fn staging_dir(&self) -> &Path {
+        self.options.tmp_dir.as_deref().unwrap_or(&self.output_path)
+    }
+
+    /// The error for a batch whose file could not be written while its records were collected.
+    fn batch_file_error(&mut self, source: io::Error) -> VcfBatcherError {
+        let batch_number = self.report.batches.len() + 1;
+        let path = self.staging_dir().to_path_buf();
+        self.write_error(batch_number, &path, source.into())
+    }
+
+    /// Saves the next batch and records it in the report. On failure, the error carries everything that was
+    /// completed so far.
+    fn save(
+        &mut self,
+        headers: &str,
+        contents: BatchContents,
+        record_count: usize,
+        label: BatchLabel,
+    ) -> Result<PathBuf, VcfBatcherError> {
+        let batch_number = self.report.batches.len() + 1;
+        let compression_level = self.options.compression_level;
+        let (first, last) = match &contents {
+            BatchContents::Records { records, .. } => (
+                records.lines().next().and_then(Locus::from_record),
+                records.lines().next_back().and_then(Locus::from_record),
+            ),
+            BatchContents::File(file) => (file.first().cloned(), file.last().cloned()),
+        };
+        let file = self.file_name(batch_number, first.as_ref(), last.as_ref(), label);
+        let path = self.output_path.join(&file);
+        if let Some((group, _)) = label.group {
+            let dir = self.output_path.join(self.group_dir(group));
+            self.report.groups.entry(group.to_string()).or_insert(dir);
+        }
+        if let Some(template) = &self.options.name_template {
+            if !template.uses_number() && self.report.batches.iter().any(|batch| batch.path == path)
+            {
+                return Err(VcfBatcherError::InvalidTemplate {
+                    template: template.to_string(),
+                    reason: format!(
+                        "batch {} would get the same name {} as an earlier batch, add {{number}}",
+                        batch_number,
+                        file.display()
+                    ),
+                });
             }
         }
-        println!(
-            "Saved {} batches with {} samples to {}.",
-            batch_count,
-            batch_size,
-            output_path.display()
-        );
-    } else {
-        panic!("An error occurred while trying to read the file. Does it exist and is it either a .vcf or .vcf.gz file?")
+        if let Some(existing_headers) = self.append_headers.take() {
+            if existing_headers != headers {
+                return Err(VcfBatcherError::HeaderMismatch { path });
+            }
+        }
+
+        let reusable = match self.dry_run {
+            true => None,
+            false => self.reusable_batch(batch_number, &file, &path, record_count),
+        };
+        let exists = reusable.is_none()
+            && self.sink.is_none()
+            && path.exists()
+            && self.replaced.as_ref() != Some(&path);
+        if exists && self.options.overwrite == OverwritePolicy::Refuse {
+            return Err(VcfBatcherError::OutputExists {
+                path,
+                completed: Box::new(std::mem::take(&mut self.report)),
+            });
+        }
+        let skip = exists && self.options.overwrite == OverwritePolicy::Skip;
+        if skip {
+            self.report.skipped_batches += 1;
+        }
+
+        if self.dry_run {
+            self.report.batches.push(BatchInfo {
+                number: batch_number,
+                path: path.clone(),
+                records: record_count,
+                sha256: String::new(),
+                size: 0,
+                first,
+                last,
+                verified: false,
+            });
+            return Ok(path);
+        }
+
+        // Streamed batches are neither listed in a BED file nor sent to a sink, see `streams_batches`
+        let records = match &contents {
+            BatchContents::Records { records, .. } => *records,
+            BatchContents::File(_) => "",
+        };
+        let mut encoded = None;
+        let sha256 = match reusable {
+            Some(sha256) => {
+                if !self.options.quiet {
+                    println!("Keeping {}, it is already complete", path.display());
+                }
+                self.report.resumed_batches += 1;
+                sha256
+            }
+            None if skip => {
+                if !self.options.quiet {
+                    println!("Skipping {}, it already exists", path.display());
+                }
+                manifest::file_sha256(&path)
+                    .map_err(|source| self.write_error(batch_number, &path, source.into()))?
+            }
+            None => match contents {
+                BatchContents::File(batch_file) => {
+                    let started = self.report.stage_start();
+                    let sha256 = batch_file
+                        .finish(headers, &path)
+                        .map_err(|source| self.write_error(batch_number, &path, source.into()))?;
+                    if !self.options.quiet {
+                        println!("Saving {}", path.display())
+                    }
+                    self.report.stage_end(Stage::Writing, started);
+                    sha256
+                }
+                BatchContents::Records { compressed, .. } => {
+                    let contents = match self.options.format {
+                        OutputFormat::Vcf | OutputFormat::Bcf => headers.to_owned() + records,
+                        OutputFormat::Jsonl => {
+                            jsonl::jsonl_batch(headers, records, &self.options.json_fields)
+                        }
+                    };
+                    let started = self.report.stage_start();
+                    let bytes = match self.parquet_only() {
+                        #[cfg(feature = "parquet")]
+                        true => sites::parquet_table(records, &self.options.parquet_info_keys)
+                            .map(Cow::Owned)
+                            .map_err(BGZFError::from),
+                        _ if self.options.format == OutputFormat::Bcf => self.encode_bcf(&contents),
+                        _ => {
+                            match compressed.filter(|_| self.options.format == OutputFormat::Vcf) {
+                                // Compressed while the records were collected, see BatchOptions::target_compressed_size
+                                Some(compressed) => Ok(Cow::Owned(compressed)),
+                                None => encode_batch(
+                                    contents.as_bytes(),
+                                    compression_level,
+                                    self.options.zstd_options().as_ref(),
+                                ),
+                            }
+                        }
+                    }
+                    .map_err(|source| self.write_error(batch_number, &path, source))?;
+                    self.report.stage_end(Stage::Compression, started);
+
+                    let started = self.report.stage_start();
+                    let sha256 = manifest::sha256_hex(&bytes);
+                    match self.sink {
+                        Some(_) => encoded = Some(bytes.into_owned()),
+                        None => {
+                            write_staged_batch_file(&path, self.options.tmp_dir.as_deref(), &bytes)
+                                .map_err(|source| {
+                                    self.write_error(batch_number, &path, source.into())
+                                })?;
+                            #[cfg(feature = "parquet")]
+                            if self.options.parquet == ParquetExport::Alongside {
+                                let table =
+                                    sites::parquet_table(records, &self.options.parquet_info_keys);
+                                self.write_side_file(
+                                    batch_number,
+                                    sites::parquet_path(&path),
+                                    table,
+                                )?;
+                            }
+                            if self.options.site_lists {
+                                let list = sites::site_list(records).into_bytes();
+                                self.write_side_file(
+                                    batch_number,
+                                    sites::site_list_path(&path),
+                                    Ok(list),
+                                )?;
+                            }
+                            if !self.options.quiet {
+                                println!("Saving {}", path.display())
+                            }
+                        }
+                    }
+                    self.report.stage_end(Stage::Writing, started);
+                    sha256
+                }
+            },
+        };
+        let size = match &encoded {
+            Some(contents) => contents.len() as u64,
+            None => fs::metadata(&path)
+                .map_err(|source| self.write_error(batch_number, &path, source.into()))?
+                .len(),
+        };
+        let mut batch = BatchInfo {
+            number: batch_number,
+            path: path.clone(),
+            records: record_count,
+            sha256,
+            size,
+            first,
+            last,
+            verified: false,
+        };
+        if self.verifies_batches() && !skip {
+            verify::verify_batch(&batch).map_err(|reason| VcfBatcherError::VerificationFailed {
+                path: path.clone(),
+                reason,
+                completed: Box::new(std::mem::take(&mut self.report)),
+            })?;
+            batch.verified = true;
+        }
+        if self.writes_indexes() && !skip {
+            let started = self.report.stage_start();
+            self.write_side_file(
+                batch_number,
+                index::tabix_path(&path),
+                index::tabix_index(&path),
+            )?;
+            self.report.stage_end(Stage::Writing, started);
+        }
+
+        if let (Some(sink), Some(contents)) = (self.sink.as_deref_mut(), &encoded) {
+            let started = self.report.stage_start();
+            if let Err(source) = sink.write_batch(&batch, contents) {
+                return Err(self.write_error(batch_number, &path, source.into()));
+            }
+            self.report.stage_end(Stage::Writing, started);
+        }
+
+        if let Some(journal) = &mut self.journal {
+            let entry = ManifestEntry {
+                number: batch.number,
+                file: file.to_string_lossy().into_owned(),
+                records: batch.records,
+                sha256: batch.sha256.clone(),
+                size: batch.size,
+                first: batch.first.clone(),
+                last: batch.last.clone(),
+                verified: batch.verified,
+            };
+            if let Err(source) = journal.append(&entry) {
+                return Err(self.write_error(batch_number, &path, source.into()));
+            }
+        }
+
+        if let Some(bed) = &mut self.bed {
+            bed.push_str(&bed::batch_spans(records, &file.to_string_lossy()));
+        }
+        if let Some(on_batch) = &self.options.on_batch {
+            on_batch.call(&batch);
+        }
+        self.report.batches.push(batch);
+        Ok(path)
+    }
+
+    /// Writes a file that accompanies a batch, such as the list of its sites.
+    fn write_side_file(
+        &mut self,
+        batch_number: usize,
+        path: PathBuf,
+        contents: io::Result<Vec<u8>>,
+    ) -> Result<(), VcfBatcherError> {
+        contents
+            .and_then(|contents| {
+                write_staged_batch_file(&path, self.options.tmp_dir.as_deref(), &contents)
+            })
+            .map_err(|source| self.write_error(batch_number, &path, source.into()))
+    }
+
+    /// Whether written batches are read back and checked, see [`BatchOptions::verify_output`].
+    /// Only VCF files on disk are verified.
+    fn verifies_batches(&self) -> bool {
+        self.options.verify_output
+            && self.sink.is_none()
+            && self.options.format == OutputFormat::Vcf
+            && !self.parquet_only()
+    }
+
+    /// Whether an index is written next to every batch, see [`BatchOptions::index`]. Batches are read back
+    /// from their files to index them, so batches sent to sinks are not indexed.
+    fn writes_indexes(&self) -> bool {
+        self.options.index.is_some() && self.sink.is_none() && !self.parquet_only()
+    }
+
+    /// Converts the VCF text of a batch into a BCF file, see [`OutputFormat::Bcf`].
+    fn encode_bcf(&self, contents: &str) -> Result<Cow<'static, [u8]>, BGZFError> {
+        #[cfg(feature = "htslib")]
+        return bcf::bcf_batch(
+            contents.as_bytes(),
+            self.options.compression_level.is_some(),
+            self.staging_dir(),
+        )
+        .map(Cow::Owned)
+        .map_err(BGZFError::from);
+        #[cfg(not(feature = "htslib"))]
+        {
+            let _ = contents;
+            Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "BCF output needs the htslib feature",
+            )
+            .into())
+        }
+    }
+
+    /// Whether the batches are written as Parquet files instead of VCF files.
+    fn parquet_only(&self) -> bool {
+        #[cfg(feature = "parquet")]
+        return self.options.parquet == ParquetExport::Only;
+        #[cfg(not(feature = "parquet"))]
+        false
+    }
+
+    /// Returns the path of a batch relative to the output directory.
+    fn file_name(
+        &self,
+        batch_number: usize,
+        first: Option<&Locus>,
+        last: Option<&Locus>,
+        label: BatchLabel,
+    ) -> PathBuf {
+        let file_name = match label.group {
+            Some((group, group_number)) => {
+                self.group_dir(group)
+                    .join(self.vcf_file_name(group_number, first, last, label))
+            }
+            None => self.vcf_file_name(batch_number, first, last, label),
+        };
+        #[cfg(feature = "parquet")]
+        if self.parquet_only() {
+            return sites::parquet_path(&file_name);
+        }
+        match self.options.format {
+            OutputFormat::Vcf => file_name,
+            OutputFormat::Jsonl => jsonl::jsonl_path(&file_name),
+            OutputFormat::Bcf => bcf::bcf_path(&file_name),
+        }
+    }
+
+    fn vcf_file_name(
+        &self,
+        batch_number: usize,
+        first: Option<&Locus>,
+        last: Option<&Locus>,
+        label: BatchLabel,
+    ) -> PathBuf {
+        let gene = label.gene;
+        let chrom = first.map_or("", |locus| locus.chrom.as_str());
+        let extension = self.options.vcf_extension();
+        match &self.options.name_template {
+            Some(template) => template.render(&NameValues {
+                number: batch_number,
+                stem: &self.stem,
+                chrom,
+                first,
+                last,
+                gene: gene.unwrap_or_default(),
+            }),
+            None if self.options.split_by == SplitMode::Chromosome && !chrom.is_empty() => {
+                PathBuf::from(naming::sanitize_component(chrom) + extension)
+            }
+            None if self.options.split_by == SplitMode::Region => {
+                PathBuf::from(naming::sanitize_component(label.region.unwrap_or(chrom)) + extension)
+            }
+            // Gene symbols can repeat on other chromosomes, e.g. in the pseudoautosomal regions
+            None if self.options.split_by == SplitMode::Gene && !chrom.is_empty() => {
+                PathBuf::from(format!(
+                    "{}_{}{}",
+                    naming::sanitize_component(chrom),
+                    naming::sanitize_component(gene.unwrap_or(genes::INTERGENIC)),
+                    extension
+                ))
+            }
+            None => PathBuf::from(format!("batch_{:02}{}", batch_number, extension)),
+        }
+    }
+
+    /// Returns the checksum of the batch from the previous run if it has the expected size
+    /// and its file is unchanged.
+    fn reusable_batch(
+        &self,
+        batch_number: usize,
+        file: &Path,
+        path: &Path,
+        records: usize,
+    ) -> Option<String> {
+        let previous = self.previous.get(&batch_number)?;
+        if Path::new(&previous.file) != file || previous.records != records {
+            return None;
+        }
+        let sha256 = manifest::file_sha256(path).ok()?;
+        (sha256 == previous.sha256).then_some(sha256)
+    }
+
+    fn write_error(
+        &mut self,
+        batch_number: usize,
+        path: &Path,
+        source: BGZFError,
+    ) -> VcfBatcherError {
+        VcfBatcherError::BatchWrite {
+            batch_number,
+            path: path.to_path_buf(),
+            completed: Box::new(std::mem::take(&mut self.report)),
+            source,
+        }
+    }
+
+    /// Completes the run. Resumable runs, runs that verify their batches and runs that append to
+    /// earlier batches write their manifest, resumable runs also drop the journal.
+    fn finish(mut self) -> Result<RunReport, VcfBatcherError> {
+        self.report.elapsed = self.started.elapsed();
+        if let Some(bed) = &self.bed {
+            let path = self.output_path.join(bed::BED_FILE_NAME);
+            write_staged_batch_file(&path, self.options.tmp_dir.as_deref(), bed.as_bytes())
+                .map_err(|source| VcfBatcherError::BatchWrite {
+                    batch_number: self.report.batches.len(),
+                    path,
+                    completed: Box::new(self.report.clone()),
+                    source: source.into(),
+                })?;
+        }
+        let manifest_error = |source: io::Error| VcfBatcherError::BatchWrite {
+            batch_number: self.report.batches.len(),
+            path: self.output_path.join(manifest::MANIFEST_FILE_NAME),
+            completed: Box::new(self.report.clone()),
+            source: source.into(),
+        };
+        let appends = self.options.append.is_some() && self.sink.is_none();
+        if let Some(replaced) = self.replaced.as_ref().filter(|_| !self.dry_run) {
+            // The tail was written under another name, e.g. after its first chromosome
+            if !self
+                .report
+                .batches
+                .iter()
+                .any(|batch| &batch.path == replaced)
+            {
+                fs::remove_file(replaced).map_err(manifest_error)?;
+            }
+        }
+        let grouped = self.options.group_by_info.is_some() && self.sink.is_none();
+        let listed = self.options.manifest && self.sink.is_none();
+        if self.journal.is_some()
+            || ((self.verifies_batches() || appends || grouped || listed) && !self.dry_run)
+        {
+            Manifest::from_report(&self.report, true)
+                .write(&self.output_path)
+                .map_err(manifest_error)?;
+        }
+        if let Some(journal) = self.journal {
+            journal.remove().map_err(manifest_error)?;
+        }
+        Ok(self.report)
     }
 }
 
+/// The highest numeric compression level that [`parse_compression`] accepts.
+pub const MAX_COMPRESSION_LEVEL: u32 = 12;
+
 /// Parses the user input for the compression level and returns the corresponding compression level
 /// from the bgzip crate.
+///
+/// Unknown levels are treated like "none", see [`parse_compression`] for a parser that rejects them.
+#[deprecated(note = "use `parse_compression`, which rejects unknown compression levels")]
 pub fn parse_compression_level(raw_compression_level: Option<String>) -> Option<Compression> {
-    match raw_compression_level {
-        Some(user_input) => match user_input.to_lowercase().as_ref() {
-            "fast" => Some(Compression::fast()),
-            "best" => Some(Compression::best()),
-            "default" => Some(Compression::default()),
-            _ => None,
-        },
-        None => None,
-    }
-}
-
-/// Wrapper function for extract_variants_to_batches to be called from Python
-#[pyfunction]
-fn py_extract_variants_to_batches(
-    file_path: &str,
-    output_path: &str,
-    batch_size: usize,
-    compression_level: Option<String>,
-) -> PyResult<()> {
-    extract_variants_to_batches(
-        file_path,
-        batch_size,
-        Path::new(output_path),
-        parse_compression_level(compression_level),
-    );
-    Ok(())
+    raw_compression_level.and_then(|level| parse_compression(&level).ok().flatten())
 }
 
-#[pymodule]
-fn vcf_batcher(_py: Python, m: &PyModule) -> PyResult<()> {
-    m.add_function(wrap_pyfunction!(py_extract_variants_to_batches, m)?)?;
-    Ok(())
+/// Parses a compression level, which is `none` for plain, uncompressed VCF files, one of the
+/// names `fast`, `default` and `best` or a number from `0` to [`MAX_COMPRESSION_LEVEL`] for
+/// bgzipped files. The names are case-insensitive. Unknown levels are an error.
+///
+/// # Examples
+///
+/// ```
+/// use bgzip::Compression;
+/// use vcf_batcher::parse_compression;
+///
+/// assert_eq!(parse_compression("Best").unwrap(), Some(Compression::best()));
+/// assert_eq!(parse_compression("none").unwrap(), None);
+/// assert!(parse_compression("6").unwrap().is_some());
+/// assert!(parse_compression("fastest").is_err());
+/// assert!(parse_compression("13").is_err());
+/// ```
+pub fn parse_compression(level: &str) -> Result<Option<Compression>, VcfBatcherError> {
+    Ok(recompress::Level::parse(level)?.map(recompress::Level::bgzf))
 }
 
 #[cfg(test)]
+// The oldest tests compare with literal bools
+#[allow(clippy::bool_assert_comparison)]
 mod tests {
-    use bgzip::Compression;
+    use bgzip::{BGZFReader, Compression};
+
+    use std::fs::File;
+    use std::path::PathBuf;
+    use std::sync::atomic::AtomicBool;
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
 
-    use crate::{extract_variants_to_batches, is_header_line, parse_compression_level, read_lines};
+    use crate::filter::RecordFilter;
+    use crate::index::{read_contig_spans, tabix_path};
+    use crate::manifest::Manifest;
+    use crate::mask::SampleMask;
+    use crate::naming::NameTemplate;
+    use crate::testdata::SyntheticVcf;
+    use crate::{
+        extract_variants_from_reader, extract_variants_to_batches,
+        extract_variants_to_batches_with_options, is_header_line, parse_compression, plan_batches,
+        read_lines, AppendMode, BadRecordPolicy, BatchCallback, BatchOptions, CompressionFormat,
+        ContigCheck, DuplicateSamplePolicy, EmptyInputPolicy, IndexFormat, LateHeaderPolicy, Locus,
+        OverwritePolicy, ProgressCallback, SplitMode, VcfBatcherError,
+    };
 
     #[test]
     fn test_is_header_line() {
@@ -287,7 +1548,8 @@ mod tests {
             10,
             std::path::Path::new("./test_data/result_batches"),
             compression_level,
-        );
+        )
+        .unwrap();
         // Check if 10 batches were created
         for i in 1..=10 {
             let batch_file_path = match compression_level {
@@ -318,7 +1580,1340 @@ mod tests {
     }
 
     #[test]
+    fn test_write_failure_keeps_completed_batches() {
+        let output_path = std::path::Path::new("./test_data/write_failure");
+        let _ = std::fs::remove_dir_all(output_path);
+        // A directory in place of the third batch makes writing that batch fail
+        std::fs::create_dir_all(output_path.join("batch_03.vcf")).unwrap();
+
+        let error =
+            extract_variants_to_batches("./test_data/batch_01.vcf.gz", 10, output_path, None)
+                .unwrap_err();
+
+        match &error {
+            VcfBatcherError::BatchWrite {
+                batch_number,
+                completed,
+                ..
+            } => {
+                assert_eq!(*batch_number, 3);
+                assert_eq!(completed.batches.len(), 2);
+                assert_eq!(completed.total_records(), 20);
+                assert!(completed.batches.iter().all(|batch| batch.path.is_file()));
+            }
+            other => panic!("Unexpected error: {}", other),
+        }
+        assert!(!output_path.join("batch_04.vcf").exists());
+    }
+
+    #[test]
+    fn test_cancelled_run_writes_nothing() {
+        let output_path = std::path::Path::new("./test_data/cancelled");
+        let _ = std::fs::remove_dir_all(output_path);
+        let options = BatchOptions {
+            batch_size: 10,
+            cancel: Some(Arc::new(AtomicBool::new(true))),
+            ..Default::default()
+        };
+
+        let result = extract_variants_to_batches_with_options(
+            "./test_data/batch_01.vcf.gz",
+            output_path,
+            &options,
+        );
+
+        match result {
+            Err(VcfBatcherError::Interrupted { completed }) => {
+                assert!(completed.batches.is_empty())
+            }
+            other => panic!("Expected an interruption, got {:?}", other),
+        }
+        assert!(!output_path.join("batch_01.vcf").exists());
+    }
+
+    #[test]
+    fn test_locked_output_directory_is_an_error() {
+        let output_path = std::path::Path::new("./test_data/locked");
+        let _ = std::fs::remove_dir_all(output_path);
+        let _lock = crate::lock::OutputLock::acquire(output_path).unwrap();
+
+        let result =
+            extract_variants_to_batches("./test_data/batch_01.vcf.gz", 10, output_path, None);
+
+        assert!(matches!(result, Err(VcfBatcherError::OutputLocked { .. })));
+        assert!(!output_path.join("batch_01.vcf").exists());
+    }
+
+    #[test]
+    fn test_empty_input_policies() {
+        let input_path = "./test_data/inputs/header_only.vcf";
+        std::fs::create_dir_all("./test_data/inputs").unwrap();
+        std::fs::write(
+            input_path,
+            "##fileformat=VCFv4.2\n#CHROM\tPOS\tID\tREF\tALT\tQUAL\tFILTER\tINFO\n",
+        )
+        .unwrap();
+        let output_path = std::path::Path::new("./test_data/header_only");
+        let _ = std::fs::remove_dir_all(output_path);
+        let options = |empty_input| BatchOptions {
+            batch_size: 10,
+            empty_input,
+            ..Default::default()
+        };
+
+        let report = extract_variants_to_batches_with_options(
+            input_path,
+            output_path,
+            &options(EmptyInputPolicy::Skip),
+        )
+        .unwrap();
+        assert!(report.batches.is_empty());
+        assert_eq!(report.empty_input, Some(EmptyInputPolicy::Skip));
+
+        let report = extract_variants_to_batches_with_options(
+            input_path,
+            output_path,
+            &options(EmptyInputPolicy::HeaderOnly),
+        )
+        .unwrap();
+        assert_eq!(report.batches.len(), 1);
+        assert_eq!(report.batches[0].records, 0);
+        assert_eq!(
+            std::fs::read_to_string(&report.batches[0].path).unwrap(),
+            std::fs::read_to_string(input_path).unwrap()
+        );
+
+        let result = extract_variants_to_batches_with_options(
+            input_path,
+            output_path,
+            &options(EmptyInputPolicy::Error),
+        );
+        assert!(matches!(result, Err(VcfBatcherError::EmptyInput { .. })));
+    }
+
+    #[test]
+    fn test_late_header_policies() {
+        let input_path = "./test_data/inputs/late_header.vcf";
+        std::fs::create_dir_all("./test_data/inputs").unwrap();
+        std::fs::write(
+            input_path,
+            "##fileformat=VCFv4.2\n#CHROM\tPOS\tID\tREF\tALT\tQUAL\tFILTER\tINFO\n\
+             1\t100\t.\tA\tG\t.\tPASS\t.\n\
+             ##comment\n\
+             1\t200\t.\tC\tT\t.\tPASS\t.\n",
+        )
+        .unwrap();
+        let output_path = std::path::Path::new("./test_data/late_header");
+        let options = |late_header| BatchOptions {
+            batch_size: 1,
+            late_header,
+            ..Default::default()
+        };
+
+        let result = extract_variants_to_batches_with_options(
+            input_path,
+            output_path,
+            &options(LateHeaderPolicy::Error),
+        );
+        assert!(matches!(
+            result,
+            Err(VcfBatcherError::LateHeaderLine { line_number: 4, .. })
+        ));
+
+        let report = extract_variants_to_batches_with_options(
+            input_path,
+            output_path,
+            &options(LateHeaderPolicy::Skip),
+        )
+        .unwrap();
+        assert_eq!(report.late_header_lines, 1);
+        assert!(!std::fs::read_to_string(&report.batches[1].path)
+            .unwrap()
+            .contains("##comment"));
+
+        let report = extract_variants_to_batches_with_options(
+            input_path,
+            output_path,
+            &options(LateHeaderPolicy::Header),
+        )
+        .unwrap();
+        assert!(std::fs::read_to_string(&report.batches[1].path)
+            .unwrap()
+            .contains("##comment"));
+    }
+
+    #[test]
+    fn test_check_contigs() {
+        // The test file does not declare any contigs in its header
+        let output_path = std::path::Path::new("./test_data/check_contigs");
+        let options = |check_contigs| BatchOptions {
+            batch_size: 50,
+            check_contigs,
+            ..Default::default()
+        };
+
+        let report = extract_variants_to_batches_with_options(
+            "./test_data/batch_01.vcf.gz",
+            output_path,
+            &options(ContigCheck::Warn),
+        )
+        .unwrap();
+        assert_eq!(report.unknown_contigs.get("22"), Some(&100));
+
+        let result = extract_variants_to_batches_with_options(
+            "./test_data/batch_01.vcf.gz",
+            output_path,
+            &options(ContigCheck::Error),
+        );
+        assert!(matches!(
+            result,
+            Err(VcfBatcherError::UnknownContig {
+                line_number: 31,
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn test_resume_skips_completed_batches() {
+        let output_path = std::path::Path::new("./test_data/resume");
+        let _ = std::fs::remove_dir_all(output_path);
+        let options = BatchOptions {
+            batch_size: 10,
+            resume: true,
+            ..Default::default()
+        };
+
+        let first = extract_variants_to_batches_with_options(
+            "./test_data/batch_01.vcf.gz",
+            output_path,
+            &options,
+        )
+        .unwrap();
+        assert_eq!(first.resumed_batches, 0);
+        assert!(output_path
+            .join(crate::manifest::MANIFEST_FILE_NAME)
+            .is_file());
+        assert!(!output_path
+            .join(crate::manifest::JOURNAL_FILE_NAME)
+            .exists());
+
+        // One batch went missing and one was corrupted since the first run
+        std::fs::remove_file(output_path.join("batch_04.vcf")).unwrap();
+        std::fs::write(output_path.join("batch_05.vcf"), "corrupted").unwrap();
+
+        let second = extract_variants_to_batches_with_options(
+            "./test_data/batch_01.vcf.gz",
+            output_path,
+            &options,
+        )
+        .unwrap();
+        assert_eq!(second.resumed_batches, 8);
+        assert_eq!(first.batches, second.batches);
+
+        // Different options produce different batches, so nothing is reused
+        let third = extract_variants_to_batches_with_options(
+            "./test_data/batch_01.vcf.gz",
+            output_path,
+            &BatchOptions {
+                batch_size: 20,
+                ..options
+            },
+        )
+        .unwrap();
+        assert_eq!(third.resumed_batches, 0);
+    }
+
+    #[test]
+    fn test_duplicate_samples() {
+        let input_path = "./test_data/inputs/duplicate_samples.vcf";
+        std::fs::create_dir_all("./test_data/inputs").unwrap();
+        std::fs::write(
+            input_path,
+            "##fileformat=VCFv4.2\n\
+             #CHROM\tPOS\tID\tREF\tALT\tQUAL\tFILTER\tINFO\tFORMAT\tS1\tS2\tS1\n\
+             1\t100\t.\tA\tG\t.\tPASS\t.\tGT\t0|0\t0|1\t1|1\n",
+        )
+        .unwrap();
+        let output_path = std::path::Path::new("./test_data/duplicate_samples");
+        let options = |duplicate_samples| BatchOptions {
+            duplicate_samples,
+            ..Default::default()
+        };
+
+        let result = extract_variants_to_batches_with_options(
+            input_path,
+            output_path,
+            &options(DuplicateSamplePolicy::Error),
+        );
+        assert!(
+            matches!(result, Err(VcfBatcherError::DuplicateSamples { samples }) if samples == ["S1"])
+        );
+
+        let report = extract_variants_to_batches_with_options(
+            input_path,
+            output_path,
+            &options(DuplicateSamplePolicy::Warn),
+        )
+        .unwrap();
+        assert_eq!(report.duplicate_samples, vec!["S1"]);
+        assert_eq!(report.batches.len(), 1);
+    }
+
+    #[test]
+    fn test_tmp_dir() {
+        let root = std::path::Path::new("./test_data/tmp_dir");
+        let _ = std::fs::remove_dir_all(root);
+        let output_path = root.join("batches");
+        let tmp_dir = root.join("tmp");
+        let options = BatchOptions {
+            batch_size: 30,
+            compression_level: Some(Compression::fast()),
+            tmp_dir: Some(tmp_dir.clone()),
+            ..Default::default()
+        };
+
+        let report = extract_variants_to_batches_with_options(
+            "./test_data/batch_01.vcf.gz",
+            &output_path,
+            &options,
+        )
+        .unwrap();
+
+        assert_eq!(report.batches.len(), 4);
+        for batch in &report.batches {
+            assert_eq!(
+                crate::manifest::file_sha256(&batch.path).unwrap(),
+                batch.sha256
+            );
+        }
+        assert_eq!(std::fs::read_dir(&tmp_dir).unwrap().count(), 0);
+    }
+
+    #[test]
+    fn test_jsonl_format() {
+        let output_path = std::path::Path::new("./test_data/jsonl_format");
+        let _ = std::fs::remove_dir_all(output_path);
+        let options = BatchOptions {
+            batch_size: 30,
+            compression_level: Some(Compression::fast()),
+            format: crate::OutputFormat::Jsonl,
+            json_fields: vec![crate::JsonField::Chrom, crate::JsonField::Pos],
+            ..Default::default()
+        };
+
+        let report = extract_variants_to_batches_with_options(
+            "./test_data/batch_01.vcf.gz",
+            output_path,
+            &options,
+        )
+        .unwrap();
+
+        assert_eq!(
+            report.batches[0].path,
+            output_path.join("batch_01.jsonl.gz")
+        );
+        let lines: Vec<String> = read_lines(&report.batches[0].path)
+            .unwrap()
+            .map(Result::unwrap)
+            .collect();
+        assert_eq!(lines.len(), 30);
+        let record: serde_json::Value = serde_json::from_str(&lines[0]).unwrap();
+        assert_eq!(record.as_object().unwrap().len(), 2);
+        assert!(record["POS"].is_u64());
+    }
+
+    #[cfg(not(feature = "htslib"))]
+    #[test]
+    fn test_bcf_needs_htslib() {
+        let output_path = std::path::Path::new("./test_data/bcf_format");
+        let options = BatchOptions {
+            format: crate::OutputFormat::Bcf,
+            ..Default::default()
+        };
+        let result = extract_variants_to_batches_with_options(
+            "./test_data/batch_01.vcf.gz",
+            output_path,
+            &options,
+        );
+        assert!(matches!(
+            result,
+            Err(VcfBatcherError::FeatureDisabled {
+                feature: "htslib",
+                ..
+            })
+        ));
+
+        let result = extract_variants_to_batches_with_options(
+            "./test_data/inputs/cohort.bcf",
+            output_path,
+            &BatchOptions::default(),
+        );
+        assert!(matches!(
+            result,
+            Err(VcfBatcherError::FeatureDisabled { .. })
+        ));
+    }
+
+    #[test]
+    fn test_site_lists() {
+        let output_path = std::path::Path::new("./test_data/site_lists");
+        let _ = std::fs::remove_dir_all(output_path);
+        let options = BatchOptions {
+            batch_size: 30,
+            compression_level: Some(Compression::fast()),
+            site_lists: true,
+            ..Default::default()
+        };
+
+        extract_variants_to_batches_with_options(
+            "./test_data/batch_01.vcf.gz",
+            output_path,
+            &options,
+        )
+        .unwrap();
+
+        let list = std::fs::read_to_string(output_path.join("batch_04.sites.tsv")).unwrap();
+        let lines: Vec<&str> = list.lines().collect();
+        assert_eq!(lines[0], "CHROM\tPOS\tREF\tALT");
+        assert_eq!(lines.len(), 11);
+        assert_eq!(lines[1].split('\t').count(), 4);
+    }
+
+    #[test]
+    fn test_tabix_indexes() {
+        use std::io::BufRead;
+
+        let output_path = std::path::Path::new("./test_data/tabix_indexes");
+        let _ = std::fs::remove_dir_all(output_path);
+        let options = BatchOptions {
+            batch_size: 30,
+            compression_level: Some(Compression::fast()),
+            index: Some(IndexFormat::Tabix),
+            ..Default::default()
+        };
+
+        let report = extract_variants_to_batches_with_options(
+            "./test_data/batch_01.vcf.gz",
+            output_path,
+            &options,
+        )
+        .unwrap();
+
+        assert_eq!(report.batches.len(), 4);
+        for batch in &report.batches {
+            let spans = read_contig_spans(&tabix_path(&batch.path)).unwrap();
+            assert_eq!(spans.len(), 1);
+            let mut reader = BGZFReader::new(File::open(&batch.path).unwrap()).unwrap();
+            reader.bgzf_seek(spans[0].start).unwrap();
+            let mut line = String::new();
+            reader.read_line(&mut line).unwrap();
+            assert_eq!(Locus::from_record(&line), batch.first);
+        }
+
+        let options = BatchOptions {
+            compression_level: None,
+            ..options
+        };
+        let result = extract_variants_to_batches_with_options(
+            "./test_data/batch_01.vcf.gz",
+            output_path,
+            &options,
+        );
+        assert!(matches!(
+            result,
+            Err(VcfBatcherError::InvalidOptions { .. })
+        ));
+    }
+
+    #[test]
+    fn test_batches_bed() {
+        let output_path = std::path::Path::new("./test_data/batches_bed");
+        let _ = std::fs::remove_dir_all(output_path);
+        let options = BatchOptions {
+            batch_size: 30,
+            batches_bed: true,
+            ..Default::default()
+        };
+
+        let report = extract_variants_to_batches_with_options(
+            "./test_data/batch_01.vcf.gz",
+            output_path,
+            &options,
+        )
+        .unwrap();
+
+        let bed = std::fs::read_to_string(output_path.join("batches.bed")).unwrap();
+        let lines: Vec<Vec<&str>> = bed.lines().map(|line| line.split('\t').collect()).collect();
+        assert_eq!(lines.len(), 4);
+        let first = report.batches[0].first.as_ref().unwrap();
+        assert_eq!(lines[0][0], first.chrom);
+        assert_eq!(lines[0][1], (first.pos - 1).to_string());
+        assert_eq!(lines[3][3], "batch_04.vcf");
+    }
+
+    #[test]
+    fn test_verify_output() {
+        let output_path = std::path::Path::new("./test_data/verify_output");
+        let _ = std::fs::remove_dir_all(output_path);
+        let options = BatchOptions {
+            batch_size: 30,
+            compression_level: Some(Compression::fast()),
+            verify_output: true,
+            ..Default::default()
+        };
+
+        let report = extract_variants_to_batches_with_options(
+            "./test_data/batch_01.vcf.gz",
+            output_path,
+            &options,
+        )
+        .unwrap();
+
+        assert!(report.batches.iter().all(|batch| batch.verified));
+        let manifest = crate::manifest::Manifest::read(output_path)
+            .unwrap()
+            .unwrap();
+        assert_eq!(manifest.batches.len(), 4);
+        assert!(manifest.batches.iter().all(|entry| entry.verified));
+    }
+
+    #[test]
+    fn test_zstd_batches() {
+        let output_path = std::path::Path::new("./test_data/zstd_batches");
+        let _ = std::fs::remove_dir_all(output_path);
+        let options = BatchOptions {
+            batch_size: 30,
+            compression_level: Some(Compression::fast()),
+            compression_format: CompressionFormat::Zstd,
+            verify_output: true,
+            quiet: true,
+            ..Default::default()
+        };
+
+        let report = extract_variants_to_batches_with_options(
+            "./test_data/batch_01.vcf.gz",
+            output_path,
+            &options,
+        )
+        .unwrap();
+        assert_eq!(report.batches.len(), 4);
+        assert!(report.batches.iter().all(|batch| batch.verified));
+        assert_eq!(report.batches[0].path, output_path.join("batch_01.vcf.zst"));
+        let batch = std::fs::read(&report.batches[0].path).unwrap();
+        assert!(batch.starts_with(crate::recompress::ZSTD_MAGIC));
+        let lines: Vec<String> = read_lines(&report.batches[3].path)
+            .unwrap()
+            .map(Result::unwrap)
+            .collect();
+        assert_eq!(
+            lines.iter().filter(|line| !is_header_line(line)).count(),
+            10
+        );
+
+        // Batches that are collected in memory are compressed the same way
+        let mut received = Vec::new();
+        let mut sink = |batch: &crate::BatchInfo, contents: &[u8]| {
+            received.push((batch.path.clone(), contents.to_vec()));
+            Ok(())
+        };
+        crate::extract_variants_to_sink("./test_data/batch_01.vcf.gz", &mut sink, &options)
+            .unwrap();
+        assert_eq!(received[0].0, std::path::Path::new("batch_01.vcf.zst"));
+        let lines = crate::read_lines_from(received[0].1.as_slice(), None).unwrap();
+        assert_eq!(lines.count(), 30 + 30);
+
+        let options = BatchOptions {
+            index: Some(IndexFormat::Tabix),
+            ..options
+        };
+        assert!(matches!(
+            extract_variants_to_batches_with_options(
+                "./test_data/batch_01.vcf.gz",
+                output_path,
+                &options,
+            ),
+            Err(VcfBatcherError::InvalidOptions { .. })
+        ));
+    }
+
+    #[test]
+    fn test_synthetic_input() {
+        let root = std::path::Path::new("./test_data/synthetic_input");
+        let _ = std::fs::remove_dir_all(root);
+        let input = root.join("input.vcf.gz");
+        let vcf = SyntheticVcf {
+            samples: 20,
+            records: 2500,
+            ..Default::default()
+        };
+        vcf.write_file(&input, Some(Compression::fast())).unwrap();
+        let options = BatchOptions {
+            batch_size: 1000,
+            ..Default::default()
+        };
+
+        let report = extract_variants_to_batches_with_options(
+            &input.to_string_lossy(),
+            &root.join("batches"),
+            &options,
+        )
+        .unwrap();
+
+        let records: Vec<usize> = report.batches.iter().map(|batch| batch.records).collect();
+        assert_eq!(records, [1000, 1000, 500]);
+        assert_eq!(report.bad_records, 0);
+    }
+
+    #[test]
+    fn test_split_by_samples() {
+        let root = std::path::Path::new("./test_data/split_by_samples");
+        let _ = std::fs::remove_dir_all(root);
+        let input = root.join("input.vcf.gz");
+        let vcf = SyntheticVcf {
+            samples: 5,
+            records: 200,
+            ..Default::default()
+        };
+        vcf.write_file(&input, Some(Compression::fast())).unwrap();
+        let options = BatchOptions {
+            split_by: SplitMode::Samples,
+            samples_per_batch: 2,
+            compression_level: Some(Compression::fast()),
+            ..Default::default()
+        };
+
+        let report = extract_variants_to_batches_with_options(
+            &input.to_string_lossy(),
+            &root.join("streamed"),
+            &options,
+        )
+        .unwrap();
+
+        let records: Vec<usize> = report.batches.iter().map(|batch| batch.records).collect();
+        assert_eq!(records, [200, 200, 200]);
+        let last: Vec<String> = read_lines(&report.batches[2].path)
+            .unwrap()
+            .map(Result::unwrap)
+            .collect();
+        let chrom_line = last.iter().find(|line| line.starts_with("#CHROM")).unwrap();
+        assert!(chrom_line.ends_with("\tFORMAT\tSAMPLE_0005"));
+        assert!(last
+            .iter()
+            .filter(|line| !is_header_line(line))
+            .all(|line| line.split('\t').count() == 10));
+
+        // Batches that are collected in memory get the same records
+        let options = BatchOptions {
+            site_lists: true,
+            ..options
+        };
+        let collected = extract_variants_to_batches_with_options(
+            &input.to_string_lossy(),
+            &root.join("collected"),
+            &options,
+        )
+        .unwrap();
+        for (streamed, collected) in report.batches.iter().zip(&collected.batches) {
+            let lines = |path| read_lines(path).unwrap().map(Result::unwrap);
+            assert!(lines(&streamed.path).eq(lines(&collected.path)));
+        }
+    }
+
+    #[cfg(feature = "parquet")]
+    #[test]
+    fn test_parquet_export() {
+        let root = std::path::Path::new("./test_data/parquet_export");
+        let _ = std::fs::remove_dir_all(root);
+        let options = BatchOptions {
+            batch_size: 30,
+            compression_level: Some(Compression::fast()),
+            parquet: crate::ParquetExport::Alongside,
+            ..Default::default()
+        };
+
+        let report = extract_variants_to_batches_with_options(
+            "./test_data/batch_01.vcf.gz",
+            &root.join("alongside"),
+            &options,
+        )
+        .unwrap();
+        assert_eq!(report.batches.len(), 4);
+        assert!(root.join("alongside/batch_01.vcf.gz").exists());
+        assert!(root.join("alongside/batch_04.parquet").exists());
+
+        let options = BatchOptions {
+            parquet: crate::ParquetExport::Only,
+            ..options
+        };
+        let report = extract_variants_to_batches_with_options(
+            "./test_data/batch_01.vcf.gz",
+            &root.join("only"),
+            &options,
+        )
+        .unwrap();
+        assert_eq!(report.batches[0].path, root.join("only/batch_01.parquet"));
+        assert!(std::fs::read(&report.batches[0].path)
+            .unwrap()
+            .starts_with(b"PAR1"));
+        assert!(!root.join("only/batch_01.vcf.gz").exists());
+    }
+
+    #[test]
+    fn test_stage_timings() {
+        let output_path = std::path::Path::new("./test_data/stage_timings");
+        let options = BatchOptions {
+            batch_size: 30,
+            compression_level: Some(Compression::fast()),
+            timings: true,
+            ..Default::default()
+        };
+
+        let report = extract_variants_to_batches_with_options(
+            "./test_data/batch_01.vcf.gz",
+            output_path,
+            &options,
+        )
+        .unwrap();
+
+        let timings = report.timings.unwrap();
+        assert!(timings.reading > Duration::ZERO);
+        assert!(timings.compression > Duration::ZERO);
+        assert!(timings.writing > Duration::ZERO);
+        let measured: Duration = timings.stages().iter().map(|(_, duration)| *duration).sum();
+        assert!(measured <= report.elapsed);
+
+        let report =
+            extract_variants_to_batches("./test_data/batch_01.vcf.gz", 30, output_path, None)
+                .unwrap();
+        assert_eq!(report.timings, None);
+    }
+
+    #[test]
+    fn test_bad_record_policies() {
+        let input_path = "./test_data/inputs/bad_records.vcf";
+        std::fs::create_dir_all("./test_data/inputs").unwrap();
+        std::fs::write(
+            input_path,
+            "##fileformat=VCFv4.2\n\
+             #CHROM\tPOS\tID\tREF\tALT\tQUAL\tFILTER\tINFO\tFORMAT\tS1\n\
+             1\t100\t.\tA\tG\t.\tPASS\t.\tGT\t0|1\n\
+             1\t200\t.\tC\tT\t.\tPASS\t.\tGT\n\
+             1\tabc\t.\tC\tT\t.\tPASS\t.\tGT\t1|1\n\
+             1\t300\t.\tG\tA\t.\tPASS\t.\tGT\t0|0\n",
+        )
+        .unwrap();
+        let output_path = std::path::Path::new("./test_data/bad_records");
+        let _ = std::fs::remove_dir_all(output_path);
+        let options = |bad_records| BatchOptions {
+            bad_records,
+            ..Default::default()
+        };
+
+        let result = extract_variants_to_batches_with_options(
+            input_path,
+            output_path,
+            &options(BadRecordPolicy::Fail),
+        );
+        assert!(matches!(
+            result,
+            Err(VcfBatcherError::MalformedRecord { line_number: 4, .. })
+        ));
+
+        let report = extract_variants_to_batches_with_options(
+            input_path,
+            output_path,
+            &options(BadRecordPolicy::Skip),
+        )
+        .unwrap();
+        assert_eq!(report.bad_records, 2);
+        assert_eq!(report.total_records(), 2);
+        assert!(!output_path
+            .join(crate::writer::BAD_RECORDS_FILE_NAME)
+            .exists());
+
+        let report = extract_variants_to_batches_with_options(
+            input_path,
+            output_path,
+            &options(BadRecordPolicy::Quarantine),
+        )
+        .unwrap();
+        assert_eq!(report.bad_records, 2);
+        let quarantined =
+            std::fs::read_to_string(output_path.join(crate::writer::BAD_RECORDS_FILE_NAME))
+                .unwrap();
+        let lines: Vec<&str> = quarantined.lines().collect();
+        assert_eq!(lines.len(), 3);
+        assert!(
+            lines[1].starts_with("4\texpected 10 columns like the #CHROM line, found 9\t1\t200")
+        );
+        assert!(lines[2].starts_with("5\tPOS 'abc' is not a whole number\t"));
+    }
+
+    #[test]
+    fn test_line_length_limit() {
+        let options = BatchOptions {
+            max_line_length: Some(1000),
+            ..Default::default()
+        };
+        let result = extract_variants_to_batches_with_options(
+            "./test_data/batch_01.vcf.gz",
+            std::path::Path::new("./test_data/line_length_limit"),
+            &options,
+        );
+        assert!(matches!(
+            result,
+            Err(VcfBatcherError::LineTooLong {
+                line_number: 30,
+                limit: 1000
+            })
+        ));
+    }
+
+    #[test]
+    fn test_name_template() {
+        let output_path = std::path::Path::new("./test_data/name_template");
+        let _ = std::fs::remove_dir_all(output_path);
+        let options = BatchOptions {
+            batch_size: 50,
+            name_template: Some(NameTemplate::parse("chr{chrom}/{stem}_{number:03}.vcf").unwrap()),
+            resume: true,
+            ..Default::default()
+        };
+
+        let report = extract_variants_to_batches_with_options(
+            "./test_data/batch_01.vcf.gz",
+            output_path,
+            &options,
+        )
+        .unwrap();
+
+        assert_eq!(
+            report.batches[1].path,
+            output_path.join("chr22/batch_01_002.vcf")
+        );
+        assert!(report.batches[1].path.is_file());
+        let manifest = crate::manifest::Manifest::read(output_path)
+            .unwrap()
+            .unwrap();
+        assert_eq!(manifest.batches[0].file, "chr22/batch_01_001.vcf");
+
+        let options = BatchOptions {
+            name_template: Some(NameTemplate::region(".vcf.gz")),
+            compression_level: Some(Compression::fast()),
+            quiet: true,
+            ..options
+        };
+        let report = extract_variants_to_batches_with_options(
+            "./test_data/batch_01.vcf.gz",
+            &output_path.join("regions"),
+            &options,
+        )
+        .unwrap();
+        assert_eq!(
+            report.batches[0].path,
+            output_path.join("regions/22_018570346-018572476.vcf.gz")
+        );
+    }
+
+    #[test]
+    fn test_on_batch_callback() {
+        let output_path = std::path::Path::new("./test_data/on_batch");
+        let completed = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+        let options = BatchOptions {
+            batch_size: 40,
+            on_batch: Some(BatchCallback::new({
+                let completed = std::sync::Arc::clone(&completed);
+                move |batch| {
+                    assert!(batch.path.is_file());
+                    completed.lock().unwrap().push(batch.records);
+                }
+            })),
+            ..Default::default()
+        };
+
+        extract_variants_to_batches_with_options(
+            "./test_data/batch_01.vcf.gz",
+            output_path,
+            &options,
+        )
+        .unwrap();
+
+        assert_eq!(*completed.lock().unwrap(), vec![40, 40, 20]);
+    }
+
+    #[test]
+    fn test_batch_boundaries() {
+        let report = extract_variants_to_batches(
+            "./test_data/batch_01.vcf.gz",
+            60,
+            std::path::Path::new("./test_data/batch_boundaries"),
+            None,
+        )
+        .unwrap();
+
+        let first_record = read_lines("./test_data/batch_01.vcf.gz")
+            .unwrap()
+            .map(Result::unwrap)
+            .find(|line| !is_header_line(line))
+            .unwrap();
+        let [first_batch, second_batch] = &report.batches[..] else {
+            panic!("expected two batches");
+        };
+        assert_eq!(first_batch.first, crate::Locus::from_record(&first_record));
+        assert!(first_batch.last.as_ref().unwrap().pos < second_batch.first.as_ref().unwrap().pos);
+        assert_eq!(second_batch.last.as_ref().unwrap().chrom, "22");
+    }
+
+    #[test]
+    fn test_filter_records_and_samples() {
+        let input_path = "./test_data/inputs/filter.vcf";
+        std::fs::create_dir_all("./test_data/inputs").unwrap();
+        std::fs::write(
+            input_path,
+            "##fileformat=VCFv4.2\n#CHROM\tPOS\tID\tREF\tALT\tQUAL\tFILTER\tINFO\tFORMAT\tA\tB\n\
+             1\t100\t.\tA\tG\t50\tPASS\t.\tGT\t0/0\t0/1\n\
+             1\t200\t.\tC\tT\t10\tPASS\t.\tGT\t0/1\t1/1\n\
+             1\t300\t.\tC\tT\t60\tLowQual\t.\tGT\t0/1\t1/1\n\
+             2\t100\t.\tC\tT\t70\tPASS\t.\tGT\t1/1\t0/0\n",
+        )
+        .unwrap();
+        let output_path = std::path::Path::new("./test_data/filter");
+        let options = BatchOptions {
+            filter: RecordFilter {
+                regions: vec!["1:1-250".parse().unwrap(), "2".parse().unwrap()],
+                samples: Some(vec!["B".to_string()]),
+                pass_only: true,
+                min_qual: Some(20.0),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+
+        let report =
+            extract_variants_to_batches_with_options(input_path, output_path, &options).unwrap();
+
+        assert_eq!(report.total_records(), 2);
+        assert_eq!(report.filtered_records, 2);
+        let batch = std::fs::read_to_string(&report.batches[0].path).unwrap();
+        assert!(batch.contains("FORMAT\tB\n"));
+        assert!(batch.ends_with(
+            "1\t100\t.\tA\tG\t50\tPASS\t.\tGT\t0/1\n2\t100\t.\tC\tT\t70\tPASS\t.\tGT\t0/0\n"
+        ));
+
+        let options = BatchOptions {
+            filter: RecordFilter {
+                sample_order: Some(vec!["B".to_string(), "A".to_string()]),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let report =
+            extract_variants_to_batches_with_options(input_path, output_path, &options).unwrap();
+        let batch = std::fs::read_to_string(&report.batches[0].path).unwrap();
+        assert!(batch.contains("FORMAT\tB\tA\n1\t100\t.\tA\tG\t50\tPASS\t.\tGT\t0/1\t0/0\n"));
+
+        let options = BatchOptions {
+            mask: Some(SampleMask {
+                samples: vec!["B".to_string()],
+                all_fields: false,
+            }),
+            ..options
+        };
+        let report =
+            extract_variants_to_batches_with_options(input_path, output_path, &options).unwrap();
+        let batch = std::fs::read_to_string(&report.batches[0].path).unwrap();
+        assert!(batch.contains("FORMAT\tB\tA\n1\t100\t.\tA\tG\t50\tPASS\t.\tGT\t./.\t0/0\n"));
+    }
+
+    #[test]
+    fn test_split_by_chromosome() {
+        let input_path = "./test_data/inputs/chromosomes.vcf";
+        std::fs::create_dir_all("./test_data/inputs").unwrap();
+        let records = "1\t100\t.\tA\tG\t.\tPASS\t.\n\
+                       1\t200\t.\tC\tT\t.\tPASS\t.\n\
+                       chrX\t100\t.\tC\tT\t.\tPASS\t.\n";
+        let header = "##fileformat=VCFv4.2\n#CHROM\tPOS\tID\tREF\tALT\tQUAL\tFILTER\tINFO\n";
+        std::fs::write(input_path, header.to_string() + records).unwrap();
+        let output_path = std::path::Path::new("./test_data/split_by_chromosome");
+        let _ = std::fs::remove_dir_all(output_path);
+        let options = BatchOptions {
+            batch_size: 1,
+            split_by: SplitMode::Chromosome,
+            ..Default::default()
+        };
+
+        let report =
+            extract_variants_to_batches_with_options(input_path, output_path, &options).unwrap();
+
+        let batches: Vec<(PathBuf, usize)> = report
+            .batches
+            .iter()
+            .map(|batch| (batch.path.clone(), batch.records))
+            .collect();
+        assert_eq!(
+            batches,
+            vec![
+                (output_path.join("1.vcf"), 2),
+                (output_path.join("chrX.vcf"), 1)
+            ]
+        );
+
+        std::fs::write(
+            input_path,
+            header.to_string() + records + "1\t300\t.\tA\tG\t.\tPASS\t.\n",
+        )
+        .unwrap();
+        let result = extract_variants_to_batches_with_options(input_path, output_path, &options);
+        assert!(matches!(
+            result,
+            Err(VcfBatcherError::NonContiguousChromosome { line_number: 6, .. })
+        ));
+    }
+
+    #[test]
+    fn test_split_by_gene() {
+        let input_path = "./test_data/inputs/genes.vcf";
+        std::fs::create_dir_all("./test_data/inputs").unwrap();
+        // NBR2 overlaps BRCA1, its records go with the first gene of their annotation
+        let records = "17\t100\t.\tA\tG\t.\tPASS\tCSQ=G|missense_variant|MODERATE|BRCA1\n\
+                       17\t150\t.\tC\tT\t.\tPASS\tCSQ=T|intron_variant|MODIFIER|NBR2,T|upstream_gene_variant|MODIFIER|BRCA1\n\
+                       17\t200\t.\tC\tT\t.\tPASS\tCSQ=T|missense_variant|MODERATE|BRCA1\n\
+                       17\t300\t.\tG\tA\t.\tPASS\tDP=10\n\
+                       X\t100\t.\tC\tT\t.\tPASS\tCSQ=T|missense_variant|MODERATE|SHOX\n\
+                       Y\t100\t.\tC\tT\t.\tPASS\tCSQ=T|missense_variant|MODERATE|SHOX\n";
+        let header = "##fileformat=VCFv4.2\n#CHROM\tPOS\tID\tREF\tALT\tQUAL\tFILTER\tINFO\n";
+        std::fs::write(input_path, header.to_string() + records).unwrap();
+        let output_path = std::path::Path::new("./test_data/split_by_gene");
+        let _ = std::fs::remove_dir_all(output_path);
+        let options = BatchOptions {
+            split_by: SplitMode::Gene,
+            quiet: true,
+            ..Default::default()
+        };
+
+        let report =
+            extract_variants_to_batches_with_options(input_path, output_path, &options).unwrap();
+        let batches: Vec<(PathBuf, usize)> = report
+            .batches
+            .iter()
+            .map(|batch| (batch.path.clone(), batch.records))
+            .collect();
+        assert_eq!(
+            batches,
+            vec![
+                (output_path.join("17_BRCA1.vcf"), 2),
+                (output_path.join("17_NBR2.vcf"), 1),
+                (output_path.join("17_intergenic.vcf"), 1),
+                (output_path.join("X_SHOX.vcf"), 1),
+                (output_path.join("Y_SHOX.vcf"), 1),
+            ]
+        );
+
+        let _ = std::fs::remove_dir_all(output_path);
+        let options = BatchOptions {
+            genes_per_batch: 2,
+            name_template: Some(NameTemplate::parse("{number}_{gene}.vcf").unwrap()),
+            ..options
+        };
+        let report =
+            extract_variants_to_batches_with_options(input_path, output_path, &options).unwrap();
+        let records: Vec<usize> = report.batches.iter().map(|batch| batch.records).collect();
+        assert_eq!(records, [3, 1, 1, 1]);
+        let first_batch: Vec<String> = read_lines(output_path.join("1_BRCA1.vcf"))
+            .unwrap()
+            .map(Result::unwrap)
+            .filter(|line| !is_header_line(line))
+            .map(|line| line.split('\t').nth(1).unwrap().to_string())
+            .collect();
+        assert_eq!(first_batch, ["100", "200", "150"]);
+    }
+
+    #[test]
+    fn test_group_by_info() {
+        let input_path = "./test_data/inputs/clnsig.vcf";
+        std::fs::create_dir_all("./test_data/inputs").unwrap();
+        let records = "1\t100\t.\tA\tG\t.\tPASS\tCLNSIG=Benign\n\
+                       1\t200\t.\tC\tT\t.\tPASS\tCLNSIG=Pathogenic\n\
+                       1\t300\t.\tC\tT\t.\tPASS\tAF=0.1\n\
+                       1\t400\t.\tG\tA\t.\tPASS\tCLNSIG=Benign\n\
+                       2\t100\t.\tC\tT\t.\tPASS\tCLNSIG=Benign\n";
+        let header = "##fileformat=VCFv4.2\n#CHROM\tPOS\tID\tREF\tALT\tQUAL\tFILTER\tINFO\n";
+        std::fs::write(input_path, header.to_string() + records).unwrap();
+        let output_path = std::path::Path::new("./test_data/group_by_info");
+        let _ = std::fs::remove_dir_all(output_path);
+        let options = BatchOptions {
+            batch_size: 2,
+            group_by_info: Some("CLNSIG".to_string()),
+            quiet: true,
+            ..Default::default()
+        };
+
+        let report =
+            extract_variants_to_batches_with_options(input_path, output_path, &options).unwrap();
+        let batches: Vec<(PathBuf, usize)> = report
+            .batches
+            .iter()
+            .map(|batch| (batch.path.clone(), batch.records))
+            .collect();
+        assert_eq!(
+            batches,
+            vec![
+                (output_path.join("CLNSIG=Benign/batch_01.vcf"), 2),
+                (output_path.join("CLNSIG=Benign/batch_02.vcf"), 1),
+                (output_path.join("CLNSIG=Pathogenic/batch_01.vcf"), 1),
+                (output_path.join("CLNSIG=./batch_01.vcf"), 1),
+            ]
+        );
+        let manifest = Manifest::read(output_path).unwrap().unwrap();
+        assert_eq!(manifest.groups.len(), 3);
+        assert_eq!(manifest.groups["Pathogenic"], "CLNSIG=Pathogenic");
+
+        // Appended batches continue the numbering of their group
+        let options = BatchOptions {
+            append: Some(AppendMode::Continue),
+            ..options
+        };
+        let report =
+            extract_variants_to_batches_with_options(input_path, output_path, &options).unwrap();
+        assert_eq!(report.batches.len(), 8);
+        assert_eq!(
+            report.batches[6].path,
+            output_path.join("CLNSIG=Pathogenic/batch_02.vcf")
+        );
+        assert_eq!(
+            Manifest::read(output_path).unwrap().unwrap().groups.len(),
+            3
+        );
+    }
+
+    #[test]
+    fn test_extract_from_reader() {
+        let file_path = "./test_data/batch_01.vcf.gz";
+        let output_path = std::path::Path::new("./test_data/extract_from_reader");
+        let _ = std::fs::remove_dir_all(output_path);
+        let options = BatchOptions {
+            batch_size: 30,
+            ..Default::default()
+        };
+
+        // Bgzipped input is detected without a file name
+        let reader = std::fs::File::open(file_path).unwrap();
+        let report = extract_variants_from_reader(reader, output_path, &options).unwrap();
+
+        assert_eq!(report.batches.len(), 4);
+        assert_eq!(report.total_records(), 100);
+        let expected: Vec<String> = read_lines(file_path).unwrap().map(Result::unwrap).collect();
+        let header_lines = expected.iter().filter(|line| is_header_line(line)).count();
+        let first_batch: Vec<String> = read_lines(output_path.join("batch_01.vcf"))
+            .unwrap()
+            .map(Result::unwrap)
+            .collect();
+        assert_eq!(first_batch, expected[..header_lines + 30]);
+    }
+
+    #[test]
+    fn test_bgzipped_input_without_extension() {
+        // Like /dev/fd/63 from process substitution, e.g. <(bcftools view -Oz ...)
+        let input_dir = std::path::Path::new("./test_data/inputs");
+        std::fs::create_dir_all(input_dir).unwrap();
+        let file_path = input_dir.join("bgzipped_without_extension");
+        std::fs::copy("./test_data/batch_01.vcf.gz", &file_path).unwrap();
+        let output_path = std::path::Path::new("./test_data/bgzipped_without_extension");
+        let _ = std::fs::remove_dir_all(output_path);
+        let options = BatchOptions {
+            batch_size: 30,
+            ..Default::default()
+        };
+
+        let report = extract_variants_to_batches_with_options(
+            &file_path.to_string_lossy(),
+            output_path,
+            &options,
+        )
+        .unwrap();
+
+        assert_eq!(report.batches.len(), 4);
+        assert_eq!(report.total_records(), 100);
+    }
+
+    #[test]
+    fn test_gzipped_input() {
+        use std::io::{Read, Write};
+
+        use flate2::write::GzEncoder;
+
+        use crate::{is_bgzf, read_lines_from};
+
+        let mut contents = Vec::new();
+        BGZFReader::new(File::open("./test_data/batch_01.vcf.gz").unwrap())
+            .unwrap()
+            .read_to_end(&mut contents)
+            .unwrap();
+        // Written by gzip, as two members like `cat a.gz b.gz` gives, without the BGZF extra field
+        let middle = contents.len() / 2;
+        let mut gzipped = Vec::new();
+        for part in [&contents[..middle], &contents[middle..]] {
+            let mut encoder = GzEncoder::new(Vec::new(), flate2::Compression::default());
+            encoder.write_all(part).unwrap();
+            gzipped.extend(encoder.finish().unwrap());
+        }
+        assert!(!is_bgzf(&gzipped));
+        let lines: Vec<String> = read_lines_from(gzipped.as_slice(), None)
+            .unwrap()
+            .map(Result::unwrap)
+            .collect();
+        assert_eq!(lines.concat().len() + lines.len(), contents.len());
+
+        let input_dir = std::path::Path::new("./test_data/inputs");
+        std::fs::create_dir_all(input_dir).unwrap();
+        let file_path = input_dir.join("gzipped.vcf.gz");
+        std::fs::write(&file_path, &gzipped).unwrap();
+        let output_path = std::path::Path::new("./test_data/gzipped_input");
+        let _ = std::fs::remove_dir_all(output_path);
+        let options = BatchOptions {
+            batch_size: 30,
+            quiet: true,
+            ..Default::default()
+        };
+        let report = extract_variants_to_batches_with_options(
+            &file_path.to_string_lossy(),
+            output_path,
+            &options,
+        )
+        .unwrap();
+        assert_eq!(report.batches.len(), 4);
+        assert_eq!(report.total_records(), 100);
+    }
+
+    #[test]
+    fn test_overwrite_policy() {
+        let file_path = "./test_data/batch_01.vcf.gz";
+        let output_path = std::path::Path::new("./test_data/overwrite_policy");
+        let _ = std::fs::remove_dir_all(output_path);
+        let mut options = BatchOptions {
+            batch_size: 30,
+            quiet: true,
+            ..Default::default()
+        };
+        extract_variants_to_batches_with_options(file_path, output_path, &options).unwrap();
+
+        options.overwrite = OverwritePolicy::Refuse;
+        assert!(matches!(
+            extract_variants_to_batches_with_options(file_path, output_path, &options),
+            Err(VcfBatcherError::OutputExists { .. })
+        ));
+
+        options.overwrite = OverwritePolicy::Skip;
+        let report =
+            extract_variants_to_batches_with_options(file_path, output_path, &options).unwrap();
+        assert_eq!(report.skipped_batches, 4);
+        assert_eq!(report.batches.len(), 4);
+    }
+
+    #[test]
+    fn test_progress_callback() {
+        let file_path = "./test_data/batch_01.vcf.gz";
+        let output_path = std::path::Path::new("./test_data/progress_callback");
+        let _ = std::fs::remove_dir_all(output_path);
+        let updates = Arc::new(Mutex::new(Vec::new()));
+        let received = Arc::clone(&updates);
+        let options = BatchOptions {
+            batch_size: 30,
+            on_progress: Some(ProgressCallback::new(move |progress| {
+                received.lock().unwrap().push(progress.clone())
+            })),
+            quiet: true,
+            ..Default::default()
+        };
+
+        extract_variants_to_batches_with_options(file_path, output_path, &options).unwrap();
+
+        let updates = updates.lock().unwrap();
+        let total_bytes = std::fs::metadata(file_path).unwrap().len();
+        let last = updates.last().unwrap();
+        assert_eq!(last.records, 100);
+        assert_eq!(last.batches, 4);
+        assert_eq!(last.bytes_read, total_bytes);
+        assert_eq!(last.total_bytes, Some(total_bytes));
+        assert!(updates
+            .windows(2)
+            .all(|pair| pair[0].bytes_read <= pair[1].bytes_read));
+    }
+
+    #[test]
+    fn test_extract_to_sink() {
+        let mut received = Vec::new();
+        let mut sink = |batch: &crate::BatchInfo, contents: &[u8]| {
+            received.push((batch.path.clone(), contents.to_vec()));
+            Ok(())
+        };
+        let options = BatchOptions {
+            batch_size: 40,
+            ..Default::default()
+        };
+
+        let report =
+            crate::extract_variants_to_sink("./test_data/batch_01.vcf.gz", &mut sink, &options)
+                .unwrap();
+
+        assert_eq!(report.batches.len(), 3);
+        assert_eq!(received.len(), 3);
+        assert_eq!(received[2].0, std::path::Path::new("batch_03.vcf"));
+        let contents = String::from_utf8(received[2].1.clone()).unwrap();
+        assert!(contents.starts_with("##fileformat"));
+        assert_eq!(
+            contents
+                .lines()
+                .filter(|line| !is_header_line(line))
+                .count(),
+            20
+        );
+    }
+
+    #[test]
+    fn test_sink_error_stops_the_run() {
+        let mut sink = |batch: &crate::BatchInfo, _: &[u8]| {
+            if batch.number == 2 {
+                return Err(std::io::Error::other("upload failed"));
+            }
+            Ok(())
+        };
+        let options = BatchOptions {
+            batch_size: 40,
+            ..Default::default()
+        };
+
+        let error =
+            crate::extract_variants_to_sink("./test_data/batch_01.vcf.gz", &mut sink, &options)
+                .unwrap_err();
+
+        assert!(matches!(
+            error,
+            VcfBatcherError::BatchWrite {
+                batch_number: 2,
+                ..
+            }
+        ));
+        assert_eq!(error.completed_batches().unwrap().batches.len(), 1);
+    }
+
+    #[test]
+    fn test_missing_input_is_an_error() {
+        let result = extract_variants_to_batches(
+            "./test_data/does_not_exist.vcf",
+            10,
+            std::path::Path::new("./test_data/missing_input"),
+            None,
+        );
+        assert!(matches!(result, Err(VcfBatcherError::Read { .. })));
+    }
+
+    #[test]
+    #[allow(deprecated)]
     fn test_parse_compression() {
+        use crate::parse_compression_level;
+
         assert_eq!(
             parse_compression_level(Some("fast".to_string())),
             Some(Compression::fast())
@@ -334,5 +2929,88 @@ mod tests {
         assert_eq!(parse_compression_level(Some("none".to_string())), None);
         assert_eq!(parse_compression_level(Some("invalid".to_string())), None);
         assert_eq!(parse_compression_level(None), None);
+
+        assert_eq!(parse_compression("NONE").unwrap(), None);
+        assert_eq!(parse_compression("9").unwrap(), Some(Compression::best()));
+        assert_eq!(
+            parse_compression(" 0 ").unwrap(),
+            Some(Compression::new(0).unwrap())
+        );
+        for invalid in ["", "nonee", "-1", "13", "1.5"] {
+            assert!(parse_compression(invalid).is_err(), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn test_num_batches() {
+        let output_path = std::path::Path::new("./test_data/num_batches");
+        let _ = std::fs::remove_dir_all(output_path);
+        let options = BatchOptions {
+            num_batches: Some(7),
+            quiet: true,
+            ..Default::default()
+        };
+        let report = extract_variants_to_batches_with_options(
+            "./test_data/batch_01.vcf.gz",
+            output_path,
+            &options,
+        )
+        .unwrap();
+        let records: Vec<usize> = report.batches.iter().map(|batch| batch.records).collect();
+        assert_eq!(records, [15, 15, 14, 14, 14, 14, 14]);
+
+        // Records that are filtered out are not counted
+        let options = BatchOptions {
+            num_batches: Some(3),
+            filter: RecordFilter {
+                min_qual: Some(101.0),
+                ..Default::default()
+            },
+            ..options.clone()
+        };
+        let report = plan_batches("./test_data/batch_01.vcf.gz", output_path, &options).unwrap();
+        let records: Vec<usize> = report.batches.iter().map(|batch| batch.records).collect();
+        assert_eq!(records, [2, 1, 1]);
+
+        // Fewer records than batches give a batch per record
+        let options = BatchOptions {
+            num_batches: Some(6),
+            ..options
+        };
+        let report = plan_batches("./test_data/batch_01.vcf.gz", output_path, &options).unwrap();
+        assert_eq!(report.batches.len(), 4);
+
+        let vcf = "##fileformat=VCFv4.2\n#CHROM\tPOS\tID\tREF\tALT\tQUAL\tFILTER\tINFO\n";
+        assert!(matches!(
+            extract_variants_from_reader(vcf.as_bytes(), output_path, &options),
+            Err(VcfBatcherError::InvalidOptions { .. })
+        ));
+    }
+
+    #[test]
+    fn test_manifest_option() {
+        let output_path = std::path::Path::new("./test_data/manifest_option");
+        let _ = std::fs::remove_dir_all(output_path);
+        let options = BatchOptions {
+            batch_size: 40,
+            manifest: true,
+            quiet: true,
+            ..Default::default()
+        };
+        let report = extract_variants_to_batches_with_options(
+            "./test_data/batch_01.vcf.gz",
+            output_path,
+            &options,
+        )
+        .unwrap();
+
+        let manifest = Manifest::read(output_path).unwrap().unwrap();
+        assert_eq!(manifest.batches.len(), 3);
+        for (entry, batch) in manifest.batches.iter().zip(&report.batches) {
+            assert_eq!(entry.size, std::fs::metadata(&batch.path).unwrap().len());
+            assert_eq!(entry.first, batch.first);
+            assert_eq!(entry.last, batch.last);
+            assert!(entry.first.is_some());
+        }
     }
 }
@@ -0,0 +1,185 @@
+use std::error::Error;
+use std::fmt;
+use std::io::{self, BufRead, Read};
+
+/// How many lines [`read_ahead`] hands over at a time, so that the channel is not touched for every line.
+#[cfg(not(target_arch = "wasm32"))]
+const CHUNK_LINES: usize = 1024;
+
+/// How many chunks of lines [`read_ahead`] reads before they are taken, which bounds its memory.
+#[cfg(not(target_arch = "wasm32"))]
+const CHUNKS_AHEAD: usize = 16;
+
+/// Iterator over the lines of a reader, like [`std::io::Lines`], but with an optional upper bound
+/// on the length of a single line. At most `max_line_length + 1` bytes of a line are held in memory,
+/// so a pathologically long line produces an error instead of exhausting the available memory.
+#[derive(Debug)]
+pub struct BoundedLines<R> {
+    reader: R,
+    max_line_length: Option<usize>,
+    line_number: usize,
+    buffer: Vec<u8>,
+}
+
+impl<R: BufRead> BoundedLines<R> {
+    pub fn new(reader: R, max_line_length: Option<usize>) -> Self {
+        BoundedLines {
+            reader,
+            max_line_length,
+            line_number: 0,
+            buffer: Vec::new(),
+        }
+    }
+}
+
+impl<R: BufRead> Iterator for BoundedLines<R> {
+    type Item = Result<String, io::Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.buffer.clear();
+        self.line_number += 1;
+
+        let read = match self.max_line_length {
+            // One byte more than allowed for the line itself, to fit the line break
+            Some(limit) => (&mut self.reader)
+                .take(limit as u64 + 2)
+                .read_until(b'\n', &mut self.buffer),
+            None => self.reader.read_until(b'\n', &mut self.buffer),
+        };
+        match read {
+            Ok(0) => return None,
+            Ok(_) => {}
+            Err(error) => return Some(Err(error)),
+        }
+
+        if self.buffer.ends_with(b"\n") {
+            self.buffer.pop();
+            if self.buffer.ends_with(b"\r") {
+                self.buffer.pop();
+            }
+        }
+        if let Some(limit) = self.max_line_length {
+            if self.buffer.len() > limit {
+                return Some(Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    LineTooLong {
+                        line_number: self.line_number,
+                        limit,
+                    },
+                )));
+            }
+        }
+
+        Some(
+            String::from_utf8(std::mem::take(&mut self.buffer))
+                .map_err(|error| io::Error::new(io::ErrorKind::InvalidData, error)),
+        )
+    }
+}
+
+/// Error for a line that exceeds the maximum line length, carried inside an [`io::Error`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LineTooLong {
+    pub line_number: usize,
+    pub limit: usize,
+}
+
+impl LineTooLong {
+    /// Extracts the error if the given I/O error was caused by a line that was too long.
+    pub fn from_io_error(error: &io::Error) -> Option<LineTooLong> {
+        error.get_ref()?.downcast_ref::<LineTooLong>().copied()
+    }
+}
+
+impl fmt::Display for LineTooLong {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "line {} is longer than the maximum line length of {} bytes",
+            self.line_number, self.limit
+        )
+    }
+}
+
+impl Error for LineTooLong {}
+
+/// Calls `consume` with the lines of `lines`, which are read on a thread of their own, so that reading and
+/// decompressing the input overlaps with what is done with the lines. The thread stops once `consume`
+/// returns, even if not all lines were taken. Where there are no threads, e.g. in the browser, the lines are
+/// read on the calling thread.
+pub(crate) fn read_ahead<I, T>(
+    lines: I,
+    consume: impl FnOnce(&mut dyn Iterator<Item = io::Result<String>>) -> T,
+) -> T
+where
+    I: Iterator<Item = io::Result<String>> + Send,
+{
+    #[cfg(target_arch = "wasm32")]
+    {
+        let mut lines = lines;
+        return consume(&mut lines);
+    }
+    #[cfg(not(target_arch = "wasm32"))]
+    std::thread::scope(|scope| {
+        let (sender, receiver) = std::sync::mpsc::sync_channel(CHUNKS_AHEAD);
+        let reader = std::thread::Builder::new()
+            .name("vcf_batcher-reader".to_string())
+            .spawn_scoped(scope, move || {
+                // Reading on after the end fails for bgzipped input
+                let mut lines = lines.fuse().peekable();
+                while lines.peek().is_some() {
+                    let chunk: Vec<io::Result<String>> = lines.by_ref().take(CHUNK_LINES).collect();
+                    // The receiver is gone once `consume` returned
+                    if sender.send(chunk).is_err() {
+                        break;
+                    }
+                }
+            });
+        match reader {
+            Ok(_) => consume(&mut receiver.into_iter().flatten()),
+            Err(error) => consume(&mut std::iter::once(Err(error))),
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{read_ahead, BoundedLines, LineTooLong, CHUNK_LINES};
+
+    #[test]
+    fn test_bounded_lines() {
+        let input = "short\nexactly ten\r\nthis line is too long\nok\n".as_bytes();
+        let mut lines = BoundedLines::new(input, Some(11));
+
+        assert_eq!(lines.next().unwrap().unwrap(), "short");
+        assert_eq!(lines.next().unwrap().unwrap(), "exactly ten");
+        let error = lines.next().unwrap().unwrap_err();
+        assert_eq!(
+            LineTooLong::from_io_error(&error),
+            Some(LineTooLong {
+                line_number: 3,
+                limit: 11
+            })
+        );
+    }
+
+    #[test]
+    fn test_unbounded_lines() {
+        let input = "a\nb\r\nc".as_bytes();
+        let lines: Vec<String> = BoundedLines::new(input, None).map(Result::unwrap).collect();
+        assert_eq!(lines, vec!["a", "b", "c"]);
+    }
+
+    #[test]
+    fn test_read_ahead() {
+        let input = "line\n".repeat(CHUNK_LINES * 3 + 5);
+        let lines = BoundedLines::new(input.as_bytes(), None);
+        let count = read_ahead(lines, |lines| lines.map(Result::unwrap).count());
+        assert_eq!(count, CHUNK_LINES * 3 + 5);
+
+        // The reader stops when fewer lines are taken than there are
+        let lines = BoundedLines::new(input.as_bytes(), None);
+        let first = read_ahead(lines, |lines| lines.next().unwrap().unwrap());
+        assert_eq!(first, "line");
+    }
+}
@@ -0,0 +1,78 @@
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::process;
+
+use crate::VcfBatcherError;
+
+/// Name of the lock file that is created in the output directory during a run.
+pub const LOCK_FILE_NAME: &str = ".vcf_batcher.lock";
+
+/// Exclusive lock on an output directory, held for the duration of a run.
+/// The lock file is removed again when the lock is dropped.
+#[derive(Debug)]
+pub struct OutputLock {
+    path: PathBuf,
+}
+
+impl OutputLock {
+    /// Takes the lock on the output directory, creating the directory if necessary.
+    /// Fails immediately if another run already holds the lock.
+    pub fn acquire(output_path: &Path) -> Result<OutputLock, VcfBatcherError> {
+        let path = output_path.join(LOCK_FILE_NAME);
+        let lock_error = |source| VcfBatcherError::Lock {
+            path: path.clone(),
+            source,
+        };
+
+        fs::create_dir_all(output_path).map_err(lock_error)?;
+        // `create_new` fails atomically if the file already exists
+        let mut file = match OpenOptions::new().write(true).create_new(true).open(&path) {
+            Ok(file) => file,
+            Err(error) if error.kind() == io::ErrorKind::AlreadyExists => {
+                let holder = fs::read_to_string(&path).unwrap_or_default();
+                return Err(VcfBatcherError::OutputLocked {
+                    path,
+                    holder: holder.trim().to_string(),
+                });
+            }
+            Err(error) => return Err(lock_error(error)),
+        };
+
+        let lock = OutputLock { path: path.clone() };
+        writeln!(file, "{}", process::id()).map_err(lock_error)?;
+        Ok(lock)
+    }
+}
+
+impl Drop for OutputLock {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use crate::VcfBatcherError;
+
+    use super::{OutputLock, LOCK_FILE_NAME};
+
+    #[test]
+    fn test_lock_is_exclusive() {
+        let output_path = Path::new("./test_data/lock");
+        let _ = std::fs::remove_dir_all(output_path);
+
+        let lock = OutputLock::acquire(output_path).unwrap();
+        assert!(output_path.join(LOCK_FILE_NAME).is_file());
+        assert!(matches!(
+            OutputLock::acquire(output_path),
+            Err(VcfBatcherError::OutputLocked { .. })
+        ));
+
+        drop(lock);
+        assert!(!output_path.join(LOCK_FILE_NAME).exists());
+        assert!(OutputLock::acquire(output_path).is_ok());
+    }
+}
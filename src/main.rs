@@ -1,50 +1,2130 @@
-use bgzip::Compression;
-use std::path::Path;
-use std::time::Instant;
+use std::ffi::OsString;
+use std::fs;
+use std::io::{self, IsTerminal};
+use std::net::{SocketAddr, TcpListener};
+use std::path::{Path, PathBuf};
+use std::process;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
 
-use clap::Parser;
-use vcf_batcher::{extract_variants_to_batches, parse_compression_level};
+use clap::error::ErrorKind;
+use clap::{Args, CommandFactory, Parser, Subcommand, ValueEnum};
+use clap_complete::{generate, Shell};
+use clap_mangen::Man;
+use indicatif::{HumanBytes, HumanDuration, ProgressBar, ProgressStyle};
+use serde_json::{json, Value};
+#[cfg(feature = "catalog")]
+use vcf_batcher::catalog::{Catalog, CATALOG_FILE_NAME};
+use vcf_batcher::cohort::{find_inputs, split_cohort, COHORT_SUMMARY_FILE_NAME};
+use vcf_batcher::config::Config;
+use vcf_batcher::filter::Region;
+use vcf_batcher::header::read_header;
+use vcf_batcher::manifest::Manifest;
+use vcf_batcher::merge::{
+    batch_files, compression_for, merge_batches_to_writer, merge_batches_with_tmp_dir,
+};
+use vcf_batcher::metrics::{serve_metrics, WatchMetrics};
+use vcf_batcher::naming::{input_stem, NameTemplate};
+use vcf_batcher::parallel::extract_contigs_in_parallel;
+use vcf_batcher::rebalance::rebalance_batches;
+use vcf_batcher::recompress::{recompress_dir, recompress_file, Codec, Level, RecompressOptions};
+use vcf_batcher::reheader::{reheader_dir, reheader_file, HeaderEdit};
+use vcf_batcher::run_log::RunLog;
+use vcf_batcher::serve::{serve, ServeOptions};
+use vcf_batcher::sink::CommandSink;
+use vcf_batcher::stats::vcf_stats;
+#[cfg(feature = "otel")]
+use vcf_batcher::telemetry::{traces_endpoint, RunTrace};
+use vcf_batcher::testdata::SyntheticVcf;
+use vcf_batcher::validate::{validate_vcf, Severity};
+use vcf_batcher::watch::{watch_directory, WatchEvent, WatchOptions};
+use vcf_batcher::webhook::{run_payload, Webhook};
+#[cfg(feature = "parquet")]
+use vcf_batcher::ParquetExport;
+use vcf_batcher::{
+    extract_variants_from_reader, extract_variants_from_reader_to_sink,
+    extract_variants_to_batches_with_options, extract_variants_to_sink, parse_batch_sizes,
+    parse_byte_size, parse_compression, plan_batches, AppendMode, BadRecordPolicy, BatchCallback,
+    BatchOptions, CompressionFormat, ContigCheck, DuplicateRecordPolicy, DuplicateSamplePolicy,
+    EmptyInputPolicy, IndexFormat, JsonField, LateHeaderPolicy, OutputFormat, OverwritePolicy,
+    ProgressCallback, RunReport, SplitMode, StageTimings, VcfBatcherError,
+};
+
+/// Name of the installed binary, used in generated completions.
+const BIN_NAME: &str = "vcf_batcher_cli";
+
+/// Path that stands for stdin as input and for stdout as output.
+const STDIO_PATH: &str = "-";
+
+/// Exit code if `validate` found errors in the file.
+const EXIT_INVALID: i32 = 1;
+/// Exit code for invalid arguments, options or configuration files. Also used by clap.
+const EXIT_USAGE: i32 = 2;
+/// Exit code if the input cannot be read or does not have the expected structure.
+const EXIT_INPUT: i32 = 3;
+/// Exit code if the output cannot be written.
+const EXIT_OUTPUT: i32 = 4;
+/// Exit code for bugs, i.e. panics (`EX_SOFTWARE` of sysexits.h).
+const EXIT_INTERNAL: i32 = 70;
+/// Minimum time between two progress events of `--progress json`.
+const JSON_PROGRESS_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Exit code used when the run was stopped by SIGINT or SIGTERM (128 + SIGINT).
+const EXIT_INTERRUPTED: i32 = 130;
 
 #[derive(Parser)]
-#[command(author, version, about, long_about = None)]
+#[command(name = BIN_NAME, author, version, about, long_about = None)]
+#[command(args_conflicts_with_subcommands = true, subcommand_negates_reqs = true)]
+#[command(after_help = "Arguments can also be read from a file with @path, one argument per line.")]
 /// Clap command line interface for the vcf_batcher crate
+///
+/// Without a subcommand, the arguments of `split` are expected, so that
+/// `vcf_batcher_cli input.vcf output/` keeps working. Arguments can also be read
+/// from files, see [`expand_argument_files`].
+struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    #[command(flatten)]
+    split: SplitArgs,
+
+    #[command(flatten)]
+    global: GlobalArgs,
+}
+
+/// Flags that apply to all subcommands.
+#[derive(Args, Clone, Copy)]
+struct GlobalArgs {
+    /// Print the result (or the error) as a single JSON document on stdout; other messages go to stderr
+    #[arg(long, global = true)]
+    json: bool,
+
+    /// Print the causes of errors and the batches that were completed before a failure
+    #[arg(short, long, global = true)]
+    verbose: bool,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Split a VCF file into batches (the default if no subcommand is given)
+    Split(Box<SplitArgs>),
+    /// Concatenate the batches in a directory into a single VCF file
+    Merge(MergeArgs),
+    /// Count the header lines, records, samples and records per contig of a VCF file
+    Stats(StatsArgs),
+    /// Check the structure of a VCF file; exits with 1 if errors were found
+    Validate(ValidateArgs),
+    /// Show the header metadata of a VCF file: fileformat, contigs, INFO and FORMAT fields and samples
+    Inspect(InspectArgs),
+    /// Watch a directory and split every VCF file that appears in it into batches in a subdirectory of the output
+    Watch(Box<WatchArgs>),
+    /// Split the VCF files of a directory, or the given files, into batches in a subdirectory of the output each,
+    /// several files at a time
+    Cohort(Box<CohortArgs>),
+    /// Run an HTTP service that accepts split jobs and reports their status and batches
+    Serve(ServeArgs),
+    /// Write a deterministic synthetic VCF file, e.g. as an input for benchmarks
+    Generate(GenerateArgs),
+    /// Change the codec or compression level of a VCF file or of the batches in a directory
+    Recompress(RecompressArgs),
+    /// Rewrite the batches in a directory with a different batch size into another directory
+    Rebalance(RebalanceArgs),
+    /// Replace or patch the header of a VCF file or of the batches in a directory
+    Reheader(ReheaderArgs),
+    /// Print a shell completion script, e.g. `vcf_batcher_cli completions bash > /etc/bash_completion.d/vcf_batcher_cli`
+    Completions(CompletionsArgs),
+    /// Generate man pages from the command line definition, for packagers
+    #[command(hide = true)]
+    Mangen(MangenArgs),
+}
+
 /// Possible parameters are:
 /// - input_path: The path to the file to read
 /// - output_path: The path to the directory to write
 /// - (-b, --batch_size): How many lines of data should be contained in the file, excluding the header
-/// - (-c, --compression_level): BGzip compression level, options are "Default", Fast", "Best" and "None".
-struct Cli {
-    /// The path to the file to read
-    input_path: String,
+/// - (--batch-sizes): A schedule of batch sizes such as 100,1000,25000..., whose last size applies to the rest
+/// - (-c, --compression_level): none for plain VCF files, or fast, default, best or 0-12 to bgzip them
+/// - (--compression-format): Compress the batches with bgzf, the default, or with zstd into .vcf.zst files
+/// - (--num-batches): Divide the records into this many batches of nearly the same size instead
+/// - (--target-compressed-size): End a batch once its file reaches this size, e.g. 500M
+/// - (--target-size): End a batch once its records reach this size before compression, e.g. 500MB
+/// - (--split-by): Split into batches of records, one batch per chromosome or one per gene
+/// - (--genes-per-batch): How many genes each batch has with --split-by gene
+/// - (--group-by-info): Write one set of batches per value of this INFO key, each in a subdirectory
+/// - (--regions-bed): Write one batch per interval of this BED file, with the records that lie in it
+/// - (--samples-per-batch): Split the sample columns instead, into batches with all records and this many samples
+/// - (--on-empty-input): What to do if the input contains no variant records
+/// - (--on-late-header): What to do with header lines that appear after variant records
+/// - (--check-contigs): Verify record contigs against the header's ##contig lines
+/// - (--on-duplicate-samples): What to do if the header lists the same sample more than once
+/// - (--on-bad-record): Fail on, skip or quarantine records with a wrong number of columns or an invalid POS
+/// - (--dedup): Keep the first of, fail on or report records with the same CHROM, POS, REF and ALT
+/// - (--max-line-length): Fail on lines longer than this size instead of reading them into memory
+/// - (--resume): Keep complete batches of a previous identical run instead of rewriting them
+/// - (--append): Add the batches to those of earlier runs in the output directory, continuing their numbering
+/// - (--progress): Show a progress bar, a line per batch or JSON events on stderr
+/// - (--no-progress): Print a line per batch instead of showing a progress bar
+/// - (--config): Read the options from a TOML file; flags take precedence over its values
+/// - (--profile): Apply a named profile of options from the config file or a built-in one
+/// - (--dry-run): Read the input and list the batches that would be written, without writing them
+/// - (--parallel-contigs): Split the contigs of an indexed input concurrently, each into a subdirectory
+/// - (--threads): How many threads compress the batches, by default one per CPU core
+/// - (--pipe-to): Pipe every batch into this shell command instead of writing it to a file
+/// - (--pipe-jobs): How many --pipe-to commands run at the same time
+/// - (--regions): Only keep records in these regions, e.g. chr1:10000-20000 or chr2
+/// - (--samples): Only keep these sample columns, in this order
+/// - (--sample-order): Write the sample columns in the order of the names in this file
+/// - (--mask-samples): Set the genotypes of the samples in this file to missing
+/// - (--pass-only): Only keep records whose FILTER is PASS
+/// - (--min-qual): Only keep records with at least this QUAL
+/// - (--prefix-from-input): Name the batches after the input file, e.g. cohortA_batch_001.vcf.gz
+/// - (--region-names): Name the batches after their span, e.g. chr1_000000001-005000000.vcf.gz
+/// - (--log-file): Append a timestamped, structured log of the run to this file
+/// - (--tmp-dir): Write the batches to this directory first and move them to the output once complete
+/// - (--timings): Print the time spent in each stage of the run at the end
+/// - (--htslib): Read the input with htslib, only with the htslib feature
+/// - (--format): Write the batches as VCF, JSON Lines or, with the htslib feature, BCF files
+/// - (--json-fields): The fields of the JSON objects with --format jsonl
+/// - (--site-lists): Write the CHROM, POS, REF and ALT of every batch to a .sites.tsv file next to it
+/// - (--batches-bed): Write the genomic span of every batch to batches.bed in the output directory
+/// - (--index): Write an index next to every compressed batch, e.g. batch_01.vcf.gz.tbi with tabix
+/// - (--verify-output): Read every batch back after writing it and check it, marking it verified in the manifest
+/// - (--manifest): Write manifest.json with the file, record count, span, size and checksum of every batch
+/// - (--parquet): Also or only write the variant sites of the batches as Parquet files, only with the parquet feature
+/// - (--parquet-info): INFO keys that get a column of their own in the Parquet files
+/// - (--catalog): Record the run and its batches in a SQLite catalog, only with the catalog feature
+/// - (--otlp-endpoint): Export a trace of the run to an OpenTelemetry collector, only with the otel feature
+/// - (--output-template): Template for the file names of the batches, e.g. '{stem}_part{num:04}.vcf.gz'
+/// - (--force): Overwrite batch files that already exist
+/// - (--no-clobber): Keep batch files that already exist and only write the missing ones
+/// - (-y, --yes): Overwrite existing batch files without asking
+///
+/// The options are optional so that they can be given in the config file instead,
+/// their defaults are those of [`BatchOptions`].
+#[derive(Args)]
+struct SplitArgs {
+    /// The path to the file to read, or - to read from stdin
+    input_path: Option<String>,
 
     /// The path to the directory to write
-    output_path: String,
+    output_path: Option<String>,
+
+    /// Read the options from a TOML file whose keys are named like the flags, e.g. `batch-size = 1000`.
+    /// Flags given on the command line take precedence over the values of the file
+    #[arg(long)]
+    config: Option<PathBuf>,
 
-    /// How many lines of data should be contained in the file, excluding the header
-    #[arg(short, long, default_value_t = 25000)]
-    batch_size: usize,
+    /// Apply a named profile of options, defined in a [profiles.NAME] table of the --config file or built in:
+    /// imputation (one bgzipped file per chromosome), scatter (small, fast batches named after the input) or
+    /// archive (large batches with the best compression). Flags take precedence over the profile
+    #[arg(long)]
+    profile: Option<String>,
 
-    /// BGzip compression level, options are "Default", Fast", "Best" and "None".
+    /// How many lines of data should be contained in the file, excluding the header [default: 25000]
     #[arg(short, long)]
+    batch_size: Option<usize>,
+
+    /// A schedule of batch sizes instead of a single --batch-size, e.g. 100,1000,25000... for two small batches
+    /// to smoke-test downstream steps with before all further batches get 25000 records
+    #[arg(long, value_name = "SIZES", value_parser = parse_batch_sizes, conflicts_with = "batch_size")]
+    // Spelled out so that clap takes the schedule as one value instead of repeated values
+    batch_sizes: Option<::std::vec::Vec<usize>>,
+
+    /// Divide the records into this many batches of nearly the same size instead of batches of --batch-size
+    /// records, e.g. for a fixed number of cluster jobs. The records are counted first, so the input is read twice
+    #[arg(
+        long,
+        value_name = "N",
+        value_parser = clap::value_parser!(u64).range(1..),
+        conflicts_with_all = ["batch_size", "batch_sizes", "target_compressed_size", "target_size", "split_by",
+            "group_by_info", "regions_bed", "samples_per_batch", "parallel_contigs"]
+    )]
+    num_batches: Option<u64>,
+
+    /// Compression of the batches: none writes plain .vcf files, fast, default, best or a level from
+    /// 0 to 12 writes bgzipped .vcf.gz files [default: none]
+    #[arg(short, long, value_parser = compression_level_argument)]
     compression_level: Option<String>,
+
+    /// How the batches are compressed at --compression-level: bgzf into .vcf.gz files, which htslib reads and
+    /// tabix indexes, or zstd into .vcf.zst files on all threads, which are smaller and faster to write. With
+    /// zstd, fast, default and best are zstd levels 1, 3 and 19 as with recompress, and numbers are used as they are
+    /// [default: bgzf]
+    #[arg(long, value_enum, conflicts_with_all = ["index", "target_compressed_size"])]
+    compression_format: Option<CompressionFormat>,
+
+    /// End a batch once its file reaches this size on disk, e.g. 500M or 2GB, measured after compression.
+    /// Batches end between records, so they are slightly larger. Without --batch-size, there is no record limit
+    #[arg(long, value_name = "SIZE", value_parser = parse_byte_size)]
+    target_compressed_size: Option<u64>,
+
+    /// End a batch once its header and records reach this size as plain VCF text, e.g. 500MB, measured before
+    /// compression. Batches end between records, so they are slightly larger. Without --batch-size, there is no
+    /// record limit
+    #[arg(long, value_name = "SIZE", value_parser = parse_byte_size)]
+    target_size: Option<u64>,
+
+    /// Where batches end: after --batch-size records, at every chromosome (chr1.vcf.gz, ...) or at every gene
+    /// named in the VEP CSQ or SnpEff ANN annotations (chr17_TP53.vcf.gz, ...), e.g. for gene-burden tests [default: records]
+    #[arg(long, value_enum, value_name = "MODE")]
+    split_by: Option<SplitMode>,
+
+    /// How many genes each batch has with --split-by gene [default: 1]
+    #[arg(long, value_name = "N", value_parser = clap::value_parser!(u64).range(1..))]
+    genes_per_batch: Option<u64>,
+
+    /// Write one set of batches per value of this INFO key, e.g. CLNSIG, each in a subdirectory named like
+    /// CLNSIG=Pathogenic that appears with the first record of the value. manifest.json maps the values to them
+    #[arg(long, value_name = "KEY")]
+    group_by_info: Option<String>,
+
+    /// Write one batch per interval of this BED file, e.g. the capture regions of an exome kit, with the records
+    /// that lie in it. Batches are named after the name column if it tells the intervals apart, otherwise like
+    /// chr1_000010001-000020000.vcf.gz. Records outside of all intervals are left out
+    #[arg(long, value_name = "FILE", conflicts_with_all = ["split_by", "group_by_info"])]
+    regions_bed: Option<PathBuf>,
+
+    /// Split the sample columns instead of the records: every batch has all records, but only this many samples,
+    /// with the FORMAT column and a #CHROM line that lists them. All batches are written at the same time
+    #[arg(
+        long,
+        value_name = "N",
+        value_parser = clap::value_parser!(u64).range(1..),
+        conflicts_with_all = ["split_by", "group_by_info", "regions_bed"]
+    )]
+    samples_per_batch: Option<u64>,
+
+    /// What to do if the input contains headers but no variant records [default: skip]
+    #[arg(long, value_enum)]
+    on_empty_input: Option<EmptyInputPolicy>,
+
+    /// What to do with header lines that appear after the first variant record [default: error]
+    #[arg(long, value_enum)]
+    on_late_header: Option<LateHeaderPolicy>,
+
+    /// Verify that every record's CHROM is declared in the header's ##contig lines [default: off]
+    #[arg(long, value_enum)]
+    check_contigs: Option<ContigCheck>,
+
+    /// What to do if the #CHROM header line lists the same sample more than once [default: error]
+    #[arg(long, value_enum)]
+    on_duplicate_samples: Option<DuplicateSamplePolicy>,
+
+    /// What to do with records that have a different number of columns than the #CHROM line or a
+    /// POS that is not a number; quarantine writes them to bad_records.txt in the output directory [default: fail]
+    #[arg(long, value_enum)]
+    on_bad_record: Option<BadRecordPolicy>,
+
+    /// What to do with records that repeat the CHROM, POS, REF and ALT of a record at the same position:
+    /// keep-first leaves them out, error stops the run and report only counts them. By default, duplicates are not checked
+    #[arg(long, value_enum)]
+    dedup: Option<DuplicateRecordPolicy>,
+
+    /// Keep batches that a previous run with the same input and options already completed
+    /// (verified via the manifest and checksums) and only write the missing ones
+    #[arg(long)]
+    resume: bool,
+
+    /// Add the batches to those of earlier runs in the output directory, e.g. for a new delivery of a cohort:
+    /// continue their numbering, or rebalance to also fill up their last batch with the first records
+    /// of the input. The input must have the same header; manifest.json lists all batches
+    #[arg(
+        long,
+        value_enum,
+        value_name = "MODE",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "continue"
+    )]
+    append: Option<AppendMode>,
+
+    /// Fail on lines longer than this size (e.g. 64M) instead of reading them into memory
+    #[arg(long, value_parser = parse_byte_size)]
+    max_line_length: Option<u64>,
+
+    /// How to show the progress: a bar (with auto only if stderr is a terminal), a line per batch, or
+    /// newline-delimited JSON events on stderr for workflow engines and dashboards
+    #[arg(long, value_enum, default_value_t = ProgressFormat::Auto)]
+    progress: ProgressFormat,
+
+    /// Print a line per batch instead of showing a progress bar, the same as --progress lines
+    #[arg(long, conflicts_with = "progress")]
+    no_progress: bool,
+
+    /// Read the input and list the batches that would be written, without writing anything
+    #[arg(long)]
+    dry_run: bool,
+
+    /// Split the contigs of a bgzipped input concurrently, each into a subdirectory named after it,
+    /// e.g. chr1/batch_01.vcf. Needs a tabix (.tbi) or CSI (.csi) index next to the input
+    #[arg(long, conflicts_with = "dry_run")]
+    parallel_contigs: bool,
+
+    /// How many threads compress the blocks of the batches, and split the contigs with --parallel-contigs,
+    /// by default one per CPU core. The input is read on a thread of its own besides them
+    #[arg(long, value_name = "N", value_parser = clap::value_parser!(u64).range(1..))]
+    threads: Option<u64>,
+
+    /// Pipe every batch into this shell command instead of writing it to a file, e.g. 'bcftools stats -'.
+    /// The command runs once per batch, with the number of the batch in $VCF_BATCHER_BATCH_NUMBER and the name
+    /// its file would have had in $VCF_BATCHER_BATCH_FILE. No output directory is needed
+    #[arg(long, value_name = "COMMAND", conflicts_with_all = ["dry_run", "parallel_contigs"])]
+    pipe_to: Option<String>,
+
+    /// How many --pipe-to commands run at the same time [default: 1]
+    #[arg(long, value_name = "N", requires = "pipe_to", value_parser = clap::value_parser!(u64).range(1..))]
+    pipe_jobs: Option<u64>,
+
+    /// Only keep records in this region, e.g. chr1:10000-20000, chr1:10000- or chr2.
+    /// Can be repeated to keep records in any of several regions
+    #[arg(long = "regions", value_name = "REGION", value_parser = Region::from_str)]
+    regions: Vec<Region>,
+
+    /// Only keep these sample columns, in this order, as a comma-separated list or by repeating the flag
+    #[arg(long, value_name = "SAMPLE", value_delimiter = ',')]
+    samples: Option<Vec<String>>,
+
+    /// Write the sample columns in the order of the names in this file, one per line, e.g. so that all
+    /// batches of several inputs have the same sample order. The file must list every sample of the input
+    #[arg(long, value_name = "FILE", conflicts_with = "samples")]
+    sample_order: Option<PathBuf>,
+
+    /// Set the GT of the samples in this file, one name per line, to missing in every record, e.g. to redact
+    /// participants who withdrew their consent. INFO fields such as AC and AF are not recomputed
+    #[arg(long, value_name = "FILE")]
+    mask_samples: Option<PathBuf>,
+
+    /// Set all FORMAT fields of the masked samples to missing, not only GT
+    #[arg(long, requires = "mask_samples")]
+    mask_all_fields: bool,
+
+    /// Only keep records whose FILTER column is PASS
+    #[arg(long)]
+    pass_only: bool,
+
+    /// Only keep records with at least this QUAL. Records without a QUAL are dropped
+    #[arg(long, value_name = "QUAL")]
+    min_qual: Option<f64>,
+
+    /// Name the batches after the input file, e.g. cohortA_batch_001.vcf.gz for cohortA.vcf.gz, so that
+    /// batches of different inputs cannot be mixed up
+    #[arg(long, conflicts_with = "output_template")]
+    prefix_from_input: bool,
+
+    /// Name the batches after the span of their records, e.g. chr1_000000001-005000000.vcf.gz, so that
+    /// downstream tools can tell the interval to process from the file name alone
+    #[arg(long, conflicts_with_all = ["output_template", "prefix_from_input"])]
+    region_names: bool,
+
+    /// Append a timestamped log of the run to this file, with its parameters, every batch, warnings and
+    /// the final summary as one JSON object per line. The log is written independently of the console output
+    #[arg(long)]
+    log_file: Option<PathBuf>,
+
+    /// Write the batches to this directory first and move them into the output directory once they are
+    /// complete, e.g. a fast local disk when the output is on slow network storage
+    #[arg(long)]
+    tmp_dir: Option<PathBuf>,
+
+    /// Print the time spent reading and decompressing the input, filtering, compressing and writing at
+    /// the end of the run, to see whether more threads or faster storage would help
+    #[arg(long)]
+    timings: bool,
+
+    /// Read the input with htslib, e.g. to parse it exactly like bcftools does. BCF inputs (.bcf) are always read with htslib
+    #[cfg(feature = "htslib")]
+    #[arg(long)]
+    htslib: bool,
+
+    /// The format of the batches: vcf, jsonl to write every record as a JSON object on a line of its own,
+    /// e.g. batch_01.jsonl.gz, or bcf, which needs the htslib feature. A --output-template that ends in .bcf
+    /// selects bcf [default: vcf]
+    #[arg(long, value_enum)]
+    format: Option<OutputFormat>,
+
+    /// The fields of the JSON objects with --format jsonl, in this order, as a comma-separated list or by
+    /// repeating the flag [default: all]
+    #[arg(long, value_enum, value_name = "FIELD", value_delimiter = ',')]
+    json_fields: Option<Vec<JsonField>>,
+
+    /// Write the CHROM, POS, REF and ALT of the records of every batch to a .sites.tsv file next to it, e.g.
+    /// batch_01.sites.tsv, as a lookup table for joins that does not require parsing VCF
+    #[arg(long)]
+    site_lists: bool,
+
+    /// Write the genomic span of every batch, with its file name in the name column, to batches.bed in the
+    /// output directory, e.g. to pass a batch's regions to GATK-style tools with -L
+    #[arg(long)]
+    batches_bed: bool,
+
+    /// Write an index next to every batch, e.g. batch_01.vcf.gz.tbi with tabix, so that bcftools and other tools
+    /// can query the batches without indexing them first. Needs compressed VCF batches
+    #[arg(long, value_enum)]
+    index: Option<IndexFormat>,
+
+    /// Read every batch back after it was written and check its checksum, its BGZF blocks, its record count and the
+    /// positions of its first and last record. Verified batches are marked in manifest.json; a batch that does not
+    /// match stops the run
+    #[arg(long)]
+    verify_output: bool,
+
+    /// Write manifest.json to the output directory, listing the file, record count, first and last position, size
+    /// and checksum of every batch, e.g. for a workflow engine to fan out over the batches
+    #[arg(long)]
+    manifest: bool,
+
+    /// Write the variant sites (CHROM, POS, ID, REF, ALT, QUAL, FILTER) of every batch as a Parquet file, e.g.
+    /// batch_01.parquet, alongside the VCF batch or instead of it [default: off]
+    #[cfg(feature = "parquet")]
+    #[arg(long, value_enum)]
+    parquet: Option<ParquetExport>,
+
+    /// INFO keys that get a column of their own, named INFO_<KEY>, in the Parquet files, as a comma-separated
+    /// list or by repeating the flag
+    #[cfg(feature = "parquet")]
+    #[arg(long, value_name = "KEY", value_delimiter = ',')]
+    parquet_info: Option<Vec<String>>,
+
+    /// Record the run, its input and its batches in a SQLite catalog, which is created if necessary and
+    /// shared by all runs that use it [default: <OUTPUT>/catalog.sqlite]
+    #[cfg(feature = "catalog")]
+    #[arg(long, value_name = "PATH", num_args = 0..=1)]
+    catalog: Option<Option<PathBuf>>,
+
+    /// Export a trace of the run, with a span per batch, to the OTLP/HTTP receiver of an OpenTelemetry
+    /// collector, e.g. http://collector:4318. The run joins the trace of a TRACEPARENT environment variable
+    #[cfg(feature = "otel")]
+    #[arg(long, value_name = "URL", env = "OTEL_EXPORTER_OTLP_ENDPOINT", value_parser = traces_endpoint)]
+    otlp_endpoint: Option<Webhook>,
+
+    /// Template for the file names of the batches, e.g. '{stem}_part{num:04}.vcf.gz'. Supports the
+    /// placeholders {number} (or {num}, zero-padded with {number:04}), {stem}, {chrom}, {start}, {end},
+    /// {region} and {gene}
+    #[arg(long, visible_alias = "name-template", value_parser = NameTemplate::parse)]
+    output_template: Option<NameTemplate>,
+
+    /// Overwrite batch files that already exist. By default, the run stops at the first existing file
+    #[arg(short, long, conflicts_with = "no_clobber")]
+    force: bool,
+
+    /// Keep batch files that already exist and only write the missing ones
+    #[arg(short, long)]
+    no_clobber: bool,
+
+    /// Overwrite existing batch files without asking. Without it, the CLI asks before overwriting
+    /// if it runs in a terminal and fails otherwise
+    #[arg(short, long, conflicts_with = "no_clobber")]
+    yes: bool,
+}
+
+#[derive(Args)]
+struct WatchArgs {
+    /// The directory to watch for new .vcf and .vcf.gz files
+    incoming_dir: PathBuf,
+
+    /// The directory to write the batches to, in a subdirectory per input named after the file
+    output_dir: PathBuf,
+
+    /// Where inputs are moved after they were batched [default: INCOMING_DIR/done]
+    #[arg(long)]
+    done_dir: Option<PathBuf>,
+
+    /// Where inputs are moved if they could not be batched [default: INCOMING_DIR/failed]
+    #[arg(long)]
+    failed_dir: Option<PathBuf>,
+
+    /// Only split a new file once its size did not change for this many seconds, so that files
+    /// that are still being copied are not read half-way
+    #[arg(long, default_value_t = 2.0)]
+    settle_seconds: f64,
+
+    /// Read the options from a TOML file, as for split. The input and output of the file are ignored
+    #[arg(long)]
+    config: Option<PathBuf>,
+
+    /// Apply a named profile of options, defined in a [profiles.NAME] table of the --config file or built in:
+    /// imputation (one bgzipped file per chromosome), scatter (small, fast batches named after the input) or
+    /// archive (large batches with the best compression). Flags take precedence over the profile
+    #[arg(long)]
+    profile: Option<String>,
+
+    /// How many lines of data should be contained in the file, excluding the header [default: 25000]
+    #[arg(short, long)]
+    batch_size: Option<usize>,
+
+    /// Compression of the batches: none writes plain .vcf files, fast, default, best or a level from
+    /// 0 to 12 writes bgzipped .vcf.gz files [default: none]
+    #[arg(short, long, value_parser = compression_level_argument)]
+    compression_level: Option<String>,
+
+    /// Template for the file names of the batches, see split
+    #[arg(long, visible_alias = "name-template", value_parser = NameTemplate::parse)]
+    output_template: Option<NameTemplate>,
+
+    /// Write the batches to this directory first and move them into the output directory once they are complete
+    #[arg(long)]
+    tmp_dir: Option<PathBuf>,
+
+    /// Serve Prometheus metrics of the processed files, records, batches and errors at
+    /// http://ADDRESS/metrics, e.g. 0.0.0.0:9898
+    #[arg(long, value_name = "ADDRESS")]
+    metrics_addr: Option<SocketAddr>,
+
+    /// POST a JSON notification with the input, status, batches and record count of every input that was
+    /// batched or failed to this http:// URL
+    #[arg(long, value_name = "URL", value_parser = Webhook::parse)]
+    notify_url: Option<Webhook>,
+}
+
+#[derive(Args)]
+struct CohortArgs {
+    /// A directory whose .vcf, .vcf.gz and .vcf.bgz files are split, or the VCF files to split
+    #[arg(required = true, num_args = 1..)]
+    inputs: Vec<PathBuf>,
+
+    /// The directory that gets a subdirectory with the batches and the manifest of every input,
+    /// e.g. batches/sampleA/ for sampleA.vcf.gz, and a cohort.json summary of all inputs
+    output_dir: PathBuf,
+
+    /// How many inputs are split at the same time [default: the number of CPUs]
+    #[arg(short, long, value_name = "N", value_parser = clap::value_parser!(u64).range(1..))]
+    jobs: Option<u64>,
+
+    /// Read the options from a TOML file, as for split. The input and output of the file are ignored
+    #[arg(long)]
+    config: Option<PathBuf>,
+
+    /// Apply a named profile of options, defined in a [profiles.NAME] table of the --config file or built in:
+    /// imputation (one bgzipped file per chromosome), scatter (small, fast batches named after the input) or
+    /// archive (large batches with the best compression). Flags take precedence over the profile
+    #[arg(long)]
+    profile: Option<String>,
+
+    /// How many lines of data should be contained in the file, excluding the header [default: 25000]
+    #[arg(short, long)]
+    batch_size: Option<usize>,
+
+    /// Compression of the batches: none writes plain .vcf files, fast, default, best or a level from
+    /// 0 to 12 writes bgzipped .vcf.gz files [default: none]
+    #[arg(short, long, value_parser = compression_level_argument)]
+    compression_level: Option<String>,
+
+    /// Template for the file names of the batches, see split
+    #[arg(long, visible_alias = "name-template", value_parser = NameTemplate::parse)]
+    output_template: Option<NameTemplate>,
+
+    /// Write the batches to this directory first and move them into the output directory once they are complete
+    #[arg(long)]
+    tmp_dir: Option<PathBuf>,
+
+    /// Overwrite batch files that already exist; by default, an input whose batches exist fails
+    #[arg(short, long)]
+    force: bool,
+}
+
+#[derive(Args)]
+struct ServeArgs {
+    /// The address to listen on, e.g. 0.0.0.0:8080 to accept jobs from other hosts
+    #[arg(long, value_name = "ADDRESS", default_value = "127.0.0.1:8080")]
+    listen: SocketAddr,
+
+    /// How many jobs run at the same time; further jobs are queued
+    #[arg(long, default_value_t = 1)]
+    workers: usize,
+
+    /// POST a JSON notification with the input, status, batches and record count of every job that
+    /// completes or fails to this http:// URL
+    #[arg(long, value_name = "URL", value_parser = Webhook::parse)]
+    notify_url: Option<Webhook>,
+}
+
+#[derive(Args)]
+struct MergeArgs {
+    /// The directory containing the batches
+    batch_dir: PathBuf,
+
+    /// The merged VCF file, or - to write it to stdout
+    output_file: PathBuf,
+
+    /// Compression of the merged file: none writes a plain VCF file, fast, default, best or a level
+    /// from 0 to 12 bgzips it. By default, the file is bgzipped if its name ends in .gz
+    #[arg(short, long, value_parser = compression_level_argument)]
+    compression_level: Option<String>,
+
+    /// Write the merged file to this directory first and move it to the output file once it is complete
+    #[arg(long)]
+    tmp_dir: Option<PathBuf>,
+}
+
+#[derive(Args)]
+struct StatsArgs {
+    /// The VCF file to count
+    file: PathBuf,
+}
+
+#[derive(Args)]
+struct ValidateArgs {
+    /// The VCF file to check
+    file: PathBuf,
+}
+
+#[derive(Args)]
+struct InspectArgs {
+    /// The VCF file whose header to show
+    file: PathBuf,
+
+    /// Only print the sample names, one per line
+    #[arg(long)]
+    samples: bool,
+}
+
+#[derive(Args)]
+struct GenerateArgs {
+    /// The VCF file to write, or - to write it to stdout
+    output_file: PathBuf,
+
+    /// The number of sample columns
+    #[arg(long, default_value_t = 3)]
+    samples: usize,
+
+    /// The number of variant records, spread evenly over the contigs
+    #[arg(long, default_value_t = 1000)]
+    records: usize,
+
+    /// The contigs of the records
+    #[arg(
+        long,
+        value_name = "CONTIG",
+        value_delimiter = ',',
+        default_value = "chr1,chr2,chr3"
+    )]
+    contigs: Vec<String>,
+
+    /// The same seed always generates the same file
+    #[arg(long, default_value_t = 0)]
+    seed: u64,
+
+    /// Compression of the file: none writes a plain VCF file, fast, default, best or a level
+    /// from 0 to 12 bgzips it. By default, the file is bgzipped if its name ends in .gz
+    #[arg(short, long, value_parser = compression_level_argument)]
+    compression_level: Option<String>,
+}
+
+#[derive(Args)]
+struct RecompressArgs {
+    /// The VCF file, or the directory of batches, to recompress
+    input: PathBuf,
+
+    /// The recompressed file, or the directory for the recompressed batches, which may be the input directory
+    output: PathBuf,
+
+    /// The codec of the recompressed files
+    #[arg(long, value_enum, default_value_t = Codec::Bgzf)]
+    codec: Codec,
+
+    /// Compression level: none writes plain VCF files, fast, default, best or a level from 0 to 12
+    /// compresses them with the codec
+    #[arg(short, long, default_value = "default", value_parser = compression_level_argument)]
+    compression: String,
+
+    /// How many threads compress BGZF blocks and zstd frames, by default one per CPU core
+    #[arg(long)]
+    threads: Option<usize>,
+}
+
+#[derive(Args)]
+struct RebalanceArgs {
+    /// The directory containing the batches
+    batch_dir: PathBuf,
+
+    /// The directory for the rebalanced batches, which must not be the batch directory
+    output_dir: PathBuf,
+
+    /// How many records each rebalanced batch contains
+    #[arg(short, long, value_parser = clap::value_parser!(u64).range(1..))]
+    batch_size: u64,
+
+    /// Compression of the rebalanced batches: none writes plain VCF files, fast, default, best or a level
+    /// from 0 to 12 bgzips them. By default, they are bgzipped if the first batch is
+    #[arg(short, long, value_parser = compression_level_argument)]
+    compression_level: Option<String>,
+
+    /// Overwrite batch files that already exist in the output directory
+    #[arg(long)]
+    force: bool,
+}
+
+#[derive(Args)]
+#[command(group(clap::ArgGroup::new("edit").required(true).args(["header", "patch"])))]
+struct ReheaderArgs {
+    /// The VCF file, or the directory of batches, whose header is changed
+    input: PathBuf,
+
+    /// The file, or the directory, to write the changed files to. By default, they are changed in place
+    output: Option<PathBuf>,
+
+    /// A file with the new header, which replaces the whole header. It must end with a #CHROM line with
+    /// as many columns as the old one
+    #[arg(long, value_name = "FILE")]
+    header: Option<PathBuf>,
+
+    /// A file with ## lines that replace the lines with the same key and ID, e.g. a ##contig line, or are added
+    #[arg(long, value_name = "FILE")]
+    patch: Option<PathBuf>,
+}
+
+#[derive(Args)]
+struct CompletionsArgs {
+    /// The shell to generate completions for
+    #[arg(value_enum)]
+    shell: Shell,
+}
+
+#[derive(Args)]
+struct MangenArgs {
+    /// Write a page per subcommand (vcf_batcher_cli.1, vcf_batcher_cli-split.1, ...) into this
+    /// directory instead of printing the main page to stdout
+    #[arg(long)]
+    out_dir: Option<PathBuf>,
 }
 
 fn main() {
+    let cli = Cli::parse_from(expand_argument_files(std::env::args_os()));
+    let global = cli.global;
+    install_panic_hook(global.verbose);
+    let result = match cli.command.unwrap_or(Command::Split(Box::new(cli.split))) {
+        Command::Split(args) => split(*args, global),
+        Command::Merge(args) => merge(args, global.json),
+        Command::Stats(args) => stats(args, global.json),
+        Command::Validate(args) => validate(args, global.json),
+        Command::Inspect(args) => inspect(args, global.json),
+        Command::Watch(args) => watch(*args, global.json),
+        Command::Cohort(args) => cohort(*args, global.json),
+        Command::Serve(args) => {
+            serve_jobs(args);
+            Ok(())
+        }
+        Command::Generate(args) => generate_vcf(args),
+        Command::Recompress(args) => recompress(args, global.json),
+        Command::Rebalance(args) => rebalance(args, global.json),
+        Command::Reheader(args) => reheader(args, global.json),
+        Command::Completions(args) => {
+            completions(args);
+            Ok(())
+        }
+        Command::Mangen(args) => mangen(args),
+    };
+    if let Err(error) = result {
+        if global.json {
+            print_json(&error_json(&error));
+        }
+        report_error(&error, global.verbose);
+        process::exit(exit_code(&error));
+    }
+}
+
+/// Replaces every argument of the form `@path` with the lines of that file, one argument per line, so that
+/// long invocations can be stored next to the data or passed by schedulers that limit the command line length.
+/// Empty lines are skipped. Arguments after `--` and arguments starting with `@@` are kept, the latter
+/// without their first `@`.
+fn expand_argument_files(args: impl IntoIterator<Item = OsString>) -> Vec<OsString> {
+    let mut expanded = Vec::new();
+    let mut args = args.into_iter();
+    // The name of the program
+    expanded.extend(args.next());
+    while let Some(arg) = args.next() {
+        let Some(text) = arg.to_str() else {
+            expanded.push(arg);
+            continue;
+        };
+        if text == "--" {
+            expanded.push(arg);
+            expanded.extend(args.by_ref());
+            break;
+        }
+        if let Some(escaped) = text.strip_prefix("@@") {
+            expanded.push(format!("@{}", escaped).into());
+            continue;
+        }
+        let Some(path) = text.strip_prefix('@').filter(|path| !path.is_empty()) else {
+            expanded.push(arg);
+            continue;
+        };
+        match fs::read_to_string(path) {
+            Ok(contents) => expanded.extend(
+                contents
+                    .lines()
+                    .filter(|line| !line.is_empty())
+                    .map(OsString::from),
+            ),
+            Err(error) => Cli::command()
+                .error(
+                    ErrorKind::Io,
+                    format!("could not read the argument file {}: {}", path, error),
+                )
+                .exit(),
+        }
+    }
+    expanded
+}
+
+/// Checks a compression level while the arguments are parsed, but keeps it as it was written,
+/// so that it can be merged with the value of a config file.
+fn compression_level_argument(level: &str) -> Result<String, VcfBatcherError> {
+    parse_compression(level).map(|_| level.to_string())
+}
+
+/// Groups errors by their cause, so that scripts can tell whether a run should be retried
+/// (output errors), the input has to be fixed or the invocation is wrong.
+fn exit_code(error: &VcfBatcherError) -> i32 {
+    match error {
+        VcfBatcherError::InvalidTemplate { .. }
+        | VcfBatcherError::InvalidHeader { .. }
+        | VcfBatcherError::InvalidCompressionLevel { .. }
+        | VcfBatcherError::InvalidRegion { .. }
+        | VcfBatcherError::InvalidFilter { .. }
+        | VcfBatcherError::UnknownColumns { .. }
+        | VcfBatcherError::SameDirectory { .. }
+        | VcfBatcherError::InvalidConfig { .. }
+        | VcfBatcherError::UnknownProfile { .. }
+        | VcfBatcherError::InvalidOptions { .. }
+        | VcfBatcherError::FeatureDisabled { .. } => EXIT_USAGE,
+        VcfBatcherError::Read { .. }
+        | VcfBatcherError::NoBatches { .. }
+        | VcfBatcherError::HeaderMismatch { .. }
+        | VcfBatcherError::EmptyInput { .. }
+        | VcfBatcherError::LineTooLong { .. }
+        | VcfBatcherError::LateHeaderLine { .. }
+        | VcfBatcherError::UnknownContig { .. }
+        | VcfBatcherError::DuplicateSamples { .. }
+        | VcfBatcherError::UnknownSamples { .. }
+        | VcfBatcherError::UnorderedSamples { .. }
+        | VcfBatcherError::NonContiguousChromosome { .. }
+        | VcfBatcherError::Watch { .. }
+        | VcfBatcherError::MalformedRecord { .. }
+        | VcfBatcherError::DuplicateRecord { .. } => EXIT_INPUT,
+        VcfBatcherError::BatchWrite { .. }
+        | VcfBatcherError::Write { .. }
+        | VcfBatcherError::OutputExists { .. }
+        | VcfBatcherError::VerificationFailed { .. }
+        | VcfBatcherError::CommandFailed { .. }
+        | VcfBatcherError::OutputLocked { .. }
+        | VcfBatcherError::Lock { .. } => EXIT_OUTPUT,
+        VcfBatcherError::Interrupted { .. } => EXIT_INTERRUPTED,
+        VcfBatcherError::CohortFailed { first, .. } => exit_code(first),
+    }
+}
+
+fn watch(args: WatchArgs, json: bool) -> Result<(), VcfBatcherError> {
+    let file_config = match &args.config {
+        Some(path) => Config::read(path)?,
+        None => Config::default(),
+    }
+    .with_profile(args.profile.as_deref())?;
+    let config = file_config.merge(Config {
+        batch_size: args.batch_size,
+        compression_level: args.compression_level,
+        name_template: args
+            .output_template
+            .map(|template| template.as_str().to_string()),
+        tmp_dir: args.tmp_dir,
+        ..Default::default()
+    });
+    let cancel = Arc::new(AtomicBool::new(false));
+    install_signal_handler(Arc::clone(&cancel));
+    let options = BatchOptions {
+        cancel: Some(cancel),
+        quiet: true,
+        // An input whose batches already exist is moved to the failed directory
+        overwrite: config.overwrite.unwrap_or(OverwritePolicy::Refuse),
+        ..config.to_options()?
+    };
+    let defaults = WatchOptions::new(&args.incoming_dir);
+    let watch_options = WatchOptions {
+        done_dir: args.done_dir.unwrap_or(defaults.done_dir),
+        failed_dir: args.failed_dir.unwrap_or(defaults.failed_dir),
+        settle_time: Duration::try_from_secs_f64(args.settle_seconds).unwrap_or_else(|_| {
+            Cli::command()
+                .error(
+                    ErrorKind::ValueValidation,
+                    "--settle-seconds must be a non-negative number",
+                )
+                .exit()
+        }),
+    };
+
+    let metrics = Arc::new(WatchMetrics::new());
+    if let Some(address) = args.metrics_addr {
+        let address = serve_metrics(address, Arc::clone(&metrics)).unwrap_or_else(|error| {
+            Cli::command()
+                .error(
+                    ErrorKind::Io,
+                    format!("cannot serve metrics on {address}: {error}"),
+                )
+                .exit()
+        });
+        eprintln!("Serving metrics at http://{address}/metrics");
+    }
+
+    eprintln!(
+        "Watching {} for VCF files, press Ctrl-C to stop",
+        args.incoming_dir.display()
+    );
+    watch_directory(
+        &args.incoming_dir,
+        &args.output_dir,
+        &options,
+        &watch_options,
+        |event| {
+            metrics.record(&event);
+            if let Some(webhook) = &args.notify_url {
+                let payload = match &event {
+                    WatchEvent::Batched { input, report } => {
+                        run_payload(input, &report.output_path, Ok(report))
+                    }
+                    WatchEvent::Failed { input, error } => {
+                        run_payload(input, &args.output_dir.join(input_stem(input)), Err(error))
+                    }
+                };
+                if let Err(error) = webhook.notify(&payload) {
+                    eprintln!("Warning: could not notify {webhook}: {error}");
+                }
+            }
+            print_watch_event(event, json)
+        },
+    )
+}
+
+fn cohort(args: CohortArgs, json: bool) -> Result<(), VcfBatcherError> {
+    let file_config = match &args.config {
+        Some(path) => Config::read(path)?,
+        None => Config::default(),
+    }
+    .with_profile(args.profile.as_deref())?;
+    let config = file_config.merge(Config {
+        batch_size: args.batch_size,
+        compression_level: args.compression_level,
+        name_template: args
+            .output_template
+            .map(|template| template.as_str().to_string()),
+        tmp_dir: args.tmp_dir,
+        overwrite: args.force.then_some(OverwritePolicy::Overwrite),
+        ..Default::default()
+    });
+    let cancel = Arc::new(AtomicBool::new(false));
+    install_signal_handler(Arc::clone(&cancel));
+    let options = BatchOptions {
+        cancel: Some(cancel),
+        // The inputs would interleave their messages, a line per input is printed instead
+        quiet: true,
+        overwrite: config.overwrite.unwrap_or(OverwritePolicy::Refuse),
+        ..config.to_options()?
+    };
+
+    let inputs = match args.inputs.as_slice() {
+        [directory] if directory.is_dir() => {
+            find_inputs(directory).map_err(|source| VcfBatcherError::Read {
+                path: directory.clone(),
+                source,
+            })?
+        }
+        inputs => inputs.to_vec(),
+    };
+    if inputs.is_empty() {
+        Cli::command()
+            .error(
+                ErrorKind::InvalidValue,
+                format!("{} contains no VCF files", args.inputs[0].display()),
+            )
+            .exit()
+    }
+    let jobs = args.jobs.map_or_else(
+        || thread::available_parallelism().map_or(1, usize::from),
+        |jobs| jobs as usize,
+    );
+    eprintln!(
+        "Splitting {} inputs, {} at a time",
+        inputs.len(),
+        jobs.min(inputs.len())
+    );
+    let report = split_cohort(
+        &inputs,
+        &args.output_dir,
+        &options,
+        jobs,
+        |run| match &run.result {
+            // With --json, the summary of all inputs is printed at the end
+            Ok(_) if json => {}
+            Ok(report) => println!(
+                "Split {} into {} batches with {} records in {}",
+                run.input.display(),
+                report.batches.len(),
+                report.total_records(),
+                report.output_path.display()
+            ),
+            Err(error) => eprintln!("Error: could not split {}: {}", run.input.display(), error),
+        },
+    )?;
+
+    let summary = report.summary();
+    match json {
+        true => print_json(&json!(summary)),
+        false => println!(
+            "Split {} of {} inputs into {} batches with {} records in {:.1} seconds, see {}",
+            summary.inputs - summary.failed,
+            summary.inputs,
+            summary.batches,
+            summary.records,
+            summary.elapsed_seconds,
+            args.output_dir.join(COHORT_SUMMARY_FILE_NAME).display()
+        ),
+    }
+    let mut runs = report.runs.into_iter();
+    match runs.find_map(|run| run.result.err()) {
+        Some(first) => Err(VcfBatcherError::CohortFailed {
+            failed: summary.failed,
+            inputs: summary.inputs,
+            first: Box::new(first),
+        }),
+        None => Ok(()),
+    }
+}
+
+fn serve_jobs(args: ServeArgs) {
+    let listener = TcpListener::bind(args.listen).unwrap_or_else(|error| {
+        Cli::command()
+            .error(
+                ErrorKind::Io,
+                format!("cannot listen on {}: {error}", args.listen),
+            )
+            .exit()
+    });
+    eprintln!(
+        "Accepting split jobs at http://{}/jobs, press Ctrl-C to stop",
+        args.listen
+    );
+    serve(
+        listener,
+        &ServeOptions {
+            workers: args.workers,
+            webhook: args.notify_url,
+        },
+    )
+}
+
+/// Prints what happened to a file in the watched directory.
+fn print_watch_event(event: WatchEvent, json: bool) {
+    match event {
+        // One document per input, since the watch does not end by itself
+        WatchEvent::Batched { input, report } if json => {
+            print_json(&json!({ "ok": true, "input": input, "report": report }))
+        }
+        WatchEvent::Failed { input, error } if json => {
+            let mut document = error_json(&error);
+            document["input"] = json!(input);
+            print_json(&document);
+        }
+        WatchEvent::Batched { input, report } => println!(
+            "Split {} into {} batches with {} records in {}",
+            input.display(),
+            report.batches.len(),
+            report.total_records(),
+            report.output_path.display()
+        ),
+        WatchEvent::Failed { input, error } => {
+            eprintln!("Error: could not split {}: {}", input.display(), error)
+        }
+    }
+}
+
+fn split(args: SplitArgs, global: GlobalArgs) -> Result<(), VcfBatcherError> {
     let start = Instant::now();
-    let args = Cli::parse();
+    let json = global.json;
+    let file_config = match &args.config {
+        Some(path) => Config::read(path)?,
+        None => Config::default(),
+    }
+    .with_profile(args.profile.as_deref())?;
+    let config = file_config.merge(Config {
+        input: args.input_path.map(PathBuf::from),
+        output: args.output_path.map(PathBuf::from),
+        log_file: args.log_file,
+        tmp_dir: args.tmp_dir,
+        batch_size: args.batch_size,
+        batch_sizes: args.batch_sizes,
+        num_batches: args.num_batches.map(|batches| batches as usize),
+        target_compressed_size: args.target_compressed_size,
+        target_size: args.target_size,
+        compression_level: args.compression_level,
+        compression_format: args.compression_format,
+        split_by: args.split_by,
+        genes_per_batch: args.genes_per_batch.map(|genes| genes as usize),
+        group_by_info: args.group_by_info,
+        regions_bed: args.regions_bed,
+        samples_per_batch: args.samples_per_batch.map(|samples| samples as usize),
+        on_empty_input: args.on_empty_input,
+        on_late_header: args.on_late_header,
+        check_contigs: args.check_contigs,
+        on_duplicate_samples: args.on_duplicate_samples,
+        on_bad_record: args.on_bad_record,
+        dedup: args.dedup,
+        resume: args.resume.then_some(true),
+        append: args.append,
+        max_line_length: args.max_line_length,
+        regions: (!args.regions.is_empty())
+            .then(|| args.regions.iter().map(Region::to_string).collect()),
+        samples: args.samples,
+        sample_order: args.sample_order,
+        mask_samples: args.mask_samples,
+        mask_all_fields: args.mask_all_fields.then_some(true),
+        pass_only: args.pass_only.then_some(true),
+        min_qual: args.min_qual,
+        name_template: args
+            .output_template
+            .map(|template| template.as_str().to_string()),
+        prefix_from_input: args.prefix_from_input.then_some(true),
+        region_names: args.region_names.then_some(true),
+        format: args.format,
+        json_fields: args.json_fields,
+        site_lists: args.site_lists.then_some(true),
+        batches_bed: args.batches_bed.then_some(true),
+        index: args.index,
+        verify_output: args.verify_output.then_some(true),
+        manifest: args.manifest.then_some(true),
+        #[cfg(feature = "parquet")]
+        parquet: args.parquet,
+        #[cfg(feature = "parquet")]
+        parquet_info: args.parquet_info,
+        overwrite: match (args.force, args.no_clobber) {
+            (true, _) => Some(OverwritePolicy::Overwrite),
+            (_, true) => Some(OverwritePolicy::Skip),
+            _ => None,
+        },
+        ..Default::default()
+    });
+    // Batches that are piped into a command are not written anywhere
+    let output_path = match &args.pipe_to {
+        Some(_) => config.output.as_deref().or(Some(Path::new(""))),
+        None => config.output.as_deref(),
+    };
+    let (Some(input_path), Some(output_path)) = (&config.input, output_path) else {
+        Cli::command()
+            .error(
+                ErrorKind::MissingRequiredArgument,
+                "the input and output paths must be given, either as arguments or in the --config file",
+            )
+            .exit()
+    };
+    let input_path = input_path.to_string_lossy();
 
-    let input_path = args.input_path;
-    let output_path = Path::new(&args.output_path);
-    let batch_size = args.batch_size;
+    let cancel = Arc::new(AtomicBool::new(false));
+    install_signal_handler(Arc::clone(&cancel));
 
-    let compression_level: Option<Compression> = parse_compression_level(args.compression_level);
+    let mut options = BatchOptions {
+        cancel: Some(cancel),
+        // The per-batch lines would end up in the JSON document on stdout
+        quiet: json,
+        // Unlike the library, the CLI does not replace files unless asked to
+        overwrite: config.overwrite.unwrap_or(OverwritePolicy::Refuse),
+        timings: args.timings,
+        ..config.to_options()?
+    };
+    let progress = match args.no_progress {
+        true => ProgressFormat::Lines,
+        false => args.progress,
+    };
+    let events = RunEvents {
+        log: config.log_file.as_ref().map(RunLog::create).transpose()?,
+        json_progress: progress == ProgressFormat::Json,
+    };
+    if events.is_active() {
+        events.emit(
+            "start",
+            json!({ "version": env!("CARGO_PKG_VERSION"), "dry_run": args.dry_run, "parameters": config }),
+        );
+        let batch_events = events.clone();
+        options.on_batch = Some(BatchCallback::new(move |batch| {
+            batch_events.emit("batch", json!(batch))
+        }));
+    }
+    let batch_size = match (
+        options.target_compressed_size,
+        options.target_size,
+        options.num_batches,
+    ) {
+        (Some(size), _, _) => format!("about {} bytes", size),
+        (None, Some(size), _) => format!("about {} bytes before compression", size),
+        (None, None, Some(batches)) => format!("1/{} of the records", batches),
+        (None, None, None) => options
+            .batch_sizes
+            .iter()
+            .chain([&options.batch_size])
+            .map(usize::to_string)
+            .collect::<Vec<_>>()
+            .join(", "),
+    };
+    let from_stdin = input_path == STDIO_PATH;
+    if from_stdin && args.dry_run {
+        Cli::command()
+            .error(
+                ErrorKind::ArgumentConflict,
+                "--dry-run cannot be used when reading from stdin, since the input would be consumed",
+            )
+            .exit()
+    }
+    if from_stdin && options.num_batches.is_some() {
+        Cli::command()
+            .error(
+                ErrorKind::ArgumentConflict,
+                "--num-batches needs an input file to count the records of, it cannot read from stdin",
+            )
+            .exit()
+    }
+    if from_stdin && args.parallel_contigs {
+        Cli::command()
+            .error(
+                ErrorKind::ArgumentConflict,
+                "--parallel-contigs needs an indexed input file, it cannot read from stdin",
+            )
+            .exit()
+    }
+    if args.dry_run {
+        // The per-batch lines and the summary are replaced by the listing of the planned batches
+        options.quiet = true;
+        let report = plan_batches(&input_path, output_path, &options)?;
+        print_plan(&report, json, &events);
+        return events.finish(&report);
+    }
+    if config.overwrite.is_none() && !options.resume && args.pipe_to.is_none() {
+        options.overwrite = confirm_overwrite(output_path, args.yes, from_stdin)?;
+    }
+    if args.pipe_to.is_some() && config.output.is_some() {
+        events.warning("nothing is written to the output directory with --pipe-to");
+    }
+    if from_stdin && options.resume {
+        events.warning("--resume has no effect when reading from stdin");
+    }
+    // The size of stdin is unknown, so there is nothing to show a bar for
+    let progress_bar = match progress {
+        ProgressFormat::Auto => !from_stdin && io::stderr().is_terminal(),
+        ProgressFormat::Bar => !from_stdin,
+        ProgressFormat::Lines | ProgressFormat::Json => false,
+    }
+    .then(|| attach_progress_bar(&mut options));
+    if progress == ProgressFormat::Json {
+        attach_json_progress(&mut options);
+    }
+    #[cfg(feature = "otel")]
+    let trace = args.otlp_endpoint.as_ref().map(|_| {
+        let trace = RunTrace::start(std::env::var("TRACEPARENT").ok().as_deref());
+        trace.instrument(&mut options);
+        trace
+    });
 
-    extract_variants_to_batches(&input_path, batch_size, output_path, compression_level);
+    if let Some(threads) = args.threads {
+        // Blocks are compressed on the global thread pool, which only its first use can size
+        let _ = rayon::ThreadPoolBuilder::new()
+            .num_threads(threads as usize)
+            .build_global();
+    }
+    let result = match from_stdin {
+        _ if args.pipe_to.is_some() => pipe_batches(
+            args.pipe_to.as_deref().unwrap_or_default(),
+            args.pipe_jobs.unwrap_or(1) as usize,
+            &input_path,
+            &options,
+        ),
+        true => extract_variants_from_reader(io::stdin().lock(), output_path, &options),
+        false if args.parallel_contigs => {
+            extract_contigs_in_parallel(&input_path, output_path, &options)
+        }
+        #[cfg(feature = "htslib")]
+        false if args.htslib || input_path.ends_with(".bcf") => {
+            vcf_batcher::htslib::extract_variants_with_htslib(&input_path, output_path, &options)
+        }
+        false => extract_variants_to_batches_with_options(&input_path, output_path, &options),
+    };
+    if let Some(progress_bar) = progress_bar {
+        progress_bar.finish();
+    }
+    #[cfg(feature = "otel")]
+    if let (Some(trace), Some(endpoint)) = (trace, &args.otlp_endpoint) {
+        let traces = trace.finish(Path::new(&*input_path), output_path, result.as_ref());
+        if let Err(error) = endpoint.notify(&traces) {
+            events.warning(&format!(
+                "could not export the trace to {endpoint}: {error}"
+            ));
+        }
+    }
+    match result {
+        Ok(report) => {
+            report_warnings(&report, &events);
+            #[cfg(feature = "catalog")]
+            if let Some(catalog) = &args.catalog {
+                let path = catalog
+                    .clone()
+                    .unwrap_or_else(|| output_path.join(CATALOG_FILE_NAME));
+                Catalog::open(path)?.record_run(
+                    Path::new(&*input_path),
+                    &report,
+                    &json!(config),
+                )?;
+            }
+            if let Some(timings) = &report.timings {
+                print_timings(timings, report.elapsed);
+            }
+            if json {
+                print_json(&json!({ "ok": true, "report": report }));
+            }
+            events.finish(&report)?;
+        }
+        Err(error) => {
+            events.fail(&error);
+            match &error {
+                VcfBatcherError::CommandFailed { failures, .. } => {
+                    for failure in failures {
+                        eprintln!(
+                            "The command failed for batch {} ({}): {}",
+                            failure.number,
+                            failure.path.display(),
+                            failure.reason
+                        );
+                    }
+                }
+                // There is no output directory to write a manifest to
+                _ if args.pipe_to.is_some() => {}
+                _ => report_completed_batches(&error, output_path, global.verbose),
+            }
+            if let VcfBatcherError::OutputExists { .. } = error {
+                eprintln!(
+                    "Pass --force or --yes to overwrite existing batches or --no-clobber to keep them"
+                );
+            }
+            return Err(error);
+        }
+    }
 
     let elapsed_time = start.elapsed();
-    println!(
+    let summary = format!(
         "Extracted variants into batches of size {} in: {} seconds",
         batch_size,
         elapsed_time.as_secs_f32()
     );
+    match json {
+        true => eprintln!("{}", summary),
+        false => println!("{}", summary),
+    }
+    Ok(())
+}
+
+/// Pipes the batches into a command each instead of writing them, see `--pipe-to`. The summary names
+/// the command and is only printed once all commands exited.
+fn pipe_batches(
+    command: &str,
+    jobs: usize,
+    input_path: &str,
+    options: &BatchOptions,
+) -> Result<RunReport, VcfBatcherError> {
+    let mut sink = CommandSink::new(command, jobs);
+    let sink_options = BatchOptions {
+        quiet: true,
+        ..options.clone()
+    };
+    let report = match input_path == STDIO_PATH {
+        true => extract_variants_from_reader_to_sink(io::stdin().lock(), &mut sink, &sink_options),
+        false => extract_variants_to_sink(input_path, &mut sink, &sink_options),
+    }
+    .and_then(|report| sink.finish(report))?;
+    if !options.quiet {
+        println!("Piped {} batches to `{}`.", report.batches.len(), command);
+    }
+    Ok(report)
+}
+
+/// How the progress of a split run is shown.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum ProgressFormat {
+    /// A progress bar if stderr is a terminal and the input is a file, otherwise a line per batch
+    Auto,
+    /// A progress bar, unless the input is read from stdin
+    Bar,
+    /// A line per batch
+    Lines,
+    /// Newline-delimited JSON events on stderr
+    Json,
+}
+
+/// Records the events of a split run besides the console output: in the `--log-file` and,
+/// with `--progress json`, as newline-delimited JSON objects on stderr.
+#[derive(Clone)]
+struct RunEvents {
+    log: Option<RunLog>,
+    json_progress: bool,
+}
+
+impl RunEvents {
+    /// Whether the events are recorded anywhere.
+    fn is_active(&self) -> bool {
+        self.log.is_some() || self.json_progress
+    }
+
+    fn emit(&self, event: &str, fields: Value) {
+        if self.json_progress {
+            print_progress_event(event, fields.clone());
+        }
+        if let Some(log) = &self.log {
+            log.event(event, fields);
+        }
+    }
+
+    /// Shows a warning on stderr, as an event with `--progress json`, and adds it to the log.
+    fn warning(&self, message: &str) {
+        match self.json_progress {
+            true => print_progress_event("warning", json!({ "message": message })),
+            false => eprintln!("Warning: {}", message),
+        }
+        if let Some(log) = &self.log {
+            log.warning(message);
+        }
+    }
+
+    /// Records the summary of a successful run and closes the log.
+    fn finish(self, report: &RunReport) -> Result<(), VcfBatcherError> {
+        self.emit(
+            "finish",
+            json!({
+                "batches": report.batches.len(),
+                "records": report.total_records(),
+                "filtered_records": report.filtered_records,
+                "bad_records": report.bad_records,
+                "duplicate_records": report.duplicate_records,
+                "resumed_batches": report.resumed_batches,
+                "skipped_batches": report.skipped_batches,
+                "existing_batches": report.existing_batches,
+                "groups": report.groups,
+                "elapsed_seconds": report.elapsed.as_secs_f64(),
+                "timings": report.timings,
+            }),
+        );
+        match self.log {
+            Some(log) => log.finish(),
+            None => Ok(()),
+        }
+    }
+
+    /// Records the error a run failed with and closes the log.
+    fn fail(self, error: &VcfBatcherError) {
+        self.emit(
+            "error",
+            json!({
+                "message": error.to_string(),
+                "exit_code": exit_code(error),
+                "completed_batches": error.completed_batches().map_or(0, |completed| completed.batches.len()),
+            }),
+        );
+        if let Some(log) = self.log {
+            // The error of the run is more important than one of the log
+            let _ = log.finish();
+        }
+    }
+}
+
+/// Prints an event of `--progress json` as a single line on stderr.
+fn print_progress_event(event: &str, fields: Value) {
+    let mut line = serde_json::Map::new();
+    line.insert("event".to_string(), Value::String(event.to_string()));
+    if let Value::Object(fields) = fields {
+        line.extend(fields);
+    }
+    eprintln!("{}", Value::Object(line));
+}
+
+/// Prints the progress of the run as JSON events on stderr, at most one per [`JSON_PROGRESS_INTERVAL`].
+/// Completed batches are reported as events of their own, see [`RunEvents`].
+fn attach_json_progress(options: &mut BatchOptions) {
+    let last_event: Mutex<Option<Instant>> = Mutex::new(None);
+    options.on_progress = Some(ProgressCallback::new(move |progress| {
+        let mut last_event = last_event.lock().unwrap_or_else(|error| error.into_inner());
+        if last_event.is_some_and(|time| time.elapsed() < JSON_PROGRESS_INTERVAL) {
+            return;
+        }
+        *last_event = Some(Instant::now());
+        let mut fields = json!(progress);
+        fields["stage"] = json!("split");
+        fields["records_per_second"] = json!(progress.records_per_second());
+        fields["bytes_per_second"] = json!(progress.bytes_per_second());
+        fields["remaining_seconds"] = json!(progress
+            .remaining()
+            .map(|remaining| remaining.as_secs_f64()));
+        print_progress_event("progress", fields);
+    }));
+    // The per-batch lines are replaced by the events
+    options.quiet = true;
+}
+
+/// Decides what to do with existing batch files if neither `--force` nor `--no-clobber` was given:
+/// with `--yes` they are overwritten, in a terminal the user is asked if the output directory
+/// already contains VCF files, and otherwise the run fails at the first existing file.
+/// If the user declines, the run stops before the input is read.
+fn confirm_overwrite(
+    output_path: &Path,
+    yes: bool,
+    from_stdin: bool,
+) -> Result<OverwritePolicy, VcfBatcherError> {
+    if yes {
+        return Ok(OverwritePolicy::Overwrite);
+    }
+    // The answer cannot be read if stdin is the input or nobody is watching
+    if from_stdin || !io::stdin().is_terminal() || !io::stdout().is_terminal() {
+        return Ok(OverwritePolicy::Refuse);
+    }
+    let existing = count_vcf_files(output_path);
+    if existing == 0 {
+        return Ok(OverwritePolicy::Refuse);
+    }
+    eprint!(
+        "{} already contains {} VCF files, batches with the same names will be overwritten. Continue? [y/N] ",
+        output_path.display(),
+        existing
+    );
+    let mut answer = String::new();
+    io::stdin()
+        .read_line(&mut answer)
+        .map_err(|source| VcfBatcherError::Read {
+            path: PathBuf::from(STDIO_PATH),
+            source,
+        })?;
+    match answer.trim().to_lowercase().as_str() {
+        "y" | "yes" => Ok(OverwritePolicy::Overwrite),
+        // Stop before reading the input instead of failing at the first existing batch
+        _ => Err(VcfBatcherError::OutputExists {
+            path: output_path.to_path_buf(),
+            completed: Box::default(),
+        }),
+    }
+}
+
+/// Counts the `.vcf` and `.vcf.gz` files in a directory and its subdirectories, which name templates may create.
+fn count_vcf_files(directory: &Path) -> usize {
+    let Ok(entries) = fs::read_dir(directory) else {
+        return 0;
+    };
+    entries
+        .flatten()
+        .map(|entry| {
+            let path = entry.path();
+            let name = entry.file_name().to_string_lossy().into_owned();
+            if path.is_dir() {
+                count_vcf_files(&path)
+            } else {
+                usize::from(name.ends_with(".vcf") || name.ends_with(".vcf.gz"))
+            }
+        })
+        .sum()
+}
+
+/// Lists the batches of a dry run, see [`plan_batches`].
+fn print_plan(report: &RunReport, json: bool, events: &RunEvents) {
+    if json {
+        print_json(&json!({ "ok": true, "dry_run": true, "report": report }));
+        return;
+    }
+    let batches = &report.batches[report.existing_batches..];
+    println!(
+        "Would write {} batches with {} records to {}:",
+        batches.len(),
+        batches.iter().map(|batch| batch.records).sum::<usize>(),
+        report.output_path.display()
+    );
+    for batch in batches {
+        println!("  {} ({} records)", batch.path.display(), batch.records);
+    }
+    if report.filtered_records > 0 {
+        println!("{} records would be filtered out", report.filtered_records);
+    }
+    report_warnings(report, events);
+}
+
+/// Shows the progress of the run in a bar on stderr instead of printing a line per batch.
+fn attach_progress_bar(options: &mut BatchOptions) -> ProgressBar {
+    let progress_bar = ProgressBar::new(0).with_style(
+        ProgressStyle::with_template("{bar:40} {bytes}/{total_bytes} {msg}")
+            .unwrap_or_else(|_| ProgressStyle::default_bar()),
+    );
+    let bar = progress_bar.clone();
+    options.on_progress = Some(ProgressCallback::new(move |progress| {
+        if let Some(total_bytes) = progress.total_bytes {
+            bar.set_length(total_bytes);
+        }
+        bar.set_position(progress.bytes_read);
+        // indicatif's own rate and ETA only see the last few updates, which jump with the size of the records
+        let remaining = match progress.remaining() {
+            Some(remaining) => format!(", {} remaining", HumanDuration(remaining)),
+            None => String::new(),
+        };
+        bar.set_message(format!(
+            "({}/s{remaining}) {} records ({:.0}/s), {} batches written",
+            HumanBytes(progress.bytes_per_second() as u64),
+            progress.records,
+            progress.records_per_second(),
+            progress.batches
+        ));
+    }));
+    options.quiet = true;
+    progress_bar
+}
+
+fn merge(args: MergeArgs, json: bool) -> Result<(), VcfBatcherError> {
+    if args.output_file == Path::new(STDIO_PATH) {
+        if json {
+            Cli::command()
+                .error(
+                    ErrorKind::ArgumentConflict,
+                    "--json cannot be used when the merged file is written to stdout",
+                )
+                .exit()
+        }
+        let compression_level = match args.compression_level {
+            Some(level) => parse_compression(&level)?,
+            None => None,
+        };
+        let report =
+            merge_batches_to_writer(&args.batch_dir, io::stdout().lock(), compression_level)?;
+        eprintln!(
+            "Merged {} batches with {} records",
+            report.batches.len(),
+            report.records
+        );
+        return Ok(());
+    }
+
+    let compression_level = match args.compression_level {
+        Some(level) => parse_compression(&level)?,
+        None => compression_for(&args.output_file),
+    };
+    let report = merge_batches_with_tmp_dir(
+        &args.batch_dir,
+        &args.output_file,
+        compression_level,
+        args.tmp_dir.as_deref(),
+    )?;
+    if json {
+        print_json(&json!({ "ok": true, "report": report }));
+        return Ok(());
+    }
+    println!(
+        "Merged {} batches with {} records into {}",
+        report.batches.len(),
+        report.records,
+        report.output.display()
+    );
+    Ok(())
+}
+
+fn generate_vcf(args: GenerateArgs) -> Result<(), VcfBatcherError> {
+    let vcf = SyntheticVcf {
+        samples: args.samples,
+        records: args.records,
+        contigs: args.contigs,
+        seed: args.seed,
+    };
+    if args.output_file == Path::new(STDIO_PATH) {
+        let compression_level = match args.compression_level {
+            Some(level) => parse_compression(&level)?,
+            None => None,
+        };
+        return vcf
+            .write(io::stdout().lock(), compression_level)
+            .map_err(|source| VcfBatcherError::Write {
+                path: args.output_file,
+                source: source.into(),
+            });
+    }
+
+    let compression_level = match args.compression_level {
+        Some(level) => parse_compression(&level)?,
+        None => compression_for(&args.output_file),
+    };
+    vcf.write_file(&args.output_file, compression_level)?;
+    eprintln!(
+        "Generated {} records with {} samples in {}",
+        vcf.records,
+        vcf.samples,
+        args.output_file.display()
+    );
+    Ok(())
+}
+
+fn recompress(args: RecompressArgs, json: bool) -> Result<(), VcfBatcherError> {
+    let threads = args
+        .threads
+        .unwrap_or_else(|| thread::available_parallelism().map_or(1, |threads| threads.get()));
+    // BGZF blocks are compressed on the global thread pool, which only its first use can size
+    let _ = rayon::ThreadPoolBuilder::new()
+        .num_threads(threads)
+        .build_global();
+    let options = RecompressOptions {
+        codec: args.codec,
+        level: Level::parse(&args.compression)?,
+        threads,
+    };
+    let files = match args.input.is_dir() {
+        true => recompress_dir(&args.input, &args.output, &options)?,
+        false => vec![recompress_file(&args.input, &args.output, &options)?],
+    };
+    if json {
+        print_json(&json!({ "ok": true, "files": files }));
+        return Ok(());
+    }
+    for file in &files {
+        println!(
+            "Recompressed {} ({} bytes) to {} ({} bytes)",
+            file.input.display(),
+            file.input_bytes,
+            file.output.display(),
+            file.output_bytes
+        );
+    }
+    Ok(())
+}
+
+fn reheader(args: ReheaderArgs, json: bool) -> Result<(), VcfBatcherError> {
+    let edit = match (&args.header, &args.patch) {
+        (Some(header), _) => HeaderEdit::read(header, false)?,
+        (None, Some(patch)) => HeaderEdit::read(patch, true)?,
+        (None, None) => unreachable!("clap requires --header or --patch"),
+    };
+    let output = args.output.as_ref().unwrap_or(&args.input);
+    let files = match args.input.is_dir() {
+        true => reheader_dir(&args.input, output, &edit)?,
+        false => vec![reheader_file(&args.input, output, &edit)?],
+    };
+    if json {
+        print_json(&json!({ "ok": true, "files": files }));
+        return Ok(());
+    }
+    for file in &files {
+        println!(
+            "Changed the header of {} into {} ({} bytes recompressed, output {} bytes)",
+            file.input.display(),
+            file.output.display(),
+            file.recompressed_bytes,
+            file.output_bytes
+        );
+    }
+    Ok(())
+}
+
+fn rebalance(args: RebalanceArgs, json: bool) -> Result<(), VcfBatcherError> {
+    let compression_level = match args.compression_level {
+        Some(level) => parse_compression(&level)?,
+        None => batch_files(&args.batch_dir, &args.output_dir)?
+            .first()
+            .and_then(|batch| compression_for(batch)),
+    };
+    let options = BatchOptions {
+        batch_size: args.batch_size as usize,
+        compression_level,
+        overwrite: match args.force {
+            true => OverwritePolicy::Overwrite,
+            false => OverwritePolicy::Refuse,
+        },
+        quiet: json,
+        ..Default::default()
+    };
+    let report = rebalance_batches(&args.batch_dir, &args.output_dir, &options)?;
+    if json {
+        print_json(&json!({ "ok": true, "report": report }));
+    }
+    Ok(())
+}
+
+fn stats(args: StatsArgs, json: bool) -> Result<(), VcfBatcherError> {
+    let stats = vcf_stats(&args.file)?;
+    if json {
+        print_json(&json!({ "ok": true, "stats": stats }));
+        return Ok(());
+    }
+    println!("File:         {}", stats.path.display());
+    println!("Header lines: {}", stats.header_lines);
+    println!("Records:      {}", stats.records);
+    println!("Samples:      {}", stats.samples.len());
+    if !stats.records_per_contig.is_empty() {
+        println!("Records per contig:");
+        for (contig, records) in &stats.records_per_contig {
+            println!("  {}\t{}", contig, records);
+        }
+    }
+    Ok(())
+}
+
+fn validate(args: ValidateArgs, json: bool) -> Result<(), VcfBatcherError> {
+    let issues = validate_vcf(&args.file)?;
+    let errors = issues
+        .iter()
+        .filter(|issue| issue.severity == Severity::Error)
+        .count();
+    if json {
+        print_json(&json!({ "ok": errors == 0, "issues": issues }));
+    } else {
+        for issue in &issues {
+            println!("{}", issue);
+        }
+        println!(
+            "{}: {} errors, {} warnings",
+            args.file.display(),
+            errors,
+            issues.len() - errors
+        );
+    }
+    if errors > 0 {
+        process::exit(EXIT_INVALID);
+    }
+    Ok(())
+}
+
+fn inspect(args: InspectArgs, json: bool) -> Result<(), VcfBatcherError> {
+    let header = read_header(&args.file)?;
+    if json {
+        print_json(&json!({ "ok": true, "header": header }));
+        return Ok(());
+    }
+    if args.samples {
+        for sample in &header.samples {
+            println!("{}", sample);
+        }
+        return Ok(());
+    }
+
+    let or_dot = |value: &Option<String>| value.clone().unwrap_or_else(|| ".".to_string());
+    println!(
+        "File format: {}",
+        header.fileformat.as_deref().unwrap_or("unknown")
+    );
+    println!("Samples:     {}", header.samples.len());
+    println!("Contigs:     {}", header.contigs.len());
+    for contig in &header.contigs {
+        match contig.length {
+            Some(length) => println!("  {}\t{}", contig.name, length),
+            None => println!("  {}", contig.name),
+        }
+    }
+    for (key, fields) in [("INFO", &header.info), ("FORMAT", &header.format)] {
+        println!("{} fields: {}", key, fields.len());
+        for field in fields {
+            println!(
+                "  {}\t{}\t{}\t{}",
+                field.id,
+                or_dot(&field.number),
+                or_dot(&field.kind),
+                or_dot(&field.description)
+            );
+        }
+    }
+    Ok(())
+}
+
+/// Writes the completion script for the binary's name, which differs from the package name.
+fn completions(args: CompletionsArgs) {
+    generate(args.shell, &mut Cli::command(), BIN_NAME, &mut io::stdout());
+}
+
+fn mangen(args: MangenArgs) -> Result<(), VcfBatcherError> {
+    let command = Cli::command();
+    let Some(out_dir) = args.out_dir else {
+        return Man::new(command)
+            .render(&mut io::stdout())
+            .map_err(|source| write_error(Path::new("stdout"), source));
+    };
+
+    fs::create_dir_all(&out_dir).map_err(|source| write_error(&out_dir, source))?;
+    let subcommands: Vec<_> = command
+        .get_subcommands()
+        .filter(|subcommand| !subcommand.is_hide_set())
+        .map(|subcommand| {
+            let name = format!("{}-{}", BIN_NAME, subcommand.get_name());
+            subcommand
+                .clone()
+                .name(name)
+                .version(env!("CARGO_PKG_VERSION"))
+        })
+        .collect();
+    for page in std::iter::once(command).chain(subcommands) {
+        let path = out_dir.join(format!("{}.1", page.get_name()));
+        let mut contents = Vec::new();
+        Man::new(page)
+            .render(&mut contents)
+            .and_then(|_| fs::write(&path, contents))
+            .map_err(|source| write_error(&path, source))?;
+        eprintln!("Wrote {}", path.display());
+    }
+    Ok(())
+}
+
+fn write_error(path: &Path, source: io::Error) -> VcfBatcherError {
+    VcfBatcherError::Write {
+        path: path.to_path_buf(),
+        source: source.into(),
+    }
+}
+
+/// Prints a JSON document on a single line of stdout, for `--json`.
+fn print_json(value: &Value) {
+    println!("{}", value);
+}
+
+/// Describes an error for `--json`, including the batches that were completed before it.
+fn error_json(error: &VcfBatcherError) -> Value {
+    json!({
+        "ok": false,
+        "error": error.to_string(),
+        "exit_code": exit_code(error),
+        "interrupted": matches!(error, VcfBatcherError::Interrupted { .. }),
+        "completed": error.completed_batches(),
+    })
+}
+
+/// Replaces the panic message and backtrace by a single line, since panics are bugs that users
+/// cannot act on, apart from reporting them. `--verbose` adds where the panic happened.
+fn install_panic_hook(verbose: bool) {
+    std::panic::set_hook(Box::new(move |info| {
+        let payload = info.payload();
+        let message = payload
+            .downcast_ref::<&str>()
+            .copied()
+            .or_else(|| payload.downcast_ref::<String>().map(String::as_str))
+            .unwrap_or("unknown panic");
+        // A closed pipe (e.g. `vcf_batcher_cli stats input.vcf | head`) is not an error worth reporting
+        if message.contains("Broken pipe") {
+            process::exit(EXIT_OUTPUT);
+        }
+        eprintln!(
+            "Error: internal error: {}. Please report this at {}/issues",
+            message,
+            env!("CARGO_PKG_REPOSITORY")
+        );
+        if let (true, Some(location)) = (verbose, info.location()) {
+            eprintln!("  at {}", location);
+        }
+        process::exit(EXIT_INTERNAL);
+    }));
+}
+
+/// Stops batching on Ctrl-C or SIGTERM so that the current batch can be finished cleanly.
+/// A second signal exits immediately.
+fn install_signal_handler(cancel: Arc<AtomicBool>) {
+    let result = ctrlc::set_handler(move || {
+        if cancel.swap(true, Ordering::SeqCst) {
+            process::exit(EXIT_INTERRUPTED);
+        }
+        eprintln!("Received interrupt, stopping after the current batch...");
+    });
+    if let Err(error) = result {
+        eprintln!("Warning: could not install signal handler: {}", error);
+    }
+}
+
+/// Prints the time spent in each stage of a run on stderr, with its share of the elapsed time.
+fn print_timings(timings: &StageTimings, elapsed: Duration) {
+    let share = |duration: Duration| match elapsed.is_zero() {
+        true => 0.0,
+        false => 100.0 * duration.as_secs_f64() / elapsed.as_secs_f64(),
+    };
+    let measured: Duration = timings.stages().iter().map(|(_, duration)| *duration).sum();
+    eprintln!("Timings:");
+    for (stage, duration) in timings
+        .stages()
+        .into_iter()
+        .chain([("other", elapsed.saturating_sub(measured))])
+    {
+        eprintln!(
+            "  {:<26} {:>9.3}s {:>5.1}%",
+            stage,
+            duration.as_secs_f64(),
+            share(duration)
+        );
+    }
+}
+
+/// Prints warnings about problems in the input that did not stop the run.
+fn report_warnings(report: &RunReport, events: &RunEvents) {
+    if report.bad_records > 0 {
+        events.warning(&format!(
+            "left out {} malformed records",
+            report.bad_records
+        ));
+    }
+    if report.duplicate_records > 0 {
+        events.warning(&format!(
+            "{} records repeat the CHROM, POS, REF and ALT of an earlier record",
+            report.duplicate_records
+        ));
+    }
+    if !report.duplicate_samples.is_empty() {
+        events.warning(&format!(
+            "the header contains duplicate sample names: {}",
+            report.duplicate_samples.join(", ")
+        ));
+    }
+    if !report.unknown_contigs.is_empty() {
+        let contigs: Vec<String> = report
+            .unknown_contigs
+            .iter()
+            .map(|(contig, records)| format!("  {} ({} records)", contig, records))
+            .collect();
+        events.warning(&format!(
+            "records on contigs that are not declared in the header:\n{}",
+            contigs.join("\n")
+        ));
+    }
+}
+
+/// Prints the error on one line. With `--verbose`, the chain of underlying errors follows.
+fn report_error(error: &VcfBatcherError, verbose: bool) {
+    let message = error.to_string();
+    eprintln!("Error: {}", message);
+    if !verbose {
+        return;
+    }
+    let mut source = std::error::Error::source(error);
+    while let Some(cause) = source {
+        // Most messages already end with their immediate cause
+        let cause_message = cause.to_string();
+        if !message.contains(&cause_message) {
+            eprintln!("  caused by: {}", cause_message);
+        }
+        source = cause.source();
+    }
+}
+
+/// If batching had already started, records which batches are complete and can be trusted in an
+/// (incomplete) manifest in the output directory. With `--verbose`, the batches are also listed.
+fn report_completed_batches(error: &VcfBatcherError, output_path: &Path, verbose: bool) {
+    let Some(completed) = error.completed_batches() else {
+        return;
+    };
+    if completed.batches.is_empty() {
+        // An empty manifest would replace the one of an earlier run in the same directory
+        return;
+    }
+    if verbose {
+        eprintln!(
+            "{} batches were written completely before the failure:",
+            completed.batches.len()
+        );
+        for batch in &completed.batches {
+            eprintln!("  {} ({} records)", batch.path.display(), batch.records);
+        }
+    }
+    match Manifest::from_report(completed, false).write(output_path) {
+        Ok(manifest_path) => eprintln!(
+            "{} batches were written completely before the failure, they are listed in {}",
+            completed.batches.len(),
+            manifest_path.display()
+        ),
+        Err(error) => eprintln!("Could not write manifest: {}", error),
+    }
 }
@@ -0,0 +1,362 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::naming::NameTemplate;
+use crate::{BatchOptions, CompressionFormat, Locus, RunReport, SplitMode};
+
+/// Name of the manifest file that is written into the output directory.
+pub const MANIFEST_FILE_NAME: &str = "manifest.json";
+
+/// Name of the journal that records completed batches while a resumable run is in progress.
+pub const JOURNAL_FILE_NAME: &str = ".vcf_batcher.journal";
+
+/// Machine-readable description of the batches in an output directory.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Manifest {
+    /// Whether the run finished. If `false`, the input was only partially batched,
+    /// but every batch listed in the manifest is complete.
+    pub complete: bool,
+    /// Identifies the input file and the options that produced the batches.
+    /// A re-run with the same fingerprint produces identical batches.
+    #[serde(default)]
+    pub fingerprint: String,
+    pub batches: Vec<ManifestEntry>,
+    /// The directory of the batches of every value of [`BatchOptions::group_by_info`], relative to the
+    /// output directory, by value.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub groups: BTreeMap<String, String>,
+}
+
+/// A single batch listed in the manifest.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ManifestEntry {
+    pub number: usize,
+    /// File name of the batch, relative to the output directory.
+    pub file: String,
+    pub records: usize,
+    /// SHA-256 checksum of the batch file, as a lowercase hex string.
+    #[serde(default)]
+    pub sha256: String,
+    /// Size of the batch file in bytes, e.g. to request resources for the jobs that process it.
+    #[serde(default)]
+    pub size: u64,
+    /// Position of the first record, to hand the span of the batch to the jobs that process it.
+    #[serde(default)]
+    pub first: Option<Locus>,
+    /// Position of the last record.
+    #[serde(default)]
+    pub last: Option<Locus>,
+    /// Whether the batch was read back and checked after it was written.
+    #[serde(default)]
+    pub verified: bool,
+}
+
+impl Manifest {
+    pub fn from_report(report: &RunReport, complete: bool) -> Self {
+        Manifest {
+            complete,
+            fingerprint: report.fingerprint.clone(),
+            batches: report
+                .batches
+                .iter()
+                .map(|batch| ManifestEntry {
+                    number: batch.number,
+                    file: relative_path(&batch.path, &report.output_path),
+                    records: batch.records,
+                    sha256: batch.sha256.clone(),
+                    size: batch.size,
+                    first: batch.first.clone(),
+                    last: batch.last.clone(),
+                    verified: batch.verified,
+                })
+                .collect(),
+            groups: report
+                .groups
+                .iter()
+                .map(|(group, dir)| (group.clone(), relative_path(dir, &report.output_path)))
+                .collect(),
+        }
+    }
+
+    /// Reads the manifest from the output directory, if there is one.
+    pub fn read(output_path: &Path) -> io::Result<Option<Manifest>> {
+        match fs::read_to_string(output_path.join(MANIFEST_FILE_NAME)) {
+            Ok(contents) => Ok(Some(serde_json::from_str(&contents)?)),
+            Err(error) if error.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(error) => Err(error),
+        }
+    }
+
+    /// Writes the manifest to `manifest.json` in the output directory and returns its path.
+    /// The file is replaced atomically, so readers never observe a half-written manifest.
+    pub fn write(&self, output_path: &Path) -> io::Result<PathBuf> {
+        fs::create_dir_all(output_path)?;
+        let manifest_path = output_path.join(MANIFEST_FILE_NAME);
+        let temporary_path = output_path.join(format!(".{}.tmp", MANIFEST_FILE_NAME));
+
+        let contents = serde_json::to_string_pretty(self)?;
+        fs::write(&temporary_path, contents)?;
+        fs::rename(&temporary_path, &manifest_path)?;
+        Ok(manifest_path)
+    }
+}
+
+/// Append-only list of completed batches that is updated after every batch,
+/// so that a crashed run can be resumed even if no manifest was written.
+/// The first line holds the fingerprint of the run, every following line one [`ManifestEntry`].
+#[derive(Debug)]
+pub(crate) struct Journal {
+    path: PathBuf,
+    file: File,
+}
+
+impl Journal {
+    pub(crate) fn create(output_path: &Path, fingerprint: &str) -> io::Result<Journal> {
+        let path = output_path.join(JOURNAL_FILE_NAME);
+        let mut file = File::create(&path)?;
+        writeln!(file, "{}", fingerprint)?;
+        Ok(Journal { path, file })
+    }
+
+    pub(crate) fn append(&mut self, entry: &ManifestEntry) -> io::Result<()> {
+        writeln!(self.file, "{}", serde_json::to_string(entry)?)?;
+        self.file.sync_data()
+    }
+
+    pub(crate) fn remove(self) -> io::Result<()> {
+        drop(self.file);
+        fs::remove_file(self.path)
+    }
+}
+
+/// Collects the batches that a previous run with the same fingerprint recorded
+/// in its manifest or journal, keyed by batch number.
+pub(crate) fn previous_batches(
+    output_path: &Path,
+    fingerprint: &str,
+) -> HashMap<usize, ManifestEntry> {
+    let mut batches = HashMap::new();
+
+    if let Ok(Some(manifest)) = Manifest::read(output_path) {
+        if manifest.fingerprint == fingerprint {
+            batches.extend(manifest.batches.into_iter().map(|b| (b.number, b)));
+        }
+    }
+
+    if let Ok(file) = File::open(output_path.join(JOURNAL_FILE_NAME)) {
+        let mut lines = BufReader::new(file).lines().map_while(Result::ok);
+        if lines.next().as_deref() == Some(fingerprint) {
+            // A crash can leave a truncated last line behind, which is simply ignored
+            let entries =
+                lines.filter_map(|line| serde_json::from_str::<ManifestEntry>(&line).ok());
+            batches.extend(entries.map(|b| (b.number, b)));
+        }
+    }
+
+    batches
+}
+
+/// Computes the fingerprint of a run from the identity of the input file
+/// (path, size and modification time) and all options that affect the written batches.
+pub(crate) fn run_fingerprint(file_path: &str, options: &BatchOptions) -> io::Result<String> {
+    let metadata = fs::metadata(file_path)?;
+    let modified = metadata
+        .modified()?
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_nanos())
+        .unwrap_or_default();
+    // Pipes from process substitution, e.g. /dev/fd/63, link to names that cannot be resolved
+    let input = fs::canonicalize(file_path).unwrap_or_else(|_| PathBuf::from(file_path));
+
+    let description = format!(
+        "input={}\nsize={}\nmodified={}\nbatch_size={}\ncompression={:?}\nempty_input={:?}\nlate_header={:?}\nname_template={}\nsplit_by={:?}\nfilter={:?}\n",
+        input.display(),
+        metadata.len(),
+        modified,
+        options.batch_size,
+        options.compression_level,
+        options.empty_input,
+        options.late_header,
+        options
+            .name_template
+            .as_ref()
+            .map(NameTemplate::as_str)
+            .unwrap_or_default(),
+        options.split_by,
+        options.filter,
+    );
+    // Only added if set, so that runs without it keep the fingerprint of earlier versions
+    let description = match options.dedup {
+        Some(dedup) => format!("{description}dedup={dedup:?}\n"),
+        None => description,
+    };
+    let description = match &options.mask {
+        Some(mask) => format!("{description}mask={mask:?}\n"),
+        None => description,
+    };
+    let description = match &options.group_by_info {
+        Some(key) => format!("{description}group_by_info={key}\n"),
+        None => description,
+    };
+    let description = match options.split_by {
+        SplitMode::Gene => format!("{description}genes_per_batch={}\n", options.genes_per_batch),
+        _ => description,
+    };
+    let description = match options.split_by {
+        SplitMode::Samples => format!(
+            "{description}samples_per_batch={}\n",
+            options.samples_per_batch
+        ),
+        _ => description,
+    };
+    let description = match options.split_by {
+        SplitMode::Region => format!("{description}split_regions={:?}\n", options.split_regions),
+        _ => description,
+    };
+    let description = match options.batch_sizes.is_empty() {
+        true => description,
+        false => format!("{description}batch_sizes={:?}\n", options.batch_sizes),
+    };
+    let description = match options.target_compressed_size {
+        Some(size) => format!("{description}target_compressed_size={size}\n"),
+        None => description,
+    };
+    let description = match options.target_size {
+        Some(size) => format!("{description}target_size={size}\n"),
+        None => description,
+    };
+    let description = match options.compression_format {
+        CompressionFormat::Zstd => format!("{description}compression_format=zstd\n"),
+        CompressionFormat::Bgzf => description,
+    };
+    Ok(sha256_hex(description.as_bytes()))
+}
+
+/// Returns the SHA-256 checksum of the given bytes as a lowercase hex string.
+pub(crate) fn sha256_hex(bytes: &[u8]) -> String {
+    format!("{:x}", Sha256::digest(bytes))
+}
+
+/// Returns the SHA-256 checksum of a file as a lowercase hex string.
+pub fn file_sha256(path: &Path) -> io::Result<String> {
+    let mut file = File::open(path)?;
+    let mut hasher = Sha256::new();
+    let mut buffer = vec![0; 64 * 1024];
+    loop {
+        let read = file.read(&mut buffer)?;
+        if read == 0 {
+            break;
+        }
+        hasher.update(&buffer[..read]);
+    }
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+/// Returns the path of a batch relative to the output directory.
+fn relative_path(path: &Path, output_path: &Path) -> String {
+    path.strip_prefix(output_path)
+        .unwrap_or(path)
+        .to_string_lossy()
+        .into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::OpenOptions;
+    use std::io::Write;
+    use std::path::{Path, PathBuf};
+
+    use crate::{BatchInfo, Locus, RunReport};
+
+    use super::{
+        file_sha256, previous_batches, Journal, Manifest, ManifestEntry, JOURNAL_FILE_NAME,
+    };
+
+    #[test]
+    fn test_write_manifest() {
+        let output_path = Path::new("./test_data/manifest");
+        let report = RunReport {
+            batches: vec![BatchInfo {
+                number: 1,
+                path: PathBuf::from("./test_data/manifest/batch_01.vcf.gz"),
+                records: 10,
+                sha256: "abc".to_string(),
+                size: 2048,
+                first: Some(Locus {
+                    chrom: "chr1".to_string(),
+                    pos: 100,
+                }),
+                last: None,
+                verified: true,
+            }],
+            output_path: output_path.to_path_buf(),
+            fingerprint: "fingerprint".to_string(),
+            ..Default::default()
+        };
+
+        let manifest_path = Manifest::from_report(&report, false)
+            .write(output_path)
+            .unwrap();
+        let written: serde_json::Value =
+            serde_json::from_str(&std::fs::read_to_string(manifest_path).unwrap()).unwrap();
+
+        assert_eq!(written["complete"], false);
+        assert_eq!(written["fingerprint"], "fingerprint");
+        assert_eq!(written["batches"][0]["file"], "batch_01.vcf.gz");
+        assert_eq!(written["batches"][0]["records"], 10);
+        assert_eq!(written["batches"][0]["size"], 2048);
+        assert_eq!(written["batches"][0]["first"]["pos"], 100);
+        assert_eq!(
+            Manifest::read(output_path).unwrap(),
+            Some(Manifest::from_report(&report, false))
+        );
+    }
+
+    #[test]
+    fn test_previous_batches_from_journal() {
+        let output_path = Path::new("./test_data/journal");
+        let _ = std::fs::remove_dir_all(output_path);
+        std::fs::create_dir_all(output_path).unwrap();
+        let entry = ManifestEntry {
+            number: 1,
+            file: "batch_01.vcf".to_string(),
+            records: 10,
+            sha256: "abc".to_string(),
+            size: 0,
+            first: None,
+            last: None,
+            verified: false,
+        };
+
+        let mut journal = Journal::create(output_path, "fingerprint").unwrap();
+        journal.append(&entry).unwrap();
+        // Simulate a crash in the middle of writing the next entry
+        let mut file = OpenOptions::new()
+            .append(true)
+            .open(output_path.join(JOURNAL_FILE_NAME))
+            .unwrap();
+        write!(file, "{{\"number\": 2").unwrap();
+
+        let batches = previous_batches(output_path, "fingerprint");
+        assert_eq!(batches.len(), 1);
+        assert_eq!(batches[&1], entry);
+        assert!(previous_batches(output_path, "other fingerprint").is_empty());
+    }
+
+    #[test]
+    fn test_file_sha256() {
+        let path = Path::new("./test_data/inputs/checksum.txt");
+        std::fs::create_dir_all("./test_data/inputs").unwrap();
+        std::fs::write(path, "abc").unwrap();
+        assert_eq!(
+            file_sha256(path).unwrap(),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+    }
+}
@@ -0,0 +1,139 @@
+//! Redacting the genotypes of samples, e.g. of participants who withdrew their consent, while the
+//! records are batched, see [`crate::BatchOptions::mask`].
+
+use crate::{header, VcfBatcherError};
+
+/// Samples whose genotypes are set to missing in every record.
+///
+/// Only the sample columns are changed: INFO fields that summarize the genotypes, such as `AC`,
+/// `AN` or `AF`, are written as they are.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SampleMask {
+    /// The names of the samples.
+    pub samples: Vec<String>,
+    /// Set all FORMAT fields of the samples to missing, not only GT.
+    pub all_fields: bool,
+}
+
+impl SampleMask {
+    /// Returns the indices of the columns of the samples in the `#CHROM` header line.
+    pub fn column_indices(&self, chrom_line: &str) -> Result<Vec<usize>, VcfBatcherError> {
+        let available = header::sample_names(chrom_line);
+        let mut indices = Vec::new();
+        let mut unknown = Vec::new();
+        for sample in &self.samples {
+            match available.iter().position(|available| available == sample) {
+                Some(index) => indices.push(index + 9),
+                None => unknown.push(sample.clone()),
+            }
+        }
+        if !unknown.is_empty() {
+            return Err(VcfBatcherError::UnknownSamples { samples: unknown });
+        }
+        Ok(indices)
+    }
+
+    /// Sets GT, or with [`SampleMask::all_fields`] every FORMAT field, of the columns at `indices`
+    /// to missing. A missing GT keeps the ploidy and phasing of the genotype, e.g. `0|1` becomes `.|.`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use vcf_batcher::mask::SampleMask;
+    ///
+    /// let mask = SampleMask { samples: vec!["B".to_string()], ..Default::default() };
+    /// let chrom_line = "#CHROM\tPOS\tID\tREF\tALT\tQUAL\tFILTER\tINFO\tFORMAT\tA\tB";
+    /// let indices = mask.column_indices(chrom_line).unwrap();
+    /// assert_eq!(
+    ///     mask.apply("1\t5\t.\tA\tC\t.\tPASS\t.\tGT:DP\t0/1:12\t1|1:9", &indices),
+    ///     "1\t5\t.\tA\tC\t.\tPASS\t.\tGT:DP\t0/1:12\t.|.:9"
+    /// );
+    /// ```
+    pub fn apply(&self, record: &str, indices: &[usize]) -> String {
+        let mut columns: Vec<&str> = record.split('\t').collect();
+        let gt = columns
+            .get(8)
+            .and_then(|format| format.split(':').position(|field| field == "GT"));
+        if gt.is_none() && !self.all_fields {
+            return record.to_string();
+        }
+
+        let mut masked = Vec::with_capacity(indices.len());
+        for &index in indices {
+            let Some(column) = columns.get(index) else {
+                continue;
+            };
+            let fields: Vec<String> = column
+                .split(':')
+                .enumerate()
+                .map(|(position, value)| match Some(position) == gt {
+                    true => missing_genotype(value),
+                    false if self.all_fields => ".".to_string(),
+                    false => value.to_string(),
+                })
+                .collect();
+            masked.push((index, fields.join(":")));
+        }
+        for (index, column) in &masked {
+            columns[*index] = column;
+        }
+        columns.join("\t")
+    }
+}
+
+/// A missing genotype with the ploidy and phasing of `genotype`, e.g. `./.` for `0/1`.
+fn missing_genotype(genotype: &str) -> String {
+    genotype
+        .chars()
+        .filter(|character| matches!(character, '/' | '|'))
+        .fold(".".to_string(), |mut missing, separator| {
+            missing.push(separator);
+            missing.push('.');
+            missing
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SampleMask;
+    use crate::VcfBatcherError;
+
+    #[test]
+    fn test_sample_mask() {
+        let chrom_line = "#CHROM\tPOS\tID\tREF\tALT\tQUAL\tFILTER\tINFO\tFORMAT\tA\tB\tC";
+        let mask = SampleMask {
+            samples: vec!["C".to_string(), "A".to_string()],
+            all_fields: true,
+        };
+        let indices = mask.column_indices(chrom_line).unwrap();
+        assert_eq!(indices, [11, 9]);
+
+        assert_eq!(
+            mask.apply(
+                "1\t5\t.\tA\tC\t.\tPASS\t.\tGT:AD:DP\t1\t0/1:3,4:7\t0|1|2:1,2:3",
+                &indices
+            ),
+            "1\t5\t.\tA\tC\t.\tPASS\t.\tGT:AD:DP\t.\t0/1:3,4:7\t.|.|.:.:."
+        );
+        // Without GT, only all fields are masked
+        let record = "1\t5\t.\tA\tC\t.\tPASS\t.\tDP\t3\t4\t5";
+        assert_eq!(
+            mask.apply(record, &indices),
+            "1\t5\t.\tA\tC\t.\tPASS\t.\tDP\t.\t4\t."
+        );
+        let gt_only = SampleMask {
+            all_fields: false,
+            ..mask.clone()
+        };
+        assert_eq!(gt_only.apply(record, &indices), record);
+
+        let unknown = SampleMask {
+            samples: vec!["D".to_string()],
+            ..Default::default()
+        };
+        assert!(matches!(
+            unknown.column_indices(chrom_line),
+            Err(VcfBatcherError::UnknownSamples { samples }) if samples == vec!["D"]
+        ));
+    }
+}
@@ -0,0 +1,539 @@
+//! Merging batches back into a single VCF file.
+
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use bgzip::{write::BGZFMultiThreadWriter, Compression};
+use serde::Serialize;
+
+use crate::manifest::Manifest;
+use crate::{is_header_line, move_file, read_lines, staging_path, VcfBatcherError};
+
+/// Summary of a merge.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct MergeReport {
+    /// The merged VCF file.
+    pub output: PathBuf,
+    /// The batches that were merged, in order.
+    pub batches: Vec<PathBuf>,
+    /// Number of variant records in the merged file.
+    pub records: usize,
+}
+
+/// Concatenates the batches in `batch_dir` into a single VCF file with one header.
+///
+/// The batches are taken from the manifest of the directory if there is one, otherwise all
+/// `.vcf` and `.vcf.gz` files are merged, ordered by the batch number in their names.
+/// The header of every batch must be identical to the header of the first one.
+/// If `output_file` ends in `.gz`, the merged file is bgzipped.
+///
+/// The merged file is written under a temporary name and only renamed to `output_file`
+/// once it is complete.
+///
+/// # Examples
+///
+/// ```
+/// use std::path::Path;
+/// use vcf_batcher::extract_variants_to_batches;
+/// use vcf_batcher::merge::merge_batches;
+///
+/// let batch_dir = Path::new("test_data/doc_merge");
+/// extract_variants_to_batches("test_data/batch_01.vcf.gz", 30, batch_dir, None).unwrap();
+///
+/// let report = merge_batches(batch_dir, Path::new("test_data/doc_merge/merged.vcf")).unwrap();
+/// assert_eq!(report.batches.len(), 4);
+/// assert_eq!(report.records, 100);
+/// ```
+pub fn merge_batches(batch_dir: &Path, output_file: &Path) -> Result<MergeReport, VcfBatcherError> {
+    merge_batches_with_compression(batch_dir, output_file, compression_for(output_file))
+}
+
+/// The compression [`merge_batches`] uses for a merged file: bgzip with the default level if
+/// the name of the file ends in `.gz`, none otherwise.
+pub fn compression_for(output_file: &Path) -> Option<Compression> {
+    let bgzipped = output_file.to_string_lossy().ends_with(".gz");
+    bgzipped.then(Compression::default)
+}
+
+/// Same as [`merge_batches`], but with an explicit compression level for the merged file
+/// instead of one derived from its name. `None` writes an uncompressed file.
+pub fn merge_batches_with_compression(
+    batch_dir: &Path,
+    output_file: &Path,
+    compression_level: Option<Compression>,
+) -> Result<MergeReport, VcfBatcherError> {
+    merge_batches_with_tmp_dir(batch_dir, output_file, compression_level, None)
+}
+
+/// Same as [`merge_batches_with_compression`], but the merged file is written to `tmp_dir` and then
+/// moved to `output_file`, e.g. to assemble it on a fast local disk when the output is on slow
+/// network storage. Without a `tmp_dir`, it is written next to `output_file`.
+pub fn merge_batches_with_tmp_dir(
+    batch_dir: &Path,
+    output_file: &Path,
+    compression_level: Option<Compression>,
+    tmp_dir: Option<&Path>,
+) -> Result<MergeReport, VcfBatcherError> {
+    let batches = batch_files(batch_dir, output_file)?;
+    if batches.is_empty() {
+        return Err(VcfBatcherError::NoBatches {
+            path: batch_dir.to_path_buf(),
+        });
+    }
+
+    let file_name = output_file
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let temporary_path = match tmp_dir {
+        Some(tmp_dir) => staging_path(tmp_dir, output_file),
+        None => output_file.with_file_name(format!(".{}.tmp", file_name)),
+    };
+
+    let result =
+        write_merged_file(&batches, &temporary_path, compression_level).and_then(|records| {
+            let parent = output_file
+                .parent()
+                .filter(|parent| !parent.as_os_str().is_empty());
+            parent
+                .map_or(Ok(()), fs::create_dir_all)
+                .and_then(|_| move_file(&temporary_path, output_file))
+                .map(|_| records)
+                .map_err(|source| VcfBatcherError::Write {
+                    path: output_file.to_path_buf(),
+                    source: source.into(),
+                })
+        });
+    match result {
+        Ok(records) => Ok(MergeReport {
+            output: output_file.to_path_buf(),
+            batches,
+            records,
+        }),
+        Err(error) => {
+            let _ = fs::remove_file(&temporary_path);
+            Err(error)
+        }
+    }
+}
+
+/// Same as [`merge_batches_with_compression`], but the merged VCF file is written to `writer`,
+/// e.g. to stdout in a pipeline. The `output` of the returned report is empty.
+///
+/// # Examples
+///
+/// ```
+/// use std::path::Path;
+/// use vcf_batcher::extract_variants_to_batches;
+/// use vcf_batcher::merge::merge_batches_to_writer;
+///
+/// let batch_dir = Path::new("test_data/doc_merge_to_writer");
+/// extract_variants_to_batches("test_data/batch_01.vcf.gz", 40, batch_dir, None).unwrap();
+///
+/// let mut merged = Vec::new();
+/// let report = merge_batches_to_writer(batch_dir, &mut merged, None).unwrap();
+/// assert_eq!(report.records, 100);
+/// assert!(merged.starts_with(b"##fileformat=VCFv4.1"));
+/// ```
+pub fn merge_batches_to_writer<W: Write>(
+    batch_dir: &Path,
+    writer: W,
+    compression_level: Option<Compression>,
+) -> Result<MergeReport, VcfBatcherError> {
+    let batches = batch_files(batch_dir, Path::new(""))?;
+    if batches.is_empty() {
+        return Err(VcfBatcherError::NoBatches {
+            path: batch_dir.to_path_buf(),
+        });
+    }
+
+    let output_name = Path::new("the output");
+    let writer = VcfWriter::new(writer, compression_level);
+    let records = write_merged(&batches, writer, output_name)?;
+    Ok(MergeReport {
+        output: PathBuf::new(),
+        batches,
+        records,
+    })
+}
+
+/// Writes the merged batches into a new file, which is synced to disk before returning.
+fn write_merged_file(
+    batches: &[PathBuf],
+    path: &Path,
+    compression: Option<Compression>,
+) -> Result<usize, VcfBatcherError> {
+    let write_error = |source: io::Error| VcfBatcherError::Write {
+        path: path.to_path_buf(),
+        source: source.into(),
+    };
+
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).map_err(write_error)?;
+    }
+    let file = File::create(path).map_err(write_error)?;
+    let sync_handle = file.try_clone().map_err(write_error)?;
+    let records = write_merged(batches, VcfWriter::new(file, compression), path)?;
+    sync_handle.sync_all().map_err(write_error)?;
+    Ok(records)
+}
+
+/// Writes the header of the first batch and the records of all batches, returns the number of records.
+/// `path` names the output in errors.
+fn write_merged<W: Write>(
+    batches: &[PathBuf],
+    mut writer: VcfWriter<W>,
+    path: &Path,
+) -> Result<usize, VcfBatcherError> {
+    let write_error = |source: io::Error| VcfBatcherError::Write {
+        path: path.to_path_buf(),
+        source: source.into(),
+    };
+
+    let mut first_header: Option<Vec<String>> = None;
+    let mut records = 0;
+    for batch in batches {
+        let read_error = |source| VcfBatcherError::Read {
+            path: batch.clone(),
+            source,
+        };
+
+        let mut header = Vec::new();
+        let mut header_checked = false;
+        for line in read_lines(batch).map_err(read_error)? {
+            let line = line.map_err(read_error)?;
+            if is_header_line(&line) {
+                header.push(line);
+                continue;
+            }
+
+            if !header_checked {
+                match &first_header {
+                    Some(first_header) if *first_header != header => {
+                        return Err(VcfBatcherError::HeaderMismatch {
+                            path: batch.clone(),
+                        })
+                    }
+                    Some(_) => {}
+                    None => {
+                        for header_line in &header {
+                            writeln!(writer, "{}", header_line).map_err(write_error)?;
+                        }
+                        first_header = Some(std::mem::take(&mut header));
+                    }
+                }
+                header_checked = true;
+            }
+            writeln!(writer, "{}", line).map_err(write_error)?;
+            records += 1;
+        }
+    }
+
+    // Batches without records (e.g. header-only output of an empty input) still provide the header
+    if first_header.is_none() {
+        let header = read_lines(&batches[0])
+            .map_err(|source| VcfBatcherError::Read {
+                path: batches[0].clone(),
+                source,
+            })?
+            .map_while(Result::ok)
+            .filter(|line| is_header_line(line));
+        for header_line in header {
+            writeln!(writer, "{}", header_line).map_err(write_error)?;
+        }
+    }
+
+    writer.finish().map_err(write_error)?;
+    Ok(records)
+}
+
+/// Writer for a plain or bgzipped VCF file.
+pub(crate) enum VcfWriter<W: Write> {
+    Plain(BufWriter<W>),
+    Bgzf(BGZFMultiThreadWriter<W>),
+}
+
+impl<W: Write> VcfWriter<W> {
+    pub(crate) fn new(writer: W, compression: Option<Compression>) -> Self {
+        match compression {
+            Some(level) => VcfWriter::Bgzf(BGZFMultiThreadWriter::new(writer, level)),
+            None => VcfWriter::Plain(BufWriter::new(writer)),
+        }
+    }
+
+    /// Flushes all buffered data and, if the output is bgzipped, writes the end-of-file marker.
+    pub(crate) fn finish(self) -> io::Result<()> {
+        match self {
+            VcfWriter::Plain(mut writer) => writer.flush(),
+            VcfWriter::Bgzf(writer) => writer.close().map(|_| ()),
+        }
+    }
+}
+
+impl<W: Write> Write for VcfWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            VcfWriter::Plain(writer) => writer.write(buf),
+            VcfWriter::Bgzf(writer) => writer.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            VcfWriter::Plain(writer) => writer.flush(),
+            VcfWriter::Bgzf(writer) => writer.flush(),
+        }
+    }
+}
+
+/// Returns the batches in a directory in order, see [`merge_batches`].
+pub fn batch_files(batch_dir: &Path, exclude: &Path) -> Result<Vec<PathBuf>, VcfBatcherError> {
+    let read_error = |source| VcfBatcherError::Read {
+        path: batch_dir.to_path_buf(),
+        source,
+    };
+
+    if let Some(manifest) = Manifest::read(batch_dir).map_err(read_error)? {
+        let mut entries = manifest.batches;
+        entries.sort_by_key(|entry| entry.number);
+        return Ok(entries
+            .into_iter()
+            .map(|entry| batch_dir.join(entry.file))
+            .collect());
+    }
+
+    let mut batches = Vec::new();
+    for entry in fs::read_dir(batch_dir).map_err(read_error)? {
+        let path = entry.map_err(read_error)?.path();
+        let name = path
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let is_vcf = [".vcf", ".vcf.gz", ".vcf.zst"]
+            .iter()
+            .any(|extension| name.ends_with(extension));
+        if is_vcf && !name.starts_with('.') && path.is_file() && !is_same_file(&path, exclude) {
+            batches.push(path);
+        }
+    }
+    batches.sort_by_cached_key(|path| (batch_number(path), path.clone()));
+    Ok(batches)
+}
+
+/// The last number in the file name, e.g. 12 for `batch_12.vcf.gz`.
+fn batch_number(path: &Path) -> Option<u64> {
+    let name = path.file_name()?.to_string_lossy();
+    let digits: String = name
+        .rsplit(|character: char| !character.is_ascii_digit())
+        .find(|part| !part.is_empty())?
+        .to_string();
+    digits.parse().ok()
+}
+
+fn is_same_file(a: &Path, b: &Path) -> bool {
+    match (fs::canonicalize(a), fs::canonicalize(b)) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::{Path, PathBuf};
+
+    use bgzip::Compression;
+    use proptest::prelude::*;
+
+    use super::{
+        batch_number, merge_batches, merge_batches_with_compression, merge_batches_with_tmp_dir,
+    };
+    use crate::testdata::SyntheticVcf;
+    use crate::{
+        extract_variants_to_batches, extract_variants_to_batches_with_options, is_header_line,
+        read_lines, BatchOptions, EmptyInputPolicy, SplitMode, VcfBatcherError,
+    };
+
+    fn lines(path: &Path) -> Vec<String> {
+        read_lines(path).unwrap().map(Result::unwrap).collect()
+    }
+
+    /// Files are read as bgzipped if their name ends in `.gz`.
+    fn vcf_path(root: &Path, name: &str, compression: Option<Compression>) -> PathBuf {
+        match compression {
+            Some(_) => root.join(format!("{name}.vcf.gz")),
+            None => root.join(format!("{name}.vcf")),
+        }
+    }
+
+    fn compression() -> impl Strategy<Value = Option<Compression>> {
+        prop_oneof![Just(None), Just(Some(Compression::fast()))]
+    }
+
+    proptest! {
+        #![proptest_config(ProptestConfig::with_cases(48))]
+
+        /// Splitting any valid VCF file and merging the batches gives back the same lines, including
+        /// files without records, with a single record and batches larger than the file.
+        #[test]
+        fn test_split_merge_round_trip(
+            samples in 1usize..5,
+            records in prop_oneof![Just(0usize), Just(1), 0usize..80],
+            contigs in 1usize..4,
+            seed in any::<u64>(),
+            input_compression in compression(),
+            batch_size in 1usize..40,
+            by_chromosome in any::<bool>(),
+            batch_compression in compression(),
+            merged_compression in compression(),
+        ) {
+            let root = Path::new("./test_data/split_merge_round_trip");
+            let _ = std::fs::remove_dir_all(root);
+            let input = vcf_path(root, "input", input_compression);
+            let vcf = SyntheticVcf {
+                samples,
+                records,
+                contigs: (1..=contigs).map(|contig| format!("chr{contig}")).collect(),
+                seed,
+            };
+            vcf.write_file(&input, input_compression).unwrap();
+            let options = BatchOptions {
+                batch_size,
+                split_by: match by_chromosome {
+                    true => SplitMode::Chromosome,
+                    false => SplitMode::Records,
+                },
+                compression_level: batch_compression,
+                empty_input: EmptyInputPolicy::HeaderOnly,
+                quiet: true,
+                ..Default::default()
+            };
+            let batch_dir = root.join("batches");
+            let merged = vcf_path(root, "merged", merged_compression);
+
+            let report = extract_variants_to_batches_with_options(
+                &input.to_string_lossy(),
+                &batch_dir,
+                &options,
+            )
+            .unwrap();
+            merge_batches_with_compression(&batch_dir, &merged, merged_compression).unwrap();
+
+            let original = lines(&input);
+            let header: Vec<&String> = original.iter().take_while(|line| is_header_line(line)).collect();
+            prop_assert_eq!(report.total_records(), records);
+            for batch in &report.batches {
+                let batch = lines(&batch.path);
+                prop_assert!(batch.len() > header.len() || records == 0);
+                prop_assert_eq!(batch.iter().take(header.len()).collect::<Vec<_>>(), header.clone());
+            }
+            prop_assert_eq!(lines(&merged), original);
+        }
+    }
+
+    #[test]
+    fn test_merge_round_trip() {
+        let batch_dir = Path::new("./test_data/merge_round_trip");
+        let merged = Path::new("./test_data/merge_round_trip/merged.vcf.gz");
+        let _ = std::fs::remove_dir_all(batch_dir);
+        extract_variants_to_batches("./test_data/batch_01.vcf.gz", 9, batch_dir, None).unwrap();
+
+        let report = merge_batches(batch_dir, merged).unwrap();
+
+        assert_eq!(report.batches.len(), 12);
+        assert!(report.batches[9].ends_with("batch_10.vcf"));
+        let original: Vec<String> = read_lines("./test_data/batch_01.vcf.gz")
+            .unwrap()
+            .map(Result::unwrap)
+            .collect();
+        let merged: Vec<String> = read_lines(merged).unwrap().map(Result::unwrap).collect();
+        assert_eq!(merged, original);
+    }
+
+    #[test]
+    fn test_merge_round_trip_of_unsafe_chromosome_names() {
+        let root = Path::new("./test_data/merge_unsafe_names");
+        let _ = std::fs::remove_dir_all(root);
+        let input = root.join("input.vcf");
+        SyntheticVcf {
+            records: 20,
+            contigs: vec!["../../etc".to_string(), "chr1".to_string()],
+            ..Default::default()
+        }
+        .write_file(&input, None)
+        .unwrap();
+        let options = BatchOptions {
+            split_by: SplitMode::Chromosome,
+            quiet: true,
+            ..Default::default()
+        };
+        let batch_dir = root.join("batches");
+        let report = extract_variants_to_batches_with_options(
+            &input.to_string_lossy(),
+            &batch_dir,
+            &options,
+        )
+        .unwrap();
+        assert_eq!(report.batches[0].path, batch_dir.join("_._.._etc.vcf"));
+
+        let merged = root.join("merged.vcf");
+        let report = merge_batches(&batch_dir, &merged).unwrap();
+
+        assert_eq!(report.batches.len(), 2);
+        assert_eq!(lines(&merged), lines(&input));
+    }
+
+    #[test]
+    fn test_merge_rejects_different_headers() {
+        let batch_dir = Path::new("./test_data/merge_mismatch");
+        let _ = std::fs::remove_dir_all(batch_dir);
+        std::fs::create_dir_all(batch_dir).unwrap();
+        std::fs::write(batch_dir.join("batch_01.vcf"), "##a\n#CHROM\n1\t1\n").unwrap();
+        std::fs::write(batch_dir.join("batch_02.vcf"), "##b\n#CHROM\n1\t2\n").unwrap();
+
+        let error = merge_batches(batch_dir, &batch_dir.join("merged.vcf")).unwrap_err();
+
+        assert!(matches!(
+            error,
+            VcfBatcherError::HeaderMismatch { path } if path.ends_with("batch_02.vcf")
+        ));
+        assert!(!batch_dir.join("merged.vcf").exists());
+    }
+
+    #[test]
+    fn test_merge_with_explicit_compression() {
+        let batch_dir = Path::new("./test_data/merge_compression");
+        let merged = Path::new("./test_data/merge_compression/merged.vcf");
+        let _ = std::fs::remove_dir_all(batch_dir);
+        extract_variants_to_batches("./test_data/batch_01.vcf.gz", 50, batch_dir, None).unwrap();
+
+        merge_batches_with_compression(batch_dir, merged, Some(bgzip::Compression::fast()))
+            .unwrap();
+
+        let contents = std::fs::read(merged).unwrap();
+        assert_eq!(&contents[..2], &[0x1f, 0x8b]);
+    }
+
+    #[test]
+    fn test_merge_with_tmp_dir() {
+        let root = Path::new("./test_data/merge_tmp_dir");
+        let batch_dir = root.join("batches");
+        let tmp_dir = root.join("tmp");
+        let merged = root.join("merged").join("merged.vcf");
+        let _ = std::fs::remove_dir_all(root);
+        extract_variants_to_batches("./test_data/batch_01.vcf.gz", 50, &batch_dir, None).unwrap();
+
+        let report = merge_batches_with_tmp_dir(&batch_dir, &merged, None, Some(&tmp_dir)).unwrap();
+
+        assert_eq!(report.records, 100);
+        assert!(merged.exists());
+        assert_eq!(std::fs::read_dir(&tmp_dir).unwrap().count(), 0);
+    }
+
+    #[test]
+    fn test_batch_number() {
+        assert_eq!(batch_number(&PathBuf::from("batch_12.vcf.gz")), Some(12));
+        assert_eq!(batch_number(&PathBuf::from("chr2_part007.vcf")), Some(7));
+        assert_eq!(batch_number(&PathBuf::from("batch.vcf")), None);
+    }
+}
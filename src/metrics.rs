@@ -0,0 +1,239 @@
+//! Prometheus metrics of a [`crate::watch::watch_directory`] run, served over HTTP so that a
+//! monitoring stack can alert when the ingestion stalls.
+
+use std::fmt::Write as _;
+use std::io;
+use std::net::{SocketAddr, TcpListener, ToSocketAddrs};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::http::{self, Response};
+use crate::watch::WatchEvent;
+
+/// The content type of the Prometheus text format.
+const CONTENT_TYPE: &str = "text/plain; version=0.0.4; charset=utf-8";
+
+/// Counters of the files a watch has processed, updated with [`WatchMetrics::record`] and shared
+/// with the thread that serves them.
+///
+/// # Examples
+///
+/// ```
+/// use std::path::PathBuf;
+/// use vcf_batcher::metrics::WatchMetrics;
+/// use vcf_batcher::watch::WatchEvent;
+/// use vcf_batcher::VcfBatcherError;
+///
+/// let metrics = WatchMetrics::new();
+/// metrics.record(&WatchEvent::Failed {
+///     input: PathBuf::from("incoming/broken.vcf"),
+///     error: VcfBatcherError::EmptyInput { path: PathBuf::from("incoming/broken.vcf") },
+/// });
+/// assert!(metrics.render().contains("vcf_batcher_errors_total 1\n"));
+/// ```
+#[derive(Debug)]
+pub struct WatchMetrics {
+    counters: Mutex<Counters>,
+}
+
+#[derive(Debug, Default)]
+struct Counters {
+    started: f64,
+    files_processed: u64,
+    errors: u64,
+    records: u64,
+    batches: u64,
+    records_per_second: f64,
+    last_batched: Option<f64>,
+}
+
+impl WatchMetrics {
+    /// Starts counting from zero, with the current time as the start of the watch.
+    pub fn new() -> Self {
+        WatchMetrics {
+            counters: Mutex::new(Counters {
+                started: unix_time(),
+                ..Default::default()
+            }),
+        }
+    }
+
+    /// Counts a file that was batched or failed.
+    pub fn record(&self, event: &WatchEvent) {
+        let mut counters = self
+            .counters
+            .lock()
+            .unwrap_or_else(|error| error.into_inner());
+        counters.files_processed += 1;
+        match event {
+            WatchEvent::Batched { report, .. } => {
+                let records = report.total_records();
+                counters.records += records as u64;
+                counters.batches += report.batches.len() as u64;
+                let seconds = report.elapsed.as_secs_f64();
+                if seconds > 0.0 {
+                    counters.records_per_second = records as f64 / seconds;
+                }
+                counters.last_batched = Some(unix_time());
+            }
+            WatchEvent::Failed { .. } => counters.errors += 1,
+        }
+    }
+
+    /// The metrics in the Prometheus text format.
+    pub fn render(&self) -> String {
+        let counters = self
+            .counters
+            .lock()
+            .unwrap_or_else(|error| error.into_inner());
+        let metrics = [
+            (
+                "vcf_batcher_watch_start_time_seconds",
+                "gauge",
+                "Unix time at which the watch started.",
+                counters.started,
+            ),
+            (
+                "vcf_batcher_files_processed_total",
+                "counter",
+                "Input files that were batched or failed.",
+                counters.files_processed as f64,
+            ),
+            (
+                "vcf_batcher_errors_total",
+                "counter",
+                "Input files that could not be batched and were moved to the failed directory.",
+                counters.errors as f64,
+            ),
+            (
+                "vcf_batcher_records_total",
+                "counter",
+                "Variant records written to batches.",
+                counters.records as f64,
+            ),
+            (
+                "vcf_batcher_batches_written_total",
+                "counter",
+                "Batches written.",
+                counters.batches as f64,
+            ),
+            (
+                "vcf_batcher_records_per_second",
+                "gauge",
+                "Records per second of the last input that was batched.",
+                counters.records_per_second,
+            ),
+        ];
+
+        let mut text = String::new();
+        for (name, kind, help, value) in metrics {
+            let _ = write!(
+                text,
+                "# HELP {name} {help}\n# TYPE {name} {kind}\n{name} {value}\n"
+            );
+        }
+        // Left out until the first input was batched, so that alerts do not see a time of 0
+        if let Some(last_batched) = counters.last_batched {
+            let name = "vcf_batcher_last_batched_timestamp_seconds";
+            let _ = write!(
+                text,
+                "# HELP {name} Unix time at which the last input was batched.\n\
+                 # TYPE {name} gauge\n{name} {last_batched}\n"
+            );
+        }
+        text
+    }
+}
+
+impl Default for WatchMetrics {
+    fn default() -> Self {
+        WatchMetrics::new()
+    }
+}
+
+/// Serves the metrics at `/metrics` of `address` from a background thread, which runs until the
+/// process exits. Returns the address that is listened on, which tells the port if port 0 was given.
+pub fn serve_metrics(
+    address: impl ToSocketAddrs,
+    metrics: Arc<WatchMetrics>,
+) -> io::Result<SocketAddr> {
+    let listener = TcpListener::bind(address)?;
+    let address = listener.local_addr()?;
+    thread::spawn(move || {
+        http::serve_connections(listener, |request| {
+            match (request.method.as_str(), request.path.as_str()) {
+                ("GET", "/metrics") => Response::text(200, CONTENT_TYPE, metrics.render()),
+                ("GET", _) => Response::text(404, "text/plain", "not found\n".to_string()),
+                _ => Response::text(405, "text/plain", "only GET is supported\n".to_string()),
+            }
+        })
+    });
+    Ok(address)
+}
+
+fn unix_time() -> f64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs_f64()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Read, Write};
+    use std::net::TcpStream;
+    use std::path::PathBuf;
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use super::{serve_metrics, WatchMetrics};
+    use crate::watch::WatchEvent;
+    use crate::{BatchInfo, RunReport};
+
+    fn get(address: std::net::SocketAddr, path: &str) -> String {
+        let mut stream = TcpStream::connect(address).unwrap();
+        write!(stream, "GET {path} HTTP/1.1\r\nHost: localhost\r\n\r\n").unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        response
+    }
+
+    #[test]
+    fn test_serve_metrics() {
+        let metrics = Arc::new(WatchMetrics::new());
+        let address = serve_metrics("127.0.0.1:0", Arc::clone(&metrics)).unwrap();
+        assert!(!get(address, "/metrics").contains("vcf_batcher_last_batched_timestamp_seconds"));
+
+        let batch = BatchInfo {
+            number: 1,
+            path: PathBuf::from("output/cohortA/batch_01.vcf"),
+            records: 30,
+            sha256: String::new(),
+            size: 0,
+            first: None,
+            last: None,
+            verified: false,
+        };
+        metrics.record(&WatchEvent::Batched {
+            input: PathBuf::from("incoming/cohortA.vcf.gz"),
+            report: Box::new(RunReport {
+                batches: vec![batch.clone(), batch],
+                elapsed: Duration::from_secs(2),
+                ..Default::default()
+            }),
+        });
+
+        let response = get(address, "/metrics");
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(response.contains("# TYPE vcf_batcher_records_total counter\n"));
+        assert!(response.contains("\nvcf_batcher_files_processed_total 1\n"));
+        assert!(response.contains("\nvcf_batcher_errors_total 0\n"));
+        assert!(response.contains("\nvcf_batcher_records_total 60\n"));
+        assert!(response.contains("\nvcf_batcher_batches_written_total 2\n"));
+        assert!(response.contains("\nvcf_batcher_records_per_second 30\n"));
+        assert!(response.contains("\nvcf_batcher_last_batched_timestamp_seconds "));
+
+        assert!(get(address, "/").starts_with("HTTP/1.1 404 Not Found\r\n"));
+    }
+}
@@ -0,0 +1,423 @@
+use std::fmt;
+use std::path::{Component, Path, PathBuf};
+
+use crate::{Locus, VcfBatcherError};
+
+/// How many digits the positions in `{region}` are zero-padded to, enough for the longest human chromosome.
+const POSITION_WIDTH: usize = 9;
+
+/// Template for the file names of batches, e.g. `{stem}_part{number:04}.vcf.gz`.
+///
+/// Supported placeholders are:
+/// - `{number}` (or `{num}`): the number of the batch, optionally zero-padded with `{number:04}`
+/// - `{stem}`: the name of the input file without its `.vcf`/`.vcf.gz` extension
+/// - `{chrom}`: the CHROM of the first record in the batch
+/// - `{start}` and `{end}`: the POS of the first and the last record in the batch, optionally
+///   zero-padded with `{start:09}`
+/// - `{region}`: the span of the batch, e.g. `chr1_000000001-005000000`, or
+///   `chr1_248000001-chr2_001500000` if it continues on the next chromosome
+/// - `{gene}`: the first gene of the batch with [`crate::SplitMode::Gene`], empty otherwise
+///
+/// A template must contain `{number}`, `{start}`, `{region}` or `{gene}`, otherwise all batches get the same name.
+///
+/// Literal braces are written as `{{` and `}}`. Templates may contain subdirectories, but no
+/// absolute paths or `..` components, and values inserted into the template are sanitized so that
+/// they cannot contain path separators or characters that are invalid on common file systems.
+///
+/// # Examples
+///
+/// ```
+/// use vcf_batcher::naming::{NameTemplate, NameValues};
+///
+/// let template = NameTemplate::parse("{chrom}/shard_{number:04}.vcf").unwrap();
+/// let values = NameValues {
+///     number: 7,
+///     stem: "cohort",
+///     chrom: "HLA-DRB1*15:01:01",
+///     first: None,
+///     last: None,
+///     gene: "",
+/// };
+/// assert_eq!(template.render(&values).to_str(), Some("HLA-DRB1_15_01_01/shard_0007.vcf"));
+///
+/// assert!(NameTemplate::parse("../batch_{number}.vcf").is_err());
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NameTemplate {
+    source: String,
+    parts: Vec<Part>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Part {
+    Literal(String),
+    Number { width: usize },
+    Stem,
+    Chrom,
+    Start { width: usize },
+    End { width: usize },
+    Region,
+    Gene,
+}
+
+/// The values that are inserted into a [`NameTemplate`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct NameValues<'a> {
+    pub number: usize,
+    pub stem: &'a str,
+    pub chrom: &'a str,
+    /// The first record of the batch, `None` if it has no records.
+    pub first: Option<&'a Locus>,
+    /// The last record of the batch, `None` if it has no records.
+    pub last: Option<&'a Locus>,
+    /// The first gene of the batch, see [`crate::SplitMode::Gene`].
+    pub gene: &'a str,
+}
+
+impl NameTemplate {
+    /// Parses and validates a template.
+    pub fn parse(template: &str) -> Result<NameTemplate, VcfBatcherError> {
+        let invalid = |reason: &str| VcfBatcherError::InvalidTemplate {
+            template: template.to_string(),
+            reason: reason.to_string(),
+        };
+
+        let mut parts = Vec::new();
+        let mut literal = String::new();
+        let mut characters = template.chars().peekable();
+        while let Some(character) = characters.next() {
+            match character {
+                '{' if characters.peek() == Some(&'{') => {
+                    characters.next();
+                    literal.push('{');
+                }
+                '}' if characters.peek() == Some(&'}') => {
+                    characters.next();
+                    literal.push('}');
+                }
+                '{' => {
+                    let mut placeholder = String::new();
+                    loop {
+                        match characters.next() {
+                            Some('}') => break,
+                            Some(character) => placeholder.push(character),
+                            None => {
+                                return Err(invalid("a placeholder is missing its closing '}'"))
+                            }
+                        }
+                    }
+                    if !literal.is_empty() {
+                        parts.push(Part::Literal(std::mem::take(&mut literal)));
+                    }
+                    parts.push(parse_placeholder(&placeholder).ok_or_else(|| {
+                        invalid(&format!(
+                            "unknown placeholder {{{}}}, use {{number}}, {{stem}}, {{chrom}}, {{start}}, {{end}}, {{region}} or {{gene}}",
+                            placeholder
+                        ))
+                    })?);
+                }
+                '}' => return Err(invalid("unmatched '}', write '}}' for a literal brace")),
+                _ => literal.push(character),
+            }
+        }
+        if !literal.is_empty() {
+            parts.push(Part::Literal(literal));
+        }
+
+        if !parts.iter().any(|part| {
+            matches!(
+                part,
+                Part::Number { .. } | Part::Start { .. } | Part::Region | Part::Gene
+            )
+        }) {
+            return Err(invalid(
+                "the template must contain {number}, {start}, {region} or {gene}, otherwise all batches get the same name",
+            ));
+        }
+        let literals: String = parts
+            .iter()
+            .map(|part| match part {
+                Part::Literal(literal) => literal.as_str(),
+                _ => "x",
+            })
+            .collect();
+        if !is_relative_without_traversal(Path::new(&literals)) || literals.ends_with('/') {
+            return Err(invalid(
+                "the template must be a relative file name without '..' components",
+            ));
+        }
+
+        Ok(NameTemplate {
+            source: template.to_string(),
+            parts,
+        })
+    }
+
+    /// The template `{stem}_batch_{number:03}` followed by the extension of the batches, e.g. `.vcf.gz`, which
+    /// prefixes the batches with the name of the input, so that batches of different inputs cannot be mixed up.
+    pub fn input_prefixed(extension: &str) -> NameTemplate {
+        NameTemplate::parse(&format!("{{stem}}_batch_{{number:03}}{}", extension))
+            .expect("the input prefixed template is valid")
+    }
+
+    /// The template `{region}` followed by the extension of the batches, e.g. `.vcf.gz`, which names the
+    /// batches after their span, e.g. `chr1_000000001-005000000.vcf.gz`.
+    pub fn region(extension: &str) -> NameTemplate {
+        NameTemplate::parse(&format!("{{region}}{}", extension))
+            .expect("the region template is valid")
+    }
+
+    /// Renders the template into a path relative to the output directory.
+    pub fn render(&self, values: &NameValues) -> PathBuf {
+        let mut name = String::new();
+        for part in &self.parts {
+            match part {
+                Part::Literal(literal) => name.push_str(literal),
+                Part::Number { width } => {
+                    name.push_str(&format!("{:0width$}", values.number, width = width))
+                }
+                Part::Stem => name.push_str(&sanitize_component(values.stem)),
+                Part::Chrom => name.push_str(&sanitize_component(values.chrom)),
+                Part::Start { width } => push_position(&mut name, values.first, *width),
+                Part::End { width } => push_position(&mut name, values.last, *width),
+                Part::Region => match (values.first, values.last) {
+                    (Some(first), Some(last)) => {
+                        name.push_str(&sanitize_component(&first.chrom));
+                        name.push('_');
+                        push_position(&mut name, Some(first), POSITION_WIDTH);
+                        name.push('-');
+                        if last.chrom != first.chrom {
+                            name.push_str(&sanitize_component(&last.chrom));
+                            name.push('_');
+                        }
+                        push_position(&mut name, Some(last), POSITION_WIDTH);
+                    }
+                    _ => name.push_str(&sanitize_component(values.chrom)),
+                },
+                Part::Gene => name.push_str(&sanitize_component(values.gene)),
+            }
+        }
+        PathBuf::from(name)
+    }
+
+    /// The template as it was written.
+    pub fn as_str(&self) -> &str {
+        &self.source
+    }
+
+    /// Whether the template uses the `{chrom}` placeholder.
+    pub fn uses_chrom(&self) -> bool {
+        self.parts.contains(&Part::Chrom)
+    }
+
+    /// Whether the template uses the `{number}` placeholder, which makes the names of all batches
+    /// unique. Names from the positions alone repeat if a batch only has records at one position.
+    pub fn uses_number(&self) -> bool {
+        self.parts
+            .iter()
+            .any(|part| matches!(part, Part::Number { .. }))
+    }
+}
+
+impl fmt::Display for NameTemplate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.source)
+    }
+}
+
+/// Appends the position of a record, or `0` for a batch without records.
+fn push_position(name: &mut String, locus: Option<&Locus>, width: usize) {
+    let pos = locus.map_or(0, |locus| locus.pos);
+    name.push_str(&format!("{:0width$}", pos, width = width));
+}
+
+fn parse_placeholder(placeholder: &str) -> Option<Part> {
+    let (name, format) = match placeholder.split_once(':') {
+        Some((name, format)) => (name, Some(format)),
+        None => (placeholder, None),
+    };
+    match (name.trim(), format) {
+        ("number" | "num", None) => Some(Part::Number { width: 0 }),
+        ("number" | "num", Some(width)) => Some(Part::Number {
+            width: width.parse().ok()?,
+        }),
+        ("stem", None) => Some(Part::Stem),
+        ("chrom", None) => Some(Part::Chrom),
+        ("start", None) => Some(Part::Start { width: 0 }),
+        ("start", Some(width)) => Some(Part::Start {
+            width: width.parse().ok()?,
+        }),
+        ("end", None) => Some(Part::End { width: 0 }),
+        ("end", Some(width)) => Some(Part::End {
+            width: width.parse().ok()?,
+        }),
+        ("region", None) => Some(Part::Region),
+        ("gene", None) => Some(Part::Gene),
+        _ => None,
+    }
+}
+
+fn is_relative_without_traversal(path: &Path) -> bool {
+    path.components()
+        .all(|component| matches!(component, Component::Normal(_) | Component::CurDir))
+}
+
+/// Makes a value safe to use as (part of) a single file name component: path separators and
+/// characters that are invalid on common file systems (e.g. `*` and `:` in `HLA-DRB1*15:01:01`)
+/// are replaced by `_`, and the special names `.` and `..` are escaped. A leading `.` is replaced as
+/// well, since hidden files are not found as batches, e.g. by [`crate::merge::batch_files`].
+pub fn sanitize_component(value: &str) -> String {
+    match value {
+        "" => return "_".to_string(),
+        "." | ".." => return "_".repeat(value.len()),
+        _ => {}
+    }
+    value
+        .chars()
+        .enumerate()
+        .map(|(position, character)| match character {
+            '.' if position == 0 => '_',
+            '/' | '\\' | ':' | '*' | '?' | '"' | '<' | '>' | '|' => '_',
+            character if character.is_control() => '_',
+            character => character,
+        })
+        .collect()
+}
+
+/// Returns the name of the input file without its directory and VCF extensions,
+/// e.g. `cohortA` for `data/cohortA.vcf.gz`.
+pub fn input_stem(file_path: &Path) -> String {
+    let name = file_path
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let mut stem = name.as_str();
+    for extension in [".gz", ".bgz", ".zst", ".vcf", ".bcf"] {
+        stem = stem.strip_suffix(extension).unwrap_or(stem);
+    }
+    stem.to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::{input_stem, sanitize_component, NameTemplate, NameValues};
+    use crate::Locus;
+
+    const VALUES: NameValues = NameValues {
+        number: 3,
+        stem: "cohortA",
+        chrom: "chr1",
+        first: None,
+        last: None,
+        gene: "TP53",
+    };
+
+    #[test]
+    fn test_render_template() {
+        let template = NameTemplate::parse("{stem}_part{num:04}.vcf.gz").unwrap();
+        assert_eq!(
+            template.render(&VALUES),
+            Path::new("cohortA_part0003.vcf.gz")
+        );
+
+        assert_eq!(
+            NameTemplate::input_prefixed(".vcf.gz").render(&VALUES),
+            Path::new("cohortA_batch_003.vcf.gz")
+        );
+
+        let template = NameTemplate::parse("{{{chrom}}}_{number}.vcf").unwrap();
+        assert_eq!(template.render(&VALUES), Path::new("{chr1}_3.vcf"));
+        assert!(template.uses_chrom());
+
+        let template = NameTemplate::parse("genes/{gene}.vcf").unwrap();
+        assert_eq!(template.render(&VALUES), Path::new("genes/TP53.vcf"));
+        assert!(!template.uses_number());
+    }
+
+    #[test]
+    fn test_render_region() {
+        let locus = |chrom: &str, pos| Locus {
+            chrom: chrom.to_string(),
+            pos,
+        };
+        let (first, last) = (locus("chr1", 1), locus("chr1", 5_000_000));
+        let values = NameValues {
+            first: Some(&first),
+            last: Some(&last),
+            ..VALUES
+        };
+        assert_eq!(
+            NameTemplate::region(".vcf.gz").render(&values),
+            Path::new("chr1_000000001-005000000.vcf.gz")
+        );
+        let template = NameTemplate::parse("{chrom}/{start}-{end:06}.vcf").unwrap();
+        assert_eq!(template.render(&values), Path::new("chr1/1-5000000.vcf"));
+        assert!(!template.uses_number());
+
+        let last = locus("chr2", 1500);
+        let values = NameValues {
+            last: Some(&last),
+            ..values
+        };
+        assert_eq!(
+            NameTemplate::region(".vcf").render(&values),
+            Path::new("chr1_000000001-chr2_000001500.vcf")
+        );
+        assert_eq!(
+            NameTemplate::region(".vcf").render(&VALUES),
+            Path::new("chr1.vcf")
+        );
+    }
+
+    #[test]
+    fn test_invalid_templates() {
+        for template in [
+            "batch.vcf",
+            "batch_{number.vcf",
+            "batch_{size}.vcf",
+            "batch_{number:x}.vcf",
+            "{chrom}_{end}.vcf",
+            "{region:09}.vcf",
+            "{gene:04}.vcf",
+            "batch}_{number}.vcf",
+            "../batch_{number}.vcf",
+            "out/../../batch_{number}.vcf",
+            "/tmp/batch_{number}.vcf",
+            "{number}/",
+        ] {
+            assert!(
+                NameTemplate::parse(template).is_err(),
+                "{} should be invalid",
+                template
+            );
+        }
+    }
+
+    #[test]
+    fn test_sanitize_component() {
+        assert_eq!(sanitize_component("HLA-DRB1*15:01:01"), "HLA-DRB1_15_01_01");
+        assert_eq!(sanitize_component("../etc/passwd"), "_._etc_passwd");
+        assert_eq!(sanitize_component(".hidden"), "_hidden");
+        assert_eq!(sanitize_component(".."), "__");
+        assert_eq!(sanitize_component(""), "_");
+        assert_eq!(sanitize_component("chrUn_KI270742v1"), "chrUn_KI270742v1");
+    }
+
+    #[test]
+    fn test_values_cannot_escape_the_output_directory() {
+        let template = NameTemplate::parse("{chrom}/{number}.vcf").unwrap();
+        let values = NameValues {
+            chrom: "..",
+            ..VALUES
+        };
+        assert_eq!(template.render(&values), Path::new("__/3.vcf"));
+    }
+
+    #[test]
+    fn test_input_stem() {
+        assert_eq!(input_stem(Path::new("data/cohortA.vcf.gz")), "cohortA");
+        assert_eq!(input_stem(Path::new("cohortB.vcf")), "cohortB");
+    }
+}
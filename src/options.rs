@@ -0,0 +1,617 @@
+use std::fmt;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use bgzip::Compression;
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+
+use crate::filter::RecordFilter;
+use crate::mask::SampleMask;
+use crate::naming::NameTemplate;
+use crate::recompress::{Level, RecompressOptions};
+use crate::regions::BedRegion;
+use crate::{BatchInfo, Progress};
+
+/// What to do when the input contains headers but no variant records.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum EmptyInputPolicy {
+    /// Write a single batch that only contains the header.
+    HeaderOnly,
+    /// Write no batches at all.
+    #[default]
+    Skip,
+    /// Fail with an error.
+    Error,
+}
+
+/// What to do with header (`#`) lines that appear after the first variant record.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum LateHeaderPolicy {
+    /// Fail with an error.
+    #[default]
+    Error,
+    /// Drop the line.
+    Skip,
+    /// Add the line to the header of all following batches.
+    Header,
+}
+
+/// Whether to verify that every record's CHROM is declared in a `##contig` header line.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ContigCheck {
+    /// Do not check contigs.
+    #[default]
+    Off,
+    /// Count records on undeclared contigs and list them in the report.
+    Warn,
+    /// Fail at the first record on an undeclared contig.
+    Error,
+}
+
+/// What to do if the `#CHROM` header line lists the same sample more than once.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum DuplicateSamplePolicy {
+    /// Fail with an error.
+    #[default]
+    Error,
+    /// List the duplicated samples in the report.
+    Warn,
+    /// Do not check sample names.
+    Allow,
+}
+
+/// What to do with variant records that are malformed: records with a different number of columns
+/// than the `#CHROM` line (or fewer than the 8 fixed columns without one) or a POS that is not a whole number.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum BadRecordPolicy {
+    /// Stop with an error.
+    #[default]
+    Fail,
+    /// Leave the record out and count it in the report.
+    Skip,
+    /// Leave the record out, count it in the report and write it with its line number and the
+    /// reason to `bad_records.txt` in the output directory.
+    Quarantine,
+}
+
+/// What to do with a variant record that has the same CHROM, POS, REF and ALT as an earlier one, see
+/// [`BatchOptions::dedup`]. Duplicates are found among consecutive records at the same position, so all
+/// duplicates are found in sorted files and those that directly follow each other in unsorted ones.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum DuplicateRecordPolicy {
+    /// Keep the first record and leave out its duplicates, counting them in the report.
+    #[default]
+    KeepFirst,
+    /// Stop with an error.
+    Error,
+    /// Keep all records and count the duplicates in the report.
+    Report,
+}
+
+/// What to do if a batch file already exists in the output directory.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum OverwritePolicy {
+    /// Replace the existing file.
+    #[default]
+    Overwrite,
+    /// Keep the existing file and continue with the next batch, e.g. to fill in missing batches.
+    Skip,
+    /// Fail with an error before the file is touched.
+    Refuse,
+}
+
+/// The index that is written next to every batch, see [`BatchOptions::index`]. Tabix indexes are built with
+/// [`crate::index::tabix_index`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum IndexFormat {
+    /// A tabix index, e.g. `batch_01.vcf.gz.tbi`.
+    Tabix,
+}
+
+/// Whether the variant sites of the batches are written as Parquet files, see [`crate::sites::parquet_table`].
+#[cfg(feature = "parquet")]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ParquetExport {
+    /// Only write VCF batches.
+    #[default]
+    Off,
+    /// Write a Parquet file next to every batch, e.g. `batch_01.parquet` next to `batch_01.vcf.gz`.
+    Alongside,
+    /// Write the batches as Parquet files instead of VCF files.
+    Only,
+}
+
+/// The format that the batches are written in. JSON Lines are converted with [`crate::jsonl::jsonl_batch`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum OutputFormat {
+    /// VCF files with the header of the input.
+    #[default]
+    Vcf,
+    /// JSON Lines, one object per record, e.g. `batch_01.jsonl.gz`.
+    Jsonl,
+    /// BCF files, e.g. `batch_01.bcf`, converted with htslib. Needs the `htslib` feature.
+    Bcf,
+}
+
+/// How compressed VCF batches are compressed, see [`BatchOptions::compression_format`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum CompressionFormat {
+    /// Blocked gzip, e.g. `batch_01.vcf.gz`, which htslib reads and tabix can index.
+    #[default]
+    Bgzf,
+    /// Zstandard frames compressed on all threads, e.g. `batch_01.vcf.zst`, which are smaller and faster to
+    /// write, but cannot be read by htslib or indexed.
+    Zstd,
+}
+
+/// A field of the JSON objects that records are converted to with [`OutputFormat::Jsonl`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum JsonField {
+    Chrom,
+    Pos,
+    Id,
+    Ref,
+    Alt,
+    Qual,
+    Filter,
+    Info,
+    /// The FORMAT values of every sample.
+    Samples,
+}
+
+/// Where one batch ends and the next one begins. Record batches hold [`BatchOptions::batch_size`] records, gene
+/// batches hold [`BatchOptions::genes_per_batch`] genes, which are read from the annotations as
+/// [`crate::genes::GeneAnnotations`] describes; records without a gene are named [`crate::genes::INTERGENIC`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum SplitMode {
+    /// Batches of a fixed number of records, the batch size.
+    #[default]
+    Records,
+    /// One batch per chromosome, named after it (e.g. `chr1.vcf.gz`) unless a name template is given.
+    /// The records of each chromosome must be contiguous in the input.
+    Chromosome,
+    /// One batch per gene, or per group of genes if more genes per batch are set, with all records whose VEP
+    /// `CSQ` or SnpEff `ANN` annotation names the gene. Records of overlapping genes go with the first gene of their annotation,
+    /// records without a gene into one batch per chromosome like `chr17_intergenic.vcf.gz`. Batches are named
+    /// like `chr17_TP53.vcf.gz` unless a name template is given. The records of each chromosome must be
+    /// contiguous in the input and are held in memory until it ends.
+    Gene,
+    /// One batch per interval of [`BatchOptions::split_regions`], with the records that lie in it, see
+    /// [`crate::split_by_regions`]. Records in overlapping intervals go into the batch of each, records outside
+    /// of all intervals are left out and counted as filtered. Batches are named after the name column of the
+    /// BED file if it tells all intervals apart, otherwise after their span like `chr1_000010001-000020000.vcf.gz`,
+    /// unless a name template is given. The records of each chromosome must be contiguous in the input and
+    /// sorted by position.
+    #[value(skip)]
+    #[serde(skip)]
+    Region,
+    /// Batches of [`BatchOptions::samples_per_batch`] sample columns, each with all records, the FORMAT column
+    /// and a `#CHROM` line that lists its samples. The samples are taken in the order of the input, after
+    /// [`RecordFilter::samples`] selected them. All batches are written at the same time, so there is one open
+    /// file per batch. An input without samples gives a single batch.
+    #[value(skip)]
+    #[serde(skip)]
+    Samples,
+}
+
+/// How the batches of a run are added to those already in the output directory, see [`BatchOptions::append`].
+/// [`AppendMode::Rebalance`] needs [`SplitMode::Records`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum AppendMode {
+    /// Keep the existing batches and continue their numbering.
+    #[default]
+    Continue,
+    /// Also fill up the last existing batch, if it has fewer records than the batch size, with the
+    /// first records of the input. Only when splitting by records.
+    Rebalance,
+}
+
+/// Options controlling how a VCF file is split into batches.
+///
+/// # Examples
+///
+/// ```
+/// use vcf_batcher::BatchOptions;
+///
+/// let options = BatchOptions {
+///     batch_size: 1000,
+///     ..Default::default()
+/// };
+/// ```
+#[derive(Debug, Clone)]
+pub struct BatchOptions {
+    /// How many lines of data should be contained in each batch, excluding the header.
+    pub batch_size: usize,
+    /// The sizes of the first batches, before [`BatchOptions::batch_size`] applies to the rest, e.g. a few small
+    /// batches to smoke-test downstream steps with, see [`BatchOptions::set_batch_sizes`]. With
+    /// [`BatchOptions::group_by_info`], every group starts with these sizes. Only used with [`SplitMode::Records`].
+    pub batch_sizes: Vec<usize>,
+    /// Divide the records into this many batches of nearly the same size instead, e.g. for a scheduler that
+    /// expects a fixed number of shards. The records are counted in a first pass over the input, which applies
+    /// the filters, so the input is read twice and cannot be a stream. Inputs with fewer records get a batch per
+    /// record. Only used with [`SplitMode::Records`], and replaces the batch sizes.
+    pub num_batches: Option<usize>,
+    /// With [`SplitMode::Records`], also end a batch once its file reaches this many bytes, e.g. because storage
+    /// and transfer quotas are in bytes on disk rather than in records. Bgzipped batches are compressed while
+    /// their records are collected, so the actual compressed size is measured. Batches end between records, so
+    /// they are larger by up to one BGZF block, which holds about 64 KiB of records before compression. With
+    /// [`OutputFormat::Jsonl`] or Parquet output, the size of the batch as a VCF file is measured.
+    /// `None` ends batches after [`BatchOptions::batch_size`] records only.
+    pub target_compressed_size: Option<u64>,
+    /// With [`SplitMode::Records`], also end a batch once its header and records reach this many bytes as plain
+    /// VCF text, before compression, e.g. so that the batches of a sites-only file and of a file with thousands of
+    /// samples hold similar amounts of data. Batches end between records, so they are larger by up to one record.
+    /// `None` ends batches after [`BatchOptions::batch_size`] records only.
+    pub target_size: Option<u64>,
+    /// BGzip compression level of the batches. `None` writes uncompressed files.
+    pub compression_level: Option<Compression>,
+    /// How the batches are compressed if a [`BatchOptions::compression_level`] is given. With
+    /// [`CompressionFormat::Zstd`], the level is mapped to zstd levels as [`BatchOptions::zstd_level`] says.
+    /// Only VCF batches can be compressed with zstd, and neither indexed nor split at a compressed size.
+    pub compression_format: CompressionFormat,
+    /// The compression level as it was parsed, which zstd batches are compressed at like `recompress --codec
+    /// zstd` compresses files, so that `best` is zstd level 19 rather than the 9 of BGZF. `None` takes the
+    /// number of [`BatchOptions::compression_level`] as the zstd level.
+    pub zstd_level: Option<Level>,
+    /// Flag that can be set from another thread (e.g. a signal handler) to stop batching.
+    /// Batches that are already being written are finished, the batch that is still being
+    /// collected is discarded.
+    pub cancel: Option<Arc<AtomicBool>>,
+    /// What to do if the input contains no variant records.
+    pub empty_input: EmptyInputPolicy,
+    /// What to do with header lines that appear after the first variant record.
+    pub late_header: LateHeaderPolicy,
+    /// Whether to check record contigs against the `##contig` header lines.
+    pub check_contigs: ContigCheck,
+    /// What to do if the header lists the same sample more than once.
+    pub duplicate_samples: DuplicateSamplePolicy,
+    /// What to do with malformed variant records.
+    pub bad_records: BadRecordPolicy,
+    /// What to do with records that repeat the CHROM, POS, REF and ALT of an earlier record, e.g. from an
+    /// upstream caller that emits some variants twice, which breaks joint calling. `None` does not check for duplicates.
+    pub dedup: Option<DuplicateRecordPolicy>,
+    /// What to do if a batch file already exists. Batches that are kept because of
+    /// [`BatchOptions::resume`] do not count as existing.
+    pub overwrite: OverwritePolicy,
+    /// Keep batches that a previous run with identical input and options already wrote
+    /// (verified by checksum) instead of rewriting them. Also records progress in a journal
+    /// and writes a manifest at the end, so that this run can be resumed in turn.
+    pub resume: bool,
+    /// Add the batches to those of earlier runs in the output directory, e.g. to batch this month's
+    /// delivery without splitting the whole cohort again. The existing batches are taken from the manifest or,
+    /// without one, found and read like [`crate::merge::merge_batches`] does; the batches of the input must
+    /// have the same header. The report lists the existing batches first, see
+    /// [`crate::RunReport::existing_batches`], and a manifest of all batches is written. `None` starts at batch 1.
+    pub append: Option<AppendMode>,
+    /// Lines longer than this many bytes stop the run with an error instead of being read
+    /// into memory completely. `None` allows lines of any length.
+    pub max_line_length: Option<usize>,
+    /// Template for the file names of the batches, relative to the output directory.
+    /// `None` names them `batch_01.vcf`, `batch_02.vcf`, ... (with `.gz` if they are compressed).
+    pub name_template: Option<NameTemplate>,
+    /// Where batches are split, by record count or by chromosome.
+    pub split_by: SplitMode,
+    /// Route the records into one set of batches per value of this INFO key, e.g. `CLNSIG`, in a subdirectory of
+    /// the output directory named like `CLNSIG=Pathogenic`. Records without the key go into `CLNSIG=.`, flags into
+    /// `KEY=true`. Every group is numbered and split on its own, and its subdirectory only appears with its first
+    /// batch. The groups are listed in [`crate::RunReport::groups`] and in the manifest, which is always written.
+    /// `None` writes all records into the same set of batches.
+    pub group_by_info: Option<String>,
+    /// How many genes each batch has with [`SplitMode::Gene`], in the order that they appear. The last batch
+    /// of a chromosome may have fewer. Values below 1 count as 1.
+    pub genes_per_batch: usize,
+    /// How many sample columns each batch has with [`SplitMode::Samples`]. The last batch may have fewer.
+    /// Values below 1 count as 1.
+    pub samples_per_batch: usize,
+    /// The intervals that the records are split into with [`SplitMode::Region`], e.g. from
+    /// [`crate::regions::read_bed`].
+    pub split_regions: Vec<BedRegion>,
+    /// A directory that batches are written to before they are moved into the output directory,
+    /// e.g. a fast local disk when the output is on slow network storage. Batches then only appear
+    /// in the output directory once they are complete. `None` writes them into the output directory directly.
+    pub tmp_dir: Option<PathBuf>,
+    /// Which records and samples are written into the batches.
+    pub filter: RecordFilter,
+    /// Samples whose genotypes are set to missing in every record, e.g. because they withdrew their consent.
+    /// The samples are masked before [`RecordFilter::samples`] selects the sample columns. `None` masks no sample.
+    pub mask: Option<SampleMask>,
+    /// The format of the batches. Ignored if [`BatchOptions::parquet`] writes Parquet files only.
+    pub format: OutputFormat,
+    /// The fields of the JSON objects with [`OutputFormat::Jsonl`], in this order. Empty for all fields.
+    pub json_fields: Vec<JsonField>,
+    /// Write the CHROM, POS, REF and ALT of the records of every batch to a `.sites.tsv` file next to it,
+    /// e.g. `batch_01.sites.tsv`. Not written for sinks. See [`crate::sites::site_list`].
+    pub site_lists: bool,
+    /// Write the genomic span of every batch, with its file name, to a BED file in the output directory,
+    /// see [`crate::bed::BED_FILE_NAME`]. Not written for sinks. See [`crate::bed::batch_spans`].
+    pub batches_bed: bool,
+    /// Write an index next to every batch, e.g. `batch_01.vcf.gz.tbi`, so that tools such as bcftools can
+    /// query the batches right away. Needs bgzipped VCF batches, i.e. a compression level and
+    /// [`OutputFormat::Vcf`]. Not written for sinks. `None` writes no index.
+    pub index: Option<IndexFormat>,
+    /// Read every VCF batch back after it was written and check its checksum, its BGZF blocks, its record count
+    /// and the positions of its first and last record, see [`crate::verify::verify_batch`]. Verified batches are
+    /// marked in the report and in a manifest in the output directory. Batches that were skipped because they
+    /// already existed and batches written to sinks are not verified.
+    pub verify_output: bool,
+    /// Write a manifest of the batches to the output directory at the end of every run, see
+    /// [`crate::manifest::Manifest`], e.g. for workflow engines to fan out over the batches without reading them.
+    /// Runs that resume, append, verify or group always write one.
+    pub manifest: bool,
+    /// Whether the variant sites of the batches are also, or only, written as Parquet files.
+    #[cfg(feature = "parquet")]
+    pub parquet: ParquetExport,
+    /// INFO keys that get a column of their own in the Parquet files, e.g. `AF` or `DP`.
+    #[cfg(feature = "parquet")]
+    pub parquet_info_keys: Vec<String>,
+    /// Called on the batching thread after each batch is complete, e.g. to start processing it
+    /// before the whole input is split.
+    pub on_batch: Option<BatchCallback>,
+    /// Called on the batching thread every few thousand lines and once at the end of the run,
+    /// e.g. to draw a progress bar.
+    pub on_progress: Option<ProgressCallback>,
+    /// Do not print a line per batch and a summary to stdout, e.g. because a progress bar is shown.
+    pub quiet: bool,
+    /// Measure the time spent reading, filtering, compressing and writing, see [`crate::RunReport::timings`].
+    /// Off by default, since measuring every line has a small cost.
+    pub timings: bool,
+}
+
+impl BatchOptions {
+    /// Whether the [`BatchOptions::cancel`] flag is set.
+    pub fn is_cancelled(&self) -> bool {
+        self.cancel
+            .as_ref()
+            .is_some_and(|cancel| cancel.load(Ordering::Relaxed))
+    }
+
+    /// Sets a schedule of batch sizes: the batches get the sizes in order, and every batch after the last
+    /// size gets that size, as [`BatchOptions::batch_size`]. An empty schedule changes nothing.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use vcf_batcher::{parse_batch_sizes, BatchOptions};
+    ///
+    /// let mut options = BatchOptions::default();
+    /// options.set_batch_sizes(&parse_batch_sizes("100,1000,25000...").unwrap());
+    /// assert_eq!(options.batch_sizes, [100, 1000]);
+    /// assert_eq!(options.batch_size, 25000);
+    /// assert_eq!(options.batch_size_of(2), 1000);
+    /// assert_eq!(options.batch_size_of(7), 25000);
+    /// ```
+    pub fn set_batch_sizes(&mut self, sizes: &[usize]) {
+        if let Some((last, first)) = sizes.split_last() {
+            self.batch_sizes = first.to_vec();
+            self.batch_size = *last;
+        }
+    }
+
+    /// How many records the batch with the given number, counted from 1, has at most.
+    pub fn batch_size_of(&self, number: usize) -> usize {
+        number
+            .checked_sub(1)
+            .and_then(|index| self.batch_sizes.get(index))
+            .copied()
+            .unwrap_or(self.batch_size)
+    }
+
+    /// How batches are compressed with zstd, or `None` if they are not, see [`BatchOptions::zstd_level`].
+    pub(crate) fn zstd_options(&self) -> Option<RecompressOptions> {
+        let level = self.compression_level?;
+        (self.compression_format == CompressionFormat::Zstd).then(|| {
+            RecompressOptions::zstd_batches(
+                self.zstd_level.unwrap_or_else(|| Level::of_bgzf(level)),
+            )
+        })
+    }
+
+    /// The extension of VCF batches: `.vcf`, or `.vcf.gz` or `.vcf.zst` if they are compressed.
+    pub(crate) fn vcf_extension(&self) -> &'static str {
+        match (self.compression_level, self.compression_format) {
+            (None, _) => ".vcf",
+            (Some(_), CompressionFormat::Bgzf) => ".vcf.gz",
+            (Some(_), CompressionFormat::Zstd) => ".vcf.zst",
+        }
+    }
+}
+
+impl Default for BatchOptions {
+    fn default() -> Self {
+        BatchOptions {
+            batch_size: 25000,
+            batch_sizes: Vec::new(),
+            num_batches: None,
+            target_compressed_size: None,
+            target_size: None,
+            compression_level: None,
+            compression_format: CompressionFormat::default(),
+            zstd_level: None,
+            cancel: None,
+            empty_input: EmptyInputPolicy::default(),
+            late_header: LateHeaderPolicy::default(),
+            check_contigs: ContigCheck::default(),
+            duplicate_samples: DuplicateSamplePolicy::default(),
+            bad_records: BadRecordPolicy::default(),
+            dedup: None,
+            overwrite: OverwritePolicy::default(),
+            resume: false,
+            append: None,
+            max_line_length: None,
+            name_template: None,
+            split_by: SplitMode::default(),
+            genes_per_batch: 1,
+            samples_per_batch: 1,
+            split_regions: Vec::new(),
+            group_by_info: None,
+            tmp_dir: None,
+            filter: RecordFilter::default(),
+            mask: None,
+            format: OutputFormat::default(),
+            json_fields: Vec::new(),
+            site_lists: false,
+            batches_bed: false,
+            index: None,
+            verify_output: false,
+            manifest: false,
+            #[cfg(feature = "parquet")]
+            parquet: ParquetExport::default(),
+            #[cfg(feature = "parquet")]
+            parquet_info_keys: Vec::new(),
+            on_batch: None,
+            on_progress: None,
+            quiet: false,
+            timings: false,
+        }
+    }
+}
+
+/// Function that is called with every completed batch, see [`BatchOptions::on_batch`].
+///
+/// # Examples
+///
+/// ```
+/// use vcf_batcher::{BatchCallback, BatchOptions};
+///
+/// let options = BatchOptions {
+///     on_batch: Some(BatchCallback::new(|batch| println!("{} is ready", batch.path.display()))),
+///     ..Default::default()
+/// };
+/// ```
+#[derive(Clone)]
+pub struct BatchCallback(Arc<dyn Fn(&BatchInfo) + Send + Sync>);
+
+impl BatchCallback {
+    pub fn new(callback: impl Fn(&BatchInfo) + Send + Sync + 'static) -> Self {
+        BatchCallback(Arc::new(callback))
+    }
+
+    pub(crate) fn call(&self, batch: &BatchInfo) {
+        (self.0)(batch)
+    }
+}
+
+impl fmt::Debug for BatchCallback {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("BatchCallback")
+    }
+}
+
+/// Function that is called with the progress of a run, see [`BatchOptions::on_progress`].
+///
+/// # Examples
+///
+/// ```
+/// use vcf_batcher::{BatchOptions, ProgressCallback};
+///
+/// let options = BatchOptions {
+///     on_progress: Some(ProgressCallback::new(|progress| {
+///         eprintln!("{} records, {} batches", progress.records, progress.batches)
+///     })),
+///     ..Default::default()
+/// };
+/// ```
+#[derive(Clone)]
+pub struct ProgressCallback(Arc<dyn Fn(&Progress) + Send + Sync>);
+
+impl ProgressCallback {
+    pub fn new(callback: impl Fn(&Progress) + Send + Sync + 'static) -> Self {
+        ProgressCallback(Arc::new(callback))
+    }
+
+    pub(crate) fn call(&self, progress: &Progress) {
+        (self.0)(progress)
+    }
+}
+
+impl fmt::Debug for ProgressCallback {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("ProgressCallback")
+    }
+}
+
+/// Parses a schedule of batch sizes such as `100,1000,25000...`, where the last size applies to all
+/// remaining batches, see [`BatchOptions::set_batch_sizes`]. The trailing `...` is optional.
+///
+/// # Examples
+///
+/// ```
+/// use vcf_batcher::parse_batch_sizes;
+///
+/// assert_eq!(parse_batch_sizes("100,1000,25000..."), Ok(vec![100, 1000, 25000]));
+/// assert_eq!(parse_batch_sizes("500"), Ok(vec![500]));
+/// assert!(parse_batch_sizes("100,0...").is_err());
+/// ```
+pub fn parse_batch_sizes(input: &str) -> Result<Vec<usize>, String> {
+    let sizes = input.trim();
+    let sizes = sizes.strip_suffix("...").unwrap_or(sizes);
+    let sizes = sizes
+        .split(',')
+        .map(|size| match size.trim().parse::<usize>() {
+            Ok(0) => Err(format!("'{}' has a batch size of 0", input)),
+            Ok(size) => Ok(size),
+            Err(_) => Err(format!("'{}' is not a valid batch size", size.trim())),
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(sizes)
+}
+
+/// Parses a size such as `500M`, `2GB` or `1024`, following the conventions of GNU `split`:
+/// `K`, `M`, `G` and `T` (or `KiB`, `MiB`, ...) are powers of 1024, `KB`, `MB`, `GB` and `TB` powers of 1000.
+///
+/// # Examples
+///
+/// ```
+/// use vcf_batcher::parse_byte_size;
+///
+/// assert_eq!(parse_byte_size("500M"), Ok(500 * 1024 * 1024));
+/// assert_eq!(parse_byte_size("2GB"), Ok(2_000_000_000));
+/// assert!(parse_byte_size("lots").is_err());
+/// ```
+pub fn parse_byte_size(input: &str) -> Result<u64, String> {
+    let input = input.trim();
+    let split = input
+        .find(|character: char| !character.is_ascii_digit())
+        .unwrap_or(input.len());
+    let (number, unit) = input.split_at(split);
+    let number: u64 = number
+        .parse()
+        .map_err(|_| format!("'{}' is not a valid size", input))?;
+
+    let multiplier: u64 = match unit.trim().to_ascii_uppercase().as_str() {
+        "" | "B" => 1,
+        "K" | "KIB" => 1 << 10,
+        "M" | "MIB" => 1 << 20,
+        "G" | "GIB" => 1 << 30,
+        "T" | "TIB" => 1 << 40,
+        "KB" => 1_000,
+        "MB" => 1_000_000,
+        "GB" => 1_000_000_000,
+        "TB" => 1_000_000_000_000,
+        _ => {
+            return Err(format!(
+                "'{}' has an unknown unit, use one of B, K, M, G, T, KB, MB, GB or TB",
+                input
+            ))
+        }
+    };
+    number
+        .checked_mul(multiplier)
+        .ok_or_else(|| format!("'{}' is too large", input))
+}
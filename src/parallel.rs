@@ -0,0 +1,306 @@
+//! Splitting the contigs of an indexed, bgzipped VCF file concurrently, each into a directory of its own.
+
+use std::borrow::Cow;
+use std::fs::File;
+use std::io::{self, BufReader};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+
+use bgzip::BGZFReader;
+use rayon::prelude::*;
+
+use crate::index::{self, ContigSpan};
+use crate::lines::{BoundedLines, LineTooLong};
+use crate::lock::OutputLock;
+use crate::manifest::{self, Manifest};
+use crate::writer::BatchWriter;
+use crate::{
+    header, is_header_line, naming, read_lines_with_limit, BatchOptions, BatchOutput, Destination,
+    EmptyInputPolicy, Instant, Progress, RunReport, StageTimings, VcfBatcherError,
+};
+
+/// Same as [`crate::extract_variants_to_batches_with_options`], but the contigs of the input are read and
+/// split concurrently on rayon's thread pool, using the tabix (`.tbi`) or CSI (`.csi`) index next to the
+/// input to find where each contig starts. The batches of a contig are written to a directory named after
+/// it, e.g. `chr1/batch_01.vcf`, and [`RunReport::groups`] lists these directories by contig.
+///
+/// The reports of the contigs are merged in the order that the contigs appear in the input, with the
+/// batches numbered through, and a manifest of all batches is written to the output directory.
+/// [`BatchOptions::on_progress`] is called whenever a contig is complete. Contigs whose records are all
+/// filtered out get no directory.
+///
+/// Fails with [`VcfBatcherError::Read`] if the input has no index. If a contig fails, the error of the
+/// first failed contig is returned, while the other contigs are still completed.
+pub fn extract_contigs_in_parallel(
+    file_path: &str,
+    output_path: &Path,
+    options: &BatchOptions,
+) -> Result<RunReport, VcfBatcherError> {
+    let read_error = |path: &Path, source| VcfBatcherError::Read {
+        path: path.to_path_buf(),
+        source,
+    };
+    if options.num_batches.is_some() {
+        return Err(VcfBatcherError::InvalidOptions {
+            reason: "a number of batches cannot be set when the contigs are split concurrently"
+                .to_string(),
+        });
+    }
+    let _lock = OutputLock::acquire(output_path)?;
+    let started = Instant::now();
+    let index_path = index::find_index(Path::new(file_path)).ok_or_else(|| {
+        read_error(
+            Path::new(file_path),
+            io::Error::new(
+                io::ErrorKind::NotFound,
+                "there is no .tbi or .csi index next to it",
+            ),
+        )
+    })?;
+    let contigs =
+        index::read_contig_spans(&index_path).map_err(|error| read_error(&index_path, error))?;
+    if contigs.is_empty() {
+        // Without records, the run only applies the empty input policy
+        return crate::extract_variants(
+            file_path,
+            Destination::Directory(Cow::Borrowed(output_path)),
+            options,
+        );
+    }
+
+    let headers = read_lines_with_limit(file_path, options.max_line_length)
+        .map_err(|error| read_error(Path::new(file_path), error))?
+        .take_while(|line| line.as_ref().map_or(true, |line| is_header_line(line)))
+        .collect::<Result<Vec<String>, io::Error>>()
+        .map_err(|error| read_error(Path::new(file_path), error))?;
+    let fingerprint = manifest::run_fingerprint(file_path, options)
+        .map_err(|error| read_error(Path::new(file_path), error))?;
+    let contig_options = BatchOptions {
+        // The contigs would interleave their messages, a summary is printed instead
+        quiet: true,
+        on_progress: None,
+        empty_input: EmptyInputPolicy::Skip,
+        ..options.clone()
+    };
+
+    let total_bytes = std::fs::metadata(file_path)
+        .map(|metadata| metadata.len())
+        .ok();
+    let bytes_read = AtomicU64::new(0);
+    let records = AtomicUsize::new(0);
+    let batches = AtomicUsize::new(0);
+    let results: Vec<Result<RunReport, VcfBatcherError>> = contigs
+        .par_iter()
+        .map(|contig| {
+            let report = extract_contig(
+                file_path,
+                &output_path.join(naming::sanitize_component(&contig.name)),
+                contig,
+                &headers,
+                &contig_options,
+                &fingerprint,
+            )?;
+            if let Some(on_progress) = &options.on_progress {
+                let contig_bytes = contig.compressed_bytes();
+                let contig_records = report.total_records() + report.filtered_records;
+                let contig_batches = report.batches.len();
+                on_progress.call(&Progress {
+                    bytes_read: bytes_read.fetch_add(contig_bytes, Ordering::Relaxed)
+                        + contig_bytes,
+                    total_bytes,
+                    records: records.fetch_add(contig_records, Ordering::Relaxed) + contig_records,
+                    batches: batches.fetch_add(contig_batches, Ordering::Relaxed) + contig_batches,
+                    elapsed: started.elapsed(),
+                });
+            }
+            Ok(report)
+        })
+        .collect();
+
+    let mut report = RunReport {
+        output_path: output_path.to_path_buf(),
+        fingerprint,
+        timings: options.timings.then(StageTimings::default),
+        ..Default::default()
+    };
+    for (contig, result) in contigs.iter().zip(results) {
+        merge_report(&mut report, &contig.name, result?);
+    }
+    report.elapsed = started.elapsed();
+    if report.batches.is_empty() {
+        report.empty_input = Some(options.empty_input);
+        if options.empty_input == EmptyInputPolicy::Error {
+            return Err(VcfBatcherError::EmptyInput {
+                path: PathBuf::from(file_path),
+            });
+        }
+    }
+    Manifest::from_report(&report, true)
+        .write(output_path)
+        .map_err(|source| VcfBatcherError::Write {
+            path: output_path.join(manifest::MANIFEST_FILE_NAME),
+            source: source.into(),
+        })?;
+    if !options.quiet {
+        println!(
+            "Saved {} batches of {} contigs to {}.",
+            report.batches.len() - report.existing_batches,
+            report.groups.len(),
+            output_path.display()
+        );
+    }
+    Ok(report)
+}
+
+/// Splits the records of one contig into batches in `contig_path`.
+fn extract_contig(
+    file_path: &str,
+    contig_path: &Path,
+    contig: &ContigSpan,
+    headers: &[String],
+    options: &BatchOptions,
+    fingerprint: &str,
+) -> Result<RunReport, VcfBatcherError> {
+    let read_error = |source| VcfBatcherError::Read {
+        path: PathBuf::from(file_path),
+        source,
+    };
+    let file = File::open(file_path).map_err(read_error)?;
+    let mut reader =
+        BGZFReader::new(BufReader::new(file)).map_err(|error| read_error(error.into_io_error()))?;
+    reader
+        .bgzf_seek(contig.start)
+        .map_err(|error| read_error(error.into_io_error()))?;
+
+    let output = BatchOutput::new(
+        naming::input_stem(Path::new(file_path)),
+        Destination::Directory(Cow::Borrowed(contig_path)),
+        Cow::Borrowed(options),
+        Some(fingerprint.to_string()),
+    )?;
+    let mut writer = BatchWriter::new(file_path.to_string(), output);
+    for line in headers {
+        writer.write_line(line)?;
+    }
+    for line in BoundedLines::new(reader, options.max_line_length) {
+        let line = line.map_err(|error| match LineTooLong::from_io_error(&error) {
+            Some(LineTooLong { line_number, limit }) => {
+                VcfBatcherError::LineTooLong { line_number, limit }
+            }
+            None => read_error(error),
+        })?;
+        // The index only gives where the contig starts, it ends where the next one begins
+        if header::record_chrom(&line) != contig.name {
+            break;
+        }
+        writer.write_line(&line)?;
+    }
+    writer.finish()
+}
+
+/// Adds the report of a contig to the report of the run, numbering its batches after the earlier ones.
+fn merge_report(report: &mut RunReport, contig: &str, contig_report: RunReport) {
+    if !contig_report.batches.is_empty() {
+        report
+            .groups
+            .insert(contig.to_string(), contig_report.output_path.clone());
+    }
+    for mut batch in contig_report.batches {
+        batch.number = report.batches.len() + 1;
+        report.batches.push(batch);
+    }
+    report.late_header_lines += contig_report.late_header_lines;
+    for (unknown, count) in contig_report.unknown_contigs {
+        *report.unknown_contigs.entry(unknown).or_default() += count;
+    }
+    // Every contig is split with the same header
+    report.duplicate_samples = contig_report.duplicate_samples;
+    report.filtered_records += contig_report.filtered_records;
+    report.bad_records += contig_report.bad_records;
+    report.duplicate_records += contig_report.duplicate_records;
+    report.resumed_batches += contig_report.resumed_batches;
+    report.skipped_batches += contig_report.skipped_batches;
+    report.existing_batches += contig_report.existing_batches;
+    if let (Some(timings), Some(contig_timings)) = (&mut report.timings, contig_report.timings) {
+        for (total, time) in [
+            (&mut timings.reading, contig_timings.reading),
+            (&mut timings.filtering, contig_timings.filtering),
+            (&mut timings.compression, contig_timings.compression),
+            (&mut timings.writing, contig_timings.writing),
+        ] {
+            *total += time;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::extract_contigs_in_parallel;
+    use crate::index::tests::write_indexed_vcf;
+    use crate::manifest::Manifest;
+    use crate::{BatchOptions, VcfBatcherError};
+
+    #[test]
+    fn test_extract_contigs_in_parallel() {
+        let input_dir = Path::new("./test_data/inputs");
+        std::fs::create_dir_all(input_dir).unwrap();
+        let input_path = input_dir.join("parallel.vcf.gz");
+        let record = |chrom: &str, pos: usize| format!("{chrom}\t{pos}\t.\tA\tG\t.\tPASS\t.");
+        let headers = "##fileformat=VCFv4.2\n#CHROM\tPOS\tID\tREF\tALT\tQUAL\tFILTER\tINFO\n";
+        write_indexed_vcf(
+            &input_path,
+            headers,
+            &[
+                ("chr1", (1..=5).map(|pos| record("chr1", pos)).collect()),
+                ("chr2", (1..=2).map(|pos| record("chr2", pos)).collect()),
+                ("chrX", vec![record("chrX", 7)]),
+            ],
+        );
+
+        let output_path = Path::new("./test_data/parallel");
+        let _ = std::fs::remove_dir_all(output_path);
+        let options = BatchOptions {
+            batch_size: 2,
+            quiet: true,
+            ..Default::default()
+        };
+        let report =
+            extract_contigs_in_parallel(input_path.to_str().unwrap(), output_path, &options)
+                .unwrap();
+        let batches: Vec<_> = report
+            .batches
+            .iter()
+            .map(|batch| (batch.number, batch.path.strip_prefix(output_path).unwrap()))
+            .collect();
+        assert_eq!(
+            batches,
+            [
+                (1, Path::new("chr1/batch_01.vcf")),
+                (2, Path::new("chr1/batch_02.vcf")),
+                (3, Path::new("chr1/batch_03.vcf")),
+                (4, Path::new("chr2/batch_01.vcf")),
+                (5, Path::new("chrX/batch_01.vcf")),
+            ]
+        );
+        assert_eq!(report.total_records(), 8);
+        assert_eq!(report.groups["chrX"], output_path.join("chrX"));
+        let contents = std::fs::read_to_string(output_path.join("chr2/batch_01.vcf")).unwrap();
+        assert_eq!(
+            contents,
+            headers.to_string() + &record("chr2", 1) + "\n" + &record("chr2", 2) + "\n"
+        );
+        let manifest = Manifest::read(output_path).unwrap().unwrap();
+        assert_eq!(manifest.batches.len(), 5);
+        assert_eq!(manifest.groups["chr1"], "chr1");
+
+        let error = extract_contigs_in_parallel(
+            "test_data/batch_01.vcf.gz",
+            Path::new("./test_data/parallel_unindexed"),
+            &options,
+        )
+        .unwrap_err();
+        assert!(matches!(error, VcfBatcherError::Read { .. }));
+    }
+}
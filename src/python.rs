@@ -0,0 +1,985 @@
+//! Python bindings of the VCF batcher.
+
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+
+use bgzip::{BGZFError, Compression};
+use clap::ValueEnum;
+use pyo3::exceptions::{PyFileNotFoundError, PyPermissionError, PyTypeError, PyValueError};
+use pyo3::prelude::*;
+use pyo3::types::{PyBytes, PyDict, PyList};
+
+use crate::filter::Region;
+use crate::header::{read_header, read_sample_names, FieldDefinition};
+use crate::mask::SampleMask;
+use crate::merge::{merge_batches, merge_batches_with_compression, MergeReport};
+use crate::naming::NameTemplate;
+use crate::recompress::Level;
+use crate::regions::read_bed;
+use crate::stats::{summarize_batches, vcf_stats, VcfStats};
+use crate::validate::validate_vcf;
+use crate::writer;
+use crate::{
+    extract_variants_to_batches_with_options, extract_variants_to_sink, parse_batch_sizes,
+    parse_byte_size, parse_compression, read_lines, BatchCallback, BatchInfo, BatchOptions,
+    BatchSink, Locus, Progress, ProgressCallback, ReaderLines, RunReport, SplitMode,
+    VcfBatcherError,
+};
+
+// The macro of pyo3 0.18 checks a cfg that newer compilers do not know about
+#[allow(unexpected_cfgs)]
+mod exceptions {
+    pyo3::create_exception!(
+        vcf_batcher,
+        VcfBatcherError,
+        pyo3::exceptions::PyException,
+        "Raised when a VCF file cannot be split into batches."
+    );
+}
+
+/// The kind of the IO error that caused an error, so that files that are missing or cannot be
+/// accessed raise the matching built-in exceptions.
+fn io_error_kind(error: &VcfBatcherError) -> Option<io::ErrorKind> {
+    match error {
+        VcfBatcherError::Read { source, .. } | VcfBatcherError::Lock { source, .. } => {
+            Some(source.kind())
+        }
+        VcfBatcherError::BatchWrite {
+            source: BGZFError::IoError(source),
+            ..
+        }
+        | VcfBatcherError::Write {
+            source: BGZFError::IoError(source),
+            ..
+        } => Some(source.kind()),
+        _ => None,
+    }
+}
+
+impl From<VcfBatcherError> for PyErr {
+    fn from(error: VcfBatcherError) -> PyErr {
+        let message = error.to_string();
+        match io_error_kind(&error) {
+            Some(io::ErrorKind::NotFound) => return PyFileNotFoundError::new_err(message),
+            Some(io::ErrorKind::PermissionDenied) => return PyPermissionError::new_err(message),
+            _ => {}
+        }
+        match error {
+            VcfBatcherError::InvalidTemplate { .. }
+            | VcfBatcherError::InvalidHeader { .. }
+            | VcfBatcherError::InvalidCompressionLevel { .. }
+            | VcfBatcherError::InvalidRegion { .. }
+            | VcfBatcherError::InvalidFilter { .. }
+            | VcfBatcherError::UnknownSamples { .. }
+            | VcfBatcherError::UnorderedSamples { .. }
+            | VcfBatcherError::UnknownColumns { .. }
+            | VcfBatcherError::InvalidConfig { .. }
+            | VcfBatcherError::UnknownProfile { .. } => PyValueError::new_err(message),
+            VcfBatcherError::NoBatches { .. } => PyFileNotFoundError::new_err(message),
+            _ => exceptions::VcfBatcherError::new_err(message),
+        }
+    }
+}
+
+/// Paths are accepted as `str` or `os.PathLike`, but the input path is passed to the library as `&str`.
+fn utf8_path(path: &Path) -> PyResult<&str> {
+    path.to_str()
+        .ok_or_else(|| PyValueError::new_err(format!("{} is not valid UTF-8", path.display())))
+}
+
+/// Parses a compression level with [`parse_compression`], which rejects unknown compression
+/// levels instead of silently writing uncompressed batches.
+fn compression_level_argument(compression_level: Option<String>) -> PyResult<Option<Compression>> {
+    match compression_level {
+        None => Ok(None),
+        Some(level) => Ok(parse_compression(&level)?),
+    }
+}
+
+/// Builds the options of a run from the arguments every splitting function takes and the keyword
+/// arguments for filters, split modes and policies, so that Python has the same options as the CLI.
+/// `None` values keep the default of an option.
+fn batch_options(
+    batch_size: usize,
+    compression_level: Option<String>,
+    kwargs: Option<&PyDict>,
+) -> PyResult<BatchOptions> {
+    if batch_size == 0 {
+        return Err(PyValueError::new_err("batch_size must be at least 1"));
+    }
+    let level = match compression_level {
+        Some(level) => Level::parse(&level)?,
+        None => None,
+    };
+    let mut options = BatchOptions {
+        batch_size,
+        compression_level: level.map(Level::bgzf),
+        zstd_level: level,
+        ..Default::default()
+    };
+    for (key, value) in kwargs.into_iter().flatten() {
+        let key: &str = key.extract()?;
+        if value.is_none() {
+            continue;
+        }
+        match key {
+            "regions" => {
+                // A single region may be given as a plain string
+                let regions: Vec<String> = match value.extract::<String>() {
+                    Ok(region) => vec![region],
+                    Err(_) => value.extract()?,
+                };
+                options.filter.regions = regions
+                    .iter()
+                    .map(|region| region.parse::<Region>())
+                    .collect::<Result<_, _>>()?;
+            }
+            "samples" => {
+                if value.extract::<String>().is_ok() {
+                    return Err(PyTypeError::new_err(
+                        "samples must be a list of sample names, not a string",
+                    ));
+                }
+                options.filter.samples = Some(value.extract()?);
+            }
+            "sample_order" => {
+                if value.extract::<String>().is_ok() {
+                    return Err(PyTypeError::new_err(
+                        "sample_order must be a list of sample names, not a string",
+                    ));
+                }
+                options.filter.sample_order = Some(value.extract()?);
+            }
+            "mask_samples" => {
+                if value.extract::<String>().is_ok() {
+                    return Err(PyTypeError::new_err(
+                        "mask_samples must be a list of sample names, not a string",
+                    ));
+                }
+                options.mask.get_or_insert_with(SampleMask::default).samples = value.extract()?;
+            }
+            "mask_all_fields" => {
+                if value.extract()? {
+                    options
+                        .mask
+                        .get_or_insert_with(SampleMask::default)
+                        .all_fields = true;
+                }
+            }
+            "pass_only" => options.filter.pass_only = value.extract()?,
+            "min_qual" => options.filter.min_qual = Some(value.extract()?),
+            "split_by" => options.split_by = enum_argument(key, value)?,
+            "group_by_info" => options.group_by_info = Some(value.extract()?),
+            "regions_bed" => {
+                let path: PathBuf = value.extract()?;
+                options.split_by = SplitMode::Region;
+                options.split_regions = read_bed(&path)?;
+            }
+            "batch_sizes" => {
+                // A list of sizes or a schedule such as "100,1000,25000...", replacing batch_size
+                let sizes: Vec<usize> = match value.extract::<String>() {
+                    Ok(sizes) => parse_batch_sizes(&sizes).map_err(PyValueError::new_err)?,
+                    Err(_) => value.extract()?,
+                };
+                if sizes.is_empty() || sizes.contains(&0) {
+                    return Err(PyValueError::new_err(
+                        "batch_sizes must be a non-empty list of sizes of at least 1",
+                    ));
+                }
+                options.set_batch_sizes(&sizes);
+            }
+            "num_batches" => {
+                let num_batches: usize = value.extract()?;
+                if num_batches == 0 {
+                    return Err(PyValueError::new_err("num_batches must be at least 1"));
+                }
+                options.num_batches = Some(num_batches);
+            }
+            "target_compressed_size" => {
+                // A number of bytes or a size such as "500M"
+                let size = match value.extract::<String>() {
+                    Ok(size) => parse_byte_size(&size).map_err(PyValueError::new_err)?,
+                    Err(_) => value.extract()?,
+                };
+                if size == 0 {
+                    return Err(PyValueError::new_err(
+                        "target_compressed_size must be at least 1 byte",
+                    ));
+                }
+                options.target_compressed_size = Some(size);
+            }
+            "target_size" => {
+                // A number of bytes or a size such as "500MB"
+                let size = match value.extract::<String>() {
+                    Ok(size) => parse_byte_size(&size).map_err(PyValueError::new_err)?,
+                    Err(_) => value.extract()?,
+                };
+                if size == 0 {
+                    return Err(PyValueError::new_err("target_size must be at least 1 byte"));
+                }
+                options.target_size = Some(size);
+            }
+            "samples_per_batch" => {
+                options.split_by = SplitMode::Samples;
+                options.samples_per_batch = value.extract()?;
+                if options.samples_per_batch == 0 {
+                    return Err(PyValueError::new_err(
+                        "samples_per_batch must be at least 1",
+                    ));
+                }
+            }
+            "genes_per_batch" => {
+                options.genes_per_batch = value.extract()?;
+                if options.genes_per_batch == 0 {
+                    return Err(PyValueError::new_err("genes_per_batch must be at least 1"));
+                }
+            }
+            "name_template" => options.name_template = Some(NameTemplate::parse(value.extract()?)?),
+            "resume" => options.resume = value.extract()?,
+            "append" => options.append = Some(enum_argument(key, value)?),
+            "tmp_dir" => options.tmp_dir = Some(value.extract()?),
+            "timings" => options.timings = value.extract()?,
+            "overwrite" => options.overwrite = enum_argument(key, value)?,
+            "max_line_length" => options.max_line_length = Some(value.extract()?),
+            "check_contigs" => options.check_contigs = enum_argument(key, value)?,
+            "on_empty_input" => options.empty_input = enum_argument(key, value)?,
+            "on_late_header" => options.late_header = enum_argument(key, value)?,
+            "on_duplicate_samples" => options.duplicate_samples = enum_argument(key, value)?,
+            "on_bad_record" => options.bad_records = enum_argument(key, value)?,
+            "dedup" => options.dedup = Some(enum_argument(key, value)?),
+            "format" => options.format = enum_argument(key, value)?,
+            "compression_format" => options.compression_format = enum_argument(key, value)?,
+            "site_lists" => options.site_lists = value.extract()?,
+            "index" => options.index = Some(enum_argument(key, value)?),
+            "batches_bed" => options.batches_bed = value.extract()?,
+            "verify_output" => options.verify_output = value.extract()?,
+            "manifest" => options.manifest = value.extract()?,
+            "json_fields" => {
+                let fields: Vec<&PyAny> = value.extract()?;
+                options.json_fields = fields
+                    .into_iter()
+                    .map(|field| enum_argument(key, field))
+                    .collect::<Result<_, _>>()?;
+            }
+            // Attached to every run, see Interruption
+            "on_progress" => {}
+            _ => {
+                return Err(PyTypeError::new_err(format!(
+                    "unexpected keyword argument '{}'",
+                    key
+                )))
+            }
+        }
+    }
+    options.filter.validate()?;
+    Ok(options)
+}
+
+/// The `on_progress` keyword argument, a callable that is passed the progress of a run as a dict.
+fn progress_callable(kwargs: Option<&PyDict>) -> PyResult<Option<PyObject>> {
+    match kwargs.and_then(|kwargs| kwargs.get_item("on_progress")) {
+        Some(callable) if callable.is_none() => Ok(None),
+        Some(callable) if !callable.is_callable() => {
+            Err(PyTypeError::new_err("on_progress must be callable"))
+        }
+        Some(callable) => Ok(Some(callable.into())),
+        None => Ok(None),
+    }
+}
+
+/// Stops a run that was started from Python, whose GIL is released while it runs. The run is cancelled
+/// when the `on_progress` callable returns `False` or raises, or when Ctrl+C is pressed, which Python only
+/// notices while a thread holds the GIL. The GIL is therefore taken every few thousand lines to check for
+/// signals and call `on_progress`.
+#[derive(Clone)]
+struct Interruption {
+    /// The exception that stopped the run, which is raised instead of its error.
+    error: Arc<Mutex<Option<PyErr>>>,
+}
+
+impl Interruption {
+    /// Adds a cancel flag, or uses the one the options have, and a progress callback to the options of a run.
+    fn attach(options: &mut BatchOptions, on_progress: Option<PyObject>) -> Self {
+        let interruption = Interruption {
+            error: Arc::new(Mutex::new(None)),
+        };
+        let cancel = Arc::clone(options.cancel.get_or_insert_with(Default::default));
+        let error = Arc::clone(&interruption.error);
+        options.on_progress = Some(ProgressCallback::new(move |progress| {
+            if cancel.load(Ordering::Relaxed) {
+                return;
+            }
+            let proceed = Python::with_gil(|py| {
+                py.check_signals()?;
+                let Some(on_progress) = &on_progress else {
+                    return Ok(true);
+                };
+                let returned = on_progress.call1(py, (progress_to_dict(py, progress)?,))?;
+                // Only an explicit False stops the run, not a callable that returns None
+                Ok(!matches!(returned.extract::<bool>(py), Ok(false)))
+            });
+            match proceed {
+                Ok(true) => {}
+                Ok(false) => cancel.store(true, Ordering::Relaxed),
+                Err(exception) => {
+                    *error
+                        .lock()
+                        .unwrap_or_else(|poisoned| poisoned.into_inner()) = Some(exception);
+                    cancel.store(true, Ordering::Relaxed);
+                }
+            }
+        }));
+        interruption
+    }
+
+    /// The result of the run, or the exception that stopped it.
+    fn result<T>(&self, result: Result<T, VcfBatcherError>) -> PyResult<T> {
+        let exception = self
+            .error
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .take();
+        match exception {
+            Some(exception) if result.is_err() => Err(exception),
+            _ => Ok(result?),
+        }
+    }
+}
+
+/// Parses a keyword argument such as `split_by="chromosome"` with the values of the CLI option.
+fn enum_argument<T: ValueEnum>(name: &str, value: &PyAny) -> PyResult<T> {
+    let value: &str = value.extract()?;
+    T::from_str(value, true).map_err(|_| {
+        let expected: Vec<String> = T::value_variants()
+            .iter()
+            .filter_map(|variant| variant.to_possible_value())
+            .map(|variant| format!("'{}'", variant.get_name()))
+            .collect();
+        PyValueError::new_err(format!(
+            "unknown {} '{}', expected one of {}",
+            name,
+            value,
+            expected.join(", ")
+        ))
+    })
+}
+
+/// Wrapper function for extract_variants_to_batches to be called from Python
+///
+/// The GIL is released while the batches are written, so other Python threads keep running.
+/// Returns the report of the run as a dict.
+#[pyfunction]
+#[pyo3(signature = (file_path, output_path, batch_size, compression_level = None, **options))]
+fn py_extract_variants_to_batches(
+    py: Python,
+    file_path: PathBuf,
+    output_path: PathBuf,
+    batch_size: usize,
+    compression_level: Option<String>,
+    options: Option<&PyDict>,
+) -> PyResult<PyObject> {
+    let on_progress = progress_callable(options)?;
+    let mut options = batch_options(batch_size, compression_level, options)?;
+    let interruption = Interruption::attach(&mut options, on_progress);
+    let file_path = utf8_path(&file_path)?;
+    let result = py.allow_threads(|| {
+        extract_variants_to_batches_with_options(file_path, &output_path, &options)
+    });
+    report_to_dict(py, &interruption.result(result)?)
+}
+
+/// Iterator over the batches of a split that runs in a background thread,
+/// returned by [`iter_batches`].
+#[pyclass(module = "vcf_batcher")]
+struct BatchIterator {
+    batches: Receiver<(PathBuf, usize)>,
+    worker: Option<JoinHandle<Result<RunReport, VcfBatcherError>>>,
+    cancel: Arc<AtomicBool>,
+    interruption: Interruption,
+}
+
+#[pymethods]
+impl BatchIterator {
+    fn __iter__(slf: PyRef<'_, Self>) -> PyRef<'_, Self> {
+        slf
+    }
+
+    fn __next__(&mut self, py: Python) -> PyResult<Option<(PathBuf, usize)>> {
+        let batches = &mut self.batches;
+        if let Ok(batch) = py.allow_threads(move || batches.recv()) {
+            return Ok(Some(batch));
+        }
+        // The sender is dropped when the worker finishes, so all batches have been received
+        match self.worker.take() {
+            Some(worker) => match py.allow_threads(|| worker.join()) {
+                Ok(result) => self.interruption.result(result).map(|_| None),
+                Err(_) => Err(exceptions::VcfBatcherError::new_err(
+                    "the batching thread panicked",
+                )),
+            },
+            None => Ok(None),
+        }
+    }
+}
+
+impl Drop for BatchIterator {
+    fn drop(&mut self) {
+        // Stop the worker if the iterator is abandoned before it is exhausted
+        self.cancel.store(true, Ordering::Relaxed);
+    }
+}
+
+/// Splits a VCF file into batches in a background thread and yields `(path, records)`
+/// for every batch as soon as it is written completely.
+#[pyfunction]
+#[pyo3(signature = (file_path, output_path, batch_size = 25000, compression_level = None, **options))]
+fn iter_batches(
+    file_path: PathBuf,
+    output_path: PathBuf,
+    batch_size: usize,
+    compression_level: Option<String>,
+    options: Option<&PyDict>,
+) -> PyResult<BatchIterator> {
+    let on_progress = progress_callable(options)?;
+    let mut options = batch_options(batch_size, compression_level, options)?;
+    let file_path = utf8_path(&file_path)?.to_string();
+    let (sender, batches) = mpsc::channel();
+    let cancel = Arc::new(AtomicBool::new(false));
+    options.cancel = Some(Arc::clone(&cancel));
+    let interruption = Interruption::attach(&mut options, on_progress);
+    options.on_batch = Some(BatchCallback::new(move |batch| {
+        // The receiver is gone if the iterator was dropped, the run is being cancelled then
+        let _ = sender.send((batch.path.clone(), batch.records));
+    }));
+
+    let worker = thread::spawn(move || {
+        extract_variants_to_batches_with_options(&file_path, &output_path, &options)
+    });
+    Ok(BatchIterator {
+        batches,
+        worker: Some(worker),
+        cancel,
+        interruption,
+    })
+}
+
+/// Passes every batch to a Python callable instead of writing it to a file.
+struct CallbackSink {
+    callback: PyObject,
+    /// The exception raised by the callback, which is re-raised once the run has stopped.
+    error: Option<PyErr>,
+}
+
+impl BatchSink for CallbackSink {
+    fn write_batch(&mut self, batch: &BatchInfo, contents: &[u8]) -> io::Result<()> {
+        Python::with_gil(|py| {
+            let name = batch.path.to_string_lossy();
+            match self.callback.call1(py, (name, PyBytes::new(py, contents))) {
+                Ok(_) => Ok(()),
+                Err(error) => {
+                    self.error = Some(error);
+                    Err(io::Error::other("the callback raised an exception"))
+                }
+            }
+        })
+    }
+}
+
+/// Splits a VCF file into batches and calls `callback(name, contents)` for every batch instead of
+/// writing files. `contents` are the bytes of the batch (header and records), bgzipped if a
+/// compression level is given, and `name` is the file name the batch would have had.
+/// Returns the report of the run as a dict.
+#[pyfunction]
+#[pyo3(signature = (file_path, callback, batch_size = 25000, compression_level = None, **options))]
+fn extract_to_callback(
+    py: Python,
+    file_path: PathBuf,
+    callback: PyObject,
+    batch_size: usize,
+    compression_level: Option<String>,
+    options: Option<&PyDict>,
+) -> PyResult<PyObject> {
+    let on_progress = progress_callable(options)?;
+    let mut options = batch_options(batch_size, compression_level, options)?;
+    let interruption = Interruption::attach(&mut options, on_progress);
+    let file_path = utf8_path(&file_path)?;
+    let mut sink = CallbackSink {
+        callback,
+        error: None,
+    };
+
+    let result = py.allow_threads(|| extract_variants_to_sink(file_path, &mut sink, &options));
+    if let Some(error) = sink.error {
+        return Err(error);
+    }
+    report_to_dict(py, &interruption.result(result)?)
+}
+
+/// Concatenates the batches in a directory into a single VCF file. Without a compression level,
+/// the file is bgzipped with the default level if its name ends in `.gz`.
+#[pyfunction]
+#[pyo3(name = "merge_batches", signature = (batch_dir, output_file, compression = None))]
+fn merge_batches_py(
+    py: Python,
+    batch_dir: PathBuf,
+    output_file: PathBuf,
+    compression: Option<String>,
+) -> PyResult<PyObject> {
+    let compression_level = compression_level_argument(compression)?;
+    let report = py.allow_threads(|| merge_into(&batch_dir, &output_file, compression_level))?;
+    merge_report_to_dict(py, &report)
+}
+
+fn merge_into(
+    batch_dir: &Path,
+    output_file: &Path,
+    compression_level: Option<Compression>,
+) -> Result<MergeReport, VcfBatcherError> {
+    match compression_level {
+        Some(level) => merge_batches_with_compression(batch_dir, output_file, Some(level)),
+        None => merge_batches(batch_dir, output_file),
+    }
+}
+
+/// Counts the header lines, records, samples and records per contig of a VCF file.
+#[pyfunction]
+#[pyo3(name = "stats")]
+fn stats_py(py: Python, file_path: PathBuf) -> PyResult<PyObject> {
+    let stats = py.allow_threads(|| vcf_stats(&file_path))?;
+    stats_to_dict(py, &stats)
+}
+
+/// Iterator over the lines of a VCF file, returned by [`read_lines_py`].
+#[pyclass(module = "vcf_batcher")]
+struct LineIterator {
+    path: PathBuf,
+    lines: ReaderLines,
+}
+
+#[pymethods]
+impl LineIterator {
+    fn __iter__(slf: PyRef<'_, Self>) -> PyRef<'_, Self> {
+        slf
+    }
+
+    fn __next__(&mut self) -> PyResult<Option<String>> {
+        match self.lines.next() {
+            Some(Ok(line)) => Ok(Some(line)),
+            Some(Err(source)) => Err(VcfBatcherError::Read {
+                path: self.path.clone(),
+                source,
+            }
+            .into()),
+            None => Ok(None),
+        }
+    }
+}
+
+/// Yields the lines of a plain or bgzipped VCF file without their line endings.
+#[pyfunction]
+#[pyo3(name = "read_lines")]
+fn read_lines_py(file_path: PathBuf) -> PyResult<LineIterator> {
+    let lines = read_lines(&file_path).map_err(|source| VcfBatcherError::Read {
+        path: file_path.clone(),
+        source,
+    })?;
+    Ok(LineIterator {
+        path: file_path,
+        lines,
+    })
+}
+
+/// Returns the sample names of a VCF file, reading only its header.
+#[pyfunction]
+fn list_samples(py: Python, file_path: PathBuf) -> PyResult<Vec<String>> {
+    Ok(py.allow_threads(|| read_sample_names(&file_path))?)
+}
+
+/// Returns the metadata of the header of a VCF file as a dict, reading only the header.
+#[pyfunction]
+#[pyo3(name = "read_header")]
+fn read_header_py(py: Python, file_path: PathBuf) -> PyResult<PyObject> {
+    let header = py.allow_threads(|| read_header(&file_path))?;
+    let fields_to_dict = |fields: &[FieldDefinition]| -> PyResult<PyObject> {
+        let dict = PyDict::new(py);
+        for field in fields {
+            let definition = PyDict::new(py);
+            definition.set_item("number", &field.number)?;
+            definition.set_item("type", &field.kind)?;
+            definition.set_item("description", &field.description)?;
+            dict.set_item(&field.id, definition)?;
+        }
+        Ok(dict.into())
+    };
+
+    let contigs = PyList::empty(py);
+    for contig in &header.contigs {
+        let dict = PyDict::new(py);
+        dict.set_item("name", &contig.name)?;
+        dict.set_item("length", contig.length)?;
+        contigs.append(dict)?;
+    }
+
+    let dict = PyDict::new(py);
+    dict.set_item("fileformat", &header.fileformat)?;
+    dict.set_item("contigs", contigs)?;
+    dict.set_item("info", fields_to_dict(&header.info)?)?;
+    dict.set_item("format", fields_to_dict(&header.format)?)?;
+    dict.set_item("samples", &header.samples)?;
+    Ok(dict.into())
+}
+
+/// Summarizes the batches in an output directory as a list of flat dicts, one per batch, so that
+/// `pandas.DataFrame(summarize(output_dir))` has one column per key.
+#[pyfunction]
+fn summarize(py: Python, output_dir: PathBuf) -> PyResult<PyObject> {
+    let summaries = py.allow_threads(|| summarize_batches(&output_dir))?;
+    let list = PyList::empty(py);
+    for summary in summaries {
+        let dict = PyDict::new(py);
+        dict.set_item("path", &summary.path)?;
+        dict.set_item("n_records", summary.records)?;
+        dict.set_item(
+            "chrom_start",
+            summary.first.as_ref().map(|locus| &locus.chrom),
+        )?;
+        dict.set_item("pos_start", summary.first.as_ref().map(|locus| locus.pos))?;
+        dict.set_item("chrom_end", summary.last.as_ref().map(|locus| &locus.chrom))?;
+        dict.set_item("pos_end", summary.last.as_ref().map(|locus| locus.pos))?;
+        dict.set_item("bytes", summary.bytes)?;
+        list.append(dict)?;
+    }
+    Ok(list.into())
+}
+
+/// Checks the structure of a VCF file and returns the issues that were found as a list of dicts.
+#[pyfunction]
+fn validate(py: Python, file_path: PathBuf) -> PyResult<PyObject> {
+    let issues = py.allow_threads(|| validate_vcf(&file_path))?;
+    let list = PyList::empty(py);
+    for issue in issues {
+        let dict = PyDict::new(py);
+        dict.set_item("severity", issue.severity.to_string())?;
+        dict.set_item("line", issue.line_number)?;
+        dict.set_item("message", issue.message)?;
+        list.append(dict)?;
+    }
+    Ok(list.into())
+}
+
+/// Splits VCF files into batches with options that are configured once.
+#[pyclass(
+    module = "vcf_batcher",
+    text_signature = "(batch_size=25000, compression_level=None, **options)"
+)]
+struct VcfBatcher {
+    options: BatchOptions,
+    on_progress: Option<PyObject>,
+    /// The arguments as they were passed from Python, for `__repr__`.
+    arguments: Vec<String>,
+}
+
+#[pymethods]
+impl VcfBatcher {
+    #[new]
+    #[pyo3(signature = (batch_size = 25000, compression_level = None, **options))]
+    fn new(
+        py: Python,
+        batch_size: usize,
+        compression_level: Option<String>,
+        options: Option<&PyDict>,
+    ) -> PyResult<Self> {
+        let mut arguments = vec![
+            format!("batch_size={}", batch_size),
+            format!(
+                "compression_level={}",
+                compression_level.to_object(py).as_ref(py).repr()?
+            ),
+        ];
+        for (key, value) in options.into_iter().flatten() {
+            arguments.push(format!("{}={}", key, value.repr()?));
+        }
+        Ok(VcfBatcher {
+            on_progress: progress_callable(options)?,
+            options: batch_options(batch_size, compression_level, options)?,
+            arguments,
+        })
+    }
+
+    /// Splits the input file into batches in the output directory and returns the report as a dict.
+    fn run(&self, py: Python, file_path: PathBuf, output_path: PathBuf) -> PyResult<PyObject> {
+        let file_path = utf8_path(&file_path)?;
+        // Every run has a cancel flag of its own, so that a cancelled run does not cancel the next one
+        let mut options = self.options.clone();
+        let interruption = Interruption::attach(&mut options, self.on_progress.clone());
+        let result = py.allow_threads(|| {
+            extract_variants_to_batches_with_options(file_path, &output_path, &options)
+        });
+        report_to_dict(py, &interruption.result(result)?)
+    }
+
+    /// Counts the header lines, records, samples and records per contig of a VCF file.
+    fn stats(&self, py: Python, file_path: PathBuf) -> PyResult<PyObject> {
+        let stats = py.allow_threads(|| vcf_stats(&file_path))?;
+        stats_to_dict(py, &stats)
+    }
+
+    /// Concatenates the batches in a directory into a single VCF file, compressed with the
+    /// configured compression level, or bgzipped with the default level if its name ends in `.gz`.
+    fn merge(&self, py: Python, batch_dir: PathBuf, output_file: PathBuf) -> PyResult<PyObject> {
+        let compression_level = self.options.compression_level;
+        let report =
+            py.allow_threads(|| merge_into(&batch_dir, &output_file, compression_level))?;
+        merge_report_to_dict(py, &report)
+    }
+
+    fn __repr__(&self) -> String {
+        format!("VcfBatcher({})", self.arguments.join(", "))
+    }
+}
+
+/// Context manager that splits lines written from Python into batches, see [`writer::BatchWriter`].
+/// Not thread-safe, because the writer may borrow its destination.
+#[pyclass(
+    module = "vcf_batcher",
+    unsendable,
+    text_signature = "(output_dir, batch_size=25000, compression=None, **options)"
+)]
+struct BatchWriter {
+    /// `None` once the writer was closed.
+    writer: Option<writer::BatchWriter<'static>>,
+    /// The report of the closed writer.
+    report: Option<RunReport>,
+}
+
+impl BatchWriter {
+    fn writer(&mut self) -> PyResult<&mut writer::BatchWriter<'static>> {
+        self.writer
+            .as_mut()
+            .ok_or_else(|| PyValueError::new_err("the BatchWriter is closed"))
+    }
+}
+
+#[pymethods]
+impl BatchWriter {
+    #[new]
+    #[pyo3(signature = (output_dir, batch_size = 25000, compression = None, **options))]
+    fn new(
+        output_dir: PathBuf,
+        batch_size: usize,
+        compression: Option<String>,
+        options: Option<&PyDict>,
+    ) -> PyResult<Self> {
+        if progress_callable(options)?.is_some() {
+            return Err(PyTypeError::new_err(
+                "unexpected keyword argument 'on_progress', the lines are written from Python",
+            ));
+        }
+        let options = batch_options(batch_size, compression, options)?;
+        Ok(BatchWriter {
+            writer: Some(writer::BatchWriter::create(output_dir, options)?),
+            report: None,
+        })
+    }
+
+    /// Adds a header line or a variant record. A trailing newline is removed.
+    fn write(&mut self, line: &str) -> PyResult<()> {
+        Ok(self.writer()?.write_line(line)?)
+    }
+
+    /// Adds every line of an iterable.
+    fn writelines(&mut self, lines: &PyAny) -> PyResult<()> {
+        let writer = self.writer()?;
+        for line in lines.iter()? {
+            writer.write_line(line?.extract()?)?;
+        }
+        Ok(())
+    }
+
+    /// Adds a variant record given as its columns, which are converted with `str()` and joined by tabs.
+    fn write_record(&mut self, columns: &PyAny) -> PyResult<()> {
+        let columns = columns
+            .iter()?
+            .map(|column| Ok(column?.str()?.to_str()?.to_owned()))
+            .collect::<PyResult<Vec<String>>>()?;
+        Ok(self.writer()?.write_line(&columns.join("\t"))?)
+    }
+
+    /// Writes the last batch and returns the report of the run as a dict.
+    /// Closing a closed writer returns the same report again.
+    fn close(&mut self, py: Python) -> PyResult<PyObject> {
+        if let Some(writer) = self.writer.take() {
+            self.report = Some(writer.finish()?);
+        }
+        match &self.report {
+            Some(report) => report_to_dict(py, report),
+            None => Err(PyValueError::new_err("the BatchWriter failed")),
+        }
+    }
+
+    /// The report of the batches that were completed so far.
+    #[getter]
+    fn report(&self, py: Python) -> PyResult<PyObject> {
+        match (&self.writer, &self.report) {
+            (Some(writer), _) => report_to_dict(py, writer.report()),
+            (None, Some(report)) => report_to_dict(py, report),
+            (None, None) => Err(PyValueError::new_err("the BatchWriter failed")),
+        }
+    }
+
+    fn __enter__(slf: PyRef<'_, Self>) -> PyRef<'_, Self> {
+        slf
+    }
+
+    /// Writes the last batch if the block completed normally. If it raised an exception, the
+    /// records after the last complete batch are discarded and the exception propagates.
+    fn __exit__(
+        &mut self,
+        py: Python,
+        exception_type: Option<&PyAny>,
+        _exception: Option<&PyAny>,
+        _traceback: Option<&PyAny>,
+    ) -> PyResult<bool> {
+        match exception_type {
+            None => {
+                self.close(py)?;
+            }
+            Some(_) => {
+                // Dropping the writer discards the incomplete batch and releases the output directory
+                if let Some(writer) = self.writer.take() {
+                    self.report = Some(writer.report().clone());
+                }
+            }
+        }
+        Ok(false)
+    }
+}
+
+/// Converts a [`Progress`] into the dict that is passed to `on_progress` callbacks.
+fn progress_to_dict(py: Python, progress: &Progress) -> PyResult<PyObject> {
+    let dict = PyDict::new(py);
+    dict.set_item("bytes_read", progress.bytes_read)?;
+    dict.set_item("total_bytes", progress.total_bytes)?;
+    dict.set_item("records", progress.records)?;
+    dict.set_item("batches", progress.batches)?;
+    dict.set_item("elapsed_seconds", progress.elapsed.as_secs_f64())?;
+    dict.set_item("records_per_second", progress.records_per_second())?;
+    dict.set_item("bytes_per_second", progress.bytes_per_second())?;
+    dict.set_item(
+        "remaining_seconds",
+        progress
+            .remaining()
+            .map(|remaining| remaining.as_secs_f64()),
+    )?;
+    Ok(dict.into())
+}
+
+/// Converts a [`RunReport`] into a dict, so that pipelines can register the written batches.
+fn report_to_dict(py: Python, report: &RunReport) -> PyResult<PyObject> {
+    let locus_to_dict = |locus: &Option<Locus>| -> PyResult<PyObject> {
+        match locus {
+            Some(locus) => {
+                let dict = PyDict::new(py);
+                dict.set_item("chrom", &locus.chrom)?;
+                dict.set_item("pos", locus.pos)?;
+                Ok(dict.into())
+            }
+            None => Ok(py.None()),
+        }
+    };
+
+    let batches = PyList::empty(py);
+    for batch in &report.batches {
+        let dict = PyDict::new(py);
+        dict.set_item("number", batch.number)?;
+        dict.set_item("path", &batch.path)?;
+        dict.set_item("records", batch.records)?;
+        dict.set_item("sha256", &batch.sha256)?;
+        dict.set_item("size", batch.size)?;
+        dict.set_item("first", locus_to_dict(&batch.first)?)?;
+        dict.set_item("last", locus_to_dict(&batch.last)?)?;
+        dict.set_item("verified", batch.verified)?;
+        batches.append(dict)?;
+    }
+
+    let dict = PyDict::new(py);
+    dict.set_item("output_path", &report.output_path)?;
+    dict.set_item("batches", batches)?;
+    dict.set_item("total_records", report.total_records())?;
+    dict.set_item("elapsed_seconds", report.elapsed.as_secs_f64())?;
+    dict.set_item("resumed_batches", report.resumed_batches)?;
+    dict.set_item("skipped_batches", report.skipped_batches)?;
+    dict.set_item("existing_batches", report.existing_batches)?;
+    dict.set_item("groups", report.groups.clone())?;
+    dict.set_item("filtered_records", report.filtered_records)?;
+    dict.set_item("bad_records", report.bad_records)?;
+    dict.set_item("duplicate_records", report.duplicate_records)?;
+    dict.set_item(
+        "empty_input",
+        report
+            .empty_input
+            .and_then(|policy| policy.to_possible_value())
+            .map(|value| value.get_name().to_string()),
+    )?;
+    dict.set_item("late_header_lines", report.late_header_lines)?;
+    dict.set_item("unknown_contigs", report.unknown_contigs.clone())?;
+    dict.set_item("duplicate_samples", &report.duplicate_samples)?;
+    let timings = match &report.timings {
+        Some(timings) => {
+            let timings_dict = PyDict::new(py);
+            timings_dict.set_item("reading_seconds", timings.reading.as_secs_f64())?;
+            timings_dict.set_item("filtering_seconds", timings.filtering.as_secs_f64())?;
+            timings_dict.set_item("compression_seconds", timings.compression.as_secs_f64())?;
+            timings_dict.set_item("writing_seconds", timings.writing.as_secs_f64())?;
+            Some(timings_dict)
+        }
+        None => None,
+    };
+    dict.set_item("timings", timings)?;
+    Ok(dict.into())
+}
+
+fn stats_to_dict(py: Python, stats: &VcfStats) -> PyResult<PyObject> {
+    let dict = PyDict::new(py);
+    dict.set_item("path", &stats.path)?;
+    dict.set_item("header_lines", stats.header_lines)?;
+    dict.set_item("records", stats.records)?;
+    dict.set_item("samples", &stats.samples)?;
+    dict.set_item("records_per_contig", stats.records_per_contig.clone())?;
+    Ok(dict.into())
+}
+
+fn merge_report_to_dict(py: Python, report: &MergeReport) -> PyResult<PyObject> {
+    let dict = PyDict::new(py);
+    dict.set_item("output", &report.output)?;
+    dict.set_item("batches", &report.batches)?;
+    dict.set_item("records", report.records)?;
+    Ok(dict.into())
+}
+
+#[pymodule]
+fn vcf_batcher(py: Python, m: &PyModule) -> PyResult<()> {
+    m.add(
+        "VcfBatcherError",
+        py.get_type::<exceptions::VcfBatcherError>(),
+    )?;
+    m.add_function(wrap_pyfunction!(py_extract_variants_to_batches, m)?)?;
+    m.add_function(wrap_pyfunction!(iter_batches, m)?)?;
+    m.add_function(wrap_pyfunction!(extract_to_callback, m)?)?;
+    m.add_function(wrap_pyfunction!(merge_batches_py, m)?)?;
+    m.add_function(wrap_pyfunction!(stats_py, m)?)?;
+    m.add_function(wrap_pyfunction!(read_lines_py, m)?)?;
+    m.add_function(wrap_pyfunction!(list_samples, m)?)?;
+    m.add_function(wrap_pyfunction!(read_header_py, m)?)?;
+    m.add_function(wrap_pyfunction!(summarize, m)?)?;
+    m.add_function(wrap_pyfunction!(validate, m)?)?;
+    m.add_class::<BatchIterator>()?;
+    m.add_class::<LineIterator>()?;
+    m.add_class::<VcfBatcher>()?;
+    m.add_class::<BatchWriter>()?;
+    Ok(())
+}
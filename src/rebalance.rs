@@ -0,0 +1,159 @@
+//! Rewriting a set of batches with a different batch size, without merging them into one file first.
+
+use std::fs;
+use std::path::Path;
+
+use crate::merge::batch_files;
+use crate::writer::BatchWriter;
+use crate::{is_header_line, read_lines, BatchOptions, RunReport, VcfBatcherError};
+
+/// Streams the records of the batches in `batch_dir`, which are found like
+/// [`crate::merge::merge_batches`] finds them, into new batches in `output_dir` as the options say,
+/// e.g. with another [`BatchOptions::batch_size`]. The header and the order of the records are kept.
+/// The header of every batch with records must be identical to the header of the first one.
+///
+/// The batches are streamed, so no merged file is written and only the new batch that is being
+/// collected is held in memory. `output_dir` must not be `batch_dir`, whose batches would be replaced
+/// while they are read.
+///
+/// # Examples
+///
+/// ```
+/// use std::path::Path;
+/// use vcf_batcher::rebalance::rebalance_batches;
+/// use vcf_batcher::{extract_variants_to_batches, BatchOptions};
+///
+/// let batch_dir = Path::new("test_data/doc_rebalance");
+/// extract_variants_to_batches("test_data/batch_01.vcf.gz", 30, batch_dir, None).unwrap();
+///
+/// let options = BatchOptions { batch_size: 45, quiet: true, ..Default::default() };
+/// let report = rebalance_batches(batch_dir, &batch_dir.join("rebalanced"), &options).unwrap();
+/// assert_eq!(report.batches.len(), 3);
+/// assert_eq!(report.batches[2].records, 10);
+/// ```
+pub fn rebalance_batches(
+    batch_dir: &Path,
+    output_dir: &Path,
+    options: &BatchOptions,
+) -> Result<RunReport, VcfBatcherError> {
+    if let (Ok(batch_dir), Ok(output)) = (fs::canonicalize(batch_dir), fs::canonicalize(output_dir))
+    {
+        if batch_dir == output {
+            return Err(VcfBatcherError::SameDirectory { path: output });
+        }
+    }
+    let batches = batch_files(batch_dir, output_dir)?;
+    if batches.is_empty() {
+        return Err(VcfBatcherError::NoBatches {
+            path: batch_dir.to_path_buf(),
+        });
+    }
+
+    let mut writer = BatchWriter::create(output_dir, options.clone())?;
+    let mut first_header: Option<Vec<String>> = None;
+    for batch in &batches {
+        let read_error = |source| VcfBatcherError::Read {
+            path: batch.clone(),
+            source,
+        };
+
+        let mut header = Vec::new();
+        let mut header_checked = false;
+        for line in read_lines(batch).map_err(read_error)? {
+            let line = line.map_err(read_error)?;
+            if is_header_line(&line) {
+                header.push(line);
+                continue;
+            }
+
+            if !header_checked {
+                match &first_header {
+                    Some(first_header) if *first_header != header => {
+                        return Err(VcfBatcherError::HeaderMismatch {
+                            path: batch.clone(),
+                        })
+                    }
+                    Some(_) => {}
+                    None => {
+                        for header_line in &header {
+                            writer.write_line(header_line)?;
+                        }
+                        first_header = Some(std::mem::take(&mut header));
+                    }
+                }
+                header_checked = true;
+            }
+            writer.write_line(&line)?;
+        }
+    }
+
+    // Batches without records (e.g. header-only output of an empty input) still provide the header
+    if first_header.is_none() {
+        let read_error = |source| VcfBatcherError::Read {
+            path: batches[0].clone(),
+            source,
+        };
+        for line in read_lines(&batches[0]).map_err(read_error)? {
+            let line = line.map_err(read_error)?;
+            if is_header_line(&line) {
+                writer.write_line(&line)?;
+            }
+        }
+    }
+    writer.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use bgzip::Compression;
+
+    use super::rebalance_batches;
+    use crate::merge::merge_batches;
+    use crate::testdata::SyntheticVcf;
+    use crate::{
+        extract_variants_to_batches_with_options, read_lines, BatchOptions, VcfBatcherError,
+    };
+
+    #[test]
+    fn test_rebalance_batches() {
+        let root = Path::new("./test_data/rebalance");
+        let _ = std::fs::remove_dir_all(root);
+        let input = root.join("input.vcf");
+        SyntheticVcf {
+            records: 250,
+            ..Default::default()
+        }
+        .write_file(&input, None)
+        .unwrap();
+        let options = BatchOptions {
+            batch_size: 40,
+            compression_level: Some(Compression::fast()),
+            quiet: true,
+            ..Default::default()
+        };
+        let batch_dir = root.join("batches");
+        extract_variants_to_batches_with_options(input.to_str().unwrap(), &batch_dir, &options)
+            .unwrap();
+
+        let options = BatchOptions {
+            batch_size: 100,
+            ..options
+        };
+        let rebalanced = root.join("rebalanced");
+        let report = rebalance_batches(&batch_dir, &rebalanced, &options).unwrap();
+        let records: Vec<usize> = report.batches.iter().map(|batch| batch.records).collect();
+        assert_eq!(records, [100, 100, 50]);
+
+        let merged = root.join("merged.vcf");
+        merge_batches(&rebalanced, &merged).unwrap();
+        let lines = |path: &Path| -> Vec<String> {
+            read_lines(path).unwrap().map(Result::unwrap).collect()
+        };
+        assert_eq!(lines(&merged), lines(&input));
+
+        let error = rebalance_batches(&batch_dir, &batch_dir, &options).unwrap_err();
+        assert!(matches!(error, VcfBatcherError::SameDirectory { .. }));
+    }
+}
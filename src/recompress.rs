@@ -0,0 +1,634 @@
+//! Changing the compression of existing VCF files without batching them again, e.g. to turn old
+//! gzipped batches into BGZF files that tabix can index.
+//!
+//! The codec of an input is detected from its first bytes, so plain, gzipped, bgzipped and zstd
+//! compressed files can all be recompressed. Outputs are written under a temporary name and only
+//! renamed once they are complete, so a file can be recompressed in place.
+
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+
+use bgzip::Compression;
+use clap::ValueEnum;
+use flate2::read::MultiGzDecoder;
+use flate2::write::GzEncoder;
+use serde::{Deserialize, Serialize};
+
+use crate::index::{tabix_index, tabix_path};
+use crate::manifest::{file_sha256, Manifest};
+use crate::merge::{batch_files, VcfWriter};
+use crate::{is_bgzf, move_file, VcfBatcherError, GZIP_MAGIC, MAX_COMPRESSION_LEVEL};
+
+/// The first four bytes of zstd frames.
+pub(crate) const ZSTD_MAGIC: &[u8] = &[0x28, 0xb5, 0x2f, 0xfd];
+
+/// How recompressed files are compressed.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Codec {
+    /// Blocked gzip, which tabix can index and which every gzip reader can read
+    #[default]
+    Bgzf,
+    /// A single gzip stream, which cannot be indexed
+    Gzip,
+    /// Zstandard, which compresses better and faster, but is not supported by htslib
+    Zstd,
+}
+
+/// A compression level on the scale of [`crate::parse_compression`], which every [`Codec`]
+/// maps to its own levels.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Level {
+    Fast,
+    Default,
+    Best,
+    /// A level from `0` to [`MAX_COMPRESSION_LEVEL`].
+    Number(u32),
+}
+
+impl Level {
+    /// Parses `none` (which is `None`, for plain files), `fast`, `default`, `best` or a number from
+    /// `0` to [`MAX_COMPRESSION_LEVEL`]. The names are case-insensitive.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use vcf_batcher::recompress::Level;
+    ///
+    /// assert_eq!(Level::parse("Best").unwrap(), Some(Level::Best));
+    /// assert_eq!(Level::parse("4").unwrap(), Some(Level::Number(4)));
+    /// assert_eq!(Level::parse("none").unwrap(), None);
+    /// assert!(Level::parse("13").is_err());
+    /// ```
+    pub fn parse(level: &str) -> Result<Option<Level>, VcfBatcherError> {
+        match level.trim().to_lowercase().as_str() {
+            "none" => Ok(None),
+            "fast" => Ok(Some(Level::Fast)),
+            "default" => Ok(Some(Level::Default)),
+            "best" => Ok(Some(Level::Best)),
+            number => match number.parse::<u32>() {
+                Ok(number) if number <= MAX_COMPRESSION_LEVEL => Ok(Some(Level::Number(number))),
+                _ => Err(VcfBatcherError::InvalidCompressionLevel {
+                    level: level.to_string(),
+                }),
+            },
+        }
+    }
+
+    /// The level of BGZF blocks.
+    pub fn bgzf(self) -> Compression {
+        match self {
+            Level::Fast => Compression::fast(),
+            Level::Default => Compression::default(),
+            Level::Best => Compression::best(),
+            Level::Number(number) => Compression::new(number).unwrap_or_default(),
+        }
+    }
+
+    fn gzip(self) -> flate2::Compression {
+        match self {
+            Level::Fast => flate2::Compression::fast(),
+            Level::Default => flate2::Compression::default(),
+            Level::Best => flate2::Compression::best(),
+            Level::Number(number) => flate2::Compression::new(number),
+        }
+    }
+
+    /// The level with the number of a BGZF level, e.g. `Number(9)` for [`Compression::best`].
+    pub(crate) fn of_bgzf(level: Compression) -> Self {
+        (0..=MAX_COMPRESSION_LEVEL)
+            .find(|&number| Compression::new(number).is_ok_and(|compression| compression == level))
+            .map_or(Level::Default, Level::Number)
+    }
+
+    /// zstd has levels up to 22, but those above 19 need a lot of memory to decompress.
+    #[cfg(not(target_arch = "wasm32"))]
+    fn zstd(self) -> i32 {
+        match self {
+            Level::Fast => 1,
+            Level::Default => zstd::DEFAULT_COMPRESSION_LEVEL,
+            Level::Best => 19,
+            Level::Number(number) => number as i32,
+        }
+    }
+}
+
+/// How files are recompressed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RecompressOptions {
+    pub codec: Codec,
+    /// The compression level, or `None` to write plain, uncompressed VCF files.
+    pub level: Option<Level>,
+    /// How many threads compress zstd frames. BGZF blocks are compressed on rayon's global thread
+    /// pool, like when batches are written, and gzip streams on the calling thread.
+    pub threads: usize,
+}
+
+impl Default for RecompressOptions {
+    fn default() -> Self {
+        RecompressOptions {
+            codec: Codec::Bgzf,
+            level: Some(Level::Default),
+            threads: 1,
+        }
+    }
+}
+
+impl RecompressOptions {
+    /// Compresses batches with zstd at `level`, on as many threads as rayon's global pool has, see
+    /// [`crate::CompressionFormat::Zstd`].
+    pub(crate) fn zstd_batches(level: Level) -> Self {
+        RecompressOptions {
+            codec: Codec::Zstd,
+            level: Some(level),
+            threads: rayon::current_num_threads(),
+        }
+    }
+
+    /// The extension of recompressed files: `.vcf.gz`, `.vcf.zst` or `.vcf` for plain files.
+    pub fn extension(&self) -> &'static str {
+        match (self.level, self.codec) {
+            (None, _) => ".vcf",
+            (Some(_), Codec::Bgzf | Codec::Gzip) => ".vcf.gz",
+            (Some(_), Codec::Zstd) => ".vcf.zst",
+        }
+    }
+}
+
+/// A recompressed file.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct RecompressedFile {
+    pub input: PathBuf,
+    pub output: PathBuf,
+    /// The size of the input in bytes.
+    pub input_bytes: u64,
+    /// The size of the output in bytes.
+    pub output_bytes: u64,
+}
+
+/// Decompresses `input` and writes its contents to `output`, compressed as the options say.
+/// `output` may be the same file as `input`. A tabix index next to either is built again for
+/// `output`, or removed if `output` is no longer bgzipped.
+///
+/// # Examples
+///
+/// ```
+/// use std::path::Path;
+/// use vcf_batcher::recompress::{recompress_file, Codec, Level, RecompressOptions};
+///
+/// let options = RecompressOptions { codec: Codec::Gzip, level: Some(Level::Best), ..Default::default() };
+/// let output = Path::new("test_data/doc_recompress/batch_01.vcf.gz");
+/// let file = recompress_file(Path::new("test_data/batch_01.vcf.gz"), output, &options).unwrap();
+/// assert!(file.output_bytes > 0);
+/// ```
+pub fn recompress_file(
+    input: &Path,
+    output: &Path,
+    options: &RecompressOptions,
+) -> Result<RecompressedFile, VcfBatcherError> {
+    let read_error = |source: io::Error| VcfBatcherError::Read {
+        path: input.to_path_buf(),
+        source,
+    };
+    let write_error = |source: io::Error| VcfBatcherError::Write {
+        path: output.to_path_buf(),
+        source: source.into(),
+    };
+
+    let input_bytes = fs::metadata(input).map_err(read_error)?.len();
+    let mut reader = decoder(File::open(input).map_err(read_error)?).map_err(read_error)?;
+
+    write_replacing(output, |file| {
+        let mut writer = Encoder::new(file, options).map_err(write_error)?;
+        copy(&mut reader, &mut writer, read_error, write_error)?;
+        writer.finish().map_err(write_error)
+    })?;
+    update_tabix_index(input, output)?;
+
+    Ok(RecompressedFile {
+        input: input.to_path_buf(),
+        output: output.to_path_buf(),
+        input_bytes,
+        output_bytes: fs::metadata(output).map_err(write_error)?.len(),
+    })
+}
+
+/// Recompresses the batches in `batch_dir`, which are found like [`crate::merge::merge_batches`]
+/// finds them, into `output_dir`. The files keep their names, with the extension of the new codec,
+/// e.g. `batch_01.vcf.gz` becomes `batch_01.vcf.zst`. If `output_dir` is `batch_dir`, files whose
+/// name changes are removed once they were recompressed.
+///
+/// If the batches have a manifest, it is written to `output_dir` with the new names and checksums.
+/// The recompressed batches are no longer marked as verified.
+///
+/// # Examples
+///
+/// ```
+/// use std::path::Path;
+/// use vcf_batcher::extract_variants_to_batches;
+/// use vcf_batcher::recompress::{recompress_dir, RecompressOptions};
+///
+/// let batch_dir = Path::new("test_data/doc_recompress_dir");
+/// extract_variants_to_batches("test_data/batch_01.vcf.gz", 30, batch_dir, None).unwrap();
+///
+/// let options = RecompressOptions { level: None, ..Default::default() };
+/// let files = recompress_dir(batch_dir, Path::new("test_data/doc_recompress_dir/plain"), &options).unwrap();
+/// assert_eq!(files.len(), 4);
+/// assert!(files[0].output.ends_with("batch_01.vcf"));
+/// ```
+pub fn recompress_dir(
+    batch_dir: &Path,
+    output_dir: &Path,
+    options: &RecompressOptions,
+) -> Result<Vec<RecompressedFile>, VcfBatcherError> {
+    let batches = batch_files(batch_dir, output_dir)?;
+    if batches.is_empty() {
+        return Err(VcfBatcherError::NoBatches {
+            path: batch_dir.to_path_buf(),
+        });
+    }
+    let read_error = |source: io::Error| VcfBatcherError::Read {
+        path: batch_dir.to_path_buf(),
+        source,
+    };
+    let manifest = Manifest::read(batch_dir).map_err(read_error)?;
+    let in_place = fs::canonicalize(batch_dir).ok() == fs::canonicalize(output_dir).ok();
+
+    let mut files = Vec::new();
+    for batch in batches {
+        let name = batch.file_name().unwrap_or_default().to_string_lossy();
+        let output = output_dir.join(recompressed_name(&name, options));
+        let file = recompress_file(&batch, &output, options)?;
+        if in_place && output != batch {
+            let index_path = tabix_path(&batch);
+            let removed = [
+                Some(batch.clone()),
+                index_path.is_file().then_some(index_path),
+            ];
+            for path in removed.into_iter().flatten() {
+                fs::remove_file(&path).map_err(|source| VcfBatcherError::Write {
+                    path: path.clone(),
+                    source: source.into(),
+                })?;
+            }
+        }
+        files.push(file);
+    }
+
+    if let Some(mut manifest) = manifest {
+        let write_error = |source: io::Error| VcfBatcherError::Write {
+            path: output_dir.to_path_buf(),
+            source: source.into(),
+        };
+        for (entry, file) in manifest.batches.iter_mut().zip(&files) {
+            entry.file = file
+                .output
+                .file_name()
+                .unwrap_or_default()
+                .to_string_lossy()
+                .into_owned();
+            entry.sha256 = file_sha256(&file.output).map_err(write_error)?;
+            entry.verified = false;
+        }
+        manifest.write(output_dir).map_err(write_error)?;
+    }
+    Ok(files)
+}
+
+/// The name of a recompressed file, e.g. `batch_01.vcf.zst` for `batch_01.vcf.gz`.
+fn recompressed_name(name: &str, options: &RecompressOptions) -> String {
+    let stem = name
+        .strip_suffix(".gz")
+        .or_else(|| name.strip_suffix(".zst"))
+        .unwrap_or(name);
+    let stem = stem.strip_suffix(".vcf").unwrap_or(stem);
+    format!("{stem}{}", options.extension())
+}
+
+/// Writes `output` under a temporary name next to it and only moves it into place once `write`
+/// succeeded and the file was synced, so that `output` may be the file that is read.
+pub(crate) fn write_replacing(
+    output: &Path,
+    write: impl FnOnce(&mut File) -> Result<(), VcfBatcherError>,
+) -> Result<(), VcfBatcherError> {
+    let write_error = |source: io::Error| VcfBatcherError::Write {
+        path: output.to_path_buf(),
+        source: source.into(),
+    };
+    let parent = output
+        .parent()
+        .filter(|parent| !parent.as_os_str().is_empty());
+    parent
+        .map_or(Ok(()), fs::create_dir_all)
+        .map_err(write_error)?;
+    let file_name = output.file_name().unwrap_or_default().to_string_lossy();
+    let temporary_path = output.with_file_name(format!(".{}.tmp", file_name));
+
+    let result = File::create(&temporary_path)
+        .map_err(write_error)
+        .and_then(|mut file| {
+            write(&mut file)?;
+            file.sync_all().map_err(write_error)
+        })
+        .and_then(|_| move_file(&temporary_path, output).map_err(write_error));
+    if result.is_err() {
+        let _ = fs::remove_file(&temporary_path);
+    }
+    result
+}
+
+/// Builds the tabix index of `output` again after it was rewritten from `input`, since its records
+/// no longer start at the virtual offsets of the old index. The index is only written if `input` or
+/// `output` had one, and removed instead if `output` is no longer bgzipped.
+pub(crate) fn update_tabix_index(input: &Path, output: &Path) -> Result<(), VcfBatcherError> {
+    let index_path = tabix_path(output);
+    if !tabix_path(input).is_file() && !index_path.is_file() {
+        return Ok(());
+    }
+    let read_error = |source: io::Error| VcfBatcherError::Read {
+        path: output.to_path_buf(),
+        source,
+    };
+    let write_error = |source: io::Error| VcfBatcherError::Write {
+        path: index_path.clone(),
+        source: source.into(),
+    };
+
+    let mut start = Vec::new();
+    File::open(output)
+        .and_then(|file| file.take(18).read_to_end(&mut start))
+        .map_err(read_error)?;
+    if !is_bgzf(&start) {
+        return match fs::remove_file(&index_path) {
+            Err(error) if error.kind() != io::ErrorKind::NotFound => Err(write_error(error)),
+            _ => Ok(()),
+        };
+    }
+    let index = tabix_index(output).map_err(read_error)?;
+    write_replacing(&index_path, |file| {
+        file.write_all(&index).map_err(write_error)
+    })
+}
+
+/// Copies all bytes, telling errors of the reader and the writer apart.
+pub(crate) fn copy(
+    reader: &mut impl Read,
+    writer: &mut impl Write,
+    read_error: impl Fn(io::Error) -> VcfBatcherError,
+    write_error: impl Fn(io::Error) -> VcfBatcherError,
+) -> Result<(), VcfBatcherError> {
+    let mut buffer = vec![0; 64 * 1024];
+    loop {
+        let length = match reader.read(&mut buffer) {
+            Ok(0) => return Ok(()),
+            Ok(length) => length,
+            Err(error) if error.kind() == io::ErrorKind::Interrupted => continue,
+            Err(error) => return Err(read_error(error)),
+        };
+        writer.write_all(&buffer[..length]).map_err(&write_error)?;
+    }
+}
+
+/// A reader of the decompressed contents of a plain, gzipped, bgzipped or zstd compressed file.
+pub(crate) fn decoder<R: Read + 'static>(reader: R) -> io::Result<Box<dyn Read>> {
+    let mut reader = BufReader::new(reader);
+    let start = reader.fill_buf()?;
+    if start.starts_with(GZIP_MAGIC) {
+        // BGZF files are a series of gzip members
+        return Ok(Box::new(MultiGzDecoder::new(reader)));
+    }
+    if start.starts_with(ZSTD_MAGIC) {
+        #[cfg(not(target_arch = "wasm32"))]
+        return Ok(Box::new(zstd::Decoder::with_buffer(reader)?));
+        #[cfg(target_arch = "wasm32")]
+        return Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "zstd compressed files cannot be read in WebAssembly",
+        ));
+    }
+    Ok(Box::new(reader))
+}
+
+/// Writer for a recompressed file.
+pub(crate) enum Encoder<W: Write> {
+    /// A plain or bgzipped file.
+    Vcf(VcfWriter<W>),
+    Gzip(GzEncoder<BufWriter<W>>),
+    #[cfg(not(target_arch = "wasm32"))]
+    Zstd(zstd::Encoder<'static, BufWriter<W>>),
+}
+
+impl<W: Write> Encoder<W> {
+    pub(crate) fn new(writer: W, options: &RecompressOptions) -> io::Result<Self> {
+        let Some(level) = options.level else {
+            return Ok(Encoder::Vcf(VcfWriter::new(writer, None)));
+        };
+        match options.codec {
+            Codec::Bgzf => Ok(Encoder::Vcf(VcfWriter::new(writer, Some(level.bgzf())))),
+            Codec::Gzip => Ok(Encoder::Gzip(GzEncoder::new(
+                BufWriter::new(writer),
+                level.gzip(),
+            ))),
+            #[cfg(not(target_arch = "wasm32"))]
+            Codec::Zstd => {
+                let mut encoder = zstd::Encoder::new(BufWriter::new(writer), level.zstd())?;
+                if options.threads > 1 {
+                    encoder.multithread(options.threads as u32)?;
+                }
+                Ok(Encoder::Zstd(encoder))
+            }
+            #[cfg(target_arch = "wasm32")]
+            Codec::Zstd => Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "zstd compressed files cannot be written in WebAssembly",
+            )),
+        }
+    }
+
+    /// Flushes all buffered data and ends the compressed stream.
+    pub(crate) fn finish(self) -> io::Result<()> {
+        match self {
+            Encoder::Vcf(writer) => writer.finish(),
+            Encoder::Gzip(encoder) => encoder.finish()?.flush(),
+            #[cfg(not(target_arch = "wasm32"))]
+            Encoder::Zstd(encoder) => encoder.finish()?.flush(),
+        }
+    }
+}
+
+impl<W: Write> Write for Encoder<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Encoder::Vcf(writer) => writer.write(buf),
+            Encoder::Gzip(encoder) => encoder.write(buf),
+            #[cfg(not(target_arch = "wasm32"))]
+            Encoder::Zstd(encoder) => encoder.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Encoder::Vcf(writer) => writer.flush(),
+            Encoder::Gzip(encoder) => encoder.flush(),
+            #[cfg(not(target_arch = "wasm32"))]
+            Encoder::Zstd(encoder) => encoder.flush(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::path::Path;
+
+    use bgzip::Compression;
+
+    use super::{
+        recompress_dir, recompress_file, recompressed_name, Codec, Level, RecompressOptions,
+    };
+    use crate::config::Config;
+    use crate::manifest::{file_sha256, Manifest};
+    use crate::testdata::SyntheticVcf;
+    use crate::{
+        extract_variants_to_batches_with_options, is_bgzf, read_lines, BatchOptions,
+        CompressionFormat, IndexFormat,
+    };
+
+    #[test]
+    fn test_recompress_file() {
+        let root = Path::new("./test_data/recompress_file");
+        let _ = fs::remove_dir_all(root);
+        let input = root.join("input.vcf");
+        SyntheticVcf {
+            records: 500,
+            ..Default::default()
+        }
+        .write_file(&input, None)
+        .unwrap();
+        let contents = fs::read_to_string(&input).unwrap();
+
+        // Old gzipped files are upgraded to BGZF in place
+        let gzip = root.join("input.vcf.gz");
+        let options = RecompressOptions {
+            codec: Codec::Gzip,
+            ..Default::default()
+        };
+        recompress_file(&input, &gzip, &options).unwrap();
+        assert!(!is_bgzf(&fs::read(&gzip).unwrap()));
+        recompress_file(&gzip, &gzip, &RecompressOptions::default()).unwrap();
+        assert!(is_bgzf(&fs::read(&gzip).unwrap()));
+        let lines: Vec<String> = read_lines(&gzip).unwrap().map(Result::unwrap).collect();
+        assert_eq!(lines.len(), contents.lines().count());
+
+        let zstd = root.join("input.vcf.zst");
+        let options = RecompressOptions {
+            codec: Codec::Zstd,
+            level: Some(Level::Best),
+            threads: 2,
+        };
+        let file = recompress_file(&gzip, &zstd, &options).unwrap();
+        assert!(file.output_bytes < file.input_bytes);
+        assert!(fs::read(&zstd)
+            .unwrap()
+            .starts_with(&[0x28, 0xb5, 0x2f, 0xfd]));
+        let plain = root.join("plain.vcf");
+        let options = RecompressOptions {
+            level: None,
+            ..Default::default()
+        };
+        recompress_file(&zstd, &plain, &options).unwrap();
+        assert_eq!(fs::read_to_string(&plain).unwrap(), contents);
+    }
+
+    #[test]
+    fn test_recompress_dir() {
+        let batch_dir = Path::new("./test_data/recompress_dir");
+        let _ = fs::remove_dir_all(batch_dir);
+        let options = BatchOptions {
+            batch_size: 30,
+            compression_level: Some(Compression::fast()),
+            index: Some(IndexFormat::Tabix),
+            quiet: true,
+            ..Default::default()
+        };
+        let report = extract_variants_to_batches_with_options(
+            "./test_data/batch_01.vcf.gz",
+            batch_dir,
+            &options,
+        )
+        .unwrap();
+        Manifest::from_report(&report, true)
+            .write(batch_dir)
+            .unwrap();
+
+        // An index cannot point into a file that is no longer bgzipped
+        let batch = batch_dir.join("batch_01.vcf.gz");
+        let options = RecompressOptions {
+            codec: Codec::Gzip,
+            ..Default::default()
+        };
+        recompress_file(&batch, &batch, &options).unwrap();
+        assert!(!batch_dir.join("batch_01.vcf.gz.tbi").exists());
+
+        let options = RecompressOptions {
+            codec: Codec::Zstd,
+            ..Default::default()
+        };
+        let files = recompress_dir(batch_dir, batch_dir, &options).unwrap();
+        assert_eq!(files.len(), 4);
+        assert!(!batch_dir.join("batch_01.vcf.gz").exists());
+        // The indexes of the replaced batches are removed with them
+        assert!(!batch_dir.join("batch_02.vcf.gz.tbi").exists());
+        let manifest = Manifest::read(batch_dir).unwrap().unwrap();
+        assert_eq!(manifest.batches[3].file, "batch_04.vcf.zst");
+        assert_eq!(
+            manifest.batches[3].sha256,
+            file_sha256(&batch_dir.join("batch_04.vcf.zst")).unwrap()
+        );
+        assert_eq!(manifest.batches[3].records, 10);
+    }
+
+    #[test]
+    fn test_zstd_levels_of_batches() {
+        // A level is the same zstd level whether batches are split into zstd files or recompressed
+        for name in ["fast", "default", "best", "4"] {
+            let config = Config {
+                compression_level: Some(name.to_string()),
+                compression_format: Some(CompressionFormat::Zstd),
+                ..Default::default()
+            };
+            let batches = config.to_options().unwrap().zstd_options().unwrap();
+            let level = Level::parse(name).unwrap();
+            assert_eq!(batches.level.map(Level::zstd), level.map(Level::zstd));
+        }
+        let options = BatchOptions {
+            compression_level: Some(Compression::best()),
+            compression_format: CompressionFormat::Zstd,
+            zstd_level: Level::parse("best").unwrap(),
+            ..Default::default()
+        };
+        assert_eq!(
+            options.zstd_options().unwrap().level.map(Level::zstd),
+            Some(19)
+        );
+    }
+
+    #[test]
+    fn test_recompressed_name() {
+        let plain = RecompressOptions {
+            level: None,
+            ..Default::default()
+        };
+        assert_eq!(recompressed_name("batch_01.vcf.gz", &plain), "batch_01.vcf");
+        assert_eq!(
+            recompressed_name("batch_01.vcf", &RecompressOptions::default()),
+            "batch_01.vcf.gz"
+        );
+        let zstd = RecompressOptions {
+            codec: Codec::Zstd,
+            ..Default::default()
+        };
+        assert_eq!(recompressed_name("part.vcf.zst", &zstd), "part.vcf.zst");
+    }
+}
@@ -0,0 +1,298 @@
+//! Splitting records into one batch per interval of a BED file, e.g. to shard a whole-genome VCF file by the
+//! capture regions of an exome kit, see [`crate::SplitMode::Region`].
+
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+
+use crate::{read_lines, AppendLine, VcfBatcherError};
+
+/// How many digits the positions in the names of region batches are zero-padded to, as for `{region}`.
+const POSITION_WIDTH: usize = 9;
+
+/// An interval of a BED file, with the 0-based, half-open coordinates of BED.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct BedRegion {
+    pub chrom: String,
+    pub start: u64,
+    pub end: u64,
+    /// The name column, if the line has one.
+    pub name: Option<String>,
+}
+
+impl BedRegion {
+    /// Whether a record at the given 1-based POS lies in the interval.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use vcf_batcher::regions::BedRegion;
+    ///
+    /// let region = BedRegion { chrom: "chr1".to_string(), start: 99, end: 200, name: None };
+    /// assert!(region.contains("chr1", 100));
+    /// assert!(!region.contains("chr1", 99));
+    /// assert!(region.contains("chr1", 200));
+    /// ```
+    pub fn contains(&self, chrom: &str, pos: u64) -> bool {
+        self.chrom == chrom && pos > self.start && pos <= self.end
+    }
+
+    /// The interval with the 1-based, inclusive positions of VCF, zero-padded so that the names sort in
+    /// genomic order, e.g. `chr1_000000100-000000200` for the BED interval `chr1 99 200`.
+    pub fn span(&self) -> String {
+        format!(
+            "{}_{:0width$}-{:0width$}",
+            self.chrom,
+            self.start + 1,
+            self.end,
+            width = POSITION_WIDTH
+        )
+    }
+}
+
+/// Reads the intervals of a BED file, which may be gzipped. `track` and `browser` lines, comments and blank
+/// lines are skipped, and intervals that appear more than once are only kept once.
+pub fn read_bed(path: &Path) -> Result<Vec<BedRegion>, VcfBatcherError> {
+    let read_error = |source| VcfBatcherError::Read {
+        path: path.to_path_buf(),
+        source,
+    };
+    let mut regions = Vec::new();
+    let mut seen = HashSet::new();
+    for (index, line) in read_lines(path).map_err(read_error)?.enumerate() {
+        let line = line.map_err(read_error)?;
+        let line = line.trim_end();
+        if line.is_empty()
+            || line.starts_with('#')
+            || line.starts_with("track")
+            || line.starts_with("browser")
+        {
+            continue;
+        }
+        let region = parse_bed_line(line).map_err(|reason| VcfBatcherError::InvalidRegion {
+            region: line.to_string(),
+            reason: format!("line {} of {}: {reason}", index + 1, path.display()),
+        })?;
+        if seen.insert(region.clone()) {
+            regions.push(region);
+        }
+    }
+    Ok(regions)
+}
+
+fn parse_bed_line(line: &str) -> Result<BedRegion, &'static str> {
+    let mut columns = line.split('\t');
+    let (Some(chrom), Some(start), Some(end)) = (columns.next(), columns.next(), columns.next())
+    else {
+        return Err("a BED line needs the chrom, start and end columns, separated by tabs");
+    };
+    let position = |value: &str| {
+        value
+            .trim()
+            .parse::<u64>()
+            .map_err(|_| "positions must be whole numbers")
+    };
+    let (start, end) = (position(start)?, position(end)?);
+    if chrom.is_empty() {
+        return Err("the contig name is missing");
+    }
+    if end <= start {
+        return Err("the interval is empty, its end must lie after its start");
+    }
+    Ok(BedRegion {
+        chrom: chrom.to_string(),
+        start,
+        end,
+        name: columns
+            .next()
+            .map(str::trim)
+            .filter(|name| !name.is_empty() && *name != ".")
+            .map(str::to_string),
+    })
+}
+
+/// The records of one interval.
+#[derive(Debug)]
+pub(crate) struct RegionBatch {
+    /// What the batch is named after: the name of the interval, or its span if the names do not tell the
+    /// intervals apart.
+    pub(crate) name: String,
+    /// The records, each followed by a line ending.
+    pub(crate) records: String,
+    pub(crate) record_count: usize,
+    end: u64,
+}
+
+/// Collects the records of every interval while the records of a chromosome pass by in the order of their
+/// positions. An interval is opened with its first record and complete once a record lies after its end.
+#[derive(Debug)]
+pub(crate) struct RegionBatches {
+    regions: Vec<BedRegion>,
+    /// The indices of the intervals of every chromosome, by start.
+    by_chrom: HashMap<String, Vec<usize>>,
+    /// Whether the batches are named after the name column, which is only the case if every interval
+    /// has a name of its own.
+    named: bool,
+    chrom: String,
+    /// How many intervals of the chromosome were opened or skipped.
+    next: usize,
+    open: Vec<RegionBatch>,
+}
+
+impl RegionBatches {
+    pub(crate) fn new(regions: &[BedRegion]) -> Self {
+        let mut by_chrom: HashMap<String, Vec<usize>> = HashMap::new();
+        for (index, region) in regions.iter().enumerate() {
+            by_chrom
+                .entry(region.chrom.clone())
+                .or_default()
+                .push(index);
+        }
+        for indices in by_chrom.values_mut() {
+            indices.sort_by_key(|&index| (regions[index].start, regions[index].end));
+        }
+        let mut names = HashSet::new();
+        let named = regions.iter().all(|region| {
+            region
+                .name
+                .as_deref()
+                .is_some_and(|name| names.insert(name))
+        });
+        RegionBatches {
+            regions: regions.to_vec(),
+            by_chrom,
+            named,
+            chrom: String::new(),
+            next: 0,
+            open: Vec::new(),
+        }
+    }
+
+    /// Adds a record to every interval that it lies in and moves the batches that the record lies after
+    /// into `completed`. Returns whether the record lies in an interval.
+    pub(crate) fn add(
+        &mut self,
+        chrom: &str,
+        pos: u64,
+        record: &str,
+        completed: &mut Vec<RegionBatch>,
+    ) -> bool {
+        if chrom != self.chrom {
+            completed.append(&mut self.open);
+            self.chrom = chrom.to_string();
+            self.next = 0;
+        }
+        let (done, open) = std::mem::take(&mut self.open)
+            .into_iter()
+            .partition(|batch| batch.end < pos);
+        self.open = open;
+        completed.extend::<Vec<_>>(done);
+
+        let indices = self.by_chrom.get(chrom).map_or(&[][..], Vec::as_slice);
+        while let Some(&index) = indices.get(self.next) {
+            let region = &self.regions[index];
+            if region.start >= pos {
+                break;
+            }
+            self.next += 1;
+            // Intervals without records get no batch
+            if region.end >= pos {
+                self.open.push(RegionBatch {
+                    name: match (&region.name, self.named) {
+                        (Some(name), true) => name.clone(),
+                        _ => region.span(),
+                    },
+                    records: String::new(),
+                    record_count: 0,
+                    end: region.end,
+                });
+            }
+        }
+        for batch in &mut self.open {
+            batch.records.append_line(record);
+            batch.record_count += 1;
+        }
+        !self.open.is_empty()
+    }
+
+    /// Removes and returns the batches of the intervals that are still open, e.g. at the end of the input.
+    pub(crate) fn take(&mut self) -> Vec<RegionBatch> {
+        std::mem::take(&mut self.open)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::{read_bed, BedRegion, RegionBatches};
+    use crate::VcfBatcherError;
+
+    fn region(chrom: &str, start: u64, end: u64, name: Option<&str>) -> BedRegion {
+        BedRegion {
+            chrom: chrom.to_string(),
+            start,
+            end,
+            name: name.map(str::to_string),
+        }
+    }
+
+    #[test]
+    fn test_read_bed() {
+        let input_dir = Path::new("./test_data/inputs");
+        std::fs::create_dir_all(input_dir).unwrap();
+        let path = input_dir.join("regions.bed");
+        std::fs::write(
+            &path,
+            "track name=capture\n# exons\nchr1\t10\t20\tBRCA1_1\t0\t+\nchr1\t10\t20\tBRCA1_1\t0\t+\n\nchr2\t0\t5\n",
+        )
+        .unwrap();
+        assert_eq!(
+            read_bed(&path).unwrap(),
+            [
+                region("chr1", 10, 20, Some("BRCA1_1")),
+                region("chr2", 0, 5, None)
+            ]
+        );
+
+        std::fs::write(&path, "chr1\t20\t10\n").unwrap();
+        assert!(matches!(
+            read_bed(&path),
+            Err(VcfBatcherError::InvalidRegion { .. })
+        ));
+    }
+
+    #[test]
+    fn test_region_batches() {
+        let regions = [
+            region("chr1", 100, 200, None),
+            region("chr1", 0, 10, None),
+            region("chr1", 150, 300, None),
+            region("chr1", 20, 30, None),
+            region("chr2", 0, 50, Some("a")),
+        ];
+        let mut batches = RegionBatches::new(&regions);
+        let mut completed = Vec::new();
+        let mut add =
+            |chrom: &str, pos: u64| batches.add(chrom, pos, &pos.to_string(), &mut completed);
+        assert!(add("chr1", 5));
+        assert!(!add("chr1", 50));
+        assert!(add("chr1", 160));
+        assert!(add("chr1", 250));
+        assert!(add("chr2", 1));
+        let records: Vec<(&str, &str)> = completed
+            .iter()
+            .map(|batch| (batch.name.as_str(), batch.records.as_str()))
+            .collect();
+        assert_eq!(
+            records,
+            [
+                ("chr1_000000001-000000010", "5\n"),
+                ("chr1_000000101-000000200", "160\n"),
+                ("chr1_000000151-000000300", "160\n250\n"),
+            ]
+        );
+        // The names do not tell all intervals apart
+        let open = batches.take();
+        assert_eq!(open[0].name, "chr2_000000001-000000050");
+    }
+}
@@ -0,0 +1,515 @@
+//! Replacing or patching the header of existing VCF files, e.g. to fix a `##contig` line without
+//! batching the input again.
+//!
+//! Bgzipped files are rewritten block by block: only the blocks that contain the header are
+//! decompressed and compressed again, the blocks of the records are copied as they are. Plain files
+//! are copied behind the new header, gzipped and zstd compressed files are recompressed as a whole.
+
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+
+use bgzip::deflate::Decompress;
+use bgzip::read::{decompress_block, load_block};
+use bgzip::{BGZFWriter, Compression, EOF_MARKER};
+use serde::Serialize;
+
+use crate::header::StructuredLine;
+use crate::manifest::{file_sha256, Manifest};
+use crate::merge::batch_files;
+use crate::recompress::{
+    copy, decoder, update_tabix_index, write_replacing, Codec, Encoder, Level, RecompressOptions,
+    ZSTD_MAGIC,
+};
+use crate::{is_bgzf, is_header_line, read_lines, VcfBatcherError, GZIP_MAGIC};
+
+/// How the header of a file is changed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum HeaderEdit {
+    /// Replace the whole header. Its last line must be a `#CHROM` line with as many columns as the
+    /// one it replaces, since the records are not changed; the sample names may differ.
+    Replace(Vec<String>),
+    /// Replace or add meta-information lines, see [`patch_header`].
+    Patch(Vec<String>),
+}
+
+impl HeaderEdit {
+    /// Reads a new header, or with `patch` the `##` lines of a patch, from a file. Blank lines are ignored.
+    pub fn read(path: &Path, patch: bool) -> Result<HeaderEdit, VcfBatcherError> {
+        let invalid = |reason: &str| VcfBatcherError::InvalidHeader {
+            path: path.to_path_buf(),
+            reason: reason.to_string(),
+        };
+        let read_error = |source| VcfBatcherError::Read {
+            path: path.to_path_buf(),
+            source,
+        };
+
+        let mut lines = Vec::new();
+        for line in read_lines(path).map_err(read_error)? {
+            let line = line.map_err(read_error)?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            if !is_header_line(&line) {
+                return Err(invalid(&format!("'{}' is not a header line", line)));
+            }
+            if patch && !line.starts_with("##") {
+                return Err(invalid(
+                    "a patch can only contain meta-information lines starting with ##",
+                ));
+            }
+            lines.push(line);
+        }
+
+        if patch {
+            return Ok(HeaderEdit::Patch(lines));
+        }
+        let chrom_lines = lines
+            .iter()
+            .filter(|line| line.starts_with("#CHROM"))
+            .count();
+        if chrom_lines != 1 || !lines.last().is_some_and(|line| line.starts_with("#CHROM")) {
+            return Err(invalid("the header must end with its only #CHROM line"));
+        }
+        Ok(HeaderEdit::Replace(lines))
+    }
+
+    /// Applies the edit to the lines of a header. Returns why it cannot be applied otherwise.
+    pub fn apply(&self, header: &[String]) -> Result<Vec<String>, String> {
+        match self {
+            HeaderEdit::Replace(lines) => {
+                let columns = |lines: &[String]| {
+                    lines
+                        .iter()
+                        .find(|line| line.starts_with("#CHROM"))
+                        .map(|line| line.split('\t').count())
+                };
+                match (columns(header), columns(lines)) {
+                    (Some(old), Some(new)) if old != new => Err(format!(
+                        "the new #CHROM line has {} columns, but the records have {}",
+                        new, old
+                    )),
+                    _ => Ok(lines.clone()),
+                }
+            }
+            HeaderEdit::Patch(lines) => Ok(patch_header(header, lines)),
+        }
+    }
+}
+
+/// Replaces the lines of a header that a patch line has the same key and, for structured lines such
+/// as `##contig=<ID=chr1,...>`, the same ID as. Patch lines that replace no line are added after the
+/// last line with their key or, if there is none, before the `#CHROM` line.
+///
+/// # Examples
+///
+/// ```
+/// use vcf_batcher::reheader::patch_header;
+///
+/// let header = [
+///     "##fileformat=VCFv4.2",
+///     "##contig=<ID=chr1,length=1000>",
+///     "##contig=<ID=chr2,length=2000>",
+///     "#CHROM\tPOS\tID\tREF\tALT\tQUAL\tFILTER\tINFO",
+/// ]
+/// .map(String::from);
+/// let patch = ["##contig=<ID=chr1,length=248956422>", "##contig=<ID=chrX,length=156040895>"].map(String::from);
+///
+/// let patched = patch_header(&header, &patch);
+/// assert_eq!(patched[1], "##contig=<ID=chr1,length=248956422>");
+/// assert_eq!(patched[3], "##contig=<ID=chrX,length=156040895>");
+/// assert_eq!(patched.len(), 5);
+/// ```
+pub fn patch_header(header: &[String], patch: &[String]) -> Vec<String> {
+    let mut lines = header.to_vec();
+    for patch_line in patch {
+        let key = line_key(patch_line);
+        if let Some(line) = lines.iter_mut().find(|line| line_key(line) == key) {
+            *line = patch_line.clone();
+            continue;
+        }
+        let position = match lines
+            .iter()
+            .rposition(|line| line_key(line).0 == key.0 && line.starts_with("##"))
+        {
+            Some(last) => last + 1,
+            None => lines
+                .iter()
+                .position(|line| line.starts_with("#CHROM"))
+                .unwrap_or(lines.len()),
+        };
+        lines.insert(position, patch_line.clone());
+    }
+    lines
+}
+
+/// The key and, for structured lines, the ID of a meta-information line.
+fn line_key(line: &str) -> (&str, Option<String>) {
+    let key = line
+        .strip_prefix("##")
+        .and_then(|line| line.split_once('='))
+        .map_or(line, |(key, _)| key);
+    let id = StructuredLine::parse(line).and_then(|line| line.id().map(str::to_string));
+    (key, id)
+}
+
+/// A file whose header was changed.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ReheaderedFile {
+    pub input: PathBuf,
+    pub output: PathBuf,
+    /// How many bytes of the input were decompressed and compressed again: only the blocks with the
+    /// header for bgzipped files, the whole file for other compressed ones and none for plain files.
+    pub recompressed_bytes: u64,
+    /// The size of the output in bytes.
+    pub output_bytes: u64,
+}
+
+/// Writes `input` with the header changed by `edit` to `output`, which may be the same file. A tabix
+/// index next to either is built again for `output`, since its records moved.
+///
+/// # Examples
+///
+/// ```
+/// use std::path::Path;
+/// use vcf_batcher::reheader::{reheader_file, HeaderEdit};
+///
+/// let patch = HeaderEdit::Patch(vec!["##reference=GRCh38".to_string()]);
+/// let output = Path::new("test_data/doc_reheader/batch_01.vcf.gz");
+/// let file = reheader_file(Path::new("test_data/batch_01.vcf.gz"), output, &patch).unwrap();
+/// assert!(file.recompressed_bytes < file.output_bytes);
+/// ```
+pub fn reheader_file(
+    input: &Path,
+    output: &Path,
+    edit: &HeaderEdit,
+) -> Result<ReheaderedFile, VcfBatcherError> {
+    let read_error = |source: io::Error| VcfBatcherError::Read {
+        path: input.to_path_buf(),
+        source,
+    };
+    let write_error = |source: io::Error| VcfBatcherError::Write {
+        path: output.to_path_buf(),
+        source: source.into(),
+    };
+    let new_header = |header: &[String]| {
+        edit.apply(header)
+            .map_err(|reason| VcfBatcherError::InvalidHeader {
+                path: input.to_path_buf(),
+                reason,
+            })
+    };
+
+    let input_bytes = fs::metadata(input).map_err(read_error)?.len();
+    let mut reader = BufReader::new(File::open(input).map_err(read_error)?);
+    let start = reader.fill_buf().map_err(read_error)?;
+    let codec = match start {
+        _ if is_bgzf(start) => Some(Codec::Bgzf),
+        _ if start.starts_with(GZIP_MAGIC) => Some(Codec::Gzip),
+        _ if start.starts_with(ZSTD_MAGIC) => Some(Codec::Zstd),
+        _ => None,
+    };
+
+    let mut recompressed_bytes = input_bytes;
+    if codec == Some(Codec::Bgzf) {
+        let prefix = read_header_blocks(&mut reader).map_err(read_error)?;
+        recompressed_bytes = prefix.compressed_bytes;
+        let mut contents = lines_to_bytes(&new_header(&prefix.header)?);
+        contents.extend_from_slice(&prefix.records);
+        write_replacing(output, |file| {
+            let mut compressed = Vec::new();
+            let mut writer = BGZFWriter::new(&mut compressed, Compression::default());
+            writer.write_all(&contents).map_err(write_error)?;
+            writer.close().map_err(write_error)?;
+            // The remaining blocks end with their own end-of-file marker
+            let at_end = reader.fill_buf().map_err(read_error)?.is_empty();
+            if !at_end {
+                compressed.truncate(compressed.len() - EOF_MARKER.len());
+            }
+            file.write_all(&compressed).map_err(write_error)?;
+            copy(&mut reader, file, read_error, write_error)
+        })?;
+    } else {
+        let mut reader = BufReader::new(decoder(reader).map_err(read_error)?);
+        let mut header = Vec::new();
+        let mut line = Vec::new();
+        loop {
+            line.clear();
+            if reader.read_until(b'\n', &mut line).map_err(read_error)? == 0 || line[0] != b'#' {
+                break;
+            }
+            let text = String::from_utf8_lossy(&line);
+            header.push(text.trim_end_matches(['\n', '\r']).to_string());
+        }
+        let header = new_header(&header)?;
+        let options = RecompressOptions {
+            codec: codec.unwrap_or_default(),
+            level: codec.map(|_| Level::Default),
+            threads: 1,
+        };
+        if codec.is_none() {
+            recompressed_bytes = 0;
+        }
+        write_replacing(output, |file| {
+            let mut writer = Encoder::new(file, &options).map_err(write_error)?;
+            writer
+                .write_all(&lines_to_bytes(&header))
+                .map_err(write_error)?;
+            writer.write_all(&line).map_err(write_error)?;
+            copy(&mut reader, &mut writer, read_error, write_error)?;
+            writer.finish().map_err(write_error)
+        })?;
+    }
+    update_tabix_index(input, output)?;
+
+    Ok(ReheaderedFile {
+        input: input.to_path_buf(),
+        output: output.to_path_buf(),
+        recompressed_bytes,
+        output_bytes: fs::metadata(output).map_err(write_error)?.len(),
+    })
+}
+
+/// Changes the header of the batches in `batch_dir`, which are found like
+/// [`crate::merge::merge_batches`] finds them, and writes them with their names to `output_dir`,
+/// which may be `batch_dir`. If the batches have a manifest, it is written to `output_dir` with the
+/// new checksums, and the batches are no longer marked as verified.
+///
+/// # Examples
+///
+/// ```
+/// use std::path::Path;
+/// use vcf_batcher::extract_variants_to_batches;
+/// use vcf_batcher::reheader::{reheader_dir, HeaderEdit};
+///
+/// let batch_dir = Path::new("test_data/doc_reheader_dir");
+/// extract_variants_to_batches("test_data/batch_01.vcf.gz", 30, batch_dir, None).unwrap();
+///
+/// let patch = HeaderEdit::Patch(vec!["##reference=GRCh38".to_string()]);
+/// let files = reheader_dir(batch_dir, batch_dir, &patch).unwrap();
+/// assert_eq!(files.len(), 4);
+/// ```
+pub fn reheader_dir(
+    batch_dir: &Path,
+    output_dir: &Path,
+    edit: &HeaderEdit,
+) -> Result<Vec<ReheaderedFile>, VcfBatcherError> {
+    let batches = batch_files(batch_dir, output_dir)?;
+    if batches.is_empty() {
+        return Err(VcfBatcherError::NoBatches {
+            path: batch_dir.to_path_buf(),
+        });
+    }
+    let manifest = Manifest::read(batch_dir).map_err(|source| VcfBatcherError::Read {
+        path: batch_dir.to_path_buf(),
+        source,
+    })?;
+
+    let mut files = Vec::new();
+    for batch in batches {
+        let output = output_dir.join(batch.file_name().unwrap_or_default());
+        files.push(reheader_file(&batch, &output, edit)?);
+    }
+
+    if let Some(mut manifest) = manifest {
+        let write_error = |source: io::Error| VcfBatcherError::Write {
+            path: output_dir.to_path_buf(),
+            source: source.into(),
+        };
+        for (entry, file) in manifest.batches.iter_mut().zip(&files) {
+            entry.sha256 = file_sha256(&file.output).map_err(write_error)?;
+            entry.verified = false;
+        }
+        manifest.write(output_dir).map_err(write_error)?;
+    }
+    Ok(files)
+}
+
+/// The decompressed contents of the BGZF blocks that contain the header.
+struct HeaderBlocks {
+    header: Vec<String>,
+    /// The records that follow the header in the last of the blocks.
+    records: Vec<u8>,
+    /// The size of the blocks in the file.
+    compressed_bytes: u64,
+}
+
+/// Reads BGZF blocks until the first record starts or the file ends.
+fn read_header_blocks(reader: &mut impl BufRead) -> io::Result<HeaderBlocks> {
+    let invalid = |error: bgzip::BGZFError| io::Error::new(io::ErrorKind::InvalidData, error);
+    let mut decompress = Decompress::new();
+    let (mut block, mut contents) = (Vec::new(), Vec::new());
+    let mut compressed_bytes = 0;
+    let header_length = loop {
+        if reader.fill_buf()?.is_empty() {
+            break contents.len();
+        }
+        let block_header = load_block(&mut *reader, &mut block).map_err(invalid)?;
+        compressed_bytes += block_header.header_size() + block.len() as u64;
+        decompress_block(&mut contents, &block, &mut decompress).map_err(invalid)?;
+        if let Some(length) = header_length(&contents) {
+            break length;
+        }
+    };
+
+    let records = contents.split_off(header_length);
+    let header = String::from_utf8_lossy(&contents)
+        .lines()
+        .map(str::to_string)
+        .collect();
+    Ok(HeaderBlocks {
+        header,
+        records,
+        compressed_bytes,
+    })
+}
+
+/// The length of the header lines at the start of `contents`, once the first line after them started.
+fn header_length(contents: &[u8]) -> Option<usize> {
+    let mut start = 0;
+    while start < contents.len() {
+        if contents[start] != b'#' {
+            return Some(start);
+        }
+        let end = contents[start..].iter().position(|&byte| byte == b'\n')?;
+        start += end + 1;
+    }
+    None
+}
+
+/// Joins lines, each followed by a line ending.
+fn lines_to_bytes(lines: &[String]) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    for line in lines {
+        bytes.extend_from_slice(line.as_bytes());
+        bytes.push(b'\n');
+    }
+    bytes
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::{self, File};
+    use std::io::BufRead;
+    use std::path::Path;
+
+    use bgzip::{BGZFReader, Compression};
+
+    use super::{reheader_dir, reheader_file, HeaderEdit};
+    use crate::index::{read_contig_spans, tabix_index, tabix_path};
+    use crate::manifest::{file_sha256, Manifest};
+    use crate::testdata::SyntheticVcf;
+    use crate::{
+        extract_variants_to_batches_with_options, read_lines, BatchOptions, IndexFormat, Locus,
+        VcfBatcherError,
+    };
+
+    /// The header lines and the records of a file.
+    fn lines(path: &Path) -> (Vec<String>, Vec<String>) {
+        read_lines(path)
+            .unwrap()
+            .map(Result::unwrap)
+            .partition(|line| line.starts_with('#'))
+    }
+
+    #[test]
+    fn test_reheader_dir() {
+        let root = Path::new("./test_data/reheader");
+        let _ = fs::remove_dir_all(root);
+        let input = root.join("input.vcf");
+        SyntheticVcf {
+            records: 8000,
+            ..Default::default()
+        }
+        .write_file(&input, None)
+        .unwrap();
+        let options = BatchOptions {
+            batch_size: 4000,
+            compression_level: Some(Compression::fast()),
+            resume: true,
+            quiet: true,
+            ..Default::default()
+        };
+        let batch_dir = root.join("batches");
+        extract_variants_to_batches_with_options(input.to_str().unwrap(), &batch_dir, &options)
+            .unwrap();
+        let batch = batch_dir.join("batch_01.vcf.gz");
+        let (_, before) = lines(&batch);
+
+        let patch = root.join("patch.txt");
+        fs::write(
+            &patch,
+            "##contig=<ID=chr1,length=248956422>\n\n##source=fixed\n",
+        )
+        .unwrap();
+        let edit = HeaderEdit::read(&patch, true).unwrap();
+        let files = reheader_dir(&batch_dir, &batch_dir, &edit).unwrap();
+
+        assert_eq!(files.len(), 2);
+        // Only the first of the blocks of the 400 KB of records is compressed again
+        assert!(files[0].recompressed_bytes < files[0].output_bytes / 4);
+        let (header, records) = lines(&batch);
+        assert_eq!(records, before);
+        assert!(header.contains(&"##contig=<ID=chr1,length=248956422>".to_string()));
+        assert!(header.contains(&"##source=fixed".to_string()));
+        let manifest = Manifest::read(&batch_dir).unwrap().unwrap();
+        assert_eq!(manifest.batches[0].sha256, file_sha256(&batch).unwrap());
+
+        // Plain files are copied behind the new header
+        let mut header = header;
+        let chrom_line = header.pop().unwrap();
+        header.truncate(1);
+        header.push(chrom_line.replace("SAMPLE", "RENAMED"));
+        fs::write(&patch, header.join("\n")).unwrap();
+        let edit = HeaderEdit::read(&patch, false).unwrap();
+        let plain = root.join("plain.vcf");
+        let file = reheader_file(&input, &plain, &edit).unwrap();
+        assert_eq!(file.recompressed_bytes, 0);
+        assert_eq!(lines(&plain), (header, lines(&input).1));
+
+        fs::write(&patch, "#CHROM\tPOS\tID\tREF\tALT\tQUAL\tFILTER\tINFO\n").unwrap();
+        let edit = HeaderEdit::read(&patch, false).unwrap();
+        assert!(matches!(
+            reheader_file(&input, &plain, &edit),
+            Err(VcfBatcherError::InvalidHeader { .. })
+        ));
+        fs::write(&patch, "##source=fixed\n").unwrap();
+        assert!(HeaderEdit::read(&patch, false).is_err());
+    }
+
+    #[test]
+    fn test_reheader_indexed_batch() {
+        let batch_dir = Path::new("./test_data/reheader_indexed");
+        let _ = fs::remove_dir_all(batch_dir);
+        let options = BatchOptions {
+            batch_size: 30,
+            compression_level: Some(Compression::fast()),
+            index: Some(IndexFormat::Tabix),
+            quiet: true,
+            ..Default::default()
+        };
+        let report = extract_variants_to_batches_with_options(
+            "./test_data/batch_01.vcf.gz",
+            batch_dir,
+            &options,
+        )
+        .unwrap();
+
+        let edit = HeaderEdit::Patch(vec!["##source=fixed".to_string()]);
+        reheader_dir(batch_dir, batch_dir, &edit).unwrap();
+        for batch in &report.batches {
+            let index_path = tabix_path(&batch.path);
+            assert_eq!(
+                fs::read(&index_path).unwrap(),
+                tabix_index(&batch.path).unwrap()
+            );
+            let spans = read_contig_spans(&index_path).unwrap();
+            let mut reader = BGZFReader::new(File::open(&batch.path).unwrap()).unwrap();
+            reader.bgzf_seek(spans[0].start).unwrap();
+            let mut line = String::new();
+            reader.read_line(&mut line).unwrap();
+            assert_eq!(Locus::from_record(&line), batch.first);
+        }
+    }
+}
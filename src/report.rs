@@ -0,0 +1,226 @@
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize, Serializer};
+
+use crate::{header, EmptyInputPolicy, Instant};
+
+/// Information about a single batch that was written to disk.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct BatchInfo {
+    /// The number of the batch, starting at 1.
+    pub number: usize,
+    /// The path of the written batch file.
+    pub path: PathBuf,
+    /// The number of variant records (excluding headers) in the batch.
+    pub records: usize,
+    /// SHA-256 checksum of the batch file, as a lowercase hex string.
+    pub sha256: String,
+    /// Size of the batch file in bytes, 0 for batches that are only planned, see [`crate::plan_batches`].
+    pub size: u64,
+    /// Position of the first record in the batch, `None` if the batch only contains the header.
+    pub first: Option<Locus>,
+    /// Position of the last record in the batch, `None` if the batch only contains the header.
+    pub last: Option<Locus>,
+    /// Whether the batch was read back and checked after it was written, see [`crate::BatchOptions::verify_output`].
+    pub verified: bool,
+}
+
+/// Position of a variant record, taken from its CHROM and POS columns.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Locus {
+    pub chrom: String,
+    pub pos: u64,
+}
+
+impl Locus {
+    /// Reads the position of a variant record. Returns `None` if the POS column is missing or not a number.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use vcf_batcher::Locus;
+    ///
+    /// let locus = Locus::from_record("chr2\t1234\trs1\tA\tG").unwrap();
+    /// assert_eq!(locus.chrom, "chr2");
+    /// assert_eq!(locus.pos, 1234);
+    /// ```
+    pub fn from_record(line: &str) -> Option<Locus> {
+        let pos = line.split('\t').nth(1)?.parse().ok()?;
+        Some(Locus {
+            chrom: header::record_chrom(line).to_string(),
+            pos,
+        })
+    }
+}
+
+/// How far a run has progressed, see [`crate::BatchOptions::on_progress`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Progress {
+    /// Bytes of the input file that were read so far. For bgzipped files, these are compressed bytes.
+    pub bytes_read: u64,
+    /// Size of the input file, if known.
+    pub total_bytes: Option<u64>,
+    /// Variant records that were read so far, including the ones that were filtered out.
+    pub records: usize,
+    /// Batches that were completed so far.
+    pub batches: usize,
+    /// Time since the run started.
+    #[serde(rename = "elapsed_seconds", serialize_with = "serialize_seconds")]
+    pub elapsed: Duration,
+}
+
+impl Progress {
+    /// The variant records read per second since the run started.
+    pub fn records_per_second(&self) -> f64 {
+        self.records as f64 / self.elapsed.as_secs_f64().max(f64::EPSILON)
+    }
+
+    /// The bytes of the input file read per second since the run started, see [`Progress::bytes_read`].
+    pub fn bytes_per_second(&self) -> f64 {
+        self.bytes_read as f64 / self.elapsed.as_secs_f64().max(f64::EPSILON)
+    }
+
+    /// The time until the whole input is read at the rate so far, if its size is known and reading has begun.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::time::Duration;
+    /// use vcf_batcher::Progress;
+    ///
+    /// let progress = Progress {
+    ///     bytes_read: 250,
+    ///     total_bytes: Some(1000),
+    ///     records: 40,
+    ///     batches: 1,
+    ///     elapsed: Duration::from_secs(10),
+    /// };
+    /// assert_eq!(progress.records_per_second(), 4.0);
+    /// assert_eq!(progress.bytes_per_second(), 25.0);
+    /// assert_eq!(progress.remaining(), Some(Duration::from_secs(30)));
+    /// ```
+    pub fn remaining(&self) -> Option<Duration> {
+        let total_bytes = self.total_bytes?;
+        if self.bytes_read == 0 {
+            return None;
+        }
+        let left = total_bytes.saturating_sub(self.bytes_read) as f64;
+        Some(Duration::from_secs_f64(left / self.bytes_per_second()))
+    }
+}
+
+/// Summary of the batches that were written during a run.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct RunReport {
+    /// The directory the batches were written to.
+    pub output_path: PathBuf,
+    /// All batches that were written completely, in the order they were written.
+    pub batches: Vec<BatchInfo>,
+    /// Set if the input contained no variant records, with the policy that was applied.
+    pub empty_input: Option<EmptyInputPolicy>,
+    /// The number of header lines that appeared after the first variant record.
+    pub late_header_lines: usize,
+    /// Record counts per contig that is not declared in the header, if contigs were checked.
+    pub unknown_contigs: BTreeMap<String, usize>,
+    /// Sample names that appear more than once in the `#CHROM` header line.
+    pub duplicate_samples: Vec<String>,
+    /// The number of variant records that were left out because of [`crate::BatchOptions::filter`].
+    pub filtered_records: usize,
+    /// The number of malformed variant records that were left out, see [`crate::BadRecordPolicy`].
+    pub bad_records: usize,
+    /// The number of variant records that repeat an earlier record, see [`crate::BatchOptions::dedup`].
+    /// They were left out with [`crate::DuplicateRecordPolicy::KeepFirst`].
+    pub duplicate_records: usize,
+    /// Identifies the input file and the options of the run, see [`crate::manifest::Manifest`].
+    pub fingerprint: String,
+    /// The number of batches that were kept from a previous, identical run instead of being rewritten.
+    pub resumed_batches: usize,
+    /// The number of batches whose files already existed and were kept because of [`crate::OverwritePolicy::Skip`].
+    pub skipped_batches: usize,
+    /// The number of batches of earlier runs that the run appended to, which are listed first in
+    /// [`RunReport::batches`], see [`crate::BatchOptions::append`].
+    pub existing_batches: usize,
+    /// The directory of the batches of every value of [`crate::BatchOptions::group_by_info`], by value.
+    /// Empty if the records are not grouped.
+    pub groups: BTreeMap<String, PathBuf>,
+    /// Wall-clock time of the run.
+    #[serde(rename = "elapsed_seconds", serialize_with = "serialize_seconds")]
+    pub elapsed: Duration,
+    /// Time spent in the stages of the run, if [`crate::BatchOptions::timings`] is set.
+    pub timings: Option<StageTimings>,
+}
+
+/// Time spent in the stages of a run, to tell whether it is limited by the input, the CPU or the output.
+/// The rest of the elapsed time is spent on the bookkeeping between the stages.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct StageTimings {
+    /// Reading and decompressing the input and splitting it into lines. Input files are read on a thread of their
+    /// own, so for them this is the time spent waiting for lines.
+    #[serde(rename = "reading_seconds", serialize_with = "serialize_seconds")]
+    pub reading: Duration,
+    /// Checking records for malformed columns, applying the filter and selecting the samples.
+    #[serde(rename = "filtering_seconds", serialize_with = "serialize_seconds")]
+    pub filtering: Duration,
+    /// Compressing the batches.
+    #[serde(rename = "compression_seconds", serialize_with = "serialize_seconds")]
+    pub compression: Duration,
+    /// Writing the batches and computing their checksums.
+    #[serde(rename = "writing_seconds", serialize_with = "serialize_seconds")]
+    pub writing: Duration,
+}
+
+/// A stage of a run, see [`StageTimings`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Stage {
+    Reading,
+    Filtering,
+    Compression,
+    Writing,
+}
+
+/// Serializes a duration as fractional seconds, which is easier to consume than seconds and nanoseconds.
+fn serialize_seconds<S: Serializer>(duration: &Duration, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_f64(duration.as_secs_f64())
+}
+
+impl RunReport {
+    /// The total number of variant records written across all batches.
+    pub fn total_records(&self) -> usize {
+        self.batches.iter().map(|batch| batch.records).sum()
+    }
+
+    /// Starts measuring a stage, if timings are recorded.
+    pub(crate) fn stage_start(&self) -> Option<Instant> {
+        self.timings.is_some().then(Instant::now)
+    }
+
+    /// Adds the time since `started` to a stage.
+    pub(crate) fn stage_end(&mut self, stage: Stage, started: Option<Instant>) {
+        if let (Some(timings), Some(started)) = (&mut self.timings, started) {
+            *timings.stage_mut(stage) += started.elapsed();
+        }
+    }
+}
+
+impl StageTimings {
+    /// The stages with a description, in the order they happen.
+    pub fn stages(&self) -> [(&'static str, Duration); 4] {
+        [
+            ("reading and decompression", self.reading),
+            ("filtering", self.filtering),
+            ("compression", self.compression),
+            ("writing", self.writing),
+        ]
+    }
+
+    fn stage_mut(&mut self, stage: Stage) -> &mut Duration {
+        match stage {
+            Stage::Reading => &mut self.reading,
+            Stage::Filtering => &mut self.filtering,
+            Stage::Compression => &mut self.compression,
+            Stage::Writing => &mut self.writing,
+        }
+    }
+}
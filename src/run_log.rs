@@ -0,0 +1,150 @@
+//! A structured log of a run, written independently of the console output, e.g. as an audit trail.
+
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use serde_json::{Map, Value};
+use time::format_description::well_known::Rfc3339;
+use time::OffsetDateTime;
+
+use crate::VcfBatcherError;
+
+/// Appends events to a log file in the [JSON Lines](https://jsonlines.org/) format: every line is an
+/// object with the UTC `time` of the event in RFC 3339 format, the name of the `event` and its fields.
+///
+/// The log can be cloned and shared between threads, e.g. to record batches from a
+/// [`crate::BatchCallback`]. Every event is flushed, so the log is complete up to the last event even
+/// if the process is killed. Writing an event never fails; the first error is returned by [`RunLog::finish`].
+///
+/// # Examples
+///
+/// ```
+/// use serde_json::json;
+/// use vcf_batcher::run_log::RunLog;
+///
+/// # std::fs::create_dir_all("test_data/doc_run_log").unwrap();
+/// let log = RunLog::create("test_data/doc_run_log/run.log").unwrap();
+/// log.event("start", json!({ "input": "cohort.vcf.gz" }));
+/// log.event("finish", json!({ "batches": 4 }));
+/// log.finish().unwrap();
+/// ```
+#[derive(Clone)]
+pub struct RunLog {
+    path: PathBuf,
+    state: Arc<Mutex<LogState>>,
+}
+
+struct LogState {
+    writer: BufWriter<File>,
+    error: Option<io::Error>,
+}
+
+impl RunLog {
+    /// Opens the log file, appending to it if it already exists, so that the events of earlier runs are kept.
+    pub fn create(path: impl Into<PathBuf>) -> Result<RunLog, VcfBatcherError> {
+        let path = path.into();
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)
+            .map_err(|source| VcfBatcherError::Write {
+                path: path.clone(),
+                source: source.into(),
+            })?;
+        Ok(RunLog {
+            path,
+            state: Arc::new(Mutex::new(LogState {
+                writer: BufWriter::new(file),
+                error: None,
+            })),
+        })
+    }
+
+    /// The path of the log file.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Writes an event. The fields of `fields` are added to the line if it is an object,
+    /// any other value is added as `value`.
+    pub fn event(&self, event: &str, fields: Value) {
+        let mut line = Map::new();
+        let time = OffsetDateTime::now_utc()
+            .format(&Rfc3339)
+            .unwrap_or_default();
+        line.insert("time".to_string(), Value::String(time));
+        line.insert("event".to_string(), Value::String(event.to_string()));
+        match fields {
+            Value::Object(fields) => line.extend(fields),
+            Value::Null => {}
+            value => {
+                line.insert("value".to_string(), value);
+            }
+        }
+
+        let mut state = self.state.lock().unwrap_or_else(|error| error.into_inner());
+        if state.error.is_some() {
+            return;
+        }
+        let result = serde_json::to_writer(&mut state.writer, &line)
+            .map_err(io::Error::from)
+            .and_then(|_| state.writer.write_all(b"\n"))
+            .and_then(|_| state.writer.flush());
+        if let Err(error) = result {
+            state.error = Some(error);
+        }
+    }
+
+    /// Writes a warning, with the same message that is shown on the console.
+    pub fn warning(&self, message: &str) {
+        self.event("warning", serde_json::json!({ "message": message }));
+    }
+
+    /// Returns the first error that occurred while writing the log.
+    pub fn finish(self) -> Result<(), VcfBatcherError> {
+        let mut state = self.state.lock().unwrap_or_else(|error| error.into_inner());
+        match state.error.take() {
+            Some(source) => Err(VcfBatcherError::Write {
+                path: self.path,
+                source: source.into(),
+            }),
+            None => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::{json, Value};
+
+    use super::RunLog;
+
+    #[test]
+    fn test_run_log() {
+        let directory = std::path::Path::new("./test_data/run_log");
+        let _ = std::fs::remove_dir_all(directory);
+        std::fs::create_dir_all(directory).unwrap();
+        let path = directory.join("run.log");
+
+        for run in 1..=2 {
+            let log = RunLog::create(&path).unwrap();
+            log.event("start", json!({ "run": run }));
+            log.clone().warning("records on undeclared contigs");
+            log.finish().unwrap();
+        }
+
+        let lines: Vec<Value> = std::fs::read_to_string(&path)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        // Earlier runs are kept
+        assert_eq!(lines.len(), 4);
+        assert_eq!(lines[2]["event"], "start");
+        assert_eq!(lines[2]["run"], 2);
+        assert_eq!(lines[3]["message"], "records on undeclared contigs");
+        assert!(lines[0]["time"].as_str().unwrap().ends_with('Z'));
+    }
+}
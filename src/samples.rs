@@ -0,0 +1,96 @@
+//! Splitting the sample columns of a VCF file into batches that each have all records but only some samples,
+//! e.g. to run per-sample tools on a cohort in parallel, see [`crate::SplitMode::Samples`].
+
+use crate::filter::select_columns;
+use crate::stream::BatchFile;
+
+/// The number of columns before the first sample: the eight fixed columns and FORMAT.
+const FIXED_COLUMNS: usize = 9;
+
+/// The batches of a run that splits by samples, which are all collected at the same time.
+#[derive(Debug)]
+pub(crate) struct SampleBatches {
+    /// The columns of every batch: the fixed columns and FORMAT, followed by the columns of its samples.
+    columns: Vec<Vec<usize>>,
+    pub(crate) batches: Vec<SampleBatch>,
+    /// The number of records of every batch, which all have the same records.
+    pub(crate) record_count: usize,
+}
+
+/// The records of the samples of one batch.
+#[derive(Debug, Default)]
+pub(crate) struct SampleBatch {
+    /// The records, each followed by a line ending, unless they are written to `file`.
+    pub(crate) records: String,
+    /// The file the records are written to if batches are streamed.
+    pub(crate) file: Option<BatchFile>,
+}
+
+impl SampleBatches {
+    /// Groups the samples of a `#CHROM` line, or of a record if the header has none, into batches of
+    /// `samples_per_batch` samples. A line without samples gives a single batch with all columns.
+    pub(crate) fn new(line: &str, samples_per_batch: usize) -> Self {
+        let column_count = line.split('\t').count();
+        let columns: Vec<Vec<usize>> = match column_count > FIXED_COLUMNS {
+            true => (FIXED_COLUMNS..column_count)
+                .collect::<Vec<usize>>()
+                .chunks(samples_per_batch.max(1))
+                .map(|samples| (0..FIXED_COLUMNS).chain(samples.iter().copied()).collect())
+                .collect(),
+            false => vec![(0..column_count).collect()],
+        };
+        SampleBatches {
+            batches: columns.iter().map(|_| SampleBatch::default()).collect(),
+            columns,
+            record_count: 0,
+        }
+    }
+
+    /// The columns of a record or of the `#CHROM` line that belong to a batch.
+    pub(crate) fn select(&self, batch: usize, line: &str) -> String {
+        select_columns(line, &self.columns[batch])
+    }
+
+    /// The header of a batch, with its samples in the `#CHROM` line.
+    pub(crate) fn headers(&self, batch: usize, headers: &str) -> String {
+        let mut batch_headers = String::with_capacity(headers.len());
+        for line in headers.lines() {
+            match line.starts_with("#CHROM") {
+                true => batch_headers.push_str(&self.select(batch, line)),
+                false => batch_headers.push_str(line),
+            }
+            batch_headers.push('\n');
+        }
+        batch_headers
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SampleBatches;
+
+    #[test]
+    fn test_sample_batches() {
+        let chrom_line = "#CHROM\tPOS\tID\tREF\tALT\tQUAL\tFILTER\tINFO\tFORMAT\tS1\tS2\tS3";
+        let batches = SampleBatches::new(chrom_line, 2);
+        assert_eq!(batches.batches.len(), 2);
+        let headers = format!("##fileformat=VCFv4.2\n{chrom_line}\n");
+        assert_eq!(
+            batches.headers(1, &headers),
+            "##fileformat=VCFv4.2\n#CHROM\tPOS\tID\tREF\tALT\tQUAL\tFILTER\tINFO\tFORMAT\tS3\n"
+        );
+        let record = "1\t5\t.\tA\tC\t.\tPASS\t.\tGT\t0/1\t1/1\t0/0";
+        assert_eq!(
+            batches.select(0, record),
+            "1\t5\t.\tA\tC\t.\tPASS\t.\tGT\t0/1\t1/1"
+        );
+
+        // Without samples, all columns go into a single batch
+        let batches = SampleBatches::new("#CHROM\tPOS\tID\tREF\tALT\tQUAL\tFILTER\tINFO", 2);
+        assert_eq!(batches.batches.len(), 1);
+        assert_eq!(
+            batches.select(0, "1\t5\t.\tA\tC\t.\tPASS\t."),
+            "1\t5\t.\tA\tC\t.\tPASS\t."
+        );
+    }
+}
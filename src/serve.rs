@@ -0,0 +1,342 @@
+//! An HTTP service that runs split jobs, so that other services can submit inputs without wrapping
+//! the CLI.
+//!
+//! | Request | Response |
+//! |---|---|
+//! | `POST /jobs` with the options as JSON | `202` with the `id` of the queued job |
+//! | `GET /jobs` | all jobs with their status |
+//! | `GET /jobs/<id>` | the status, progress and report or error of a job |
+//! | `GET /jobs/<id>/batches` | the batches a job has written so far |
+//!
+//! The options of a job are those of a [`Config`] file as a JSON object, with the same keys, e.g.
+//! `{"input": "/data/cohortA.vcf.gz", "output": "/data/batches/cohortA", "batch-size": 1000}`. The
+//! input is a local path or a `file://` URI. Jobs are kept in memory until the service stops.
+//!
+//! With a [`ServeOptions::webhook`], the service also POSTs a notification when a job completes or fails.
+
+use std::net::TcpListener;
+use std::path::PathBuf;
+use std::sync::mpsc::{self, Receiver};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::thread;
+
+use serde::Serialize;
+use serde_json::{json, Value};
+
+use crate::config::Config;
+use crate::http::{self, Request, Response};
+use crate::webhook::{run_payload, Webhook};
+use crate::{
+    extract_variants_to_batches_with_options, BatchCallback, BatchInfo, BatchOptions,
+    OverwritePolicy, Progress, ProgressCallback, RunReport,
+};
+
+/// How the service runs its jobs.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ServeOptions {
+    /// How many jobs run at the same time, at least one. Further jobs are queued.
+    pub workers: usize,
+    /// Notified when a job completes or fails, with the payload of [`run_payload`] and the `job` id.
+    pub webhook: Option<Webhook>,
+}
+
+impl Default for ServeOptions {
+    fn default() -> Self {
+        ServeOptions {
+            workers: 1,
+            webhook: None,
+        }
+    }
+}
+
+/// Where a job is in its lifecycle.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+enum JobStatus {
+    /// Waiting for a free worker.
+    Queued,
+    Running,
+    /// All batches were written; the job has a report.
+    Completed,
+    /// The job stopped with an error; the batches it wrote before are still listed.
+    Failed,
+}
+
+#[derive(Debug, Serialize)]
+struct Job {
+    id: usize,
+    status: JobStatus,
+    input: PathBuf,
+    output: PathBuf,
+    parameters: Config,
+    progress: Option<Progress>,
+    report: Option<RunReport>,
+    error: Option<String>,
+    /// Why the webhook could not be notified about the job.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    webhook_error: Option<String>,
+    #[serde(skip)]
+    batches: Vec<BatchInfo>,
+}
+
+/// The jobs by id, which is their index plus one.
+type Jobs = Arc<Mutex<Vec<Job>>>;
+
+/// Answers requests on the listener and runs the submitted jobs on the worker threads.
+/// Runs until the process exits.
+pub fn serve(listener: TcpListener, options: &ServeOptions) {
+    let jobs = Jobs::default();
+    let (queue, receiver) = mpsc::channel();
+    let receiver = Arc::new(Mutex::new(receiver));
+    for _ in 0..options.workers.max(1) {
+        let jobs = Arc::clone(&jobs);
+        let receiver = Arc::clone(&receiver);
+        let webhook = options.webhook.clone();
+        thread::spawn(move || work(&jobs, &receiver, webhook.as_ref()));
+    }
+
+    http::serve_connections(listener, |request| {
+        let segments: Vec<&str> = request.path.trim_matches('/').split('/').collect();
+        match (request.method.as_str(), segments.as_slice()) {
+            ("POST", ["jobs"]) => match submit(&jobs, request) {
+                Ok(id) => {
+                    // The workers only stop with the process
+                    let _ = queue.send(id);
+                    Response::json(202, &json!({ "id": id, "status": JobStatus::Queued }))
+                }
+                Err(message) => error(400, message),
+            },
+            ("GET", ["jobs"]) => {
+                let jobs = lock(&jobs);
+                let jobs: Vec<Value> = jobs
+                    .iter()
+                    .map(|job| {
+                        json!({ "id": job.id, "status": job.status, "input": job.input, "output": job.output })
+                    })
+                    .collect();
+                Response::json(200, &json!({ "jobs": jobs }))
+            }
+            ("GET", ["jobs", id]) => job_response(&jobs, id, |job| json!(job)),
+            ("GET", ["jobs", id, "batches"]) => job_response(
+                &jobs,
+                id,
+                |job| json!({ "id": job.id, "batches": job.batches }),
+            ),
+            (_, ["jobs"] | ["jobs", _] | ["jobs", _, "batches"]) => {
+                error(405, format!("{} is not supported here", request.method))
+            }
+            _ => error(404, format!("there is nothing at {}", request.path)),
+        }
+    })
+}
+
+/// Checks the options of a new job and queues it.
+fn submit(jobs: &Jobs, request: &Request) -> Result<usize, String> {
+    let config: Config = serde_json::from_slice(&request.body)
+        .map_err(|error| format!("invalid job options: {error}"))?;
+    let config = config
+        .with_profile(None)
+        .map_err(|error| error.to_string())?;
+    let input = match config.input.as_deref().and_then(|input| input.to_str()) {
+        Some(input) => input_path(input)?,
+        None => return Err("the input is missing".to_string()),
+    };
+    let Some(output) = config.output.clone() else {
+        return Err("the output is missing".to_string());
+    };
+    config.to_options().map_err(|error| error.to_string())?;
+
+    let mut jobs = lock(jobs);
+    let id = jobs.len() + 1;
+    jobs.push(Job {
+        id,
+        status: JobStatus::Queued,
+        input,
+        output,
+        parameters: config,
+        progress: None,
+        report: None,
+        error: None,
+        webhook_error: None,
+        batches: Vec::new(),
+    });
+    Ok(id)
+}
+
+/// Runs queued jobs until the service stops.
+fn work(jobs: &Jobs, receiver: &Mutex<Receiver<usize>>, webhook: Option<&Webhook>) {
+    loop {
+        let Ok(id) = receiver
+            .lock()
+            .unwrap_or_else(|error| error.into_inner())
+            .recv()
+        else {
+            return;
+        };
+        run(jobs, id, webhook);
+    }
+}
+
+fn run(jobs: &Jobs, id: usize, webhook: Option<&Webhook>) {
+    let (input, output, config) = {
+        let mut jobs = lock(jobs);
+        let job = &mut jobs[id - 1];
+        job.status = JobStatus::Running;
+        (
+            job.input.clone(),
+            job.output.clone(),
+            job.parameters.clone(),
+        )
+    };
+    let progress_jobs = Arc::clone(jobs);
+    let batch_jobs = Arc::clone(jobs);
+    let result = config.to_options().and_then(|options| {
+        let options = BatchOptions {
+            quiet: true,
+            // Like the CLI, jobs do not replace files unless asked to
+            overwrite: config.overwrite.unwrap_or(OverwritePolicy::Refuse),
+            on_progress: Some(ProgressCallback::new(move |progress| {
+                lock(&progress_jobs)[id - 1].progress = Some(progress.clone());
+            })),
+            on_batch: Some(BatchCallback::new(move |batch| {
+                lock(&batch_jobs)[id - 1].batches.push(batch.clone());
+            })),
+            ..options
+        };
+        extract_variants_to_batches_with_options(&input.to_string_lossy(), &output, &options)
+    });
+
+    let notified = webhook.map(|webhook| {
+        let mut payload = run_payload(&input, &output, result.as_ref());
+        payload["job"] = json!(id);
+        webhook.notify(&payload)
+    });
+
+    let mut jobs = lock(jobs);
+    let job = &mut jobs[id - 1];
+    match result {
+        Ok(report) => {
+            job.status = JobStatus::Completed;
+            job.report = Some(report);
+        }
+        Err(error) => {
+            job.status = JobStatus::Failed;
+            job.error = Some(error.to_string());
+        }
+    }
+    if let Some(Err(error)) = notified {
+        job.webhook_error = Some(error.to_string());
+    }
+}
+
+/// The local path of an input, which may be given as a `file://` URI.
+fn input_path(input: &str) -> Result<PathBuf, String> {
+    match input.split_once("://") {
+        None => Ok(PathBuf::from(input)),
+        Some(("file", path)) => Ok(PathBuf::from(path)),
+        Some((scheme, _)) => Err(format!(
+            "{scheme}:// inputs are not supported, only local paths and file:// URIs"
+        )),
+    }
+}
+
+/// Answers with the document `describe` makes of a job, or 404 if there is no job with the id.
+fn job_response(jobs: &Jobs, id: &str, describe: impl FnOnce(&Job) -> Value) -> Response {
+    let jobs = lock(jobs);
+    let index = id.parse::<usize>().ok().and_then(|id| id.checked_sub(1));
+    match index.and_then(|index| jobs.get(index)) {
+        Some(job) => Response::json(200, &describe(job)),
+        None => error(404, format!("there is no job {id}")),
+    }
+}
+
+/// Locks the jobs. A panicking job does not leave them inconsistent, so a poisoned lock is used anyway.
+fn lock(jobs: &Jobs) -> MutexGuard<'_, Vec<Job>> {
+    jobs.lock().unwrap_or_else(|error| error.into_inner())
+}
+
+fn error(status: u16, message: String) -> Response {
+    Response::json(status, &json!({ "error": message }))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Read, Write};
+    use std::net::{SocketAddr, TcpListener, TcpStream};
+    use std::thread;
+    use std::time::Duration;
+
+    use serde_json::{json, Value};
+
+    use super::{input_path, serve, ServeOptions};
+
+    fn request(address: SocketAddr, method: &str, path: &str, body: &str) -> (u16, Value) {
+        let mut stream = TcpStream::connect(address).unwrap();
+        write!(
+            stream,
+            "{method} {path} HTTP/1.1\r\nHost: localhost\r\nContent-Length: {}\r\n\r\n{body}",
+            body.len()
+        )
+        .unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        let (head, body) = response.split_once("\r\n\r\n").unwrap();
+        let status = head.split(' ').nth(1).unwrap().parse().unwrap();
+        (status, serde_json::from_str(body).unwrap())
+    }
+
+    #[test]
+    fn test_serve() {
+        let _ = std::fs::remove_dir_all("./test_data/serve");
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        thread::spawn(move || serve(listener, &ServeOptions::default()));
+
+        let job = json!({
+            "input": "file://./test_data/batch_01.vcf.gz",
+            "output": "./test_data/serve",
+            "batch-size": 30,
+        });
+        let (status, body) = request(address, "POST", "/jobs", &job.to_string());
+        assert_eq!(status, 202);
+        assert_eq!(body, json!({ "id": 1, "status": "queued" }));
+
+        let mut job = Value::Null;
+        for _ in 0..100 {
+            job = request(address, "GET", "/jobs/1", "").1;
+            if job["status"] != "queued" && job["status"] != "running" {
+                break;
+            }
+            thread::sleep(Duration::from_millis(50));
+        }
+        assert_eq!(job["status"], "completed");
+        assert_eq!(job["parameters"]["batch-size"], 30);
+        assert_eq!(job["report"]["batches"].as_array().unwrap().len(), 4);
+
+        let (_, batches) = request(address, "GET", "/jobs/1/batches", "");
+        assert_eq!(batches["batches"].as_array().unwrap().len(), 4);
+        let (_, jobs) = request(address, "GET", "/jobs", "");
+        assert_eq!(jobs["jobs"][0]["status"], "completed");
+
+        let (status, body) = request(address, "POST", "/jobs", r#"{"input": "in.vcf"}"#);
+        assert_eq!(status, 400);
+        assert_eq!(body["error"], "the output is missing");
+        let (status, _) = request(address, "POST", "/jobs", r#"{"batch-sise": 30}"#);
+        assert_eq!(status, 400);
+        assert_eq!(request(address, "GET", "/jobs/2", "").0, 404);
+        assert_eq!(request(address, "DELETE", "/jobs/1", "").0, 405);
+    }
+
+    #[test]
+    fn test_input_path() {
+        assert_eq!(
+            input_path("/data/a.vcf").unwrap().to_str(),
+            Some("/data/a.vcf")
+        );
+        assert_eq!(
+            input_path("file:///data/a.vcf").unwrap().to_str(),
+            Some("/data/a.vcf")
+        );
+        assert!(input_path("s3://bucket/a.vcf").is_err());
+    }
+}
@@ -0,0 +1,247 @@
+//! Destinations for batches other than files in an output directory.
+
+use std::collections::VecDeque;
+use std::io::{self, Write};
+use std::path::PathBuf;
+use std::process::{Child, Command, Stdio};
+use std::thread::{self, JoinHandle};
+
+use serde::Serialize;
+
+use crate::{BatchInfo, RunReport, VcfBatcherError};
+
+/// Receives the batches of [`crate::extract_variants_to_sink`] instead of files being written.
+///
+/// Closures with the signature `FnMut(&BatchInfo, &[u8]) -> io::Result<()>` implement this trait.
+///
+/// # Examples
+///
+/// ```
+/// use vcf_batcher::{extract_variants_to_sink, BatchOptions};
+///
+/// let mut sizes = Vec::new();
+/// let mut sink = |batch: &vcf_batcher::BatchInfo, contents: &[u8]| {
+///     sizes.push((batch.number, contents.len()));
+///     Ok(())
+/// };
+/// let options = BatchOptions { batch_size: 50, ..Default::default() };
+/// extract_variants_to_sink("test_data/batch_01.vcf.gz", &mut sink, &options).unwrap();
+/// assert_eq!(sizes.len(), 2);
+/// ```
+pub trait BatchSink {
+    /// Called with every complete batch, i.e. the header followed by the records of the batch.
+    /// The contents are bgzipped if the options specify a compression level. `batch.path` is the
+    /// file name the batch would have had in an output directory.
+    ///
+    /// Returning an error stops the run with [`crate::VcfBatcherError::BatchWrite`].
+    fn write_batch(&mut self, batch: &BatchInfo, contents: &[u8]) -> io::Result<()>;
+}
+
+impl<F> BatchSink for F
+where
+    F: FnMut(&BatchInfo, &[u8]) -> io::Result<()>,
+{
+    fn write_batch(&mut self, batch: &BatchInfo, contents: &[u8]) -> io::Result<()> {
+        self(batch, contents)
+    }
+}
+
+/// Pipes every batch into the standard input of a shell command, e.g. `bcftools stats -`, to process the
+/// batches without writing them to files first. The command runs once per batch, with the number of the batch
+/// in the environment variable `VCF_BATCHER_BATCH_NUMBER` and the file name it would have had in
+/// `VCF_BATCHER_BATCH_FILE`, e.g. to name its output. Its standard output and error are those of this process.
+///
+/// Up to `max_running` commands run at the same time; the next batch waits until the oldest command exits.
+/// Commands that exit with an error do not stop the run, they are collected and returned by
+/// [`CommandSink::finish`]. A command that cannot be started stops the run.
+///
+/// # Examples
+///
+/// ```no_run
+/// use vcf_batcher::sink::CommandSink;
+/// use vcf_batcher::{extract_variants_to_sink, BatchOptions};
+///
+/// let mut sink = CommandSink::new("bcftools stats - > stats_$VCF_BATCHER_BATCH_NUMBER.txt", 4);
+/// let options = BatchOptions { batch_size: 50, ..Default::default() };
+/// let report = extract_variants_to_sink("test_data/batch_01.vcf.gz", &mut sink, &options)
+///     .and_then(|report| sink.finish(report))
+///     .unwrap();
+/// ```
+#[derive(Debug)]
+pub struct CommandSink {
+    command: String,
+    max_running: usize,
+    running: VecDeque<RunningCommand>,
+    failures: Vec<CommandFailure>,
+}
+
+/// A batch whose command failed, see [`CommandSink`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct CommandFailure {
+    /// The number of the batch.
+    pub number: usize,
+    /// The file name the batch would have had.
+    pub path: PathBuf,
+    /// Why the command failed, e.g. `exit status: 1`.
+    pub reason: String,
+}
+
+#[derive(Debug)]
+struct RunningCommand {
+    number: usize,
+    path: PathBuf,
+    child: Child,
+    /// Writes the batch to the standard input of the command, which is closed once it is written.
+    feeder: JoinHandle<io::Result<()>>,
+}
+
+impl CommandSink {
+    /// Runs `command` with `sh -c` (`cmd /C` on Windows) for every batch, at most `max_running` at a time.
+    /// Values below 1 count as 1.
+    pub fn new(command: impl Into<String>, max_running: usize) -> Self {
+        CommandSink {
+            command: command.into(),
+            max_running: max_running.max(1),
+            running: VecDeque::new(),
+            failures: Vec::new(),
+        }
+    }
+
+    /// Waits for the commands that are still running. Returns `report` if every command succeeded and
+    /// [`VcfBatcherError::CommandFailed`] with the failed batches otherwise.
+    pub fn finish(mut self, report: RunReport) -> Result<RunReport, VcfBatcherError> {
+        while let Some(running) = self.running.pop_front() {
+            self.wait(running);
+        }
+        if self.failures.is_empty() {
+            return Ok(report);
+        }
+        Err(VcfBatcherError::CommandFailed {
+            command: std::mem::take(&mut self.command),
+            failures: std::mem::take(&mut self.failures),
+            completed: Box::new(report),
+        })
+    }
+
+    fn wait(&mut self, running: RunningCommand) {
+        let RunningCommand {
+            number,
+            path,
+            mut child,
+            feeder,
+        } = running;
+        let written = feeder
+            .join()
+            .unwrap_or_else(|_| Err(io::Error::other("the batch could not be written")));
+        let reason = match (child.wait(), written) {
+            (Err(error), _) => Some(format!("could not wait for the command: {error}")),
+            (Ok(status), _) if !status.success() => Some(status.to_string()),
+            // A command may exit without reading its whole input, e.g. `head`
+            (Ok(_), Err(error)) if error.kind() != io::ErrorKind::BrokenPipe => Some(format!(
+                "could not pipe the batch into the command: {error}"
+            )),
+            (Ok(_), _) => None,
+        };
+        if let Some(reason) = reason {
+            self.failures.push(CommandFailure {
+                number,
+                path,
+                reason,
+            });
+        }
+    }
+}
+
+impl BatchSink for CommandSink {
+    fn write_batch(&mut self, batch: &BatchInfo, contents: &[u8]) -> io::Result<()> {
+        while self.running.len() >= self.max_running {
+            if let Some(running) = self.running.pop_front() {
+                self.wait(running);
+            }
+        }
+        let file_name = batch.path.file_name().unwrap_or(batch.path.as_os_str());
+        let mut child = shell_command(&self.command)
+            .env("VCF_BATCHER_BATCH_NUMBER", batch.number.to_string())
+            .env("VCF_BATCHER_BATCH_FILE", file_name)
+            .stdin(Stdio::piped())
+            .spawn()?;
+        let mut stdin = child.stdin.take().expect("stdin is piped");
+        // The command may produce output before it has read its input, so the batch is written on a thread
+        let contents = contents.to_vec();
+        let feeder = thread::spawn(move || stdin.write_all(&contents));
+        self.running.push_back(RunningCommand {
+            number: batch.number,
+            path: batch.path.clone(),
+            child,
+            feeder,
+        });
+        Ok(())
+    }
+}
+
+impl Drop for CommandSink {
+    /// Waits for the commands that are still running, e.g. if the run failed, so that none is left behind.
+    fn drop(&mut self) {
+        while let Some(running) = self.running.pop_front() {
+            self.wait(running);
+        }
+    }
+}
+
+#[cfg(not(windows))]
+fn shell_command(command: &str) -> Command {
+    let mut shell = Command::new("sh");
+    shell.arg("-c").arg(command);
+    shell
+}
+
+#[cfg(windows)]
+fn shell_command(command: &str) -> Command {
+    let mut shell = Command::new("cmd");
+    shell.arg("/C").arg(command);
+    shell
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use std::path::Path;
+
+    use super::CommandSink;
+    use crate::{extract_variants_to_sink, BatchOptions, VcfBatcherError};
+
+    #[test]
+    fn test_command_sink() {
+        let output_path = Path::new("./test_data/command_sink");
+        let _ = std::fs::remove_dir_all(output_path);
+        std::fs::create_dir_all(output_path).unwrap();
+        let options = BatchOptions {
+            batch_size: 30,
+            quiet: true,
+            ..Default::default()
+        };
+        // Counts the lines of every batch, and fails for the second one
+        let mut sink = CommandSink::new(
+            "wc -l > test_data/command_sink/$VCF_BATCHER_BATCH_FILE.txt && test $VCF_BATCHER_BATCH_NUMBER != 2",
+            2,
+        );
+        let error = extract_variants_to_sink("test_data/batch_01.vcf.gz", &mut sink, &options)
+            .and_then(|report| sink.finish(report))
+            .unwrap_err();
+
+        let VcfBatcherError::CommandFailed {
+            failures,
+            completed,
+            ..
+        } = &error
+        else {
+            panic!("{error}");
+        };
+        assert_eq!(completed.batches.len(), 4);
+        assert_eq!(failures.len(), 1);
+        assert_eq!(failures[0].number, 2);
+        assert_eq!(failures[0].reason, "exit status: 1");
+        // The header and the 30 records
+        let lines = std::fs::read_to_string(output_path.join("batch_01.vcf.txt")).unwrap();
+        assert!(lines.trim().parse::<usize>().unwrap() > 30);
+    }
+}
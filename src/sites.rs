@@ -0,0 +1,345 @@
+//! Tables of the variant sites in a batch, for joining or querying them with tools such as DuckDB
+//! or Spark without parsing VCF. Writing Parquet needs the `parquet` feature.
+
+#[cfg(feature = "parquet")]
+use std::io;
+use std::path::{Path, PathBuf};
+#[cfg(feature = "parquet")]
+use std::sync::Arc;
+
+#[cfg(feature = "parquet")]
+use parquet::basic::{Compression, LogicalType, Repetition, Type as PhysicalType};
+#[cfg(feature = "parquet")]
+use parquet::data_type::{ByteArray, ByteArrayType, DoubleType, Int64Type};
+#[cfg(feature = "parquet")]
+use parquet::file::properties::WriterProperties;
+#[cfg(feature = "parquet")]
+use parquet::file::writer::SerializedFileWriter;
+#[cfg(feature = "parquet")]
+use parquet::schema::types::Type;
+
+/// The columns of a variant record that every Parquet table has, in this order.
+pub const SITE_COLUMNS: [&str; 7] = ["CHROM", "POS", "ID", "REF", "ALT", "QUAL", "FILTER"];
+
+/// The columns of a site list, see [`site_list`].
+pub const SITE_LIST_COLUMNS: [&str; 4] = ["CHROM", "POS", "REF", "ALT"];
+
+/// Replaces the extension of a batch path, e.g. `.vcf.gz`, with `.sites.tsv`.
+///
+/// # Examples
+///
+/// ```
+/// use std::path::Path;
+/// use vcf_batcher::sites::site_list_path;
+///
+/// assert_eq!(site_list_path(Path::new("out/batch_01.vcf.gz")), Path::new("out/batch_01.sites.tsv"));
+/// ```
+pub fn site_list_path(path: &Path) -> PathBuf {
+    with_extension(path, "sites.tsv")
+}
+
+/// Lists the CHROM, POS, REF and ALT of the variant records of a batch as tab-separated values,
+/// below a line with the [`SITE_LIST_COLUMNS`].
+///
+/// # Examples
+///
+/// ```
+/// use vcf_batcher::sites::site_list;
+///
+/// let records = "chr1\t5\trs1\tA\tC\t30\tPASS\t.\nchr1\t9\t.\tG\tT,C\t.\t.\t.\n";
+/// assert_eq!(site_list(records), "CHROM\tPOS\tREF\tALT\nchr1\t5\tA\tC\nchr1\t9\tG\tT,C\n");
+/// ```
+pub fn site_list(records: &str) -> String {
+    let mut list = SITE_LIST_COLUMNS.join("\t") + "\n";
+    for record in records.lines() {
+        let mut columns = record.split('\t');
+        let (chrom, pos) = (columns.next(), columns.next());
+        let (reference, alternate) = (columns.nth(1), columns.next());
+        for (index, value) in [chrom, pos, reference, alternate].into_iter().enumerate() {
+            if index > 0 {
+                list.push('\t');
+            }
+            list.push_str(value.unwrap_or("."));
+        }
+        list.push('\n');
+    }
+    list
+}
+
+/// Replaces the `.vcf`, `.vcf.gz` or `.gz` extension of a batch path with `.parquet`.
+///
+/// # Examples
+///
+/// ```
+/// use std::path::Path;
+/// use vcf_batcher::sites::parquet_path;
+///
+/// assert_eq!(parquet_path(Path::new("out/batch_01.vcf.gz")), Path::new("out/batch_01.parquet"));
+/// assert_eq!(parquet_path(Path::new("chr1.vcf")), Path::new("chr1.parquet"));
+/// ```
+#[cfg(feature = "parquet")]
+pub fn parquet_path(path: &Path) -> PathBuf {
+    with_extension(path, "parquet")
+}
+
+/// Replaces the `.vcf` or `.jsonl` extension of a batch path, with or without `.gz`.
+fn with_extension(path: &Path, extension: &str) -> PathBuf {
+    let name = path
+        .file_name()
+        .map(|name| name.to_string_lossy())
+        .unwrap_or_default();
+    let stem = name.strip_suffix(".gz").unwrap_or(&name);
+    let stem = [".vcf", ".jsonl"]
+        .iter()
+        .find_map(|suffix| stem.strip_suffix(suffix))
+        .unwrap_or(stem);
+    path.with_file_name(format!("{stem}.{extension}"))
+}
+
+/// Encodes the sites of the variant records of a batch as a Parquet file with Snappy compression.
+///
+/// Every record becomes a row with the [`SITE_COLUMNS`]: POS is an integer, QUAL a floating point
+/// number and the others are strings. Missing values (`.`) of ID, QUAL and FILTER are null.
+/// Every key in `info_keys` adds a string column `INFO_<key>` with the value of that INFO
+/// field, which is null if the record does not have it and empty for flags.
+///
+/// # Examples
+///
+/// ```
+/// use vcf_batcher::sites::parquet_table;
+///
+/// let records = "chr1\t5\trs1\tA\tC\t30\tPASS\tAF=0.5;DB\nchr1\t9\t.\tG\tT\t.\t.\tAF=0.1\n";
+/// let bytes = parquet_table(records, &["AF".to_string(), "DB".to_string()]).unwrap();
+/// assert!(bytes.starts_with(b"PAR1"));
+/// ```
+#[cfg(feature = "parquet")]
+pub fn parquet_table(records: &str, info_keys: &[String]) -> io::Result<Vec<u8>> {
+    let invalid = |reason: String| io::Error::new(io::ErrorKind::InvalidData, reason);
+
+    let mut chrom = Vec::new();
+    let mut pos = Vec::new();
+    let mut ids = Column::default();
+    let mut reference = Vec::new();
+    let mut alternate = Vec::new();
+    let mut qual = Vec::new();
+    let mut qual_levels = Vec::new();
+    let mut filter = Column::default();
+    let mut info: Vec<Column> = info_keys.iter().map(|_| Column::default()).collect();
+    for (index, record) in records.lines().enumerate() {
+        let fields: Vec<&str> = record.split('\t').collect();
+        if fields.len() < 8 {
+            return Err(invalid(format!(
+                "record {} has {} columns instead of at least 8",
+                index + 1,
+                fields.len()
+            )));
+        }
+        chrom.push(ByteArray::from(fields[0]));
+        pos.push(
+            fields[1]
+                .parse::<i64>()
+                .map_err(|_| invalid(format!("POS '{}' is not a whole number", fields[1])))?,
+        );
+        ids.push(missing_as_null(fields[2]));
+        reference.push(ByteArray::from(fields[3]));
+        alternate.push(ByteArray::from(fields[4]));
+        match fields[5] {
+            "." => qual_levels.push(0),
+            value => {
+                qual.push(
+                    value
+                        .parse::<f64>()
+                        .map_err(|_| invalid(format!("QUAL '{}' is not a number", value)))?,
+                );
+                qual_levels.push(1);
+            }
+        }
+        filter.push(missing_as_null(fields[6]));
+        for (key, column) in info_keys.iter().zip(&mut info) {
+            column.push(info_value(fields[7], key));
+        }
+    }
+
+    let mut fields = vec![
+        column_type("CHROM", PhysicalType::BYTE_ARRAY, Repetition::REQUIRED)?,
+        column_type("POS", PhysicalType::INT64, Repetition::REQUIRED)?,
+        column_type("ID", PhysicalType::BYTE_ARRAY, Repetition::OPTIONAL)?,
+        column_type("REF", PhysicalType::BYTE_ARRAY, Repetition::REQUIRED)?,
+        column_type("ALT", PhysicalType::BYTE_ARRAY, Repetition::REQUIRED)?,
+        column_type("QUAL", PhysicalType::DOUBLE, Repetition::OPTIONAL)?,
+        column_type("FILTER", PhysicalType::BYTE_ARRAY, Repetition::OPTIONAL)?,
+    ];
+    for key in info_keys {
+        fields.push(column_type(
+            &format!("INFO_{key}"),
+            PhysicalType::BYTE_ARRAY,
+            Repetition::OPTIONAL,
+        )?);
+    }
+    let schema = Type::group_type_builder("variant_site")
+        .with_fields(fields)
+        .build()
+        .map_err(io::Error::other)?;
+    let properties = WriterProperties::builder()
+        .set_compression(Compression::SNAPPY)
+        .build();
+
+    let mut writer = SerializedFileWriter::new(Vec::new(), Arc::new(schema), Arc::new(properties))
+        .map_err(io::Error::other)?;
+    let mut row_group = writer.next_row_group().map_err(io::Error::other)?;
+    let mut index = 0;
+    // The columns are handed out in the order of the schema
+    while let Some(mut column) = row_group.next_column().map_err(io::Error::other)? {
+        let written = match index {
+            1 => column.typed::<Int64Type>().write_batch(&pos, None, None),
+            5 => column
+                .typed::<DoubleType>()
+                .write_batch(&qual, Some(&qual_levels), None),
+            _ => {
+                let strings = column.typed::<ByteArrayType>();
+                match index {
+                    0 => strings.write_batch(&chrom, None, None),
+                    2 => ids.write(strings),
+                    3 => strings.write_batch(&reference, None, None),
+                    4 => strings.write_batch(&alternate, None, None),
+                    6 => filter.write(strings),
+                    _ => info[index - SITE_COLUMNS.len()].write(strings),
+                }
+            }
+        };
+        written.map_err(io::Error::other)?;
+        column.close().map_err(io::Error::other)?;
+        index += 1;
+    }
+    row_group.close().map_err(io::Error::other)?;
+    writer.into_inner().map_err(io::Error::other)
+}
+
+/// The values of a nullable string column and their definition levels (0 for null).
+#[cfg(feature = "parquet")]
+#[derive(Default)]
+struct Column {
+    values: Vec<ByteArray>,
+    levels: Vec<i16>,
+}
+
+#[cfg(feature = "parquet")]
+impl Column {
+    fn push(&mut self, value: Option<&str>) {
+        match value {
+            Some(value) => {
+                self.values.push(ByteArray::from(value));
+                self.levels.push(1);
+            }
+            None => self.levels.push(0),
+        }
+    }
+
+    fn write(
+        &self,
+        writer: &mut parquet::column::writer::ColumnWriterImpl<'_, ByteArrayType>,
+    ) -> parquet::errors::Result<usize> {
+        writer.write_batch(&self.values, Some(&self.levels), None)
+    }
+}
+
+#[cfg(feature = "parquet")]
+fn column_type(
+    name: &str,
+    physical: PhysicalType,
+    repetition: Repetition,
+) -> io::Result<Arc<Type>> {
+    let logical = (physical == PhysicalType::BYTE_ARRAY).then_some(LogicalType::String);
+    Type::primitive_type_builder(name, physical)
+        .with_repetition(repetition)
+        .with_logical_type(logical)
+        .build()
+        .map(Arc::new)
+        .map_err(io::Error::other)
+}
+
+#[cfg(feature = "parquet")]
+fn missing_as_null(value: &str) -> Option<&str> {
+    (value != ".").then_some(value)
+}
+
+/// The value of an INFO key, empty for flags and `None` if the record does not have the key.
+#[cfg(feature = "parquet")]
+fn info_value<'a>(info: &'a str, key: &str) -> Option<&'a str> {
+    info.split(';')
+        .find_map(|field| match field.split_once('=') {
+            Some((name, value)) if name == key => Some(value),
+            None if field == key => Some(""),
+            _ => None,
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    #[cfg(feature = "parquet")]
+    use std::fs::{self, File};
+
+    #[cfg(feature = "parquet")]
+    use parquet::file::reader::{FileReader, SerializedFileReader};
+    #[cfg(feature = "parquet")]
+    use parquet::record::RowAccessor;
+
+    use super::site_list_path;
+    #[cfg(feature = "parquet")]
+    use super::{info_value, parquet_path, parquet_table};
+
+    #[test]
+    fn test_site_list_path() {
+        assert_eq!(
+            site_list_path("chr1.jsonl.gz".as_ref()).to_str(),
+            Some("chr1.sites.tsv")
+        );
+        assert_eq!(
+            site_list_path("batches/part_7.txt".as_ref()).to_str(),
+            Some("batches/part_7.txt.sites.tsv")
+        );
+    }
+
+    #[cfg(feature = "parquet")]
+    #[test]
+    fn test_info_value() {
+        assert_eq!(info_value("AF=0.5;DB;DP=10", "DP"), Some("10"));
+        assert_eq!(info_value("AF=0.5;DB;DP=10", "DB"), Some(""));
+        assert_eq!(info_value("AF=0.5;DB;DP=10", "D"), None);
+        assert_eq!(info_value(".", "AF"), None);
+    }
+
+    #[cfg(feature = "parquet")]
+    #[test]
+    fn test_parquet_table() {
+        assert_eq!(
+            parquet_path("batch_01.jsonl".as_ref()).to_str(),
+            Some("batch_01.parquet")
+        );
+
+        let records =
+            "chr1\t5\trs1\tA\tC\t30.5\tPASS\tAF=0.5;DB\nchr2\t9\t.\tG\tT,C\t.\t.\tAF=0.1\n";
+        let bytes = parquet_table(records, &["AF".to_string(), "DB".to_string()]).unwrap();
+
+        fs::create_dir_all("./test_data/sites").unwrap();
+        fs::write("./test_data/sites/table.parquet", bytes).unwrap();
+
+        let file = File::open("./test_data/sites/table.parquet").unwrap();
+        let reader = SerializedFileReader::new(file).unwrap();
+        assert_eq!(reader.metadata().file_metadata().num_rows(), 2);
+        let rows: Vec<_> = reader
+            .get_row_iter(None)
+            .unwrap()
+            .map(Result::unwrap)
+            .collect();
+        assert_eq!(rows[0].get_string(0).unwrap(), "chr1");
+        assert_eq!(rows[0].get_long(1).unwrap(), 5);
+        assert_eq!(rows[0].get_double(5).unwrap(), 30.5);
+        assert_eq!(rows[0].get_string(8).unwrap(), "");
+        assert_eq!(rows[1].get_string(4).unwrap(), "T,C");
+        assert!(rows[1].get_string(2).is_err());
+        assert!(rows[1].get_double(5).is_err());
+        assert_eq!(rows[1].get_string(7).unwrap(), "0.1");
+        assert!(rows[1].get_string(8).is_err());
+    }
+}
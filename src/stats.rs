@@ -0,0 +1,199 @@
+//! Summary statistics of a VCF file.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use serde::Serialize;
+
+use crate::merge::batch_files;
+use crate::{header, is_header_line, read_lines, Locus, VcfBatcherError};
+
+/// Counts of the lines in a VCF file.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct VcfStats {
+    pub path: PathBuf,
+    /// Number of header lines, including the `#CHROM` line.
+    pub header_lines: usize,
+    /// Number of variant records.
+    pub records: usize,
+    /// The sample names of the `#CHROM` line.
+    pub samples: Vec<String>,
+    /// Number of variant records per contig.
+    pub records_per_contig: BTreeMap<String, usize>,
+}
+
+/// Reads a (possibly bgzipped) VCF file once and counts its header lines, records and samples.
+///
+/// # Examples
+///
+/// ```
+/// use std::path::Path;
+/// use vcf_batcher::stats::vcf_stats;
+///
+/// let stats = vcf_stats(Path::new("test_data/batch_01.vcf.gz")).unwrap();
+/// assert_eq!(stats.records, 100);
+/// assert_eq!(stats.records_per_contig["22"], 100);
+/// ```
+pub fn vcf_stats(file_path: &Path) -> Result<VcfStats, VcfBatcherError> {
+    let read_error = |source| VcfBatcherError::Read {
+        path: file_path.to_path_buf(),
+        source,
+    };
+
+    let mut stats = VcfStats {
+        path: file_path.to_path_buf(),
+        ..Default::default()
+    };
+    for line in read_lines(file_path).map_err(read_error)? {
+        let line = line.map_err(read_error)?;
+        if is_header_line(&line) {
+            stats.header_lines += 1;
+            if line.starts_with("#CHROM") {
+                stats.samples = header::sample_names(&line)
+                    .into_iter()
+                    .map(str::to_string)
+                    .collect();
+            }
+            continue;
+        }
+
+        stats.records += 1;
+        let chrom = header::record_chrom(&line);
+        match stats.records_per_contig.get_mut(chrom) {
+            Some(count) => *count += 1,
+            None => {
+                stats.records_per_contig.insert(chrom.to_string(), 1);
+            }
+        }
+    }
+    Ok(stats)
+}
+
+/// Summary of a batch file in an output directory, see [`summarize_batches`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct BatchSummary {
+    pub path: PathBuf,
+    /// Number of variant records.
+    pub records: usize,
+    /// Position of the first record, `None` if the batch only contains the header.
+    pub first: Option<Locus>,
+    /// Position of the last record, `None` if the batch only contains the header.
+    pub last: Option<Locus>,
+    /// Size of the file on disk.
+    pub bytes: u64,
+}
+
+/// Reads every batch in an output directory, in the order [`crate::merge::merge_batches`] would
+/// merge them, and summarizes it. Unlike the report of a run, this also works for directories that
+/// were written by an earlier run or by another process.
+///
+/// # Examples
+///
+/// ```
+/// use std::path::Path;
+/// use vcf_batcher::extract_variants_to_batches;
+/// use vcf_batcher::stats::summarize_batches;
+///
+/// let output = Path::new("test_data/doc_summarize");
+/// extract_variants_to_batches("test_data/batch_01.vcf.gz", 60, output, None).unwrap();
+/// let batches = summarize_batches(output).unwrap();
+/// assert_eq!(batches[1].records, 40);
+/// ```
+pub fn summarize_batches(batch_dir: &Path) -> Result<Vec<BatchSummary>, VcfBatcherError> {
+    let mut summaries = Vec::new();
+    for path in batch_files(batch_dir, Path::new(""))? {
+        let read_error = |source| VcfBatcherError::Read {
+            path: path.clone(),
+            source,
+        };
+        let bytes = fs::metadata(&path).map_err(read_error)?.len();
+
+        let mut records = 0;
+        let mut first = None;
+        let mut last_record = None;
+        for line in read_lines(&path).map_err(read_error)? {
+            let line = line.map_err(read_error)?;
+            if is_header_line(&line) {
+                continue;
+            }
+            records += 1;
+            if first.is_none() {
+                first = Locus::from_record(&line);
+            }
+            last_record = Some(line);
+        }
+
+        summaries.push(BatchSummary {
+            last: last_record.as_deref().and_then(Locus::from_record),
+            path,
+            records,
+            first,
+            bytes,
+        });
+    }
+    Ok(summaries)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::{summarize_batches, vcf_stats};
+    use crate::VcfBatcherError;
+
+    #[test]
+    fn test_vcf_stats() {
+        let path = Path::new("./test_data/inputs/stats.vcf");
+        std::fs::create_dir_all("./test_data/inputs").unwrap();
+        std::fs::write(
+            path,
+            "##fileformat=VCFv4.2\n\
+             #CHROM\tPOS\tID\tREF\tALT\tQUAL\tFILTER\tINFO\tFORMAT\tA\tB\n\
+             chr1\t1\t.\tA\tC\t.\tPASS\t.\tGT\t0/1\t0/0\n\
+             chr2\t1\t.\tA\tC\t.\tPASS\t.\tGT\t0/1\t0/0\n\
+             chr1\t5\t.\tA\tC\t.\tPASS\t.\tGT\t0/1\t0/0\n",
+        )
+        .unwrap();
+
+        let stats = vcf_stats(path).unwrap();
+
+        assert_eq!(stats.header_lines, 2);
+        assert_eq!(stats.records, 3);
+        assert_eq!(stats.samples, vec!["A", "B"]);
+        assert_eq!(stats.records_per_contig["chr1"], 2);
+        assert_eq!(stats.records_per_contig["chr2"], 1);
+    }
+
+    #[test]
+    fn test_summarize_batches() {
+        let output_path = Path::new("./test_data/summarize");
+        let _ = std::fs::remove_dir_all(output_path);
+        let report = crate::extract_variants_to_batches(
+            "./test_data/batch_01.vcf.gz",
+            30,
+            output_path,
+            None,
+        )
+        .unwrap();
+
+        let summaries = summarize_batches(output_path).unwrap();
+
+        assert_eq!(summaries.len(), 4);
+        for (summary, batch) in summaries.iter().zip(&report.batches) {
+            assert_eq!(summary.path, batch.path);
+            assert_eq!(summary.records, batch.records);
+            assert_eq!(summary.first, batch.first);
+            assert_eq!(summary.last, batch.last);
+            assert_eq!(summary.bytes, std::fs::metadata(&batch.path).unwrap().len());
+        }
+    }
+
+    #[test]
+    fn test_vcf_stats_of_missing_file() {
+        assert!(matches!(
+            vcf_stats(Path::new("./test_data/does_not_exist.vcf")),
+            Err(VcfBatcherError::Read { .. })
+        ));
+    }
+}
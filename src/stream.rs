@@ -0,0 +1,306 @@
+//! Writing a batch to its file while its records are collected, so that memory stays proportional to a single
+//! record instead of a whole batch.
+
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use bgzip::write::{BGZFMultiThreadWriter, BGZFWriter, DEFAULT_COMPRESS_UNIT_SIZE};
+use bgzip::{BGZFReader, Compression};
+use sha2::{Digest, Sha256};
+
+use crate::recompress::{self, RecompressOptions};
+use crate::{is_header_line, move_file, staging_path, Locus};
+
+/// How many blocks the parallel compressor compresses at a time on each thread, which bounds its memory.
+const BLOCKS_PER_THREAD: usize = 8;
+
+/// A batch whose header and records are written to a hidden file in the output directory, or in the temporary
+/// directory if one is given, as they arrive. The file is moved to the path of the batch once the batch is
+/// complete, because its name can depend on its last record, and removed if the batch is dropped instead.
+pub(crate) struct BatchFile {
+    staged_path: PathBuf,
+    compression_level: Option<Compression>,
+    /// How the file is compressed with zstd, `None` if it is bgzipped or plain.
+    zstd: Option<RecompressOptions>,
+    /// `None` once the file was closed.
+    encoder: Option<Encoder>,
+    file: Arc<Mutex<StagedFile>>,
+    /// The header at the top of the file, which is rewritten if lines are added to the header later on.
+    headers: String,
+    first: Option<Locus>,
+    last: Option<Locus>,
+}
+
+impl std::fmt::Debug for BatchFile {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("BatchFile")
+            .field("staged_path", &self.staged_path)
+            .field("size", &self.size())
+            .finish()
+    }
+}
+
+impl BatchFile {
+    /// Creates the file of a batch in `staging_dir` and writes the header. With `measured`, compressed
+    /// blocks reach the file as soon as they are full, so that [`BatchFile::size`] does not lag behind.
+    pub(crate) fn create(
+        staging_dir: &Path,
+        headers: &str,
+        compression_level: Option<Compression>,
+        zstd: Option<RecompressOptions>,
+        measured: bool,
+    ) -> io::Result<Self> {
+        fs::create_dir_all(staging_dir)?;
+        let staged_path = staging_path(staging_dir, Path::new(".batch.vcf"));
+        let file = Arc::new(Mutex::new(StagedFile {
+            file: Some(BufWriter::new(File::create(&staged_path)?)),
+            hasher: Sha256::new(),
+            size: 0,
+            failed: false,
+        }));
+        let mut batch_file = BatchFile {
+            encoder: Some(Encoder::new(
+                SharedFile(file.clone()),
+                compression_level,
+                zstd,
+                measured,
+            )?),
+            staged_path,
+            compression_level,
+            zstd,
+            file,
+            headers: headers.to_string(),
+            first: None,
+            last: None,
+        };
+        batch_file.write(headers.as_bytes())?;
+        Ok(batch_file)
+    }
+
+    /// Appends a record and a line ending.
+    pub(crate) fn write_record(&mut self, record: &str) -> io::Result<()> {
+        self.write(record.as_bytes())?;
+        self.write(b"\n")?;
+        let locus = Locus::from_record(record);
+        if self.first.is_none() {
+            self.first = locus.clone();
+        }
+        self.last = locus;
+        Ok(())
+    }
+
+    /// Appends records that each end with a line ending, e.g. those of a batch that is refilled.
+    pub(crate) fn write_records(&mut self, records: &str) -> io::Result<()> {
+        for record in records.lines() {
+            self.write_record(record)?;
+        }
+        Ok(())
+    }
+
+    fn write(&mut self, bytes: &[u8]) -> io::Result<()> {
+        match &mut self.encoder {
+            Some(encoder) => encoder.write_all(bytes),
+            None => Err(io::Error::other("the batch file is already closed")),
+        }
+    }
+
+    /// The number of bytes that reached the file so far, i.e. its size without the blocks that are still
+    /// being compressed.
+    pub(crate) fn size(&self) -> u64 {
+        self.file.lock().map_or(0, |file| file.size)
+    }
+
+    pub(crate) fn first(&self) -> Option<&Locus> {
+        self.first.as_ref()
+    }
+
+    pub(crate) fn last(&self) -> Option<&Locus> {
+        self.last.as_ref()
+    }
+
+    /// Completes the file with `headers` at its top and moves it to `path`. Returns the SHA-256 checksum of
+    /// the file.
+    pub(crate) fn finish(mut self, headers: &str, path: &Path) -> io::Result<String> {
+        if headers != self.headers {
+            self.rewrite(headers)?;
+        }
+        self.close()?;
+        let sha256 = {
+            let mut file = lock(&self.file);
+            let writer = file
+                .file
+                .take()
+                .expect("the batch file is only finished once");
+            writer.get_ref().sync_all()?;
+            format!("{:x}", std::mem::take(&mut file.hasher).finalize())
+        };
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        move_file(&self.staged_path, path)?;
+        // The file is gone, there is nothing left to remove
+        self.staged_path = PathBuf::new();
+        Ok(sha256)
+    }
+
+    /// Writes the records to a new file below a header that changed since the file was created.
+    fn rewrite(&mut self, headers: &str) -> io::Result<()> {
+        let measured = matches!(self.encoder, Some(Encoder::Bgzf(_)));
+        self.close()?;
+        let staging_dir = self.staged_path.parent().unwrap_or(Path::new(""));
+        let mut rewritten = BatchFile::create(
+            staging_dir,
+            headers,
+            self.compression_level,
+            self.zstd,
+            measured,
+        )?;
+        let file = File::open(&self.staged_path)?;
+        let reader: Box<dyn BufRead> = match (self.compression_level, self.zstd) {
+            (Some(_), None) => Box::new(BufReader::new(
+                BGZFReader::new(file).map_err(bgzip::BGZFError::into_io_error)?,
+            )),
+            (Some(_), Some(_)) => Box::new(BufReader::new(recompress::decoder(file)?)),
+            (None, _) => Box::new(BufReader::new(file)),
+        };
+        for line in reader.lines() {
+            let line = line?;
+            if !is_header_line(&line) {
+                rewritten.write(line.as_bytes())?;
+                rewritten.write(b"\n")?;
+            }
+        }
+        rewritten.first = self.first.take();
+        rewritten.last = self.last.take();
+        // The old file is removed when it is dropped
+        std::mem::swap(self, &mut rewritten);
+        Ok(())
+    }
+
+    /// Compresses what is left, writes the end of the file and flushes it.
+    fn close(&mut self) -> io::Result<()> {
+        if let Some(encoder) = self.encoder.take() {
+            encoder.close()?;
+        }
+        match lock(&self.file).file.as_mut() {
+            Some(writer) => writer.flush(),
+            None => Ok(()),
+        }
+    }
+}
+
+impl Drop for BatchFile {
+    fn drop(&mut self) {
+        // A batch that was not completed must not look like one
+        drop(self.encoder.take());
+        if let Ok(mut file) = self.file.lock() {
+            file.file = None;
+        }
+        if !self.staged_path.as_os_str().is_empty() {
+            let _ = fs::remove_file(&self.staged_path);
+        }
+    }
+}
+
+/// Compresses the contents of a batch file, if it is compressed.
+enum Encoder {
+    Plain(SharedFile),
+    /// Writes every block as soon as it is full.
+    Bgzf(BGZFWriter<SharedFile>),
+    Parallel(BGZFMultiThreadWriter<SharedFile>),
+    Zstd(recompress::Encoder<SharedFile>),
+}
+
+impl Encoder {
+    fn new(
+        file: SharedFile,
+        compression_level: Option<Compression>,
+        zstd: Option<RecompressOptions>,
+        measured: bool,
+    ) -> io::Result<Self> {
+        Ok(match (compression_level, zstd) {
+            (None, _) => Encoder::Plain(file),
+            (Some(_), Some(options)) => Encoder::Zstd(recompress::Encoder::new(file, &options)?),
+            (Some(level), None) if measured => Encoder::Bgzf(BGZFWriter::new(file, level)),
+            (Some(level), None) => Encoder::Parallel(
+                BGZFMultiThreadWriter::with_compress_unit_size(
+                    file,
+                    DEFAULT_COMPRESS_UNIT_SIZE,
+                    BLOCKS_PER_THREAD,
+                    level,
+                    false,
+                )
+                .expect("the default block size is valid"),
+            ),
+        })
+    }
+
+    fn write_all(&mut self, bytes: &[u8]) -> io::Result<()> {
+        match self {
+            Encoder::Plain(file) => file.write_all(bytes),
+            Encoder::Bgzf(writer) => writer.write_all(bytes),
+            Encoder::Parallel(writer) => writer.write_all(bytes),
+            Encoder::Zstd(encoder) => encoder.write_all(bytes),
+        }
+    }
+
+    fn close(self) -> io::Result<()> {
+        match self {
+            Encoder::Plain(mut file) => file.flush(),
+            Encoder::Bgzf(writer) => writer.close().map(drop),
+            Encoder::Parallel(writer) => writer.close().map(drop),
+            Encoder::Zstd(encoder) => encoder.finish(),
+        }
+    }
+}
+
+/// The file that a batch is staged in, with the checksum and size of what was written to it so far.
+struct StagedFile {
+    /// `None` once the file was closed.
+    file: Option<BufWriter<File>>,
+    hasher: Sha256,
+    size: u64,
+    /// Set when a write failed. The writers of the `bgzip` crate write the rest of the file when they are
+    /// dropped and panic if that fails, so everything after the first error is discarded instead.
+    failed: bool,
+}
+
+/// The staged file, shared between the batch and the writer that compresses into it, since the writers of
+/// the `bgzip` crate do not hand back what they write to when they are closed.
+struct SharedFile(Arc<Mutex<StagedFile>>);
+
+impl Write for SharedFile {
+    fn write(&mut self, bytes: &[u8]) -> io::Result<usize> {
+        let mut file = lock(&self.0);
+        let file = &mut *file;
+        let Some(writer) = file.file.as_mut().filter(|_| !file.failed) else {
+            return Ok(bytes.len());
+        };
+        match writer.write_all(bytes) {
+            Ok(()) => {
+                file.hasher.update(bytes);
+                file.size += bytes.len() as u64;
+                Ok(bytes.len())
+            }
+            Err(error) => {
+                file.failed = true;
+                Err(error)
+            }
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        let mut file = lock(&self.0);
+        let file = &mut *file;
+        match file.file.as_mut() {
+            Some(writer) if !file.failed => writer.flush(),
+            _ => Ok(()),
+        }
+    }
+}
+
+fn lock(file: &Mutex<StagedFile>) -> MutexGuard<'_, StagedFile> {
+    file.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+}
@@ -0,0 +1,334 @@
+//! Traces of split runs in the OpenTelemetry format, enabled by the `otel` feature, so that long
+//! runs show up in distributed tracing next to the other steps of a workflow.
+//!
+//! A run is a `vcf_batcher.split` span with a `vcf_batcher.batch` child span per batch, which lasts
+//! from the end of the previous batch until the batch was written. The time spent in the stages of
+//! the run is added to the run span if [`BatchOptions::timings`] is set. The spans are encoded as
+//! OTLP JSON and sent to a collector's OTLP/HTTP endpoint, see [`traces_endpoint`].
+
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde_json::{json, Value};
+
+use crate::webhook::Webhook;
+use crate::{BatchCallback, BatchOptions, RunReport, VcfBatcherError};
+
+/// The kind of all spans, `SPAN_KIND_INTERNAL`.
+const SPAN_KIND_INTERNAL: u8 = 1;
+const STATUS_CODE_OK: u8 = 1;
+const STATUS_CODE_ERROR: u8 = 2;
+
+/// The spans of a run that is being traced.
+///
+/// # Examples
+///
+/// ```
+/// use std::path::Path;
+/// use vcf_batcher::telemetry::RunTrace;
+/// use vcf_batcher::{extract_variants_to_batches_with_options, BatchOptions};
+///
+/// let trace = RunTrace::start(Some("00-0af7651916cd43dd8448eb211c80319c-b7ad6b7169203331-01"));
+/// let mut options = BatchOptions { batch_size: 50, ..Default::default() };
+/// trace.instrument(&mut options);
+/// let input = "test_data/batch_01.vcf.gz";
+/// let output = Path::new("test_data/doc_telemetry");
+/// let result = extract_variants_to_batches_with_options(input, output, &options);
+///
+/// let traces = trace.finish(Path::new(input), output, result.as_ref());
+/// let spans = &traces["resourceSpans"][0]["scopeSpans"][0]["spans"];
+/// assert_eq!(spans.as_array().unwrap().len(), 3);
+/// assert_eq!(spans[0]["traceId"], "0af7651916cd43dd8448eb211c80319c");
+/// ```
+#[derive(Debug, Clone)]
+pub struct RunTrace {
+    state: Arc<Mutex<TraceState>>,
+}
+
+#[derive(Debug)]
+struct TraceState {
+    trace_id: String,
+    parent_span_id: Option<String>,
+    span_id: String,
+    started: u128,
+    /// When the last batch was written, or the run started.
+    last_batch: u128,
+    batch_spans: Vec<Value>,
+}
+
+impl RunTrace {
+    /// Starts the trace of a run. With the W3C `traceparent` of the workflow step that started the run,
+    /// e.g. from the `TRACEPARENT` environment variable, the run becomes a child span of that step;
+    /// otherwise, and if it is malformed, a new trace is started.
+    pub fn start(traceparent: Option<&str>) -> RunTrace {
+        let parent = traceparent.and_then(parse_traceparent);
+        let now = unix_nanos();
+        RunTrace {
+            state: Arc::new(Mutex::new(TraceState {
+                trace_id: parent
+                    .map(|(trace_id, _)| trace_id.to_string())
+                    .unwrap_or_else(|| random_id(2)),
+                parent_span_id: parent.map(|(_, span_id)| span_id.to_string()),
+                span_id: random_id(1),
+                started: now,
+                last_batch: now,
+                batch_spans: Vec::new(),
+            })),
+        }
+    }
+
+    /// Records a span per batch that the options write, in addition to calling their
+    /// [`BatchOptions::on_batch`] callback.
+    pub fn instrument(&self, options: &mut BatchOptions) {
+        let state = Arc::clone(&self.state);
+        let on_batch = options.on_batch.take();
+        options.on_batch = Some(BatchCallback::new(move |batch| {
+            {
+                let mut state = state.lock().unwrap_or_else(|error| error.into_inner());
+                let now = unix_nanos();
+                let span = span(
+                    &state,
+                    random_id(1),
+                    Some(state.span_id.clone()),
+                    "vcf_batcher.batch",
+                    (state.last_batch, now),
+                    attributes([
+                        ("vcf_batcher.batch.number", json!(batch.number)),
+                        ("vcf_batcher.batch.records", json!(batch.records)),
+                        (
+                            "vcf_batcher.batch.path",
+                            json!(batch.path.to_string_lossy()),
+                        ),
+                    ]),
+                );
+                state.batch_spans.push(span);
+                state.last_batch = now;
+            }
+            if let Some(on_batch) = &on_batch {
+                on_batch.call(batch);
+            }
+        }));
+    }
+
+    /// Ends the run span and returns all spans in the OTLP JSON encoding, ready to be exported.
+    pub fn finish(
+        &self,
+        input: &Path,
+        output: &Path,
+        result: Result<&RunReport, &VcfBatcherError>,
+    ) -> Value {
+        let state = self.state.lock().unwrap_or_else(|error| error.into_inner());
+        let mut run_attributes = vec![
+            ("vcf_batcher.input", json!(input.to_string_lossy())),
+            ("vcf_batcher.output", json!(output.to_string_lossy())),
+        ];
+        let report = match result {
+            Ok(report) => Some(report),
+            Err(error) => error.completed_batches(),
+        };
+        if let Some(report) = report {
+            run_attributes.extend([
+                ("vcf_batcher.batches", json!(report.batches.len())),
+                ("vcf_batcher.records", json!(report.total_records())),
+                (
+                    "vcf_batcher.filtered_records",
+                    json!(report.filtered_records),
+                ),
+                ("vcf_batcher.bad_records", json!(report.bad_records)),
+            ]);
+        }
+        if let Some(timings) = result.ok().and_then(|report| report.timings) {
+            run_attributes.extend([
+                (
+                    "vcf_batcher.reading_seconds",
+                    json!(timings.reading.as_secs_f64()),
+                ),
+                (
+                    "vcf_batcher.filtering_seconds",
+                    json!(timings.filtering.as_secs_f64()),
+                ),
+                (
+                    "vcf_batcher.compression_seconds",
+                    json!(timings.compression.as_secs_f64()),
+                ),
+                (
+                    "vcf_batcher.writing_seconds",
+                    json!(timings.writing.as_secs_f64()),
+                ),
+            ]);
+        }
+        let mut run_span = span(
+            &state,
+            state.span_id.clone(),
+            state.parent_span_id.clone(),
+            "vcf_batcher.split",
+            (state.started, unix_nanos()),
+            attributes(run_attributes),
+        );
+        if let Err(error) = result {
+            run_span["status"] = json!({ "code": STATUS_CODE_ERROR, "message": error.to_string() });
+        }
+
+        let spans: Vec<&Value> = std::iter::once(&run_span)
+            .chain(&state.batch_spans)
+            .collect();
+        json!({
+            "resourceSpans": [{
+                "resource": {
+                    "attributes": attributes([
+                        ("service.name", json!("vcf_batcher")),
+                        ("service.version", json!(env!("CARGO_PKG_VERSION"))),
+                    ]),
+                },
+                "scopeSpans": [{
+                    "scope": { "name": "vcf_batcher", "version": env!("CARGO_PKG_VERSION") },
+                    "spans": spans,
+                }],
+            }],
+        })
+    }
+}
+
+/// The endpoint that traces are sent to, from the base URL of a collector's OTLP/HTTP receiver
+/// like `OTEL_EXPORTER_OTLP_ENDPOINT`, e.g. `http://collector:4318`. `/v1/traces` is appended
+/// unless the URL already ends with it. The traces returned by [`RunTrace::finish`] are exported
+/// with [`Webhook::notify`].
+///
+/// # Examples
+///
+/// ```
+/// use vcf_batcher::telemetry::traces_endpoint;
+///
+/// let endpoint = traces_endpoint("http://collector:4318").unwrap();
+/// assert_eq!(endpoint.to_string(), "http://collector:4318/v1/traces");
+/// ```
+pub fn traces_endpoint(url: &str) -> Result<Webhook, String> {
+    match url.ends_with("/v1/traces") {
+        true => Webhook::parse(url),
+        false => Webhook::parse(&format!("{}/v1/traces", url.trim_end_matches('/'))),
+    }
+}
+
+/// A span of the trace, with an OK status.
+fn span(
+    state: &TraceState,
+    span_id: String,
+    parent_span_id: Option<String>,
+    name: &str,
+    (start, end): (u128, u128),
+    attributes: Value,
+) -> Value {
+    let mut span = json!({
+        "traceId": state.trace_id,
+        "spanId": span_id,
+        "name": name,
+        "kind": SPAN_KIND_INTERNAL,
+        "startTimeUnixNano": start.to_string(),
+        "endTimeUnixNano": end.to_string(),
+        "attributes": attributes,
+        "status": { "code": STATUS_CODE_OK },
+    });
+    if let Some(parent_span_id) = parent_span_id {
+        span["parentSpanId"] = json!(parent_span_id);
+    }
+    span
+}
+
+/// Encodes attributes as OTLP key-value pairs. Integers are strings in the JSON encoding.
+fn attributes<'a>(attributes: impl IntoIterator<Item = (&'a str, Value)>) -> Value {
+    attributes
+        .into_iter()
+        .map(|(key, value)| {
+            let value = match value {
+                Value::String(value) => json!({ "stringValue": value }),
+                Value::Number(number) if number.is_f64() => json!({ "doubleValue": number }),
+                Value::Number(number) => json!({ "intValue": number.to_string() }),
+                value => json!({ "stringValue": value.to_string() }),
+            };
+            json!({ "key": key, "value": value })
+        })
+        .collect()
+}
+
+/// Splits a `traceparent` of version `00` into the trace id and the span id of the parent.
+fn parse_traceparent(traceparent: &str) -> Option<(&str, &str)> {
+    let mut parts = traceparent.trim().split('-');
+    let (version, trace_id, span_id) = (parts.next()?, parts.next()?, parts.next()?);
+    let is_id = |id: &str, length: usize| {
+        id.len() == length
+            && id.bytes().all(|byte| byte.is_ascii_hexdigit())
+            && id.bytes().any(|byte| byte != b'0')
+    };
+    (version == "00" && is_id(trace_id, 32) && is_id(span_id, 16)).then_some((trace_id, span_id))
+}
+
+/// A random id of `words` times 8 bytes as lowercase hex. The ids only have to be unique, so
+/// the random keys of the standard library's hasher are enough.
+fn random_id(words: usize) -> String {
+    (0..words)
+        .map(|_| {
+            let mut hasher = RandomState::new().build_hasher();
+            hasher.write_u128(unix_nanos());
+            format!("{:016x}", hasher.finish())
+        })
+        .collect()
+}
+
+fn unix_nanos() -> u128 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_nanos()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::{Path, PathBuf};
+
+    use super::{parse_traceparent, random_id, RunTrace};
+    use crate::VcfBatcherError;
+
+    #[test]
+    fn test_parse_traceparent() {
+        assert_eq!(
+            parse_traceparent("00-0af7651916cd43dd8448eb211c80319c-b7ad6b7169203331-01"),
+            Some(("0af7651916cd43dd8448eb211c80319c", "b7ad6b7169203331"))
+        );
+        assert_eq!(
+            parse_traceparent("00-00000000000000000000000000000000-b7ad6b7169203331-01"),
+            None
+        );
+        assert_eq!(
+            parse_traceparent("01-0af7651916cd43dd-b7ad6b7169203331-01"),
+            None
+        );
+        assert_eq!(parse_traceparent("garbage"), None);
+    }
+
+    #[test]
+    fn test_failed_run() {
+        assert_ne!(random_id(2), random_id(2));
+        assert_eq!(random_id(1).len(), 16);
+
+        let trace = RunTrace::start(None);
+        let error = VcfBatcherError::EmptyInput {
+            path: PathBuf::from("empty.vcf"),
+        };
+        let traces = trace.finish(Path::new("empty.vcf"), Path::new("out"), Err(&error));
+        let span = &traces["resourceSpans"][0]["scopeSpans"][0]["spans"][0];
+        assert_eq!(span["traceId"].as_str().unwrap().len(), 32);
+        assert!(span.get("parentSpanId").is_none());
+        assert_eq!(span["status"]["code"], 2);
+        assert_eq!(
+            span["status"]["message"],
+            "empty.vcf contains no variant records"
+        );
+        assert_eq!(
+            span["attributes"][0],
+            serde_json::json!({ "key": "vcf_batcher.input", "value": { "stringValue": "empty.vcf" } })
+        );
+    }
+}
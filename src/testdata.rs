@@ -0,0 +1,259 @@
+//! Deterministic synthetic VCF files, for tests and benchmarks that need realistic inputs of a
+//! given size without shipping real genomes.
+
+use std::fmt::Write as _;
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::path::Path;
+
+use bgzip::Compression;
+
+use crate::merge::VcfWriter;
+use crate::VcfBatcherError;
+
+const BASES: [char; 4] = ['A', 'C', 'G', 'T'];
+
+/// The shape of a synthetic VCF file. The same options and seed always produce the same file.
+///
+/// The records are spread evenly over the contigs and sorted by position. Every record has an ID,
+/// REF and ALT alleles (about one in ten is an indel), a QUAL, a FILTER of `PASS` or `LowQual`,
+/// the INFO keys `AC`, `AN`, `AF` and `DP` and the FORMAT keys `GT`, `DP` and `GQ` for every sample.
+///
+/// # Examples
+///
+/// ```
+/// use vcf_batcher::testdata::SyntheticVcf;
+///
+/// let vcf = SyntheticVcf { samples: 2, records: 10, ..Default::default() };
+/// let mut contents = Vec::new();
+/// vcf.write(&mut contents, None).unwrap();
+///
+/// let contents = String::from_utf8(contents).unwrap();
+/// assert!(contents.contains("#CHROM\tPOS\tID\tREF\tALT\tQUAL\tFILTER\tINFO\tFORMAT\tSAMPLE_0001\tSAMPLE_0002\n"));
+/// assert_eq!(contents.lines().filter(|line| !line.starts_with('#')).count(), 10);
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SyntheticVcf {
+    /// The number of sample columns, named `SAMPLE_0001`, `SAMPLE_0002`, ...
+    pub samples: usize,
+    /// The number of variant records.
+    pub records: usize,
+    /// The contigs, which are declared in `##contig` lines.
+    pub contigs: Vec<String>,
+    /// Different seeds produce different files of the same shape.
+    pub seed: u64,
+}
+
+impl Default for SyntheticVcf {
+    fn default() -> Self {
+        SyntheticVcf {
+            samples: 3,
+            records: 1000,
+            contigs: vec!["chr1".to_string(), "chr2".to_string(), "chr3".to_string()],
+            seed: 0,
+        }
+    }
+}
+
+impl SyntheticVcf {
+    /// Writes the file to `writer`, bgzipped if a compression level is given.
+    pub fn write<W: Write>(&self, writer: W, compression: Option<Compression>) -> io::Result<()> {
+        let mut writer = VcfWriter::new(writer, compression);
+        writer.write_all(self.header().as_bytes())?;
+
+        let mut random = SplitMix64(self.seed);
+        let mut line = String::new();
+        let contigs = self.contigs.len().max(1);
+        for (index, contig) in self.contigs.iter().enumerate() {
+            // The first contigs get the remainder, so that the counts add up
+            let records = self.records / contigs + usize::from(index < self.records % contigs);
+            let mut pos = 0;
+            for _ in 0..records {
+                pos += 1 + random.below(200);
+                line.clear();
+                self.write_record(&mut line, contig, pos, &mut random);
+                writer.write_all(line.as_bytes())?;
+            }
+        }
+        writer.finish()
+    }
+
+    /// Writes the file to `path`, bgzipped if a compression level is given, e.g. with
+    /// [`crate::merge::compression_for`] to bgzip files whose name ends with `.gz`.
+    pub fn write_file(
+        &self,
+        path: &Path,
+        compression: Option<Compression>,
+    ) -> Result<(), VcfBatcherError> {
+        let parent = path
+            .parent()
+            .filter(|parent| !parent.as_os_str().is_empty());
+        parent
+            .map_or(Ok(()), fs::create_dir_all)
+            .and_then(|_| File::create(path))
+            .and_then(|file| self.write(file, compression))
+            .map_err(|source| VcfBatcherError::Write {
+                path: path.to_path_buf(),
+                source: source.into(),
+            })
+    }
+
+    fn header(&self) -> String {
+        let mut header = format!(
+            "##fileformat=VCFv4.2\n\
+             ##source=vcf_batcher synthetic data (seed {})\n\
+             ##FILTER=<ID=PASS,Description=\"All filters passed\">\n\
+             ##FILTER=<ID=LowQual,Description=\"Low quality\">\n\
+             ##INFO=<ID=AC,Number=A,Type=Integer,Description=\"Allele count in genotypes\">\n\
+             ##INFO=<ID=AN,Number=1,Type=Integer,Description=\"Total number of alleles in called genotypes\">\n\
+             ##INFO=<ID=AF,Number=A,Type=Float,Description=\"Allele frequency\">\n\
+             ##INFO=<ID=DP,Number=1,Type=Integer,Description=\"Combined depth across samples\">\n\
+             ##FORMAT=<ID=GT,Number=1,Type=String,Description=\"Genotype\">\n\
+             ##FORMAT=<ID=DP,Number=1,Type=Integer,Description=\"Read depth\">\n\
+             ##FORMAT=<ID=GQ,Number=1,Type=Integer,Description=\"Genotype quality\">\n",
+            self.seed
+        );
+        for contig in &self.contigs {
+            let _ = writeln!(header, "##contig=<ID={contig}>");
+        }
+        header.push_str("#CHROM\tPOS\tID\tREF\tALT\tQUAL\tFILTER\tINFO\tFORMAT");
+        for sample in 1..=self.samples {
+            let _ = write!(header, "\tSAMPLE_{sample:04}");
+        }
+        header.push('\n');
+        header
+    }
+
+    fn write_record(&self, line: &mut String, contig: &str, pos: u64, random: &mut SplitMix64) {
+        let reference = random.base();
+        let mut alternate = random.base();
+        while alternate == reference {
+            alternate = random.base();
+        }
+        let (reference, alternate) = match random.below(10) {
+            // Deletion
+            0 => (
+                format!("{reference}{}", random.bases()),
+                reference.to_string(),
+            ),
+            // Insertion
+            1 => (
+                reference.to_string(),
+                format!("{reference}{}", random.bases()),
+            ),
+            _ => (reference.to_string(), alternate.to_string()),
+        };
+        let quality = 10 + random.below(90);
+        let filter = if quality < 20 { "LowQual" } else { "PASS" };
+        // The chance of an alternate allele, from rare to common variants
+        let frequency = random.below(1000) as f64 / 1000.0;
+
+        let mut samples = String::new();
+        let (mut allele_count, mut allele_number, mut depth) = (0, 0, 0);
+        for _ in 0..self.samples {
+            let sample_depth = 5 + random.below(40);
+            depth += sample_depth;
+            let genotype = match random.below(50) {
+                0 => None,
+                _ => {
+                    let alleles = [random.chance(frequency), random.chance(frequency)];
+                    let count = alleles.iter().filter(|&&alternate| alternate).count();
+                    allele_count += count;
+                    allele_number += 2;
+                    Some(count)
+                }
+            };
+            let genotype = match genotype {
+                None => "./.",
+                Some(0) => "0/0",
+                Some(1) => "0/1",
+                Some(_) => "1/1",
+            };
+            let _ = write!(samples, "\t{genotype}:{sample_depth}:{}", random.below(100));
+        }
+        let allele_frequency = match allele_number {
+            0 => 0.0,
+            _ => allele_count as f64 / allele_number as f64,
+        };
+
+        let _ = writeln!(
+            line,
+            "{contig}\t{pos}\trs{}\t{reference}\t{alternate}\t{quality}\t{filter}\t\
+             AC={allele_count};AN={allele_number};AF={allele_frequency:.3};DP={depth}\tGT:DP:GQ{samples}",
+            random.below(100_000_000)
+        );
+    }
+}
+
+/// A small, fast generator of pseudo-random numbers, so that files are reproducible across
+/// platforms and versions without a dependency.
+struct SplitMix64(u64);
+
+impl SplitMix64 {
+    fn next(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    /// A number from 0 to `bound - 1`.
+    fn below(&mut self, bound: u64) -> u64 {
+        self.next() % bound
+    }
+
+    fn chance(&mut self, probability: f64) -> bool {
+        ((self.next() >> 11) as f64) < probability * (1u64 << 53) as f64
+    }
+
+    fn base(&mut self) -> char {
+        BASES[self.below(4) as usize]
+    }
+
+    /// One to five bases, for the inserted or deleted sequence of an indel.
+    fn bases(&mut self) -> String {
+        let count = 1 + self.below(5);
+        (0..count).map(|_| self.base()).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use bgzip::Compression;
+
+    use super::SyntheticVcf;
+    use crate::stats::vcf_stats;
+    use crate::validate::{validate_vcf, Severity};
+
+    #[test]
+    fn test_synthetic_vcf() {
+        let vcf = SyntheticVcf {
+            samples: 5,
+            records: 301,
+            seed: 7,
+            ..Default::default()
+        };
+        let path = Path::new("./test_data/testdata/synthetic.vcf.gz");
+        vcf.write_file(path, Some(Compression::fast())).unwrap();
+
+        let stats = vcf_stats(path).unwrap();
+        assert_eq!(stats.records, 301);
+        assert_eq!(stats.samples.len(), 5);
+        assert_eq!(stats.records_per_contig["chr1"], 101);
+        assert_eq!(stats.records_per_contig["chr3"], 100);
+        let issues = validate_vcf(path).unwrap();
+        assert!(issues.iter().all(|issue| issue.severity != Severity::Error));
+
+        let (mut first, mut second, mut other) = (Vec::new(), Vec::new(), Vec::new());
+        vcf.write(&mut first, None).unwrap();
+        vcf.write(&mut second, None).unwrap();
+        SyntheticVcf { seed: 8, ..vcf }
+            .write(&mut other, None)
+            .unwrap();
+        assert_eq!(first, second);
+        assert_ne!(first, other);
+    }
+}
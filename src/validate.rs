@@ -0,0 +1,320 @@
+//! Structural checks of VCF files.
+
+use std::collections::HashSet;
+use std::fmt;
+use std::io;
+use std::path::Path;
+
+use serde::Serialize;
+
+use crate::{header, is_header_line, read_lines, VcfBatcherError};
+
+/// At most this many issues are reported per file, so that a file that is broken on every line
+/// does not produce millions of identical issues.
+pub const MAX_ISSUES: usize = 1000;
+
+/// How severe an [`Issue`] is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Severity {
+    /// Tools may process the file, but the result might not be what was intended.
+    Warning,
+    /// The file does not conform to the VCF specification.
+    Error,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Severity::Warning => f.write_str("warning"),
+            Severity::Error => f.write_str("error"),
+        }
+    }
+}
+
+/// A problem found in a VCF file.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Issue {
+    pub severity: Severity,
+    /// The line the issue was found on, starting at 1. `None` for issues concerning the whole file.
+    pub line_number: Option<usize>,
+    pub message: String,
+}
+
+impl fmt::Display for Issue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.line_number {
+            Some(line_number) => write!(
+                f,
+                "{} on line {}: {}",
+                self.severity, line_number, self.message
+            ),
+            None => write!(f, "{}: {}", self.severity, self.message),
+        }
+    }
+}
+
+/// Checks the structure of a (possibly bgzipped) VCF file and returns all issues that were found.
+///
+/// The checks cover the `##fileformat` and `#CHROM` header lines, header lines after the first
+/// record, duplicate sample names, the number of columns and the POS of every record, records on
+/// contigs that are not declared in the `##contig` lines, and records that are not sorted by position.
+/// An error is only returned if the file cannot be read at all.
+///
+/// # Examples
+///
+/// ```
+/// use std::path::Path;
+/// use vcf_batcher::validate::validate_vcf;
+///
+/// let issues = validate_vcf(Path::new("test_data/batch_01.vcf.gz")).unwrap();
+/// assert!(issues.is_empty());
+/// ```
+pub fn validate_vcf(file_path: &Path) -> Result<Vec<Issue>, VcfBatcherError> {
+    let read_error = |source| VcfBatcherError::Read {
+        path: file_path.to_path_buf(),
+        source,
+    };
+
+    let mut validator = Validator::default();
+    for (line_index, line) in read_lines(file_path).map_err(read_error)?.enumerate() {
+        if validator.issues.len() >= MAX_ISSUES {
+            validator.error(
+                None,
+                format!(
+                    "stopped after {} issues, the rest of the file was not checked",
+                    MAX_ISSUES
+                ),
+            );
+            return Ok(validator.issues);
+        }
+
+        let line_number = line_index + 1;
+        match line {
+            Ok(line) => validator.check_line(line_number, &line),
+            // Invalid UTF-8 does not stop the reader, so the following lines can still be checked
+            Err(error) if error.kind() == io::ErrorKind::InvalidData => validator.error(
+                Some(line_number),
+                format!("the line cannot be read: {}", error),
+            ),
+            Err(error) => return Err(read_error(error)),
+        }
+    }
+    validator.finish();
+    Ok(validator.issues)
+}
+
+#[derive(Default)]
+struct Validator {
+    issues: Vec<Issue>,
+    /// Number of columns of the `#CHROM` line, set once it was seen.
+    columns: Option<usize>,
+    declared_contigs: HashSet<String>,
+    seen_records: bool,
+    late_header_reported: bool,
+    /// Contigs that already had records, to detect contigs that are not contiguous.
+    finished_contigs: HashSet<String>,
+    previous: Option<(String, u64)>,
+}
+
+impl Validator {
+    fn error(&mut self, line_number: Option<usize>, message: String) {
+        self.issue(Severity::Error, line_number, message);
+    }
+
+    fn warning(&mut self, line_number: Option<usize>, message: String) {
+        self.issue(Severity::Warning, line_number, message);
+    }
+
+    fn issue(&mut self, severity: Severity, line_number: Option<usize>, message: String) {
+        self.issues.push(Issue {
+            severity,
+            line_number,
+            message,
+        });
+    }
+
+    fn check_line(&mut self, line_number: usize, line: &str) {
+        if line_number == 1 && !line.starts_with("##fileformat=") {
+            self.error(
+                Some(1),
+                "the first line must be the ##fileformat header line".to_string(),
+            );
+        }
+
+        if is_header_line(line) {
+            self.check_header_line(line_number, line);
+        } else {
+            self.check_record(line_number, line);
+        }
+    }
+
+    fn check_header_line(&mut self, line_number: usize, line: &str) {
+        if self.seen_records && !self.late_header_reported {
+            self.late_header_reported = true;
+            self.error(
+                Some(line_number),
+                "header line after the first variant record".to_string(),
+            );
+        }
+        if let Some(contig) = header::contig_id(line) {
+            self.declared_contigs.insert(contig);
+        }
+        if line.starts_with("#CHROM") {
+            let columns = line.split('\t').count();
+            if columns < 8 {
+                self.error(
+                    Some(line_number),
+                    format!(
+                        "the #CHROM line has {} columns, expected at least 8",
+                        columns
+                    ),
+                );
+            }
+            let duplicates = header::duplicate_samples(&header::sample_names(line));
+            if !duplicates.is_empty() {
+                self.error(
+                    Some(line_number),
+                    format!("duplicate sample names: {}", duplicates.join(", ")),
+                );
+            }
+            self.columns = Some(columns);
+        }
+    }
+
+    fn check_record(&mut self, line_number: usize, line: &str) {
+        if !self.seen_records {
+            self.seen_records = true;
+            if self.columns.is_none() {
+                self.error(
+                    Some(line_number),
+                    "variant record before the #CHROM header line".to_string(),
+                );
+            }
+        }
+
+        let fields: Vec<&str> = line.split('\t').collect();
+        if let Some(columns) = self.columns {
+            if fields.len() != columns {
+                self.error(
+                    Some(line_number),
+                    format!(
+                        "the record has {} columns, the header {}",
+                        fields.len(),
+                        columns
+                    ),
+                );
+            }
+        }
+
+        let chrom = fields[0];
+        if !self.declared_contigs.is_empty() && !self.declared_contigs.contains(chrom) {
+            self.warning(
+                Some(line_number),
+                format!("contig {} is not declared in the header", chrom),
+            );
+        }
+
+        let pos = match fields.get(1).map(|pos| pos.parse::<u64>()) {
+            Some(Ok(pos)) => pos,
+            _ => {
+                self.error(
+                    Some(line_number),
+                    format!("POS {:?} is not a number", fields.get(1).unwrap_or(&"")),
+                );
+                return;
+            }
+        };
+
+        match self.previous.take() {
+            Some((previous_chrom, previous_pos))
+                if previous_chrom == chrom && pos < previous_pos =>
+            {
+                self.warning(
+                    Some(line_number),
+                    format!(
+                        "records are not sorted, {} comes after {}",
+                        pos, previous_pos
+                    ),
+                );
+            }
+            Some((previous_chrom, _)) if previous_chrom != chrom => {
+                if self.finished_contigs.contains(chrom) {
+                    self.warning(
+                        Some(line_number),
+                        format!("records of contig {} are not contiguous", chrom),
+                    );
+                }
+                self.finished_contigs.insert(previous_chrom);
+            }
+            _ => {}
+        }
+        self.previous = Some((chrom.to_string(), pos));
+    }
+
+    fn finish(&mut self) {
+        if self.columns.is_none() {
+            self.error(None, "the #CHROM header line is missing".to_string());
+        }
+        if !self.seen_records {
+            self.warning(None, "the file contains no variant records".to_string());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::{validate_vcf, Issue, Severity};
+
+    fn validate(name: &str, contents: &str) -> Vec<Issue> {
+        let path = Path::new("./test_data/inputs").join(name);
+        std::fs::create_dir_all("./test_data/inputs").unwrap();
+        std::fs::write(&path, contents).unwrap();
+        validate_vcf(&path).unwrap()
+    }
+
+    #[test]
+    fn test_valid_file() {
+        let issues = validate(
+            "valid.vcf",
+            "##fileformat=VCFv4.2\n\
+             ##contig=<ID=chr1>\n\
+             #CHROM\tPOS\tID\tREF\tALT\tQUAL\tFILTER\tINFO\n\
+             chr1\t1\t.\tA\tC\t.\tPASS\t.\n\
+             chr1\t5\t.\tA\tC\t.\tPASS\t.\n",
+        );
+        assert_eq!(issues, vec![]);
+    }
+
+    #[test]
+    fn test_invalid_file() {
+        let issues = validate(
+            "invalid.vcf",
+            "##contig=<ID=chr1>\n\
+             #CHROM\tPOS\tID\tREF\tALT\tQUAL\tFILTER\tINFO\tFORMAT\tA\tA\n\
+             chr1\t5\t.\tA\tC\t.\tPASS\t.\tGT\t0\t1\n\
+             chr1\t1\t.\tA\tC\t.\tPASS\t.\tGT\t0\n\
+             chr2\tx\t.\tA\tC\t.\tPASS\t.\tGT\t0\t1\n\
+             ##INFO=<ID=AF>\n",
+        );
+        let summary: Vec<(Severity, Option<usize>)> = issues
+            .iter()
+            .map(|issue| (issue.severity, issue.line_number))
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                (Severity::Error, Some(1)),
+                (Severity::Error, Some(2)),
+                (Severity::Error, Some(4)),
+                (Severity::Warning, Some(4)),
+                (Severity::Warning, Some(5)),
+                (Severity::Error, Some(5)),
+                (Severity::Error, Some(6)),
+            ]
+        );
+        assert_eq!(issues[1].message, "duplicate sample names: A");
+    }
+}
@@ -0,0 +1,158 @@
+//! Verification of written batches, see [`crate::BatchOptions::verify_output`]. Every batch is read
+//! back from disk and compared with what the run meant to write, so that the batches of a release
+//! are checked independently of the code that wrote them.
+
+use std::fs;
+use std::io::{self, Read};
+
+use bgzip::{BGZFReader, EOF_MARKER};
+
+use crate::recompress::{decoder, ZSTD_MAGIC};
+use crate::{manifest, BatchInfo, Locus, GZIP_MAGIC};
+
+/// Reads a batch back from disk and checks that its checksum, its BGZF blocks or zstd frames (if it is compressed),
+/// its record count and the positions of its first and last record match `batch`. Returns why it does
+/// not, e.g. because it was truncated or changed after it was written.
+///
+/// # Examples
+///
+/// ```
+/// use std::path::Path;
+/// use vcf_batcher::verify::verify_batch;
+/// use vcf_batcher::{extract_variants_to_batches_with_options, BatchOptions};
+///
+/// let options = BatchOptions { batch_size: 50, quiet: true, ..Default::default() };
+/// let output = Path::new("test_data/doc_verify");
+/// let report = extract_variants_to_batches_with_options("test_data/batch_01.vcf.gz", output, &options).unwrap();
+///
+/// let mut batch = report.batches[0].clone();
+/// assert_eq!(verify_batch(&batch), Ok(()));
+/// batch.records += 1;
+/// assert_eq!(
+///     verify_batch(&batch),
+///     Err("it contains 50 records instead of 51".to_string())
+/// );
+/// ```
+pub fn verify_batch(batch: &BatchInfo) -> Result<(), String> {
+    let bytes =
+        fs::read(&batch.path).map_err(|error| format!("it could not be read back: {error}"))?;
+    if !batch.sha256.is_empty() && manifest::sha256_hex(&bytes) != batch.sha256 {
+        return Err("its SHA-256 checksum differs from the one of the written bytes".to_string());
+    }
+
+    let contents = match bytes.starts_with(GZIP_MAGIC) {
+        true => {
+            if !bytes.ends_with(&EOF_MARKER) {
+                return Err("its BGZF end-of-file marker is missing".to_string());
+            }
+            let mut contents = Vec::new();
+            BGZFReader::new(&bytes[..])
+                .map_err(|error| error.to_string())
+                .and_then(|mut reader| {
+                    reader
+                        .read_to_end(&mut contents)
+                        .map_err(|error| error.to_string())
+                })
+                .map_err(|error| format!("it is not a valid BGZF file: {error}"))?;
+            contents
+        }
+        false if bytes.starts_with(ZSTD_MAGIC) => {
+            let mut contents = Vec::new();
+            decoder(io::Cursor::new(bytes))
+                .and_then(|mut reader| reader.read_to_end(&mut contents))
+                .map_err(|error| format!("it is not a valid zstd file: {error}"))?;
+            contents
+        }
+        false => bytes,
+    };
+
+    let contents = String::from_utf8_lossy(&contents);
+    let records: Vec<&str> = contents
+        .lines()
+        .filter(|line| !line.starts_with('#'))
+        .collect();
+    if records.len() != batch.records {
+        return Err(format!(
+            "it contains {} records instead of {}",
+            records.len(),
+            batch.records
+        ));
+    }
+    let first = records
+        .first()
+        .and_then(|record| Locus::from_record(record));
+    if first != batch.first {
+        return Err(format!(
+            "its first record is at {} instead of {}",
+            describe(&first),
+            describe(&batch.first)
+        ));
+    }
+    let last = records.last().and_then(|record| Locus::from_record(record));
+    if last != batch.last {
+        return Err(format!(
+            "its last record is at {} instead of {}",
+            describe(&last),
+            describe(&batch.last)
+        ));
+    }
+    Ok(())
+}
+
+fn describe(locus: &Option<Locus>) -> String {
+    match locus {
+        Some(locus) => format!("{}:{}", locus.chrom, locus.pos),
+        None => "no position".to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use bgzip::Compression;
+
+    use super::verify_batch;
+    use crate::{extract_variants_to_batches_with_options, BatchOptions, Locus};
+
+    #[test]
+    fn test_verify_batch() {
+        let output_path = Path::new("./test_data/verify_batch");
+        let _ = std::fs::remove_dir_all(output_path);
+        let options = BatchOptions {
+            batch_size: 30,
+            compression_level: Some(Compression::fast()),
+            quiet: true,
+            ..Default::default()
+        };
+        let report = extract_variants_to_batches_with_options(
+            "./test_data/batch_01.vcf.gz",
+            output_path,
+            &options,
+        )
+        .unwrap();
+        let batch = &report.batches[1];
+        assert_eq!(verify_batch(batch), Ok(()));
+
+        let mut moved = batch.clone();
+        moved.last = Some(Locus {
+            chrom: "chrX".to_string(),
+            pos: 1,
+        });
+        assert!(verify_batch(&moved)
+            .unwrap_err()
+            .starts_with("its last record is at "));
+
+        let bytes = std::fs::read(&batch.path).unwrap();
+        std::fs::write(&batch.path, &bytes[..bytes.len() - 10]).unwrap();
+        assert!(verify_batch(batch).unwrap_err().contains("checksum"));
+        let unchecked = crate::BatchInfo {
+            sha256: String::new(),
+            ..batch.clone()
+        };
+        assert_eq!(
+            verify_batch(&unchecked),
+            Err("its BGZF end-of-file marker is missing".to_string())
+        );
+    }
+}
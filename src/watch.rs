@@ -0,0 +1,267 @@
+//! Batching VCF files as they appear in a directory, e.g. for an ingestion pipeline.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::time::{Duration, Instant};
+
+use notify::{RecursiveMode, Watcher};
+
+use crate::naming::input_stem;
+use crate::{extract_variants_to_batches_with_options, BatchOptions, RunReport, VcfBatcherError};
+
+/// How often the watched directory is checked for files whose size has settled.
+const POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Where [`watch_directory`] moves the inputs it has processed and when it considers a new file complete.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WatchOptions {
+    /// Inputs are moved here after they were batched.
+    pub done_dir: PathBuf,
+    /// Inputs are moved here if they could not be batched, so that they are not retried forever.
+    pub failed_dir: PathBuf,
+    /// A new file is only batched once its size did not change for this long, so that files
+    /// that are still being copied into the directory are not read half-way.
+    pub settle_time: Duration,
+}
+
+impl WatchOptions {
+    /// Moves processed inputs to the `done` and `failed` subdirectories of the watched directory.
+    pub fn new(incoming: &Path) -> Self {
+        WatchOptions {
+            done_dir: incoming.join("done"),
+            failed_dir: incoming.join("failed"),
+            settle_time: Duration::from_secs(2),
+        }
+    }
+}
+
+/// What happened to a file in the watched directory.
+#[derive(Debug)]
+pub enum WatchEvent {
+    /// The file was batched and moved to [`WatchOptions::done_dir`].
+    Batched {
+        input: PathBuf,
+        report: Box<RunReport>,
+    },
+    /// The file could not be batched and was moved to [`WatchOptions::failed_dir`].
+    Failed {
+        input: PathBuf,
+        error: VcfBatcherError,
+    },
+}
+
+/// Watches the `incoming` directory and splits every `.vcf` or `.vcf.gz` file that appears in it
+/// into batches in `output/<stem>/`, where `<stem>` is the name of the file without its extensions.
+/// Files that are already in the directory when the watch starts are processed first.
+///
+/// Runs until the cancel flag of the options is set. A run that is interrupted by the flag stops
+/// the watch with [`VcfBatcherError::Interrupted`] and leaves its input in place; every other
+/// failure is reported through `on_event` and the watch continues with the next file.
+pub fn watch_directory(
+    incoming: &Path,
+    output: &Path,
+    options: &BatchOptions,
+    watch: &WatchOptions,
+    mut on_event: impl FnMut(WatchEvent),
+) -> Result<(), VcfBatcherError> {
+    let watch_error = |source| VcfBatcherError::Watch {
+        path: incoming.to_path_buf(),
+        source,
+    };
+    // Events report absolute paths, which are compared with the files in the directory
+    let incoming = incoming
+        .canonicalize()
+        .map_err(|source| VcfBatcherError::Read {
+            path: incoming.to_path_buf(),
+            source,
+        })?;
+
+    let (sender, receiver) = mpsc::channel();
+    let mut watcher = notify::recommended_watcher(move |event| {
+        let _ = sender.send(event);
+    })
+    .map_err(watch_error)?;
+    watcher
+        .watch(&incoming, RecursiveMode::NonRecursive)
+        .map_err(watch_error)?;
+
+    // The size of every file that is waiting to be processed and when it last changed.
+    // `None` forces a first measurement.
+    let mut pending: BTreeMap<PathBuf, (Option<u64>, Instant)> = BTreeMap::new();
+    let directory = fs::read_dir(&incoming).map_err(|source| VcfBatcherError::Read {
+        path: incoming.clone(),
+        source,
+    })?;
+    for entry in directory.flatten() {
+        pending.insert(entry.path(), (None, Instant::now()));
+    }
+
+    loop {
+        if options.is_cancelled() {
+            return Ok(());
+        }
+        match receiver.recv_timeout(POLL_INTERVAL) {
+            Ok(Ok(event)) => {
+                for path in event.paths {
+                    pending.entry(path).or_insert((None, Instant::now()));
+                }
+            }
+            Ok(Err(source)) => return Err(watch_error(source)),
+            Err(RecvTimeoutError::Timeout) => {}
+            Err(RecvTimeoutError::Disconnected) => return Ok(()),
+        }
+
+        let mut ready = Vec::new();
+        pending.retain(|path, (size, changed)| {
+            if path.parent() != Some(incoming.as_path()) || !is_vcf_file(path) {
+                return false;
+            }
+            // The file was moved away or deleted
+            let Ok(metadata) = fs::metadata(path) else {
+                return false;
+            };
+            if !metadata.is_file() {
+                return false;
+            }
+            if *size != Some(metadata.len()) {
+                *size = Some(metadata.len());
+                *changed = Instant::now();
+                return true;
+            }
+            if changed.elapsed() < watch.settle_time {
+                return true;
+            }
+            ready.push(path.clone());
+            false
+        });
+
+        for input in ready {
+            match batch_file(&input, output, options) {
+                Ok(report) => {
+                    move_into(&input, &watch.done_dir)?;
+                    on_event(WatchEvent::Batched {
+                        input,
+                        report: Box::new(report),
+                    });
+                }
+                Err(error @ VcfBatcherError::Interrupted { .. }) => return Err(error),
+                Err(error) => {
+                    move_into(&input, &watch.failed_dir)?;
+                    on_event(WatchEvent::Failed { input, error });
+                }
+            }
+        }
+    }
+}
+
+/// Splits a single input into its own subdirectory of the output directory.
+fn batch_file(
+    input: &Path,
+    output: &Path,
+    options: &BatchOptions,
+) -> Result<RunReport, VcfBatcherError> {
+    extract_variants_to_batches_with_options(
+        &input.to_string_lossy(),
+        &output.join(input_stem(input)),
+        options,
+    )
+}
+
+/// Moves a processed input into a directory, which is created if necessary.
+fn move_into(input: &Path, directory: &Path) -> Result<(), VcfBatcherError> {
+    let target = directory.join(input.file_name().unwrap_or_default());
+    fs::create_dir_all(directory)
+        .and_then(|_| fs::rename(input, &target))
+        .map_err(|source| VcfBatcherError::Write {
+            path: target,
+            source: source.into(),
+        })
+}
+
+/// Whether the file name has a VCF extension. Hidden files are skipped, since many tools copy
+/// files under a temporary hidden name first.
+pub(crate) fn is_vcf_file(path: &Path) -> bool {
+    let name = path
+        .file_name()
+        .map(|name| name.to_string_lossy())
+        .unwrap_or_default();
+    !name.starts_with('.')
+        && [".vcf", ".vcf.gz", ".vcf.bgz"]
+            .iter()
+            .any(|extension| name.ends_with(extension))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use super::{is_vcf_file, watch_directory, WatchEvent, WatchOptions};
+    use crate::BatchOptions;
+
+    #[test]
+    fn test_is_vcf_file() {
+        assert!(is_vcf_file(Path::new("incoming/cohortA.vcf.gz")));
+        assert!(is_vcf_file(Path::new("incoming/cohortA.vcf")));
+        assert!(!is_vcf_file(Path::new("incoming/.cohortA.vcf.gz")));
+        assert!(!is_vcf_file(Path::new("incoming/cohortA.vcf.gz.tbi")));
+        assert!(!is_vcf_file(Path::new("incoming/notes.txt")));
+    }
+
+    #[test]
+    fn test_watch_directory() {
+        let root = Path::new("./test_data/watch_directory");
+        let _ = std::fs::remove_dir_all(root);
+        let incoming = root.join("incoming");
+        let output = root.join("output");
+        std::fs::create_dir_all(&incoming).unwrap();
+        std::fs::copy(
+            "./test_data/batch_01.vcf.gz",
+            incoming.join("cohortA.vcf.gz"),
+        )
+        .unwrap();
+        // Header lines after the first record are an error by default
+        std::fs::write(
+            incoming.join("broken.vcf"),
+            "chr1\t1\t.\tA\tC\t.\tPASS\t.\n##fileformat=VCFv4.2\n",
+        )
+        .unwrap();
+
+        let cancel = Arc::new(AtomicBool::new(false));
+        let options = BatchOptions {
+            batch_size: 30,
+            cancel: Some(Arc::clone(&cancel)),
+            quiet: true,
+            ..Default::default()
+        };
+        let watch = WatchOptions {
+            settle_time: Duration::from_millis(10),
+            ..WatchOptions::new(&incoming)
+        };
+
+        let mut events = Vec::new();
+        watch_directory(&incoming, &output, &options, &watch, |event| {
+            events.push(event);
+            if events.len() == 2 {
+                cancel.store(true, Ordering::Relaxed);
+            }
+        })
+        .unwrap();
+
+        assert!(events.iter().any(|event| matches!(
+            event,
+            WatchEvent::Batched { report, .. } if report.batches.len() == 4
+        )));
+        assert!(events
+            .iter()
+            .any(|event| matches!(event, WatchEvent::Failed { .. })));
+        assert!(output.join("cohortA").join("batch_04.vcf").exists());
+        assert!(incoming.join("done").join("cohortA.vcf.gz").exists());
+        assert!(incoming.join("failed").join("broken.vcf").exists());
+        assert!(!incoming.join("cohortA.vcf.gz").exists());
+    }
+}
@@ -0,0 +1,185 @@
+//! Notifications about finished runs, POSTed as JSON to a URL, so that an orchestration system
+//! learns about new batches without polling the file system.
+
+use std::fmt;
+use std::io;
+use std::path::Path;
+
+use serde_json::{json, Value};
+
+use crate::{http, RunReport, VcfBatcherError};
+
+/// An `http://` URL that notifications are sent to. HTTPS is not supported, so a webhook should be
+/// reachable on a trusted network, e.g. through a sidecar that forwards to an HTTPS endpoint.
+///
+/// # Examples
+///
+/// ```
+/// use vcf_batcher::webhook::Webhook;
+///
+/// let webhook = Webhook::parse("http://orchestrator:8000/hooks/batches").unwrap();
+/// assert_eq!(webhook.to_string(), "http://orchestrator:8000/hooks/batches");
+/// assert!(Webhook::parse("https://orchestrator/hooks").is_err());
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Webhook {
+    host: String,
+    port: u16,
+    path: String,
+}
+
+impl Webhook {
+    /// Parses an `http://host[:port][/path]` URL. The port is 80 by default.
+    pub fn parse(url: &str) -> Result<Webhook, String> {
+        let Some(rest) = url.strip_prefix("http://") else {
+            return Err(format!("{url} is not an http:// URL"));
+        };
+        let (authority, path) = match rest.find('/') {
+            Some(index) => rest.split_at(index),
+            None => (rest, "/"),
+        };
+        let (host, port) = match authority.rsplit_once(':') {
+            Some((host, port)) => (
+                host,
+                port.parse()
+                    .map_err(|_| format!("{port} is not a valid port"))?,
+            ),
+            None => (authority, 80),
+        };
+        if host.is_empty() {
+            return Err(format!("{url} has no host"));
+        }
+        Ok(Webhook {
+            host: host.to_string(),
+            port,
+            path: path.to_string(),
+        })
+    }
+
+    /// POSTs the payload and fails unless the response has a 2xx status.
+    pub fn notify(&self, payload: &Value) -> io::Result<()> {
+        let status = http::post_json(&self.host, self.port, &self.path, &payload.to_string())?;
+        match status {
+            200..=299 => Ok(()),
+            status => Err(io::Error::other(format!(
+                "{self} answered with status {status}"
+            ))),
+        }
+    }
+}
+
+impl fmt::Display for Webhook {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "http://{}", self.host)?;
+        if self.port != 80 {
+            write!(f, ":{}", self.port)?;
+        }
+        f.write_str(&self.path)
+    }
+}
+
+/// The notification about a run, with the `status` `completed` or `failed`, the `input` and `output`
+/// paths, the `batches` that were written and the number of `records` in them. Failed runs also
+/// have the `error` message and list the batches that were completed before the failure.
+///
+/// # Examples
+///
+/// ```
+/// use std::path::Path;
+/// use vcf_batcher::webhook::run_payload;
+/// use vcf_batcher::RunReport;
+///
+/// let payload = run_payload(Path::new("cohortA.vcf.gz"), Path::new("batches"), Ok(&RunReport::default()));
+/// assert_eq!(payload["status"], "completed");
+/// assert_eq!(payload["records"], 0);
+/// ```
+pub fn run_payload(
+    input: &Path,
+    output: &Path,
+    result: Result<&RunReport, &VcfBatcherError>,
+) -> Value {
+    let report = match result {
+        Ok(report) => Some(report),
+        Err(error) => error.completed_batches(),
+    };
+    let mut payload = json!({
+        "status": if result.is_ok() { "completed" } else { "failed" },
+        "input": input,
+        "output": output,
+        "batches": report.map_or(&[][..], |report| &report.batches),
+        "records": report.map_or(0, RunReport::total_records),
+    });
+    if let Ok(report) = result {
+        payload["filtered_records"] = json!(report.filtered_records);
+        payload["bad_records"] = json!(report.bad_records);
+        payload["elapsed_seconds"] = json!(report.elapsed.as_secs_f64());
+    }
+    if let Err(error) = result {
+        payload["error"] = json!(error.to_string());
+    }
+    payload
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{BufRead, BufReader, Read, Write};
+    use std::net::TcpListener;
+    use std::path::Path;
+    use std::thread;
+
+    use serde_json::{json, Value};
+
+    use super::{run_payload, Webhook};
+    use crate::VcfBatcherError;
+
+    #[test]
+    fn test_parse() {
+        let webhook = Webhook::parse("http://localhost:8000").unwrap();
+        assert_eq!(webhook.to_string(), "http://localhost:8000/");
+        assert_eq!(Webhook::parse("http://hooks/a?b=1").unwrap().path, "/a?b=1");
+        assert!(Webhook::parse("http://localhost:http/").is_err());
+        assert!(Webhook::parse("http:///hooks").is_err());
+        assert!(Webhook::parse("localhost:8000").is_err());
+    }
+
+    #[test]
+    fn test_notify() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/hooks/batches", listener.local_addr().unwrap());
+        let server = thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(&stream);
+            let mut request_line = String::new();
+            reader.read_line(&mut request_line).unwrap();
+            let mut length = 0;
+            let mut header = String::new();
+            while reader.read_line(&mut header).unwrap() > 2 {
+                if let Some(value) = header.strip_prefix("Content-Length: ") {
+                    length = value.trim().parse().unwrap();
+                }
+                header.clear();
+            }
+            let mut body = vec![0; length];
+            reader.read_exact(&mut body).unwrap();
+            (&stream)
+                .write_all(b"HTTP/1.1 204 No Content\r\nContent-Length: 0\r\n\r\n")
+                .unwrap();
+            (
+                request_line,
+                serde_json::from_slice::<Value>(&body).unwrap(),
+            )
+        });
+
+        let error = VcfBatcherError::EmptyInput {
+            path: "cohortA.vcf".into(),
+        };
+        let payload = run_payload(Path::new("cohortA.vcf"), Path::new("out"), Err(&error));
+        Webhook::parse(&url).unwrap().notify(&payload).unwrap();
+
+        let (request_line, body) = server.join().unwrap();
+        assert_eq!(request_line, "POST /hooks/batches HTTP/1.1\r\n");
+        assert_eq!(body["status"], "failed");
+        assert_eq!(body["batches"], json!([]));
+        assert_eq!(body["error"], "cohortA.vcf contains no variant records");
+    }
+}
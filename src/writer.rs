@@ -0,0 +1,1180 @@
+//! Splitting a stream of VCF lines into batches.
+
+use std::borrow::Cow;
+use std::collections::{HashMap, HashSet};
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use bgzip::deflate::Compress;
+use bgzip::write::{write_block, DEFAULT_COMPRESS_UNIT_SIZE};
+use bgzip::{BGZFError, Compression};
+
+use crate::genes::{GeneAnnotations, GeneGroups};
+use crate::lock::OutputLock;
+use crate::regions::{RegionBatch, RegionBatches};
+use crate::samples::SampleBatches;
+use crate::stream::BatchFile;
+use crate::{
+    filter, header, is_header_line, AppendLine, BadRecordPolicy, BatchContents, BatchLabel,
+    BatchOptions, BatchOutput, ContigCheck, Destination, DuplicateRecordPolicy,
+    DuplicateSamplePolicy, EmptyInputPolicy, Instant, LateHeaderPolicy, RunReport, SplitMode,
+    Stage, VcfBatcherError,
+};
+
+/// Name of the file in the output directory that quarantined records are written to, see [`BadRecordPolicy::Quarantine`].
+pub const BAD_RECORDS_FILE_NAME: &str = "bad_records.txt";
+
+/// Splits VCF lines that are pushed one at a time into batches, e.g. for records that are
+/// generated by a program instead of being read from a file. Header lines are collected and
+/// written at the top of every batch, records are batched according to the [`BatchOptions`].
+///
+/// This is the same machinery that [`crate::extract_variants_to_batches_with_options`] feeds
+/// with the lines of its input file, so filters, split modes and policies behave identically.
+///
+/// # Examples
+///
+/// ```
+/// use vcf_batcher::writer::BatchWriter;
+/// use vcf_batcher::BatchOptions;
+///
+/// let options = BatchOptions { batch_size: 2, ..Default::default() };
+/// let mut writer = BatchWriter::create("test_data/doc_writer", options).unwrap();
+/// writer.write_line("##fileformat=VCFv4.2").unwrap();
+/// writer.write_line("#CHROM\tPOS\tID\tREF\tALT\tQUAL\tFILTER\tINFO").unwrap();
+/// for pos in 1..=3 {
+///     writer.write_line(&format!("chr1\t{}\t.\tA\tC\t.\tPASS\t.", pos)).unwrap();
+/// }
+/// let report = writer.finish().unwrap();
+/// assert_eq!(report.batches.len(), 2);
+/// ```
+pub struct BatchWriter<'a> {
+    output: BatchOutput<'a>,
+    /// Describes where the lines come from, for messages.
+    input_name: String,
+    headers: String,
+    /// The batches that are being collected, one per value of [`BatchOptions::group_by_info`] or a single one
+    /// without a group, in the order that their groups appeared.
+    pending: Vec<PendingBatch>,
+    pending_indices: HashMap<Option<String>, usize>,
+    line_number: usize,
+    records_read: usize,
+    seen_records: bool,
+    declared_contigs: HashSet<String>,
+    /// Number of columns of the #CHROM line, once it was read.
+    columns: Option<usize>,
+    /// Created when the first record is quarantined.
+    quarantine: Option<BufWriter<File>>,
+    /// Columns to keep if only some samples are selected, known once the #CHROM line was read.
+    sample_columns: Option<Vec<usize>>,
+    /// Columns of the masked samples, known once the #CHROM line was read.
+    mask_columns: Vec<usize>,
+    selected_samples: bool,
+    /// Chromosome of the batch that is being collected and the chromosomes that were already completed.
+    batch_chrom: String,
+    completed_chroms: HashSet<String>,
+    /// The records at the position of the last record, to find duplicates.
+    recent_records: RecentRecords,
+    /// Where the header says the gene symbols are, for [`SplitMode::Gene`].
+    gene_annotations: GeneAnnotations,
+    /// The records of the intervals that are being collected, for [`SplitMode::Region`].
+    regions: Option<RegionBatches>,
+    /// The batches of sample columns, for [`SplitMode::Samples`], known once the #CHROM line was read.
+    sample_batches: Option<SampleBatches>,
+    _lock: Option<OutputLock>,
+}
+
+impl BatchWriter<'static> {
+    /// Creates a writer that saves the batches into the output directory. The directory is
+    /// locked until the writer is finished or dropped. Name templates use `batch` for `{stem}`,
+    /// and [`BatchOptions::resume`] has no effect, because there is no input file to compare.
+    pub fn create(
+        output_path: impl Into<PathBuf>,
+        options: BatchOptions,
+    ) -> Result<Self, VcfBatcherError> {
+        let output_path = output_path.into();
+        let lock = OutputLock::acquire(&output_path)?;
+        let output = BatchOutput::new(
+            "batch".to_string(),
+            Destination::Directory(Cow::Owned(output_path)),
+            Cow::Owned(options),
+            None,
+        )?;
+        let mut writer = BatchWriter::new("the input".to_string(), output);
+        writer._lock = Some(lock);
+        Ok(writer)
+    }
+}
+
+impl<'a> BatchWriter<'a> {
+    pub(crate) fn new(input_name: String, mut output: BatchOutput<'a>) -> Self {
+        // The records of a batch that is refilled come before those of the input
+        let (pending, pending_indices) = match output.tail.take() {
+            Some(tail) => {
+                let batch = PendingBatch {
+                    size: tail.records.len() as u64,
+                    records: tail.records,
+                    record_count: tail.record_count,
+                    ..Default::default()
+                };
+                (vec![batch], HashMap::from([(None, 0)]))
+            }
+            None => (Vec::new(), HashMap::new()),
+        };
+        let regions = (output.options.split_by == SplitMode::Region)
+            .then(|| RegionBatches::new(&output.options.split_regions));
+        BatchWriter {
+            selected_samples: output.options.filter.selected_samples().is_none()
+                && output.options.mask.is_none(),
+            output,
+            input_name,
+            headers: String::new(),
+            pending,
+            pending_indices,
+            line_number: 0,
+            records_read: 0,
+            seen_records: false,
+            declared_contigs: HashSet::new(),
+            columns: None,
+            quarantine: None,
+            sample_columns: None,
+            mask_columns: Vec::new(),
+            batch_chrom: String::new(),
+            completed_chroms: HashSet::new(),
+            recent_records: RecentRecords::default(),
+            gene_annotations: GeneAnnotations::default(),
+            regions,
+            sample_batches: None,
+            _lock: None,
+        }
+    }
+
+    /// The batches that were completed so far.
+    pub fn report(&self) -> &RunReport {
+        &self.output.report
+    }
+
+    /// The number of variant records that were written so far, including filtered ones.
+    pub fn records_read(&self) -> usize {
+        self.records_read
+    }
+
+    /// Starts measuring a stage of the run that happens outside of the writer, see [`crate::StageTimings`].
+    pub(crate) fn stage_start(&self) -> Option<Instant> {
+        self.output.report.stage_start()
+    }
+
+    /// Adds the time since `started` to a stage of the run.
+    pub(crate) fn stage_end(&mut self, stage: Stage, started: Option<Instant>) {
+        self.output.report.stage_end(stage, started);
+    }
+
+    /// Adds the next line of the VCF file, a header line or a variant record. A trailing line
+    /// ending is removed. Writes a batch as soon as it is complete.
+    ///
+    /// If the cancel flag of the options is set, [`VcfBatcherError::Interrupted`] is returned
+    /// with all batches completed so far; the batch that is still being collected is discarded.
+    pub fn write_line(&mut self, line: &str) -> Result<(), VcfBatcherError> {
+        let output = &mut self.output;
+        let options = &*output.options;
+        if options.is_cancelled() {
+            // The file of a batch that is still being collected is removed when the writer is dropped
+            return Err(VcfBatcherError::Interrupted {
+                completed: Box::new(std::mem::take(&mut output.report)),
+            });
+        }
+        self.line_number += 1;
+        let line = line.strip_suffix('\n').unwrap_or(line);
+        let line = line.strip_suffix('\r').unwrap_or(line);
+
+        if is_header_line(line) {
+            if self.seen_records {
+                output.report.late_header_lines += 1;
+                match options.late_header {
+                    LateHeaderPolicy::Error => {
+                        return Err(VcfBatcherError::LateHeaderLine {
+                            line_number: self.line_number,
+                            line: line.to_string(),
+                        })
+                    }
+                    LateHeaderPolicy::Skip => return Ok(()),
+                    LateHeaderPolicy::Header => {}
+                }
+                // The compressed batches start with the header, which changes
+                for batch in &mut self.pending {
+                    batch.compressor = None;
+                }
+            }
+            if let Some(contig) = header::contig_id(line) {
+                self.declared_contigs.insert(contig);
+            }
+            self.gene_annotations.read_header(line);
+            if line.starts_with("#CHROM") {
+                self.columns = Some(line.split('\t').count());
+                check_duplicate_samples(line, options, &mut output.report)?;
+                self.sample_columns = options.filter.column_indices(line)?;
+                if let Some(mask) = &options.mask {
+                    self.mask_columns = mask.column_indices(line)?;
+                }
+                self.selected_samples = true;
+                let line = match &self.sample_columns {
+                    Some(columns) => Cow::Owned(filter::select_columns(line, columns)),
+                    None => Cow::Borrowed(line),
+                };
+                if options.split_by == SplitMode::Samples {
+                    self.sample_batches =
+                        Some(SampleBatches::new(&line, options.samples_per_batch));
+                }
+                self.headers.append_line(&line);
+                return Ok(());
+            }
+            self.headers.append_line(line);
+            return Ok(());
+        }
+        if line.is_empty() {
+            // Blank lines, e.g. at the end of a file, are not records
+            return Ok(());
+        }
+        self.seen_records = true;
+        self.records_read += 1;
+
+        let started = output.report.stage_start();
+        let malformed = header::malformed_record_reason(line, self.columns);
+        output.report.stage_end(Stage::Filtering, started);
+        if let Some(reason) = malformed {
+            match options.bad_records {
+                BadRecordPolicy::Fail => {
+                    return Err(VcfBatcherError::MalformedRecord {
+                        line_number: self.line_number,
+                        reason,
+                    })
+                }
+                BadRecordPolicy::Skip => {}
+                // There is no directory to write the file to
+                BadRecordPolicy::Quarantine if output.sink.is_some() || output.dry_run => {}
+                BadRecordPolicy::Quarantine => {
+                    let path = output.output_path.join(BAD_RECORDS_FILE_NAME);
+                    quarantine_record(&mut self.quarantine, &path, self.line_number, line, &reason)
+                        .map_err(|source| VcfBatcherError::Write {
+                            path,
+                            source: source.into(),
+                        })?;
+                }
+            }
+            output.report.bad_records += 1;
+            return Ok(());
+        }
+
+        if !self.selected_samples {
+            // Samples cannot be selected without a #CHROM line
+            return Err(VcfBatcherError::UnknownSamples {
+                samples: options
+                    .filter
+                    .selected_samples()
+                    .or(options.mask.as_ref().map(|mask| &mask.samples))
+                    .cloned()
+                    .unwrap_or_default(),
+            });
+        }
+        let started = output.report.stage_start();
+        let keep = options.filter.keeps(line);
+        output.report.stage_end(Stage::Filtering, started);
+        if !keep {
+            output.report.filtered_records += 1;
+            return Ok(());
+        }
+
+        if let Some(dedup) = options.dedup {
+            if self.recent_records.is_duplicate(line) {
+                output.report.duplicate_records += 1;
+                match dedup {
+                    DuplicateRecordPolicy::KeepFirst => return Ok(()),
+                    DuplicateRecordPolicy::Error => {
+                        return Err(VcfBatcherError::DuplicateRecord {
+                            line_number: self.line_number,
+                            record: RecentRecords::describe(line),
+                        })
+                    }
+                    DuplicateRecordPolicy::Report => {}
+                }
+            }
+        }
+
+        if options.check_contigs != ContigCheck::Off {
+            let contig = header::record_chrom(line);
+            if !self.declared_contigs.contains(contig) {
+                if options.check_contigs == ContigCheck::Error {
+                    return Err(VcfBatcherError::UnknownContig {
+                        line_number: self.line_number,
+                        contig: contig.to_string(),
+                    });
+                }
+                *output
+                    .report
+                    .unknown_contigs
+                    .entry(contig.to_string())
+                    .or_insert(0) += 1;
+            }
+        }
+
+        if matches!(
+            options.split_by,
+            SplitMode::Chromosome | SplitMode::Gene | SplitMode::Region
+        ) {
+            let chrom = header::record_chrom(line);
+            if chrom != self.batch_chrom {
+                if self.completed_chroms.contains(chrom) {
+                    return Err(VcfBatcherError::NonContiguousChromosome {
+                        line_number: self.line_number,
+                        chrom: chrom.to_string(),
+                    });
+                }
+                self.save_batch()?;
+                self.completed_chroms
+                    .insert(std::mem::replace(&mut self.batch_chrom, chrom.to_string()));
+            }
+        }
+
+        let masked = match &self.output.options.mask {
+            Some(mask) if !self.mask_columns.is_empty() => {
+                let started = self.output.report.stage_start();
+                let masked = mask.apply(line, &self.mask_columns);
+                self.output.report.stage_end(Stage::Filtering, started);
+                Cow::Owned(masked)
+            }
+            _ => Cow::Borrowed(line),
+        };
+        let selected = match &self.sample_columns {
+            Some(columns) => {
+                let started = self.output.report.stage_start();
+                let selected = filter::select_columns(&masked, columns);
+                self.output.report.stage_end(Stage::Filtering, started);
+                Cow::Owned(selected)
+            }
+            None => masked,
+        };
+        if self.output.options.split_by == SplitMode::Samples {
+            return self.write_sample_record(&selected);
+        }
+        if let Some(regions) = &mut self.regions {
+            let pos = line
+                .split('\t')
+                .nth(1)
+                .and_then(|pos| pos.parse().ok())
+                .unwrap_or_default();
+            let mut completed = Vec::new();
+            if !regions.add(header::record_chrom(line), pos, &selected, &mut completed) {
+                self.output.report.filtered_records += 1;
+            }
+            for batch in completed {
+                self.save_region(batch)?;
+            }
+            return Ok(());
+        }
+        let group = self.output.options.group_by_info.as_deref().map(|key| {
+            match header::info_value(line, key) {
+                None | Some(".") => ".",
+                // A flag
+                Some("") => "true",
+                Some(value) => value,
+            }
+        });
+        let index = self.pending_index(group);
+        let batch = &mut self.pending[index];
+        if self.output.options.split_by == SplitMode::Gene {
+            batch.genes.add(self.gene_annotations.gene(line), &selected);
+            return Ok(());
+        }
+        batch.record_count += 1;
+        batch.size += selected.len() as u64 + 1;
+        if self.output.streams_batches() {
+            let started = self.output.report.stage_start();
+            if batch.file.is_none() {
+                let mut file = self.output.open_batch(&self.headers)?;
+                // The records of a batch that is refilled
+                file.write_records(&std::mem::take(&mut batch.records))
+                    .map_err(|source| self.output.batch_file_error(source))?;
+                batch.file = Some(file);
+            }
+            if let Some(file) = &mut batch.file {
+                file.write_record(&selected)
+                    .map_err(|source| self.output.batch_file_error(source))?;
+            }
+            let stage = match self.output.options.compression_level {
+                Some(_) => Stage::Compression,
+                None => Stage::Writing,
+            };
+            self.output.report.stage_end(stage, started);
+        } else {
+            batch.records.append_line(&selected);
+        }
+
+        if self.output.options.split_by != SplitMode::Records {
+            return Ok(());
+        }
+        let mut full = batch.record_count >= self.output.options.batch_size_of(batch.saved + 1);
+        if let Some(target) = self.output.options.target_compressed_size {
+            let started = self.output.report.stage_start();
+            let size = batch
+                .track_size(
+                    &self.headers,
+                    &selected,
+                    self.output.options.compression_level,
+                )
+                .map_err(|source| VcfBatcherError::Write {
+                    path: self.output.output_path.to_path_buf(),
+                    source,
+                })?;
+            self.output.report.stage_end(Stage::Compression, started);
+            full |= size >= target;
+        }
+        if let Some(target) = self.output.options.target_size {
+            full |= self.headers.len() as u64 + batch.size >= target;
+        }
+        if full {
+            self.save_pending(index)?;
+        }
+        Ok(())
+    }
+
+    /// Returns the index of the batch that collects the records of a group, which is added when its
+    /// first record arrives.
+    fn pending_index(&mut self, group: Option<&str>) -> usize {
+        if let Some(&index) = self.pending_indices.get(&group.map(str::to_string)) {
+            return index;
+        }
+        let batch = PendingBatch {
+            group: group.map(str::to_string),
+            // The batches continue the numbering of the batches of earlier runs
+            saved: match group {
+                Some(group) => self.output.existing_group_batches(group),
+                None => self.output.report.existing_batches,
+            },
+            ..Default::default()
+        };
+        self.pending_indices
+            .insert(batch.group.clone(), self.pending.len());
+        self.pending.push(batch);
+        self.pending.len() - 1
+    }
+
+    /// Adds the columns of a record to every batch of samples, see [`SplitMode::Samples`].
+    fn write_sample_record(&mut self, record: &str) -> Result<(), VcfBatcherError> {
+        let samples = self.sample_batches.get_or_insert_with(|| {
+            // Without a #CHROM line, the samples are those of the first record
+            SampleBatches::new(record, self.output.options.samples_per_batch)
+        });
+        samples.record_count += 1;
+        let started = self.output.report.stage_start();
+        for index in 0..samples.batches.len() {
+            let selected = samples.select(index, record);
+            if !self.output.streams_batches() {
+                samples.batches[index].records.append_line(&selected);
+                continue;
+            }
+            if samples.batches[index].file.is_none() {
+                let headers = samples.headers(index, &self.headers);
+                samples.batches[index].file = Some(self.output.open_batch(&headers)?);
+            }
+            if let Some(file) = &mut samples.batches[index].file {
+                file.write_record(&selected)
+                    .map_err(|source| self.output.batch_file_error(source))?;
+            }
+        }
+        let stage = match self.output.options.compression_level {
+            Some(_) if self.output.streams_batches() => Stage::Compression,
+            _ => Stage::Writing,
+        };
+        self.output.report.stage_end(stage, started);
+        Ok(())
+    }
+
+    /// Saves the batches that are being collected, if they contain any records.
+    fn save_batch(&mut self) -> Result<(), VcfBatcherError> {
+        for index in 0..self.pending.len() {
+            self.save_pending(index)?;
+        }
+        let regions = self.regions.as_mut().map(RegionBatches::take);
+        for batch in regions.into_iter().flatten() {
+            self.save_region(batch)?;
+        }
+        if let Some(mut samples) = self.sample_batches.take() {
+            if samples.record_count == 0 {
+                return Ok(());
+            }
+            for (index, batch) in std::mem::take(&mut samples.batches).into_iter().enumerate() {
+                let contents = match batch.file {
+                    Some(file) => BatchContents::File(Box::new(file)),
+                    None => BatchContents::Records {
+                        records: &batch.records,
+                        compressed: None,
+                    },
+                };
+                let headers = samples.headers(index, &self.headers);
+                self.output.save(
+                    &headers,
+                    contents,
+                    samples.record_count,
+                    BatchLabel::default(),
+                )?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Saves the records of an interval, see [`SplitMode::Region`].
+    fn save_region(&mut self, batch: RegionBatch) -> Result<(), VcfBatcherError> {
+        let label = BatchLabel {
+            region: Some(&batch.name),
+            ..Default::default()
+        };
+        let contents = BatchContents::Records {
+            records: &batch.records,
+            compressed: None,
+        };
+        self.output
+            .save(&self.headers, contents, batch.record_count, label)?;
+        Ok(())
+    }
+
+    /// Saves the batch of a group, or with [`SplitMode::Gene`] the records of the chromosome that were collected
+    /// by gene, [`BatchOptions::genes_per_batch`] genes per batch.
+    fn save_pending(&mut self, index: usize) -> Result<(), VcfBatcherError> {
+        let batch = &mut self.pending[index];
+        batch.size = 0;
+        if let Some(file) = batch.file.take() {
+            let record_count = std::mem::take(&mut batch.record_count);
+            batch.saved += 1;
+            let label = BatchLabel {
+                group: batch.group.as_deref().map(|group| (group, batch.saved)),
+                ..Default::default()
+            };
+            self.output.save(
+                &self.headers,
+                BatchContents::File(Box::new(file)),
+                record_count,
+                label,
+            )?;
+            return Ok(());
+        }
+        let genes = batch.genes.take();
+        let mut compressed = batch
+            .compressor
+            .take()
+            .map(BatchCompressor::finish)
+            .transpose()
+            .map_err(|source| VcfBatcherError::Write {
+                path: self.output.output_path.to_path_buf(),
+                source,
+            })?;
+        let chunks: Vec<(String, usize, Option<&str>)> = match genes.is_empty() {
+            true if batch.records.is_empty() => return Ok(()),
+            true => vec![(
+                std::mem::take(&mut batch.records),
+                std::mem::take(&mut batch.record_count),
+                None,
+            )],
+            false => genes
+                .chunks(self.output.options.genes_per_batch.max(1))
+                .map(|genes| {
+                    let records = genes.iter().map(|gene| gene.records.as_str()).collect();
+                    let record_count = genes.iter().map(|gene| gene.record_count).sum();
+                    (records, record_count, Some(genes[0].name()))
+                })
+                .collect(),
+        };
+        for (records, record_count, gene) in chunks {
+            let batch = &mut self.pending[index];
+            batch.saved += 1;
+            let label = BatchLabel {
+                gene,
+                group: batch.group.as_deref().map(|group| (group, batch.saved)),
+                ..Default::default()
+            };
+            let contents = BatchContents::Records {
+                records: &records,
+                compressed: compressed.take(),
+            };
+            self.output
+                .save(&self.headers, contents, record_count, label)?;
+        }
+        Ok(())
+    }
+
+    /// Saves the last, possibly smaller batch and completes the run.
+    pub fn finish(mut self) -> Result<RunReport, VcfBatcherError> {
+        self.save_batch()?;
+        if let Some(quarantine) = &mut self.quarantine {
+            quarantine
+                .flush()
+                .map_err(|source| VcfBatcherError::Write {
+                    path: self.output.output_path.join(BAD_RECORDS_FILE_NAME),
+                    source: source.into(),
+                })?;
+        }
+
+        let options = &*self.output.options;
+        let report = &self.output.report;
+        let saved_batches = report.batches.len() - report.existing_batches;
+        if saved_batches == 0 {
+            handle_empty_input(&self.input_name, &self.headers, &mut self.output)?;
+        } else if options.quiet || self.output.dry_run {
+        } else if let Some(key) = &options.group_by_info {
+            println!(
+                "Saved {} batches for {} values of {} to {}.",
+                saved_batches,
+                report.groups.len(),
+                key,
+                self.output.destination_name()
+            );
+        } else if options.split_by == SplitMode::Chromosome {
+            println!(
+                "Saved {} batches, one per chromosome, to {}.",
+                saved_batches,
+                self.output.destination_name()
+            );
+        } else if options.split_by == SplitMode::Region {
+            println!(
+                "Saved {} batches, one per region, to {}.",
+                saved_batches,
+                self.output.destination_name()
+            );
+        } else if options.split_by == SplitMode::Samples {
+            println!(
+                "Saved {} batches with up to {} samples each to {}.",
+                saved_batches,
+                options.samples_per_batch.max(1),
+                self.output.destination_name()
+            );
+        } else if options.split_by == SplitMode::Gene {
+            println!(
+                "Saved {} batches with up to {} genes to {}.",
+                saved_batches,
+                options.genes_per_batch.max(1),
+                self.output.destination_name()
+            );
+        } else if let Some(target) = options.target_compressed_size {
+            println!(
+                "Saved {} batches of about {} bytes to {}.",
+                saved_batches,
+                target,
+                self.output.destination_name()
+            );
+        } else if let Some(target) = options.target_size {
+            println!(
+                "Saved {} batches of about {} bytes before compression to {}.",
+                saved_batches,
+                target,
+                self.output.destination_name()
+            );
+        } else {
+            println!(
+                "Saved {} batches with {} records to {}.",
+                saved_batches,
+                options.batch_size,
+                self.output.destination_name()
+            );
+        }
+        self.output.finish()
+    }
+}
+
+/// The records of a batch that is being collected.
+#[derive(Debug, Default)]
+struct PendingBatch {
+    /// The value of [`BatchOptions::group_by_info`] that the records have, `None` if they are not grouped.
+    group: Option<String>,
+    /// The records, each followed by a line ending.
+    records: String,
+    record_count: usize,
+    /// The size of the records as plain text, with their line endings, for [`BatchOptions::target_size`].
+    size: u64,
+    /// The records of the chromosome by gene instead, for [`SplitMode::Gene`].
+    genes: GeneGroups,
+    /// How many batches of the group were saved, including those of earlier runs.
+    saved: usize,
+    /// The batch compressed so far, for [`BatchOptions::target_compressed_size`]. Created with the first record
+    /// that is measured, and dropped when the header changes.
+    compressor: Option<BatchCompressor>,
+    /// The file that the records are written to instead of being collected, if the output allows it, see
+    /// [`BatchOutput::streams_batches`].
+    file: Option<BatchFile>,
+}
+
+impl PendingBatch {
+    /// Adds the last of the records to the size of the batch file and returns the size.
+    fn track_size(
+        &mut self,
+        headers: &str,
+        record: &str,
+        compression_level: Option<Compression>,
+    ) -> Result<u64, BGZFError> {
+        if let Some(file) = &self.file {
+            return Ok(file.size());
+        }
+        let Some(level) = compression_level else {
+            return Ok((headers.len() + self.records.len()) as u64);
+        };
+        match &mut self.compressor {
+            Some(compressor) => {
+                compressor.push(record.as_bytes())?;
+                compressor.push(b"\n")?;
+            }
+            // The records include the last one
+            None => {
+                let mut compressor = BatchCompressor::new(level);
+                compressor.push(headers.as_bytes())?;
+                compressor.push(self.records.as_bytes())?;
+                self.compressor = Some(compressor);
+            }
+        }
+        Ok(self.compressor.as_ref().map_or(0, BatchCompressor::size))
+    }
+}
+
+/// Compresses a batch into BGZF blocks while its records are collected, to measure its compressed size.
+/// The blocks are the same that a batch is compressed into when it is saved, so they are written as they are.
+struct BatchCompressor {
+    compress: Compress,
+    /// The complete blocks.
+    blocks: Vec<u8>,
+    /// The contents that do not fill a block yet.
+    rest: Vec<u8>,
+}
+
+impl std::fmt::Debug for BatchCompressor {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("BatchCompressor")
+            .field("blocks", &self.blocks.len())
+            .field("rest", &self.rest.len())
+            .finish()
+    }
+}
+
+impl BatchCompressor {
+    fn new(level: Compression) -> Self {
+        BatchCompressor {
+            compress: Compress::new(level),
+            blocks: Vec::new(),
+            rest: Vec::with_capacity(DEFAULT_COMPRESS_UNIT_SIZE),
+        }
+    }
+
+    fn push(&mut self, mut contents: &[u8]) -> Result<(), BGZFError> {
+        while !contents.is_empty() {
+            let length = contents
+                .len()
+                .min(DEFAULT_COMPRESS_UNIT_SIZE - self.rest.len());
+            self.rest.extend_from_slice(&contents[..length]);
+            contents = &contents[length..];
+            if self.rest.len() == DEFAULT_COMPRESS_UNIT_SIZE {
+                write_block(&mut self.blocks, &self.rest, &mut self.compress)?;
+                self.rest.clear();
+            }
+        }
+        Ok(())
+    }
+
+    /// The size of the complete blocks.
+    fn size(&self) -> u64 {
+        self.blocks.len() as u64
+    }
+
+    /// Compresses the rest and returns the contents of the batch file.
+    fn finish(mut self) -> Result<Vec<u8>, BGZFError> {
+        if !self.rest.is_empty() {
+            write_block(&mut self.blocks, &self.rest, &mut self.compress)?;
+        }
+        self.blocks.extend_from_slice(&bgzip::EOF_MARKER);
+        Ok(self.blocks)
+    }
+}
+
+/// The REF and ALT of the records at the position of the last record, to find records that repeat
+/// an earlier one, see [`DuplicateRecordPolicy`].
+#[derive(Debug, Default)]
+struct RecentRecords {
+    /// CHROM and POS of the last record.
+    locus: String,
+    /// REF and ALT of the records at that position.
+    alleles: HashSet<String>,
+}
+
+impl RecentRecords {
+    /// Adds a record and returns whether a record with the same CHROM, POS, REF and ALT was added
+    /// since the position last changed.
+    fn is_duplicate(&mut self, line: &str) -> bool {
+        let (locus, alleles) = Self::split(line);
+        if locus != self.locus {
+            self.locus = locus.to_string();
+            self.alleles.clear();
+        }
+        !self.alleles.insert(alleles.to_string())
+    }
+
+    /// Splits a record into its CHROM and POS and its REF and ALT, each still separated by a tab.
+    fn split(line: &str) -> (&str, &str) {
+        // The tabs after POS, ID, REF and ALT
+        let mut tabs = line.match_indices('\t').map(|(index, _)| index).skip(1);
+        let (pos_end, id_end) = (tabs.next(), tabs.next());
+        let alt_end = tabs.nth(1).unwrap_or(line.len());
+        match (pos_end, id_end) {
+            (Some(pos_end), Some(id_end)) => (&line[..pos_end], &line[id_end + 1..alt_end]),
+            _ => (line, ""),
+        }
+    }
+
+    /// Describes a record for messages, e.g. `chr1:10177 A>AC`.
+    fn describe(line: &str) -> String {
+        let (locus, alleles) = Self::split(line);
+        format!(
+            "{} {}",
+            locus.replacen('\t', ":", 1),
+            alleles.replacen('\t', ">", 1)
+        )
+    }
+}
+
+/// Writes a malformed record with its line number and the reason to the quarantine file,
+/// which is created (replacing the one of an earlier run) when the first record is quarantined.
+fn quarantine_record(
+    quarantine: &mut Option<BufWriter<File>>,
+    path: &Path,
+    line_number: usize,
+    line: &str,
+    reason: &str,
+) -> std::io::Result<()> {
+    let writer = match quarantine {
+        Some(writer) => writer,
+        None => {
+            if let Some(parent) = path.parent() {
+                std::fs::create_dir_all(parent)?;
+            }
+            let mut writer = BufWriter::new(File::create(path)?);
+            writeln!(writer, "#line\treason\trecord")?;
+            quarantine.insert(writer)
+        }
+    };
+    writeln!(writer, "{}\t{}\t{}", line_number, reason, line)
+}
+
+/// Applies the [`DuplicateSamplePolicy`] to the `#CHROM` header line.
+fn check_duplicate_samples(
+    chrom_line: &str,
+    options: &BatchOptions,
+    report: &mut RunReport,
+) -> Result<(), VcfBatcherError> {
+    if options.duplicate_samples == DuplicateSamplePolicy::Allow {
+        return Ok(());
+    }
+    let duplicates = header::duplicate_samples(&header::sample_names(chrom_line));
+    if duplicates.is_empty() {
+        return Ok(());
+    }
+    if options.duplicate_samples == DuplicateSamplePolicy::Error {
+        return Err(VcfBatcherError::DuplicateSamples {
+            samples: duplicates,
+        });
+    }
+    report.duplicate_samples = duplicates;
+    Ok(())
+}
+
+/// Applies the [`EmptyInputPolicy`] to an input without any variant records.
+fn handle_empty_input(
+    input_name: &str,
+    headers: &str,
+    output: &mut BatchOutput,
+) -> Result<(), VcfBatcherError> {
+    output.report.empty_input = Some(output.options.empty_input);
+    match output.options.empty_input {
+        EmptyInputPolicy::HeaderOnly => {
+            let contents = BatchContents::Records {
+                records: "",
+                compressed: None,
+            };
+            let batch = output.save(headers, contents, 0, BatchLabel::default())?;
+            if output.options.quiet || output.dry_run {
+                return Ok(());
+            }
+            println!(
+                "{} contains no variant records, saved the header to {}",
+                input_name,
+                batch.display()
+            );
+        }
+        EmptyInputPolicy::Skip if output.options.quiet || output.dry_run => {}
+        EmptyInputPolicy::Skip => {
+            println!(
+                "{} contains no variant records, no batches were saved.",
+                input_name
+            );
+        }
+        EmptyInputPolicy::Error => {
+            return Err(VcfBatcherError::EmptyInput {
+                path: Path::new(input_name).to_path_buf(),
+            })
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use bgzip::Compression;
+
+    use super::BatchWriter;
+    use crate::testdata::SyntheticVcf;
+    use crate::{
+        encode_batch, manifest, BatchOptions, DuplicateRecordPolicy, LateHeaderPolicy,
+        VcfBatcherError,
+    };
+
+    #[test]
+    fn test_batch_writer() {
+        let output_path = Path::new("./test_data/batch_writer");
+        let _ = std::fs::remove_dir_all(output_path);
+        let options = BatchOptions {
+            batch_size: 2,
+            ..Default::default()
+        };
+
+        let mut writer = BatchWriter::create(output_path, options).unwrap();
+        assert!(matches!(
+            BatchWriter::create(output_path, BatchOptions::default()),
+            Err(VcfBatcherError::OutputLocked { .. })
+        ));
+        writer.write_line("##fileformat=VCFv4.2\n").unwrap();
+        writer
+            .write_line("#CHROM\tPOS\tID\tREF\tALT\tQUAL\tFILTER\tINFO\r\n")
+            .unwrap();
+        for pos in 1..=5 {
+            writer
+                .write_line(&format!("chr1\t{}\t.\tA\tC\t.\tPASS\t.", pos))
+                .unwrap();
+        }
+        assert_eq!(writer.report().batches.len(), 2);
+        let report = writer.finish().unwrap();
+
+        assert_eq!(report.batches.len(), 3);
+        assert_eq!(
+            std::fs::read_to_string(output_path.join("batch_03.vcf")).unwrap(),
+            "##fileformat=VCFv4.2\n#CHROM\tPOS\tID\tREF\tALT\tQUAL\tFILTER\tINFO\n\
+             chr1\t5\t.\tA\tC\t.\tPASS\t.\n"
+        );
+        // The lock is released once the writer is finished
+        assert!(BatchWriter::create(output_path, BatchOptions::default()).is_ok());
+    }
+
+    #[test]
+    fn test_batch_sizes() {
+        let output_path = Path::new("./test_data/batch_sizes");
+        let _ = std::fs::remove_dir_all(output_path);
+        let mut options = BatchOptions {
+            quiet: true,
+            ..Default::default()
+        };
+        options.set_batch_sizes(&[1, 2, 3]);
+        let mut writer = BatchWriter::create(output_path, options).unwrap();
+        writer
+            .write_line("#CHROM\tPOS\tID\tREF\tALT\tQUAL\tFILTER\tINFO")
+            .unwrap();
+        for pos in 1..=10 {
+            writer
+                .write_line(&format!("chr1\t{}\t.\tA\tC\t.\tPASS\t.", pos))
+                .unwrap();
+        }
+        let report = writer.finish().unwrap();
+
+        let records: Vec<usize> = report.batches.iter().map(|batch| batch.records).collect();
+        assert_eq!(records, [1, 2, 3, 3, 1]);
+    }
+
+    #[test]
+    fn test_duplicate_records() {
+        let output_path = Path::new("./test_data/duplicate_records");
+        let records = [
+            "chr1\t10\trs1\tA\tC\t.\tPASS\t.",
+            "chr1\t10\t.\tA\tG\t.\tPASS\t.",
+            "chr1\t10\trs1_dup\tA\tC\t50\tPASS\t.",
+            "chr1\t20\t.\tA\tC\t.\tPASS\t.",
+            "chr2\t20\t.\tA\tC\t.\tPASS\t.",
+            "chr2\t20\t.\tA\tC\t.\tPASS\t.",
+        ];
+        let split = |dedup| {
+            let options = BatchOptions {
+                batch_size: 10,
+                dedup,
+                quiet: true,
+                ..Default::default()
+            };
+            let mut writer = BatchWriter::create(output_path, options).unwrap();
+            writer
+                .write_line("#CHROM\tPOS\tID\tREF\tALT\tQUAL\tFILTER\tINFO")
+                .unwrap();
+            for record in records {
+                writer.write_line(record)?;
+            }
+            writer.finish()
+        };
+
+        let report = split(None).unwrap();
+        assert_eq!((report.total_records(), report.duplicate_records), (6, 0));
+        let report = split(Some(DuplicateRecordPolicy::Report)).unwrap();
+        assert_eq!((report.total_records(), report.duplicate_records), (6, 2));
+        let report = split(Some(DuplicateRecordPolicy::KeepFirst)).unwrap();
+        assert_eq!((report.total_records(), report.duplicate_records), (4, 2));
+        let batch = std::fs::read_to_string(output_path.join("batch_01.vcf")).unwrap();
+        assert!(batch.contains("rs1\t") && !batch.contains("rs1_dup"));
+
+        let error = split(Some(DuplicateRecordPolicy::Error)).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "line 4 repeats the record chr1:10 A>C (same CHROM, POS, REF and ALT)"
+        );
+    }
+
+    #[test]
+    fn test_target_compressed_size() {
+        let output_path = Path::new("./test_data/target_compressed_size");
+        let _ = std::fs::remove_dir_all(output_path);
+        let mut vcf = Vec::new();
+        let synthetic = SyntheticVcf {
+            samples: 20,
+            records: 5000,
+            ..Default::default()
+        };
+        synthetic.write(&mut vcf, None).unwrap();
+        let target = 40_000;
+        let options = BatchOptions {
+            batch_size: usize::MAX,
+            target_compressed_size: Some(target),
+            compression_level: Some(Compression::fast()),
+            verify_output: true,
+            quiet: true,
+            ..Default::default()
+        };
+        let mut writer = BatchWriter::create(output_path, options).unwrap();
+        for line in String::from_utf8(vcf).unwrap().lines() {
+            writer.write_line(line).unwrap();
+        }
+        let report = writer.finish().unwrap();
+
+        assert!(report.batches.len() > 2);
+        assert_eq!(report.total_records(), 5000);
+        for (index, batch) in report.batches.iter().enumerate() {
+            assert!(batch.verified);
+            let size = std::fs::metadata(&batch.path).unwrap().len();
+            if index + 1 < report.batches.len() {
+                // Larger by less than one block of records
+                assert!((target..target + 65536).contains(&size), "{size}");
+            }
+        }
+        // The batches are the same as if they had been compressed when they were saved
+        let batch = std::fs::read(&report.batches[0].path).unwrap();
+        let mut contents = Vec::new();
+        std::io::Read::read_to_end(
+            &mut bgzip::BGZFReader::new(batch.as_slice()).unwrap(),
+            &mut contents,
+        )
+        .unwrap();
+        let encoded = encode_batch(&contents, Some(Compression::fast()), None).unwrap();
+        assert_eq!(encoded.as_ref(), batch.as_slice());
+    }
+
+    #[test]
+    fn test_target_size() {
+        let output_path = Path::new("./test_data/target_size");
+        let _ = std::fs::remove_dir_all(output_path);
+        let mut vcf = Vec::new();
+        let synthetic = SyntheticVcf {
+            samples: 20,
+            records: 2000,
+            ..Default::default()
+        };
+        synthetic.write(&mut vcf, None).unwrap();
+        let vcf = String::from_utf8(vcf).unwrap();
+        let longest_record = vcf.lines().map(|line| line.len() + 1).max().unwrap() as u64;
+        let target = 50_000;
+        let options = BatchOptions {
+            batch_size: usize::MAX,
+            target_size: Some(target),
+            quiet: true,
+            ..Default::default()
+        };
+        let mut writer = BatchWriter::create(output_path, options).unwrap();
+        for line in vcf.lines() {
+            writer.write_line(line).unwrap();
+        }
+        let report = writer.finish().unwrap();
+
+        assert!(report.batches.len() > 2);
+        assert_eq!(report.total_records(), 2000);
+        for batch in &report.batches[..report.batches.len() - 1] {
+            let size = std::fs::metadata(&batch.path).unwrap().len();
+            // Larger by less than one record
+            assert!((target..target + longest_record).contains(&size), "{size}");
+        }
+    }
+
+    #[test]
+    fn test_streamed_batches() {
+        let output_path = Path::new("./test_data/streamed_batches");
+        let _ = std::fs::remove_dir_all(output_path);
+        let options = BatchOptions {
+            batch_size: 3,
+            compression_level: Some(Compression::fast()),
+            late_header: LateHeaderPolicy::Header,
+            quiet: true,
+            ..Default::default()
+        };
+        let staged_files = || {
+            std::fs::read_dir(output_path)
+                .unwrap()
+                .filter(|entry| {
+                    let name = entry.as_ref().unwrap().file_name();
+                    name.to_string_lossy().starts_with(".batch.vcf")
+                })
+                .count()
+        };
+        let record = |pos: usize| format!("chr1\t{pos}\t.\tA\tC\t.\tPASS\t.");
+
+        let mut writer = BatchWriter::create(output_path, options).unwrap();
+        writer.write_line("##fileformat=VCFv4.2").unwrap();
+        writer
+            .write_line("#CHROM\tPOS\tID\tREF\tALT\tQUAL\tFILTER\tINFO")
+            .unwrap();
+        for pos in 1..=2 {
+            writer.write_line(&record(pos)).unwrap();
+        }
+        // The records are on disk before the batch is complete
+        assert_eq!(staged_files(), 1);
+        assert!(!output_path.join("batch_01.vcf.gz").exists());
+
+        // The header of the open batch is rewritten
+        writer.write_line("##comment=late").unwrap();
+        writer.write_line(&record(3)).unwrap();
+        let batch = writer.report().batches[0].clone();
+        assert_eq!(staged_files(), 0);
+        assert_eq!(batch.records, 3);
+        assert_eq!(batch.last.as_ref().unwrap().pos, 3);
+        assert_eq!(batch.sha256, manifest::file_sha256(&batch.path).unwrap());
+        let mut contents = String::new();
+        std::io::Read::read_to_string(
+            &mut bgzip::BGZFReader::new(std::fs::File::open(&batch.path).unwrap()).unwrap(),
+            &mut contents,
+        )
+        .unwrap();
+        assert!(contents.contains(&format!("##comment=late\n{}\n", record(1))));
+        assert!(contents.ends_with(&(record(3) + "\n")));
+
+        // A batch that is not completed leaves nothing behind
+        writer.write_line(&record(4)).unwrap();
+        assert_eq!(staged_files(), 1);
+        drop(writer);
+        assert_eq!(staged_files(), 0);
+        assert!(!output_path.join("batch_02.vcf.gz").exists());
+    }
+}
@@ -1,15 +1,690 @@
+"""
+Type stubs for the vcf_batcher extension module.
+"""
+
+import os
+from types import TracebackType
+from typing import Callable, Iterable, Iterator, Literal, TypedDict
+
+StrPath = str | os.PathLike[str]
+"""A path given as a string or as a `pathlib.Path` (any `os.PathLike`)."""
+
+CompressionLevel = Literal[
+    "None", "Default", "Fast", "Best", "none", "default", "fast", "best",
+    "0", "1", "2", "3", "4", "5", "6", "7", "8", "9", "10", "11", "12",
+]
+"""BGZF compression level, or "none" for plain VCF files. The names are case-insensitive."""
+
+SplitMode = Literal["records", "chromosome", "gene"]
+"""Where one batch ends and the next begins: after `batch_size` records, whenever the chromosome changes, or with
+every gene named in the VEP CSQ or SnpEff ANN annotations (`genes_per_batch` genes per batch)."""
+
+JsonField = Literal["chrom", "pos", "id", "ref", "alt", "qual", "filter", "info", "samples"]
+"""A field of the JSON objects that records are written as with `format="jsonl"`."""
+
+
+class VcfBatcherError(Exception):
+    """
+    Raised when a VCF file cannot be split into batches, e.g. because a batch could not be written
+    or the input is malformed.
+    """
+
+
+class Locus(TypedDict):
+    """Position of a variant record."""
+    chrom: str
+    pos: int
+
+
+class BatchReport(TypedDict):
+    """A single batch of a run."""
+    number: int
+    path: str
+    records: int
+    sha256: str
+    size: int
+    """Size of the batch file in bytes, 0 for a dry run."""
+    first: Locus | None
+    """Position of the first record, None for a batch without records."""
+    last: Locus | None
+    """Position of the last record, None for a batch without records."""
+    verified: bool
+    """Whether the batch was read back and checked after it was written."""
+
+
+class StageTimings(TypedDict):
+    """Time spent in the stages of a run, in seconds."""
+    reading_seconds: float
+    """Reading and decompressing the input."""
+    filtering_seconds: float
+    """Checking records, applying the filters and selecting the samples."""
+    compression_seconds: float
+    writing_seconds: float
+
+
+class Progress(TypedDict):
+    """How far a run has progressed, passed to the `on_progress` callable."""
+    bytes_read: int
+    """Bytes of the input file that were read so far. For compressed files, these are compressed bytes."""
+    total_bytes: int | None
+    records: int
+    """Records that were read so far, including the ones that were filtered out."""
+    batches: int
+    elapsed_seconds: float
+    records_per_second: float
+    bytes_per_second: float
+    remaining_seconds: float | None
+    """The time until the whole input is read at the rate so far, None if its size is unknown."""
+
+
+class RunReport(TypedDict):
+    """The report of a run."""
+    output_path: str
+    batches: list[BatchReport]
+    total_records: int
+    elapsed_seconds: float
+    resumed_batches: int
+    skipped_batches: int
+    existing_batches: int
+    """The number of batches of earlier runs that were appended to, which are listed first in `batches`."""
+    groups: dict[str, str]
+    """The directory of the batches of every value of the `group_by_info` key."""
+    filtered_records: int
+    """The number of records that were left out by the `regions`, `pass_only` and `min_qual` filters."""
+    empty_input: Literal["header-only", "skip", "error"] | None
+    late_header_lines: int
+    unknown_contigs: dict[str, int]
+    duplicate_samples: list[str]
+    bad_records: int
+    """The number of malformed records that were skipped or quarantined."""
+    duplicate_records: int
+    """The number of records that repeat an earlier record, if `dedup` was passed. They were left out with "keep-first"."""
+    timings: StageTimings | None
+    """The time spent in each stage of the run, if `timings=True` was passed."""
+
+
+class Stats(TypedDict):
+    """Counts of the lines in a VCF file."""
+    path: str
+    header_lines: int
+    records: int
+    samples: list[str]
+    records_per_contig: dict[str, int]
+
+
+class MergeReport(TypedDict):
+    """The result of merging batches."""
+    output: str
+    batches: list[str]
+    records: int
+
+
+class BatchSummary(TypedDict):
+    """A batch in an output directory, see `summarize`."""
+    path: str
+    n_records: int
+    chrom_start: str | None
+    """CHROM of the first record, None for a batch without records."""
+    pos_start: int | None
+    chrom_end: str | None
+    """CHROM of the last record, None for a batch without records."""
+    pos_end: int | None
+    bytes: int
+    """Size of the file on disk."""
+
+
+class Contig(TypedDict):
+    """A contig declared by a ##contig header line."""
+    name: str
+    length: int | None
+
+
+class FieldDefinition(TypedDict):
+    """An INFO or FORMAT field declared in the header."""
+    number: str | None
+    """E.g. "1", "A" or "."."""
+    type: str | None
+    description: str | None
+
+
+class Header(TypedDict):
+    """The metadata of a VCF header, see `read_header`."""
+    fileformat: str | None
+    """The version of the ##fileformat line, e.g. "VCFv4.2"."""
+    contigs: list[Contig]
+    info: dict[str, FieldDefinition]
+    """The INFO fields by ID."""
+    format: dict[str, FieldDefinition]
+    """The FORMAT fields by ID."""
+    samples: list[str]
+
+
+class Issue(TypedDict):
+    """A problem found in a VCF file."""
+    severity: Literal["error", "warning"]
+    line: int | None
+    """The line number, or None for issues concerning the whole file."""
+    message: str
+
+
 def py_extract_variants_to_batches(
-        file_path: str,
-        output_path: str,
+        file_path: StrPath,
+        output_path: StrPath,
         batch_size: int,
-        compression_level: str | None = None,
-) -> None:
+        compression_level: CompressionLevel | None = None,
+        *,
+        regions: str | list[str] | None = None,
+        samples: list[str] | None = None,
+        sample_order: list[str] | None = None,
+        mask_samples: list[str] | None = None,
+        mask_all_fields: bool = False,
+        pass_only: bool = False,
+        min_qual: float | None = None,
+        split_by: SplitMode = "records",
+        genes_per_batch: int = 1,
+        group_by_info: str | None = None,
+        regions_bed: StrPath | None = None,
+        samples_per_batch: int | None = None,
+        batch_sizes: list[int] | str | None = None,
+        num_batches: int | None = None,
+        target_compressed_size: int | str | None = None,
+        target_size: int | str | None = None,
+        compression_format: Literal["bgzf", "zstd"] = "bgzf",
+        on_progress: Callable[[Progress], bool | None] | None = None,
+        name_template: str | None = None,
+        resume: bool = False,
+        append: Literal["continue", "rebalance"] | None = None,
+        tmp_dir: StrPath | None = None,
+        timings: bool = False,
+        max_line_length: int | None = None,
+        check_contigs: Literal["off", "warn", "error"] = "off",
+        on_empty_input: Literal["header-only", "skip", "error"] = "skip",
+        on_late_header: Literal["error", "skip", "header"] = "error",
+        on_duplicate_samples: Literal["error", "warn", "allow"] = "error",
+        on_bad_record: Literal["fail", "skip", "quarantine"] = "fail",
+        dedup: Literal["keep-first", "error", "report"] | None = None,
+        format: Literal["vcf", "jsonl", "bcf"] = "vcf",
+        json_fields: list[JsonField] | None = None,
+        site_lists: bool = False,
+        batches_bed: bool = False,
+        index: Literal["tabix"] | None = None,
+        verify_output: bool = False,
+        manifest: bool = False,
+        overwrite: Literal["overwrite", "skip", "refuse"] = "overwrite",
+) -> RunReport:
+    """
+    Converts a large VCF file into batches of smaller VCF files containing a fixed number of records.
+
+    :param file_path: The VCF file to split into batches.
+    :param output_path: The directory where the batches will be saved.
+    :param batch_size: The number of records to include in each batch.
+    :param compression_level: The compression level to use when writing the batches. Options are "None", "Default", "Fast", "Best" and "0" to "12".
+    :param regions: Only keep records whose POS lies in one of these regions, e.g. "chr1" or "chr1:10000-20000".
+    :param samples: Only keep these sample columns, in this order.
+    :param sample_order: Write the sample columns in this order, which must list every sample of the input.
+    :param mask_samples: Set the GT of these samples to missing in every record, e.g. to redact participants who
+        withdrew their consent. INFO fields such as AC and AF are not recomputed.
+    :param mask_all_fields: Set all FORMAT fields of the masked samples to missing, not only GT.
+    :param pass_only: Only keep records whose FILTER is "PASS".
+    :param min_qual: Only keep records with at least this QUAL. Records without a QUAL are dropped.
+    :param split_by: "records" for batches of `batch_size` records, "chromosome" for one batch per chromosome.
+        Without a name template, the batches are then named after their chromosome, e.g. "chr1.vcf.gz".
+        "gene" writes one batch per gene named in the VEP CSQ or SnpEff ANN annotations, e.g. "chr17_TP53.vcf.gz".
+    :param genes_per_batch: How many genes each batch has with split_by="gene".
+    :param group_by_info: Write one set of batches per value of this INFO key, e.g. "CLNSIG", each in a subdirectory
+        named like "CLNSIG=Pathogenic". Records without the key go into "CLNSIG=.". The manifest lists the subdirectories.
+    :param regions_bed: Write one batch per interval of this BED file, e.g. the capture regions of an exome kit, with
+        the records that lie in it. Replaces split_by. Batches are named after the name column if it tells the intervals
+        apart, otherwise like "chr1_000010001-000020000.vcf.gz". Records outside of all intervals are left out.
+    :param samples_per_batch: Split the sample columns instead of the records: every batch has all records, but only
+        this many samples, with the fixed columns and FORMAT kept. Replaces split_by.
+    :param batch_sizes: A schedule of batch sizes that replaces batch_size, as a list such as [100, 1000, 25000] or a
+        string such as "100,1000,25000...". The first batches get the sizes in order, all further batches the last size.
+    :param num_batches: Divide the records into this many batches of nearly the same size instead of batches of
+        batch_size records. The records are counted first, so the input is read twice.
+    :param target_compressed_size: Also end a batch once its file reaches this many bytes, or a size such as "500M",
+        measured after compression. Batches end between records, so they are larger by up to one BGZF block.
+    :param target_size: Also end a batch once its header and records reach this many bytes as plain VCF text, or a
+        size such as "500MB", measured before compression. Batches end between records.
+    :param compression_format: "bgzf" to compress the batches into ".vcf.gz" files, or "zstd" for ".vcf.zst" files,
+        which are smaller and faster to write but cannot be read by htslib or indexed. With zstd, "Fast", "Default" and
+        "Best" are zstd levels 1, 3 and 19 and numbers are used as they are.
+    :param name_template: Template for the file names of the batches, e.g. "{stem}_{number:04}.vcf.gz".
+        Supports the placeholders {number}, {stem}, {chrom}, {start}, {end}, {region} and {gene}, e.g. "{region}.vcf.gz"
+        for "22_018570346-018572476.vcf.gz".
+    :param resume: Keep batches that an interrupted run with the same options already wrote completely.
+    :param append: Add the batches to those of earlier runs in the output directory instead of starting at batch 1.
+        "continue" continues their numbering, "rebalance" also fills up their last batch if it is not full.
+        The input must have the same header as the existing batches, and "manifest.json" lists all batches.
+    :param tmp_dir: Write the batches to this directory first and move them to the output directory once they are
+        complete, e.g. a fast local disk when the output is on slow network storage.
+    :param timings: Measure the time spent in each stage of the run and add it to the report as `timings`.
+    :param max_line_length: Fail on lines longer than this many bytes instead of reading them into memory.
+    :param check_contigs: Whether to check that the contig of every record is declared in the header.
+    :param on_empty_input: What to do if the input contains no records.
+    :param on_late_header: What to do with header lines after the first record.
+    :param on_duplicate_samples: What to do if the header lists a sample more than once.
+    :param on_bad_record: What to do with records that have a different number of columns than the #CHROM line
+        or a POS that is not a number. "quarantine" writes them to "bad_records.txt" in the output directory.
+    :param dedup: What to do with records that repeat the CHROM, POS, REF and ALT of a record at the same position.
+        By default, duplicates are not checked.
+    :param format: "vcf", "jsonl" to write every record as a JSON object on a line of its own, e.g. "batch_01.jsonl.gz", or "bcf" if the module was built with the htslib feature.
+    :param json_fields: The fields of the JSON objects with `format="jsonl"`, in this order. All fields by default.
+    :param site_lists: Write the CHROM, POS, REF and ALT of every batch to a ".sites.tsv" file next to it.
+    :param batches_bed: Write the genomic span of every batch, with its file name, to "batches.bed" in the output directory.
+    :param index: Write an index next to every batch, e.g. "batch_01.vcf.gz.tbi" with "tabix", so that bcftools can
+        query the batches right away. Needs a compression level and `format="vcf"`.
+    :param verify_output: Read every batch back after it was written and check its checksum, BGZF blocks, record count
+        and first and last position; raises an error if one does not match. Verified batches are marked in "manifest.json".
+    :param manifest: Write "manifest.json" to the output directory, listing the file, record count, first and last
+        position, size and checksum of every batch.
+    :param overwrite: What to do with batch files that already exist: replace them, keep them or raise an error.
+    :param on_progress: Called with the progress of the run every few thousand lines and once at the end. The run
+        stops if it returns False or raises; the exception is then raised instead. The GIL is released while the
+        batches are written, and taken when `on_progress` is called, which is also when Ctrl+C stops the run.
+    :return: The report of the run, listing every batch with its path, record count, checksum and the positions
+        of its first and last record.
+    :raises FileNotFoundError: If the input file does not exist.
+    :raises PermissionError: If the input cannot be read or the output directory cannot be written.
+    :raises ValueError: If an option is invalid or a selected sample is missing from the header.
+    :raises VcfBatcherError: If the input cannot be read, a batch cannot be written or `on_progress` returned False.
+    :raises KeyboardInterrupt: If Ctrl+C was pressed during the run.
+    """
+
+
+class BatchIterator(Iterator[tuple[str, int]]):
+    """Iterator over the batches of a split that runs in a background thread, see `iter_batches`."""
+
+    def __iter__(self) -> BatchIterator: ...
+
+    def __next__(self) -> tuple[str, int]: ...
+
+
+def iter_batches(
+        file_path: StrPath,
+        output_path: StrPath,
+        batch_size: int = 25000,
+        compression_level: CompressionLevel | None = None,
+        *,
+        regions: str | list[str] | None = None,
+        samples: list[str] | None = None,
+        sample_order: list[str] | None = None,
+        mask_samples: list[str] | None = None,
+        mask_all_fields: bool = False,
+        pass_only: bool = False,
+        min_qual: float | None = None,
+        split_by: SplitMode = "records",
+        genes_per_batch: int = 1,
+        group_by_info: str | None = None,
+        regions_bed: StrPath | None = None,
+        samples_per_batch: int | None = None,
+        batch_sizes: list[int] | str | None = None,
+        num_batches: int | None = None,
+        target_compressed_size: int | str | None = None,
+        target_size: int | str | None = None,
+        compression_format: Literal["bgzf", "zstd"] = "bgzf",
+        on_progress: Callable[[Progress], bool | None] | None = None,
+        name_template: str | None = None,
+        resume: bool = False,
+        append: Literal["continue", "rebalance"] | None = None,
+        tmp_dir: StrPath | None = None,
+        timings: bool = False,
+        max_line_length: int | None = None,
+        check_contigs: Literal["off", "warn", "error"] = "off",
+        on_empty_input: Literal["header-only", "skip", "error"] = "skip",
+        on_late_header: Literal["error", "skip", "header"] = "error",
+        on_duplicate_samples: Literal["error", "warn", "allow"] = "error",
+        on_bad_record: Literal["fail", "skip", "quarantine"] = "fail",
+        dedup: Literal["keep-first", "error", "report"] | None = None,
+        format: Literal["vcf", "jsonl", "bcf"] = "vcf",
+        json_fields: list[JsonField] | None = None,
+        site_lists: bool = False,
+        batches_bed: bool = False,
+        index: Literal["tabix"] | None = None,
+        verify_output: bool = False,
+        manifest: bool = False,
+        overwrite: Literal["overwrite", "skip", "refuse"] = "overwrite",
+) -> BatchIterator:
     """
-    Converts a large VCF file into batches of smaller VCF files containing a fixed number of samples.
+    Splits a VCF file into batches in a background thread and yields a `(path, records)` tuple for every batch
+    as soon as it is written completely, so that batches can be processed before the whole file is split.
+    Abandoning the iterator stops the split after the batch that is currently being written.
 
     :param file_path: The VCF file to split into batches.
     :param output_path: The directory where the batches will be saved.
-    :param batch_size: The number of samples to include in each batch.
-    :param compression_level: The compression level to use when writing the batches. Options are "Default", "Fast", and "Best".
-    :return: None
+    :param batch_size: The number of records to include in each batch.
+    :param compression_level: The compression level to use when writing the batches. Options are "None", "Default", "Fast", "Best" and "0" to "12".
+
+    The keyword arguments are those of `py_extract_variants_to_batches`.
+
+    :raises FileNotFoundError: If the input file does not exist.
+    :raises PermissionError: If the input cannot be read or the output directory cannot be written.
+    :raises ValueError: If an option is invalid or a selected sample is missing from the header.
+    :raises VcfBatcherError: If the input cannot be read or a batch cannot be written.
+    """
+
+
+def extract_to_callback(
+        file_path: StrPath,
+        callback: Callable[[str, bytes], object],
+        batch_size: int = 25000,
+        compression_level: CompressionLevel | None = None,
+        *,
+        regions: str | list[str] | None = None,
+        samples: list[str] | None = None,
+        sample_order: list[str] | None = None,
+        mask_samples: list[str] | None = None,
+        mask_all_fields: bool = False,
+        pass_only: bool = False,
+        min_qual: float | None = None,
+        split_by: SplitMode = "records",
+        genes_per_batch: int = 1,
+        group_by_info: str | None = None,
+        regions_bed: StrPath | None = None,
+        samples_per_batch: int | None = None,
+        batch_sizes: list[int] | str | None = None,
+        num_batches: int | None = None,
+        target_compressed_size: int | str | None = None,
+        target_size: int | str | None = None,
+        compression_format: Literal["bgzf", "zstd"] = "bgzf",
+        on_progress: Callable[[Progress], bool | None] | None = None,
+        name_template: str | None = None,
+        resume: bool = False,
+        append: Literal["continue", "rebalance"] | None = None,
+        tmp_dir: StrPath | None = None,
+        timings: bool = False,
+        max_line_length: int | None = None,
+        check_contigs: Literal["off", "warn", "error"] = "off",
+        on_empty_input: Literal["header-only", "skip", "error"] = "skip",
+        on_late_header: Literal["error", "skip", "header"] = "error",
+        on_duplicate_samples: Literal["error", "warn", "allow"] = "error",
+        on_bad_record: Literal["fail", "skip", "quarantine"] = "fail",
+        dedup: Literal["keep-first", "error", "report"] | None = None,
+        format: Literal["vcf", "jsonl", "bcf"] = "vcf",
+        json_fields: list[JsonField] | None = None,
+        site_lists: bool = False,
+        batches_bed: bool = False,
+        index: Literal["tabix"] | None = None,
+        verify_output: bool = False,
+        manifest: bool = False,
+        overwrite: Literal["overwrite", "skip", "refuse"] = "overwrite",
+) -> RunReport:
+    """
+    Splits a VCF file into batches and passes every batch to `callback(name, contents)` instead of writing files,
+    e.g. to upload it or to publish it to a message queue. `contents` are the bytes of the batch (header and records),
+    bgzipped if a compression level is given, and `name` is the file name the batch would have had.
+    An exception raised by the callback stops the run and is re-raised.
+    The keyword arguments are those of `py_extract_variants_to_batches`.
+
+    :return: The report of the run, see `py_extract_variants_to_batches`.
+    :raises FileNotFoundError: If the input file does not exist.
+    :raises PermissionError: If the input file cannot be read.
+    :raises ValueError: If an option is invalid or a selected sample is missing from the header.
+    :raises VcfBatcherError: If the input cannot be read.
     """
+
+
+def merge_batches(
+        batch_dir: StrPath,
+        output_file: StrPath,
+        compression: CompressionLevel | None = None,
+) -> MergeReport:
+    """
+    Concatenates the batches in a directory into a single VCF file with one header.
+    The batches are taken from the manifest of the directory if there is one, otherwise all VCF files in it are
+    merged in the order of their batch numbers.
+
+    :param batch_dir: The directory containing the batches.
+    :param output_file: The merged VCF file.
+    :param compression: The compression level of the merged file. Options are "None", "Default", "Fast", "Best" and "0" to "12".
+        If None, the file is bgzipped with the default level if its name ends in ".gz".
+    :raises FileNotFoundError: If the directory contains no batches.
+    :raises PermissionError: If a batch cannot be read or the merged file cannot be written.
+    :raises ValueError: If the compression level is invalid.
+    :raises VcfBatcherError: If the batches have different headers or the merged file cannot be written.
+    """
+
+
+def stats(file_path: StrPath) -> Stats:
+    """
+    Counts the header lines, records, samples and records per contig of a VCF file.
+
+    :raises FileNotFoundError: If the file does not exist.
+    :raises VcfBatcherError: If the file cannot be read.
+    """
+
+
+class LineIterator(Iterator[str]):
+    """Iterator over the lines of a VCF file, see `read_lines`."""
+
+    def __iter__(self) -> LineIterator: ...
+
+    def __next__(self) -> str: ...
+
+
+def read_lines(file_path: StrPath) -> LineIterator:
+    """
+    Yields the lines of a VCF file without their line endings. Files whose name ends in ".gz" are read as BGZF.
+
+    :raises FileNotFoundError: If the file does not exist.
+    :raises VcfBatcherError: If the file is not valid BGZF or a line is not valid UTF-8.
+    """
+
+
+def list_samples(file_path: StrPath) -> list[str]:
+    """
+    Returns the sample names of a VCF file in the order of its #CHROM line. Only the header is read, so this is
+    fast even for large files. A file without samples or without a #CHROM line gives an empty list.
+
+    :raises FileNotFoundError: If the file does not exist.
+    :raises VcfBatcherError: If the file cannot be read.
+    """
+
+
+def read_header(file_path: StrPath) -> Header:
+    """
+    Returns the fileformat, the declared contigs, the INFO and FORMAT definitions and the samples of a VCF file.
+    Only the header is read.
+
+    :raises FileNotFoundError: If the file does not exist.
+    :raises VcfBatcherError: If the file cannot be read.
+    """
+
+
+def summarize(output_dir: StrPath) -> list[BatchSummary]:
+    """
+    Reads every batch in an output directory, in merge order, and returns one flat dict per batch,
+    so that `pandas.DataFrame(vcf_batcher.summarize(output_dir))` has one column per key.
+    Also works for directories that were written by an earlier run.
+
+    :raises FileNotFoundError: If the directory does not exist.
+    :raises VcfBatcherError: If a batch cannot be read.
+    """
+
+
+def validate(file_path: StrPath) -> list[Issue]:
+    """
+    Checks the structure of a VCF file: the ##fileformat and #CHROM header lines, header lines after the first
+    record, duplicate sample names, the number of columns and the POS of every record, undeclared contigs and
+    the sort order of the records. At most 1000 issues are reported.
+
+    :raises FileNotFoundError: If the file does not exist.
+    :raises VcfBatcherError: If the file cannot be read.
+    """
+
+
+class VcfBatcher:
+    """
+    Splits VCF files into batches with options that are configured once.
+
+    :param batch_size: The number of records to include in each batch.
+    :param compression_level: The compression level to use when writing the batches. Options are "None", "Default", "Fast", "Best" and "0" to "12".
+
+    The keyword arguments are those of `py_extract_variants_to_batches`.
+
+    :raises ValueError: If an option is invalid.
+    """
+
+    def __init__(
+            self,
+            batch_size: int = 25000,
+            compression_level: CompressionLevel | None = None,
+            *,
+            regions: str | list[str] | None = None,
+            samples: list[str] | None = None,
+            sample_order: list[str] | None = None,
+            mask_samples: list[str] | None = None,
+            mask_all_fields: bool = False,
+            pass_only: bool = False,
+            min_qual: float | None = None,
+            split_by: SplitMode = "records",
+            genes_per_batch: int = 1,
+            group_by_info: str | None = None,
+            regions_bed: StrPath | None = None,
+            samples_per_batch: int | None = None,
+            batch_sizes: list[int] | str | None = None,
+            num_batches: int | None = None,
+            target_compressed_size: int | str | None = None,
+            target_size: int | str | None = None,
+            compression_format: Literal["bgzf", "zstd"] = "bgzf",
+            on_progress: Callable[[Progress], bool | None] | None = None,
+            name_template: str | None = None,
+            resume: bool = False,
+            append: Literal["continue", "rebalance"] | None = None,
+            tmp_dir: StrPath | None = None,
+            timings: bool = False,
+            max_line_length: int | None = None,
+            check_contigs: Literal["off", "warn", "error"] = "off",
+            on_empty_input: Literal["header-only", "skip", "error"] = "skip",
+            on_late_header: Literal["error", "skip", "header"] = "error",
+            on_duplicate_samples: Literal["error", "warn", "allow"] = "error",
+            on_bad_record: Literal["fail", "skip", "quarantine"] = "fail",
+            dedup: Literal["keep-first", "error", "report"] | None = None,
+            format: Literal["vcf", "jsonl", "bcf"] = "vcf",
+            json_fields: list[JsonField] | None = None,
+            site_lists: bool = False,
+            batches_bed: bool = False,
+            index: Literal["tabix"] | None = None,
+            verify_output: bool = False,
+            manifest: bool = False,
+            overwrite: Literal["overwrite", "skip", "refuse"] = "overwrite",
+    ) -> None: ...
+
+    def run(self, file_path: StrPath, output_path: StrPath) -> RunReport:
+        """
+        Splits the input file into batches in the output directory.
+        """
+
+    def stats(self, file_path: StrPath) -> Stats:
+        """
+        Counts the header lines, records, samples and records per contig of a VCF file.
+        """
+
+    def merge(self, batch_dir: StrPath, output_file: StrPath) -> MergeReport:
+        """
+        Concatenates the batches in a directory into a single VCF file, compressed with the configured compression
+        level, or bgzipped with the default level if no level is configured and the name of the file ends in ".gz".
+
+        :raises FileNotFoundError: If the directory contains no batches.
+        :raises PermissionError: If a batch cannot be read or the merged file cannot be written.
+        :raises VcfBatcherError: If the batches have different headers or the merged file cannot be written.
+        """
+
+
+class BatchWriter:
+    """
+    Splits lines that are written from Python into batches, e.g. records that are generated by a program.
+    Header lines are written at the top of every batch, records are batched, compressed and rotated into new
+    files like the lines of an input file.
+
+    ```python
+    with vcf_batcher.BatchWriter("batches", batch_size=1000, compression="Fast") as writer:
+        writer.write("##fileformat=VCFv4.2")
+        writer.write("#CHROM\tPOS\tID\tREF\tALT\tQUAL\tFILTER\tINFO")
+        for record in records:
+            writer.write_record([record.chrom, record.pos, ".", record.ref, record.alt, ".", "PASS", "."])
+    print(writer.report["batches"])
+    ```
+
+    Leaving the `with` block writes the last batch. If the block raises an exception, the records after the
+    last complete batch are discarded instead. The output directory is locked while the writer is open.
+    The keyword arguments are those of `py_extract_variants_to_batches`; `resume` has no effect and `on_progress` is
+    not accepted.
+
+    :param output_dir: The directory where the batches will be saved.
+    :param batch_size: The number of records to include in each batch.
+    :param compression: The compression level of the batches. Options are "None", "Default", "Fast", "Best" and "0" to "12".
+    :raises ValueError: If an option is invalid.
+    :raises PermissionError: If the output directory cannot be written.
+    :raises VcfBatcherError: If another run is writing to the output directory.
+    """
+
+    def __init__(
+            self,
+            output_dir: StrPath,
+            batch_size: int = 25000,
+            compression: CompressionLevel | None = None,
+            *,
+            regions: str | list[str] | None = None,
+            samples: list[str] | None = None,
+            sample_order: list[str] | None = None,
+            mask_samples: list[str] | None = None,
+            mask_all_fields: bool = False,
+            pass_only: bool = False,
+            min_qual: float | None = None,
+            split_by: SplitMode = "records",
+            genes_per_batch: int = 1,
+            group_by_info: str | None = None,
+            regions_bed: StrPath | None = None,
+            samples_per_batch: int | None = None,
+            batch_sizes: list[int] | str | None = None,
+            num_batches: int | None = None,
+            target_compressed_size: int | str | None = None,
+            target_size: int | str | None = None,
+            compression_format: Literal["bgzf", "zstd"] = "bgzf",
+            name_template: str | None = None,
+            resume: bool = False,
+            append: Literal["continue", "rebalance"] | None = None,
+            tmp_dir: StrPath | None = None,
+            timings: bool = False,
+            max_line_length: int | None = None,
+            check_contigs: Literal["off", "warn", "error"] = "off",
+            on_empty_input: Literal["header-only", "skip", "error"] = "skip",
+            on_late_header: Literal["error", "skip", "header"] = "error",
+            on_duplicate_samples: Literal["error", "warn", "allow"] = "error",
+            on_bad_record: Literal["fail", "skip", "quarantine"] = "fail",
+            dedup: Literal["keep-first", "error", "report"] | None = None,
+            format: Literal["vcf", "jsonl", "bcf"] = "vcf",
+            json_fields: list[JsonField] | None = None,
+            site_lists: bool = False,
+            batches_bed: bool = False,
+            index: Literal["tabix"] | None = None,
+            verify_output: bool = False,
+            manifest: bool = False,
+            overwrite: Literal["overwrite", "skip", "refuse"] = "overwrite",
+    ) -> None: ...
+
+    def write(self, line: str) -> None:
+        """Adds a header line or a variant record. A trailing newline is removed."""
+
+    def writelines(self, lines: Iterable[str]) -> None:
+        """Adds every line of an iterable."""
+
+    def write_record(self, columns: Iterable[object]) -> None:
+        """Adds a variant record given as its columns, which are converted with `str()` and joined by tabs."""
+
+    def close(self) -> RunReport:
+        """Writes the last batch and returns the report of the run. Closing a closed writer returns the report again."""
+
+    @property
+    def report(self) -> RunReport:
+        """The batches that were completed so far, or the final report once the writer is closed."""
+
+    def __enter__(self) -> BatchWriter: ...
+
+    def __exit__(
+            self,
+            exception_type: type[BaseException] | None,
+            exception: BaseException | None,
+            traceback: TracebackType | None,
+    ) -> bool: ...